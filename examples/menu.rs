@@ -0,0 +1,117 @@
+// A game menu built from retained UI widgets: a centered settings panel,
+// an anchored HUD corner and a progress bar, themed and usable with the
+// mouse, keyboard (Tab, arrows, Enter, Escape) or a gamepad. Needs a
+// TrueType font in res/.
+use anyhow::*;
+use my_engine::prelude::*;
+use sdl2::event::{Event, WindowEvent};
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    env_logger::init();
+    let map_str = |e: String| anyhow!(e);
+
+    let sdl_context = sdl2::init().map_err(map_str)?;
+    let video_subsystem = sdl_context.video().map_err(map_str)?;
+    let controller_subsystem = sdl_context.game_controller().map_err(map_str)?;
+    let window = video_subsystem
+        .window("Menu", 800, 600)
+        .position_centered()
+        .metal_view()
+        .resizable()
+        .build()?;
+    video_subsystem.text_input().start();
+
+    let mut engine = WgpuEngine::new(&window).await?;
+    let font = engine.load_font("DejaVuSans.ttf").await?;
+    let white = engine.add_solid_sprite_texture([255; 4], "ui white")?;
+
+    // Laid out for 600 pixels high and scaled to the window.
+    let mut ui = Ui::new(Theme::dark(font, white));
+    ui.reference_height = Some(600.0);
+
+    let menu = ui.add(
+        None,
+        Widget {
+            anchor: Some(Anchor::CENTER),
+            width: Length::Px(360.0),
+            ..Widget::panel(Layout { align: Align::Stretch, padding: Edges::all(24.0), ..Layout::column(12.0) })
+        },
+    )?;
+    ui.add(Some(menu), Widget::label("Settings"))?;
+    let name = ui.add(Some(menu), Widget::text_input("Player name"))?;
+    let volume_row = ui.add(
+        Some(menu),
+        Widget {
+            style: Some(StateStyles::uniform(WidgetStyle { background: [0.0; 4], text: [1.0; 4] })),
+            ..Widget::panel(Layout { align: Align::Center, ..Layout::row(12.0) })
+        },
+    )?;
+    ui.add(Some(volume_row), Widget::label("Volume"))?;
+    let volume = ui.add(Some(volume_row), Widget { width: Length::Fill(1.0), ..Widget::slider(0.0, 100.0, 80.0) })?;
+    let light = ui.add(Some(menu), Widget::button("Light theme"))?;
+    let start = ui.add(Some(menu), Widget::button("Start"))?;
+    let quit = ui.add(Some(menu), Widget::button("Quit"))?;
+    ui.set_focus(Some(start));
+
+    let hud = ui.add(
+        None,
+        Widget {
+            anchor: Some(Anchor::BOTTOM_LEFT.offset(16.0, -16.0)),
+            width: Length::Px(240.0),
+            ..Widget::panel(Layout { align: Align::Stretch, padding: Edges::all(8.0), ..Layout::column(6.0) })
+        },
+    )?;
+    let status = ui.add(Some(hud), Widget::label("Loading"))?;
+    let progress = ui.add(Some(hud), Widget::progress_bar(0.0))?;
+
+    let mut controllers = Vec::new();
+    let mut event_pump = sdl_context.event_pump().map_err(map_str)?;
+    let started = std::time::Instant::now();
+    'running: loop {
+        for event in event_pump.poll_iter() {
+            if ui.handle_event(&event) {
+                continue;
+            }
+            match event {
+                Event::Quit { .. } => break 'running,
+                Event::Window { win_event: WindowEvent::Resized(width, height), .. } => {
+                    engine.resize(WindowSize { width: width as u32, height: height as u32 });
+                }
+                Event::ControllerDeviceAdded { which, .. } => controllers.push(controller_subsystem.open(which)?),
+                _ => (),
+            }
+        }
+
+        for event in ui.drain_events() {
+            match event {
+                UiEvent::Clicked(id) if id == start => {
+                    log::info!("Starting as {:?} at volume {}", ui.text(name)?, ui.value(volume)?);
+                    ui.widget_mut(menu)?.visible = false;
+                }
+                UiEvent::Clicked(id) if id == light => {
+                    let dark = ui.theme.panel == Theme::dark(font, white).panel;
+                    ui.theme = if dark { Theme::light(font, white) } else { Theme::dark(font, white) };
+                    ui.set_text(light, if dark { "Dark theme" } else { "Light theme" })?;
+                }
+                UiEvent::Clicked(id) if id == quit => break 'running,
+                UiEvent::Back => {
+                    let shown = !ui.widget(menu)?.visible;
+                    ui.widget_mut(menu)?.visible = shown;
+                    ui.set_focus(if shown { Some(start) } else { None });
+                }
+                _ => (),
+            }
+        }
+
+        let loaded = (started.elapsed().as_secs_f32() / 5.0).min(1.0);
+        ui.set_value(progress, loaded)?;
+        ui.set_text(status, if loaded < 1.0 { "Loading" } else { "Ready" })?;
+
+        engine.draw_ui(&mut ui)?;
+        engine.update()?;
+        engine.render()?;
+    }
+
+    Ok(())
+}
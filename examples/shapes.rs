@@ -0,0 +1,117 @@
+// Builds models in code with the public API: procedural meshes sharing one
+// material, instance groups owned by the engine, a small scene graph and a
+// dynamic mesh edited every frame.
+// Tab cycles through the debug render modes, also picked in an egui window.
+use anyhow::*;
+use my_engine::{egui, prelude::*};
+use sdl2::{
+    event::{Event, WindowEvent},
+    keyboard::Keycode,
+};
+use ultraviolet::{Rotor3, Vec3};
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    env_logger::init();
+    let map_str = |e: String| anyhow!(e);
+
+    let sdl_context = sdl2::init().map_err(map_str)?;
+    let video_subsystem = sdl_context.video().map_err(map_str)?;
+    let window = video_subsystem
+        .window("Shapes", 800, 600)
+        .position_centered()
+        .metal_view()
+        .resizable()
+        .build()?;
+
+    let mut engine = WgpuEngine::new(&window).await?;
+
+    // One material, used by every mesh of the model.
+    let texture = resources::load_texture("cube-diffuse.jpg", engine.device(), engine.queue()).await?;
+    let material = Material::new(engine.device(), "checker", texture, &engine.layouts().texture);
+    let meshes = vec![
+        MeshBuilder::uv_sphere(0.5, 24, 12).build(engine.device(), "sphere", 0),
+        MeshBuilder::torus(1.0, 0.15, 32, 12).build(engine.device(), "ring", 0),
+    ];
+    let planet = engine.add_model(Model::new(meshes, vec![material]));
+    let moon = engine.load_model("cube-diffuse.jpg").await?;
+
+    let planets = engine.create_group(planet)?;
+    let moons = engine.create_group(moon)?;
+
+    // Each moon orbits its planet, so moving a planet node carries its moon along.
+    let mut graph = SceneGraph::new();
+    let mut pivots = Vec::new();
+    for i in 0..5u128 {
+        let position = Vec3::new(i as f32 * 3.0 - 6.0, 0.0, 0.0);
+        let planet_node = graph.add_node(Transform::new(position, Rotor3::identity(), Vec3::one(), i), None)?;
+        graph.bind_instance(planet_node, Some(InstanceBinding { group: planets }))?;
+
+        let pivot = graph.add_node(Transform::default(), Some(planet_node))?;
+        let moon_transform = Transform::new(Vec3::new(1.5, 0.0, 0.0), Rotor3::identity(), Vec3::new(0.3, 0.3, 0.1), i);
+        let moon_node = graph.add_node(moon_transform, Some(pivot))?;
+        graph.bind_instance(moon_node, Some(InstanceBinding { group: moons }))?;
+        pivots.push(pivot);
+    }
+
+    // A ground plane rippling under the planets, its vertices rewritten every frame.
+    let flat = MeshBuilder::plane(16.0, 6.0, 32).into_data();
+    let ground = engine.create_dynamic_mesh("ground", planet, 0, 2)?;
+    let mesh = engine.dynamic_mesh_mut(ground)?;
+    mesh.set(flat.clone());
+    mesh.transform.position = Vec3::new(0.0, -1.5, 0.0);
+
+    let mut event_pump = sdl_context.event_pump().map_err(map_str)?;
+    let start = std::time::Instant::now();
+    'running: loop {
+        for event in event_pump.poll_iter() {
+            if engine.gui_mut().handle_event(&event) {
+                continue;
+            }
+            match event {
+                Event::Quit { .. } => break 'running,
+                Event::Window { win_event: WindowEvent::Resized(width, height), .. } => {
+                    engine.resize(WindowSize { width: width as u32, height: height as u32 });
+                }
+                Event::KeyDown { keycode: Some(Keycode::Tab), repeat: false, .. } => {
+                    let mode = engine.render_mode().next();
+                    log::info!("Render mode {:?}", mode);
+                    engine.set_render_mode(mode);
+                }
+                _ => (),
+            }
+        }
+
+        let t = start.elapsed().as_secs_f32();
+        for (i, pivot) in pivots.iter().enumerate() {
+            graph.local_mut(*pivot)?.rotation = Rotor3::from_rotation_xz(t * (1.0 + i as f32 * 0.3));
+        }
+        graph.update();
+        let waves: Vec<ModelVertex> = flat
+            .vertices
+            .iter()
+            .map(|v| {
+                let mut v = *v;
+                v.position[1] = (v.position[0] + t * 2.0).sin() * 0.2;
+                v
+            })
+            .collect();
+        engine.dynamic_mesh_mut(ground)?.update_vertices(0, &waves)?;
+        engine.sync_scene_graph(&mut graph)?;
+
+        let mut mode = engine.render_mode();
+        engine.run_gui(|ctx| {
+            egui::Window::new("Debug").show(ctx, |ui| {
+                for option in RenderMode::ALL {
+                    ui.radio_value(&mut mode, option, format!("{:?}", option));
+                }
+            });
+        });
+        engine.set_render_mode(mode);
+
+        engine.update()?;
+        engine.render()?;
+    }
+
+    Ok(())
+}
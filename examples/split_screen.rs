@@ -0,0 +1,95 @@
+// Several cameras at once: two players side by side, a minimap looking down
+// on top of them and a monitor in the world showing a third camera.
+use anyhow::*;
+use my_engine::prelude::*;
+use sdl2::event::{Event, WindowEvent};
+use ultraviolet::{Rotor3, Vec3};
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    env_logger::init();
+    let map_str = |e: String| anyhow!(e);
+
+    let sdl_context = sdl2::init().map_err(map_str)?;
+    let video_subsystem = sdl_context.video().map_err(map_str)?;
+    let window = video_subsystem
+        .window("Split screen", 1200, 600)
+        .position_centered()
+        .metal_view()
+        .resizable()
+        .build()?;
+
+    let mut engine = WgpuEngine::new(&window).await?;
+
+    let cube = engine.load_model("cube-diffuse.jpg").await?;
+    let cubes = engine.create_group(cube)?;
+    for i in 0..25u32 {
+        let position = Vec3::new((i % 5) as f32 * 3.0 - 6.0, 0.0, (i / 5) as f32 * 3.0 - 6.0);
+        engine.add_instance(cubes, Instance { id: i as u128, position, rotation: Rotor3::identity(), scale: Vec3::one(), uv: UvRect::FULL })?;
+    }
+
+    // Player one is the main camera on the left half, player two the right half.
+    engine.camera_view_mut(engine.main_camera())?.viewport = Viewport::column(0, 2);
+    let player_two = engine.create_camera(
+        &CameraDesc { eye: Vec3::new(10.0, 4.0, 0.0), ..CameraDesc::default() },
+        Viewport::column(1, 2),
+        RenderTarget::Surface,
+    )?;
+
+    // Drawn last, over the seam between both halves.
+    let minimap = engine.create_camera(
+        &CameraDesc {
+            eye: Vec3::new(0.0, 30.0, 0.01),
+            ..CameraDesc::default()
+        },
+        Viewport::new(0.4, 0.0, 0.2, 0.4),
+        RenderTarget::Surface,
+    )?;
+    engine.camera_view_mut(minimap)?.order = 10;
+
+    // The monitor's screen is a quad whose texture a security camera draws to
+    // before the players look at it.
+    let screen = engine.create_render_texture(512, 256, "monitor");
+    let monitor = MeshBuilder::quad(4.0, 2.0).build_model(engine.device(), "monitor", screen, &engine.layouts().texture);
+    let monitor = engine.add_model(monitor);
+    let monitors = engine.create_group(monitor)?;
+    engine.add_instance(
+        monitors,
+        Instance { id: 0, position: Vec3::new(0.0, 3.0, -9.0), rotation: Rotor3::identity(), scale: Vec3::one(), uv: UvRect::FULL },
+    )?;
+    let security = engine.create_camera(
+        &CameraDesc { eye: Vec3::new(-8.0, 6.0, 8.0), ..CameraDesc::default() },
+        Viewport::FULL,
+        RenderTarget::Material { model: monitor, material: 0 },
+    )?;
+    engine.camera_view_mut(security)?.order = -1;
+
+    let mut event_pump = sdl_context.event_pump().map_err(map_str)?;
+    let start = std::time::Instant::now();
+    'running: loop {
+        for event in event_pump.poll_iter() {
+            match event {
+                Event::Quit { .. } => break 'running,
+                Event::Window { win_event: WindowEvent::Resized(width, height), .. } => {
+                    engine.resize(WindowSize { width: width as u32, height: height as u32 });
+                }
+                _ => (),
+            }
+        }
+
+        let t = start.elapsed().as_secs_f32();
+        let view = engine.camera_view_mut(player_two)?.camera.view_mut();
+        view.eye = Vec3::new(10.0 * t.cos(), 4.0, 10.0 * t.sin());
+
+        // What the security camera covers, as the players see it.
+        let corners = engine.camera_view(security)?.camera.frustum_corners();
+        let debug = engine.debug_mut();
+        debug.hexahedron(corners, [1.0, 0.8, 0.2, 1.0]);
+        debug.grid(Vec3::zero(), 20.0, 20, [0.5, 0.5, 0.5, 0.5]);
+
+        engine.update()?;
+        engine.render()?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,95 @@
+// Thousands of sprites sharing one texture, drawn in a single batch by an
+// orthographic camera with one world unit per pixel.
+use anyhow::*;
+use my_engine::prelude::*;
+use sdl2::event::{Event, WindowEvent};
+use ultraviolet::{Vec2, Vec3};
+
+const SPRITES: usize = 5000;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    env_logger::init();
+    let map_str = |e: String| anyhow!(e);
+
+    let sdl_context = sdl2::init().map_err(map_str)?;
+    let video_subsystem = sdl_context.video().map_err(map_str)?;
+    let window = video_subsystem
+        .window("Sprites", 800, 600)
+        .position_centered()
+        .metal_view()
+        .resizable()
+        .build()?;
+
+    let mut engine = WgpuEngine::new(&window).await?;
+    engine.camera_mut().apply_desc(&CameraDesc {
+        eye: Vec3::new(0.0, 0.0, 10.0),
+        target: Vec3::zero(),
+        up: Vec3::unit_y(),
+        projection: ProjectionDesc::Orthographic {
+            znear: 0.1,
+            zfar: 100.0,
+            anchor: [0.5, 0.5],
+            zoom: 1.0,
+            pixels_per_unit: 1.0,
+            pixel_perfect: true,
+        },
+    });
+
+    let texture = engine.load_sprite_texture("cube-diffuse.jpg").await?;
+
+    // A fixed pseudo random layout, the same every run.
+    let mut seed = 1u32;
+    let mut random = move || {
+        seed ^= seed << 13;
+        seed ^= seed >> 17;
+        seed ^= seed << 5;
+        seed as f32 / u32::MAX as f32
+    };
+    let mut sprites: Vec<(Sprite, Vec2)> = (0..SPRITES)
+        .map(|i| {
+            let mut sprite = Sprite::new(texture, Vec2::new(16.0, 16.0));
+            sprite.transform.position = Vec2::new(random() * 700.0 - 350.0, random() * 500.0 - 250.0);
+            sprite.color = [random(), random(), random(), 1.0];
+            sprite.flip_x = i % 2 == 0;
+            sprite.layer = (i % 3) as i32;
+            let velocity = Vec2::new(random() - 0.5, random() - 0.5) * 200.0;
+            (sprite, velocity)
+        })
+        .collect();
+
+    let mut event_pump = sdl_context.event_pump().map_err(map_str)?;
+    let mut last_frame = std::time::Instant::now();
+    'running: loop {
+        for event in event_pump.poll_iter() {
+            match event {
+                Event::Quit { .. } => break 'running,
+                Event::Window { win_event: WindowEvent::Resized(width, height), .. } => {
+                    engine.resize(WindowSize { width: width as u32, height: height as u32 });
+                }
+                _ => (),
+            }
+        }
+
+        let now = std::time::Instant::now();
+        let dt = (now - last_frame).as_secs_f32();
+        last_frame = now;
+        for (sprite, velocity) in sprites.iter_mut() {
+            let position = &mut sprite.transform.position;
+            *position += *velocity * dt;
+            if position.x.abs() > 350.0 {
+                velocity.x = -velocity.x;
+            }
+            if position.y.abs() > 250.0 {
+                velocity.y = -velocity.y;
+            }
+            sprite.transform.rotation += dt;
+        }
+        engine.sprites_mut().draw_all(sprites.iter().map(|(sprite, _)| *sprite));
+
+        engine.update()?;
+        engine.render()?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,99 @@
+// Text in screen space over everything, in the world on a plane and as
+// labels floating above instances. Needs a TrueType font in res/.
+use anyhow::*;
+use my_engine::prelude::*;
+use sdl2::event::{Event, WindowEvent};
+use ultraviolet::{Rotor3, Vec2, Vec3};
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    env_logger::init();
+    let map_str = |e: String| anyhow!(e);
+
+    let sdl_context = sdl2::init().map_err(map_str)?;
+    let video_subsystem = sdl_context.video().map_err(map_str)?;
+    let window = video_subsystem
+        .window("Text", 800, 600)
+        .position_centered()
+        .metal_view()
+        .resizable()
+        .build()?;
+
+    let mut engine = WgpuEngine::new(&window).await?;
+    let font = engine.load_font("DejaVuSans.ttf").await?;
+
+    let cube = engine.load_model("cube-diffuse.jpg").await?;
+    let group = engine.create_group(cube)?;
+    let mut instances = Vec::new();
+    for i in 0..3 {
+        let instance = Instance {
+            id: i,
+            position: Vec3::new(i as f32 * 3.0 - 3.0, 0.0, 0.0),
+            rotation: Rotor3::identity(),
+            scale: Vec3::one(),
+            uv: UvRect::FULL,
+        };
+        engine.add_instance(group, instance)?;
+        instances.push(instance);
+    }
+
+    let hud = TextStyle {
+        color: [1.0, 1.0, 0.6, 1.0],
+        ..TextStyle::new(font, 18.0)
+    };
+    let paragraph = TextStyle {
+        max_width: Some(260.0),
+        align: TextAlign::Right,
+        anchor: Vec2::new(1.0, 0.0),
+        ..TextStyle::new(font, 14.0)
+    };
+    let label = TextStyle {
+        anchor: Vec2::new(0.5, 1.0),
+        sdf: true,
+        ..TextStyle::new(font, 0.4)
+    };
+    let sign = TextStyle {
+        align: TextAlign::Center,
+        anchor: Vec2::new(0.5, 0.5),
+        sdf: true,
+        color: [0.2, 0.6, 1.0, 1.0],
+        ..TextStyle::new(font, 1.0)
+    };
+
+    let mut event_pump = sdl_context.event_pump().map_err(map_str)?;
+    let mut last_frame = std::time::Instant::now();
+    let mut size = WindowSize { width: 800, height: 600 };
+    'running: loop {
+        for event in event_pump.poll_iter() {
+            match event {
+                Event::Quit { .. } => break 'running,
+                Event::Window { win_event: WindowEvent::Resized(width, height), .. } => {
+                    size = WindowSize { width: width as u32, height: height as u32 };
+                    engine.resize(size);
+                }
+                _ => (),
+            }
+        }
+
+        let now = std::time::Instant::now();
+        let dt = (now - last_frame).as_secs_f32();
+        last_frame = now;
+
+        let text = engine.text_mut();
+        text.draw(&format!("{:.0} fps", 1.0 / dt.max(0.0001)), TextPlacement::Screen(Vec2::new(10.0, 10.0)), &hud);
+        text.draw(
+            "Screen text wraps to its width, keeps kerning like AVAV and reads UTF-8: grüße, привет, €5.",
+            TextPlacement::Screen(Vec2::new(size.width as f32 - 10.0, 10.0)),
+            &paragraph,
+        );
+        for instance in &instances {
+            text.draw(&format!("Cube #{}", instance.id), TextPlacement::above(instance, 1.2), &label);
+        }
+        text.draw("Distance field\ntext", TextPlacement::World(Vec3::new(0.0, -2.0, -2.0)), &sign);
+
+        engine.update()?;
+        engine.render()?;
+    }
+
+    Ok(())
+}
@@ -1,2 +1,10 @@
 pub mod wgpu_engine;
-pub mod transform;
\ No newline at end of file
+pub mod transform;
+pub mod scene_graph;
+pub mod prelude;
+
+// The UI library `Gui` runs, so games build against the same version.
+pub use egui;
+
+// The JSON library scenes and sheets are read with, for `SheetData::meta`.
+pub use serde_json;
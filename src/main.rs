@@ -1,9 +1,11 @@
 extern crate sdl2;
 extern crate wgpu;
 
-use sdl2::event::Event;
+use sdl2::{event::Event, keyboard::Keycode, mouse::MouseButton};
 use anyhow::*;
-use my_engine::wgpu_engine::{WgpuEngine, WindowSize};
+use my_engine::prelude::*;
+
+const NUM_INSTANCES_PER_ROW: u32 = 10;
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -22,26 +24,85 @@ async fn main() -> Result<()> {
 
     let mut engine = WgpuEngine::new(&window).await?;
 
+    log::warn!("Load model");
+    let model = engine.load_model("cube-diffuse.jpg").await?;
+    let group = engine.create_group(model)?;
+
+    const SPACE_BETWEEN: f32 = 3.0;
+    for i in 0..NUM_INSTANCES_PER_ROW {
+        for j in 0..NUM_INSTANCES_PER_ROW {
+            let x = SPACE_BETWEEN * (i as f32 - NUM_INSTANCES_PER_ROW as f32 / 2.0);
+            let z = SPACE_BETWEEN * (j as f32 - NUM_INSTANCES_PER_ROW as f32 / 2.0);
+
+            let position = ultraviolet::Vec3 { x, y: 0.0, z };
+
+            let rotation = if position.mag() == 0.0 {
+                ultraviolet::Rotor3::identity()
+            } else {
+                ultraviolet::Rotor3::from_rotation_between(
+                    ultraviolet::Vec3::unit_z(),
+                    position.normalized(),
+                )
+            };
+
+            let instance = Instance { position, rotation, id: (i * NUM_INSTANCES_PER_ROW + j) as u128 , scale: ultraviolet::Vec3::one(), uv: UvRect::FULL };
+            engine.add_instance(group, instance)?;
+        }
+    }
+
+    // 1: free-fly, 2: first person, 3: orbit, T: shake
+    let mut controller: Box<dyn CameraController> = Box::new(OrbitController::new(5.0, 0.005));
+    let mut shake = CameraShake::new(7);
+    let mouse = sdl_context.mouse();
+    let mut last_frame = std::time::Instant::now();
+
     let mut event_pump = sdl_context.event_pump().map_err(map_str)?;
     'running: loop {
         for event in event_pump.poll_iter() {
+            if controller.process_events(&event) {
+                continue;
+            }
             match event {
+                Event::KeyDown { keycode: Some(Keycode::Num1), .. } => {
+                    controller = Box::new(FreeFlyController::new(5.0, 0.003));
+                    mouse.set_relative_mouse_mode(controller.relative_mouse());
+                }
+                Event::KeyDown { keycode: Some(Keycode::Num2), .. } => {
+                    controller = Box::new(FpsController::new(5.0, 0.003));
+                    mouse.set_relative_mouse_mode(controller.relative_mouse());
+                }
+                Event::KeyDown { keycode: Some(Keycode::Num3), .. } => {
+                    controller = Box::new(OrbitController::new(5.0, 0.005));
+                    mouse.set_relative_mouse_mode(controller.relative_mouse());
+                }
+                Event::MouseButtonDown { mouse_btn: MouseButton::Right, x, y, .. } => {
+                    if let Some(hit) = engine.pick(x as u32, y as u32).await? {
+                        log::info!("Picked instance {} of {:?}", hit.id, hit.group);
+                    }
+                }
+                Event::KeyDown { keycode: Some(Keycode::T), .. } => {
+                    shake.add_trauma(0.5);
+                }
+                Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
+                    mouse.set_relative_mouse_mode(false);
+                }
                 Event::Quit { timestamp } => {
                     println!("Quit in {timestamp}");
                     break 'running;
                 }
-                Event::Window { win_event, .. } => match win_event {
-                    sdl2::event::WindowEvent::Resized(width, height) => {
-                        engine.resize(WindowSize{width: width as u32, height: height as u32});
-                    },
-                    _ => (),
+                Event::Window { win_event: sdl2::event::WindowEvent::Resized(width, height), .. } => {
+                    engine.resize(WindowSize{width: width as u32, height: height as u32});
                 }
                 _ => ()    
             }
         }
-        //controller.update(&mut renderer.camera.camera_position);
+        let now = std::time::Instant::now();
+        let dt = (now - last_frame).as_secs_f32();
+        last_frame = now;
+        controller.update_camera(engine.camera_mut(), dt);
+        shake.update_camera(engine.camera_mut(), dt);
         engine.update()?;
-        engine.render(&[])?;
+        engine.render()?;
     }
 
     Ok(())
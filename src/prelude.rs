@@ -0,0 +1,43 @@
+// Everything needed to build and draw a scene: `use my_engine::prelude::*;`
+pub use crate::{
+    scene_graph::{InstanceBinding, InstanceGroups, Node, NodeId, SceneGraph},
+    transform::{EulerRotation, Transform, Transform2d},
+    wgpu_engine::{
+        animation::{AnimatedSprite, AnimationClip, AnimationEvent, ClipEvent, ClipFrame, PlayMode, SpriteAnimator},
+        atlas::{
+            parse_sheet_json, AtlasBuilder, FrameTag, PackedAtlas, SheetData, SheetGrid, SpriteFrame, SpriteSheet,
+            TagDirection, UvRect,
+        },
+        camera::{Camera, Frustum, LookAt, OrthographicProjection, PerspectiveProjection, Projection, Ray, ViewProjection},
+        controller::{CameraController, FpsController, FreeFlyController, MoveKeys, OrbitController},
+        debug::{DebugDraw, DebugRenderer},
+        draw::DrawModel,
+        dynamic_mesh::{DynamicMesh, DynamicMeshId},
+        font::{Font, GlyphBitmap, GlyphId, PathCommand},
+        gui::Gui,
+        instance::{Instance, InstanceAble, InstanceManager, InstanceRaw},
+        layouts::BindGroupLayouts,
+        lod::{LodLevel, LodSettings},
+        mesh_builder::MeshBuilder,
+        picking::{PickHit, PickRect},
+        model::{Bounds, Lod, LodMetric, Material, Mesh, MeshData, Model, ModelVertex, Vertex},
+        render_mode::{RenderMode, RenderModes},
+        resources::{self, ModelLoadOptions},
+        rig::{CameraKeyframe, CameraPath, CameraShake, CameraTransition, Easing, FollowRig},
+        sprite::{Sprite, SpriteRenderer, SpriteTextureId, SpriteVertex},
+        tiled::{TiledLayer, TiledMap, TiledTileset},
+        tilemap::{Tile, TileFrame, TileLayer, Tilemap, TilemapId, TilemapRenderer, Tileset, CHUNK_SIZE},
+        scene::{
+            CameraDesc, CameraId, EngineSettings, GroupId, InstanceGroupDesc, LightDesc, LightKind, ModelId,
+            ProjectionDesc, SceneDesc,
+        },
+        text::{FontId, LayoutGlyph, LayoutLine, TextAlign, TextLayout, TextPlacement, TextRenderer, TextStyle},
+        texture::Texture,
+        ui::{
+            Align, Anchor, Direction, Edges, Justify, Layout, Length, StateStyles, Theme, Ui, UiEvent, UiRect, Widget,
+            WidgetId, WidgetKind, WidgetStyle,
+        },
+        view::{CameraView, RenderTarget, Viewport},
+        WgpuEngine, WindowSize,
+    },
+};
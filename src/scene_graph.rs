@@ -0,0 +1,424 @@
+use anyhow::{anyhow, Result};
+use crate::{
+    transform::Transform,
+    wgpu_engine::{instance::Instance, scene::GroupId, InstanceAble},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeId {
+    index: u32,
+    generation: u32,
+}
+
+// Feeds a node's world transform into instance `transform.id` of `group`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InstanceBinding {
+    pub group: GroupId,
+}
+
+// Where `SceneGraph::sync_instances` writes instances, implemented by `WgpuEngine`.
+pub trait InstanceGroups {
+    fn add_instance(&mut self, group: GroupId, instance: Instance) -> Result<()>;
+    fn update_instance(&mut self, group: GroupId, instance: Instance) -> Result<()>;
+    fn remove_instance(&mut self, group: GroupId, instance_id: u128) -> Result<Instance>;
+}
+
+#[derive(Debug)]
+pub struct Node {
+    local: Transform,
+    world: Transform,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+    binding: Option<InstanceBinding>,
+    // The local transform changed since the last `update`.
+    dirty: bool,
+    // The world transform changed since the last `sync_instances`.
+    instance_dirty: bool,
+    // The bound instance was added to its group by `sync_instances`.
+    instance_added: bool,
+}
+
+impl Node {
+    pub fn local(&self) -> &Transform {
+        &self.local
+    }
+
+    // The local transform composed with all of its ancestors, see `Transform::compose`.
+    pub fn world(&self) -> &Transform {
+        &self.world
+    }
+
+    pub fn parent(&self) -> Option<NodeId> {
+        self.parent
+    }
+
+    pub fn children(&self) -> &[NodeId] {
+        &self.children
+    }
+
+    pub fn binding(&self) -> Option<InstanceBinding> {
+        self.binding
+    }
+}
+
+struct Slot {
+    generation: u32,
+    node: Option<Node>,
+}
+
+#[derive(Default)]
+pub struct SceneGraph {
+    slots: Vec<Slot>,
+    free: Vec<u32>,
+    roots: Vec<NodeId>,
+    // Instances of removed nodes, removed from their managers on the next sync.
+    removed_instances: Vec<(InstanceBinding, u128)>,
+}
+
+impl SceneGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_node(&mut self, local: Transform, parent: Option<NodeId>) -> Result<NodeId> {
+        if let Some(parent) = parent {
+            self.node(parent)?;
+        }
+        let node = Node {
+            local,
+            world: local,
+            parent,
+            children: Vec::new(),
+            binding: None,
+            dirty: true,
+            instance_dirty: true,
+            instance_added: false,
+        };
+        let id = match self.free.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index as usize];
+                slot.node = Some(node);
+                NodeId { index, generation: slot.generation }
+            }
+            None => {
+                self.slots.push(Slot { generation: 0, node: Some(node) });
+                NodeId { index: self.slots.len() as u32 - 1, generation: 0 }
+            }
+        };
+        match parent {
+            Some(parent) => self.node_mut(parent)?.children.push(id),
+            None => self.roots.push(id),
+        }
+        Ok(id)
+    }
+
+    // Removes the node together with all of its descendants.
+    pub fn remove_node(&mut self, id: NodeId) -> Result<()> {
+        let parent = self.node(id)?.parent;
+        match parent {
+            Some(parent) => self.node_mut(parent)?.children.retain(|c| *c != id),
+            None => self.roots.retain(|r| *r != id),
+        }
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            let slot = &mut self.slots[id.index as usize];
+            let node = slot.node.take().ok_or_else(|| anyhow!("Node not found"))?;
+            slot.generation += 1;
+            self.free.push(id.index);
+            if let (Some(binding), true) = (node.binding, node.instance_added) {
+                self.removed_instances.push((binding, node.local.id));
+            }
+            stack.extend(node.children);
+        }
+        Ok(())
+    }
+
+    pub fn set_parent(&mut self, id: NodeId, parent: Option<NodeId>) -> Result<()> {
+        if let Some(parent) = parent {
+            let mut ancestor = Some(parent);
+            while let Some(a) = ancestor {
+                if a == id {
+                    return Err(anyhow!("Node cannot be parented to its own descendant"));
+                }
+                ancestor = self.node(a)?.parent;
+            }
+        }
+        match self.node(id)?.parent {
+            Some(old) => self.node_mut(old)?.children.retain(|c| *c != id),
+            None => self.roots.retain(|r| *r != id),
+        }
+        match parent {
+            Some(parent) => self.node_mut(parent)?.children.push(id),
+            None => self.roots.push(id),
+        }
+        let node = self.node_mut(id)?;
+        node.parent = parent;
+        node.dirty = true;
+        Ok(())
+    }
+
+    pub fn node(&self, id: NodeId) -> Result<&Node> {
+        self.slots
+            .get(id.index as usize)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.node.as_ref())
+            .ok_or_else(|| anyhow!("Node not found"))
+    }
+
+    fn node_mut(&mut self, id: NodeId) -> Result<&mut Node> {
+        self.slots
+            .get_mut(id.index as usize)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.node.as_mut())
+            .ok_or_else(|| anyhow!("Node not found"))
+    }
+
+    pub fn local_mut(&mut self, id: NodeId) -> Result<&mut Transform> {
+        let node = self.node_mut(id)?;
+        node.dirty = true;
+        Ok(&mut node.local)
+    }
+
+    pub fn set_local(&mut self, id: NodeId, local: Transform) -> Result<()> {
+        *self.local_mut(id)? = local;
+        Ok(())
+    }
+
+    pub fn bind_instance(&mut self, id: NodeId, binding: Option<InstanceBinding>) -> Result<()> {
+        let node = self.node_mut(id)?;
+        let old = std::mem::replace(&mut node.binding, binding);
+        let added = std::mem::replace(&mut node.instance_added, false);
+        node.instance_dirty = true;
+        let instance_id = node.local.id;
+        if let (Some(old), true) = (old, added) {
+            self.removed_instances.push((old, instance_id));
+        }
+        Ok(())
+    }
+
+    pub fn roots(&self) -> &[NodeId] {
+        &self.roots
+    }
+
+    // Recomputes the world transforms of dirty nodes and their descendants.
+    pub fn update(&mut self) {
+        let mut stack = self
+            .roots
+            .iter()
+            .map(|&id| (id, Transform::default(), false))
+            .collect::<Vec<_>>();
+        while let Some((id, parent_world, parent_changed)) = stack.pop() {
+            let Ok(node) = self.node_mut(id) else {
+                continue;
+            };
+            let changed = parent_changed || node.dirty;
+            if changed {
+                node.world = parent_world.compose(&node.local);
+                node.dirty = false;
+                node.instance_dirty = true;
+            }
+            let world = node.world;
+            stack.extend(node.children.iter().map(|&child| (child, world, changed)));
+        }
+    }
+
+    // Writes the world transforms of bound nodes changed since the last sync
+    // into their groups, adding the instances of newly bound nodes. A binding
+    // to a group that no longer exists is an error.
+    pub fn sync_instances(&mut self, groups: &mut impl InstanceGroups) -> Result<()> {
+        for (binding, instance_id) in self.removed_instances.drain(..) {
+            // Already gone if its group was destroyed.
+            let _ = groups.remove_instance(binding.group, instance_id);
+        }
+        for slot in self.slots.iter_mut() {
+            let Some(node) = slot.node.as_mut() else {
+                continue;
+            };
+            let Some(binding) = node.binding else {
+                continue;
+            };
+            if !node.instance_dirty {
+                continue;
+            }
+            let instance = node.world.to_instance();
+            if node.instance_added {
+                groups.update_instance(binding.group, instance)?;
+            } else {
+                groups.add_instance(binding.group, instance)?;
+                node.instance_added = true;
+            }
+            node.instance_dirty = false;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use ultraviolet::{Rotor3, Vec3};
+
+    use super::*;
+
+    // Instances by group and id, only groups in `groups` exist.
+    #[derive(Default)]
+    struct Groups {
+        groups: Vec<GroupId>,
+        instances: HashMap<(GroupId, u128), Instance>,
+    }
+
+    impl InstanceGroups for Groups {
+        fn add_instance(&mut self, group: GroupId, instance: Instance) -> Result<()> {
+            if !self.groups.contains(&group) {
+                return Err(anyhow!("Instance group {:?} not found", group));
+            }
+            self.instances.insert((group, instance.id), instance);
+            Ok(())
+        }
+
+        fn update_instance(&mut self, group: GroupId, instance: Instance) -> Result<()> {
+            let old = self
+                .instances
+                .get_mut(&(group, instance.id))
+                .ok_or_else(|| anyhow!("Instance {} not found", instance.id))?;
+            *old = instance;
+            Ok(())
+        }
+
+        fn remove_instance(&mut self, group: GroupId, instance_id: u128) -> Result<Instance> {
+            self.instances
+                .remove(&(group, instance_id))
+                .ok_or_else(|| anyhow!("Instance {} not found", instance_id))
+        }
+    }
+
+    fn at(x: f32, id: u128) -> Transform {
+        Transform::new(Vec3::new(x, 0.0, 0.0), Rotor3::identity(), Vec3::one(), id)
+    }
+
+    fn world_x(graph: &SceneGraph, id: NodeId) -> f32 {
+        graph.node(id).unwrap().world().position.x
+    }
+
+    #[test]
+    fn world_transforms_follow_a_moved_parent() {
+        let mut graph = SceneGraph::new();
+        let parent = graph.add_node(at(1.0, 0), None).unwrap();
+        let child = graph.add_node(at(2.0, 0), Some(parent)).unwrap();
+        let grandchild = graph.add_node(at(4.0, 0), Some(child)).unwrap();
+        graph.update();
+        assert_eq!(world_x(&graph, grandchild), 7.0);
+
+        graph.local_mut(parent).unwrap().position.x = 10.0;
+        graph.update();
+        assert_eq!(world_x(&graph, child), 12.0);
+        assert_eq!(world_x(&graph, grandchild), 16.0);
+
+        // A scaled and turned parent carries its children along.
+        let parent_local = graph.local_mut(parent).unwrap();
+        parent_local.scale = Vec3::new(2.0, 1.0, 1.0);
+        parent_local.rotation = Rotor3::from_rotation_xz(std::f32::consts::FRAC_PI_2);
+        graph.update();
+        let world = graph.node(child).unwrap().world().position;
+        let expected = graph.node(parent).unwrap().local().transform_point(Vec3::new(2.0, 0.0, 0.0));
+        assert!((world - expected).mag() < 1e-5);
+        assert!((world - Vec3::new(10.0, 0.0, 0.0)).mag() > 1.0);
+    }
+
+    #[test]
+    fn reparenting_moves_the_node_between_parents() {
+        let mut graph = SceneGraph::new();
+        let a = graph.add_node(at(1.0, 0), None).unwrap();
+        let b = graph.add_node(at(5.0, 0), None).unwrap();
+        let child = graph.add_node(at(1.0, 0), Some(a)).unwrap();
+        graph.update();
+        assert_eq!(world_x(&graph, child), 2.0);
+
+        graph.set_parent(child, Some(b)).unwrap();
+        graph.update();
+        assert_eq!(world_x(&graph, child), 6.0);
+        assert!(graph.node(a).unwrap().children().is_empty());
+        assert_eq!(graph.node(b).unwrap().children(), [child]);
+        assert_eq!(graph.node(child).unwrap().parent(), Some(b));
+
+        graph.set_parent(child, None).unwrap();
+        graph.update();
+        assert_eq!(world_x(&graph, child), 1.0);
+        assert_eq!(graph.roots(), [a, b, child]);
+    }
+
+    #[test]
+    fn cycles_are_rejected() {
+        let mut graph = SceneGraph::new();
+        let a = graph.add_node(at(1.0, 0), None).unwrap();
+        let b = graph.add_node(at(1.0, 0), Some(a)).unwrap();
+        let c = graph.add_node(at(1.0, 0), Some(b)).unwrap();
+
+        assert!(graph.set_parent(a, Some(a)).is_err());
+        assert!(graph.set_parent(a, Some(c)).is_err());
+        assert!(graph.set_parent(b, Some(c)).is_err());
+        // Nothing changed.
+        assert_eq!(graph.roots(), [a]);
+        assert_eq!(graph.node(a).unwrap().children(), [b]);
+        assert_eq!(graph.node(b).unwrap().children(), [c]);
+        assert_eq!(graph.node(a).unwrap().parent(), None);
+    }
+
+    #[test]
+    fn removing_a_node_removes_its_subtree_and_instances() {
+        let group = GroupId(1);
+        let mut groups = Groups { groups: vec![group], ..Default::default() };
+        let mut graph = SceneGraph::new();
+        let root = graph.add_node(at(0.0, 1), None).unwrap();
+        let a = graph.add_node(at(1.0, 2), Some(root)).unwrap();
+        let b = graph.add_node(at(1.0, 3), Some(a)).unwrap();
+        let sibling = graph.add_node(at(2.0, 4), Some(root)).unwrap();
+        for id in [a, b, sibling] {
+            graph.bind_instance(id, Some(InstanceBinding { group })).unwrap();
+        }
+        graph.update();
+        graph.sync_instances(&mut groups).unwrap();
+        assert_eq!(groups.instances.len(), 3);
+        assert_eq!(groups.instances[&(group, 3)].position.x, 2.0);
+
+        graph.remove_node(a).unwrap();
+        assert!(graph.node(a).is_err());
+        assert!(graph.node(b).is_err());
+        assert_eq!(graph.node(root).unwrap().children(), [sibling]);
+        graph.sync_instances(&mut groups).unwrap();
+        assert_eq!(groups.instances.keys().collect::<Vec<_>>(), [&(group, 4)]);
+
+        // Reused slots don't answer to the removed ids.
+        let reused = graph.add_node(at(0.0, 5), None).unwrap();
+        assert!(graph.node(reused).is_ok());
+        assert!(graph.node(a).is_err() && graph.node(b).is_err());
+    }
+
+    #[test]
+    fn sync_adds_then_updates_and_fails_for_missing_groups() {
+        let group = GroupId(1);
+        let mut groups = Groups { groups: vec![group], ..Default::default() };
+        let mut graph = SceneGraph::new();
+        let node = graph.add_node(at(1.0, 7), None).unwrap();
+        graph.bind_instance(node, Some(InstanceBinding { group })).unwrap();
+        graph.update();
+        graph.sync_instances(&mut groups).unwrap();
+        assert_eq!(groups.instances[&(group, 7)].position.x, 1.0);
+
+        graph.set_local(node, at(3.0, 7)).unwrap();
+        graph.update();
+        graph.sync_instances(&mut groups).unwrap();
+        assert_eq!(groups.instances.len(), 1);
+        assert_eq!(groups.instances[&(group, 7)].position.x, 3.0);
+
+        // Rebinding moves the instance, a group that is gone is an error.
+        let other = GroupId(2);
+        graph.bind_instance(node, Some(InstanceBinding { group: other })).unwrap();
+        assert!(graph.sync_instances(&mut groups).is_err());
+        assert!(groups.instances.is_empty());
+        groups.groups.push(other);
+        graph.sync_instances(&mut groups).unwrap();
+        assert_eq!(groups.instances[&(other, 7)].position.x, 3.0);
+    }
+}
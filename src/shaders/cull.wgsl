@@ -0,0 +1,121 @@
+// Compute shader: frustum culling and LOD selection for indirect draws
+
+struct Cull {
+    planes: array<vec4<f32>, 6>,
+    eye: vec4<f32>,
+    instance_count: u32,
+    // view height = view_height_scale * distance + view_height_bias
+    view_height_bias: f32,
+    view_height_scale: f32,
+    _pad0: u32,
+}
+
+// Must match instance::InstanceRaw
+struct InstanceRaw {
+    model: mat4x4<f32>,
+    normal: mat3x3<f32>,
+    lod_fade: f32,
+    id: vec4<u32>,
+    uv_rect: vec4<f32>,
+}
+
+struct CullInstance {
+    raw: InstanceRaw,
+    batch: u32,
+    _pad0: u32,
+    _pad1: u32,
+    _pad2: u32,
+}
+
+// The draws of a batch are `draw_stride` apart in the draw list, since draws
+// sharing a mesh are kept next to each other for multi draw.
+struct Batch {
+    first_draw: u32,
+    draw_count: u32,
+    draw_stride: u32,
+}
+
+// The LOD metric is either the distance or the inverse screen size, the fade
+// widths are the cross-fade bands past lod_min and lod_max.
+struct DrawInfo {
+    center: vec3<f32>,
+    radius: f32,
+    lod_min: f32,
+    lod_max: f32,
+    fade_min: f32,
+    fade_max: f32,
+    lod_radius: f32,
+    screen_space: u32,
+    output_offset: u32,
+    _pad: u32,
+}
+
+// Same layout as wgpu::util::DrawIndexedIndirectArgs
+struct DrawArgs {
+    index_count: u32,
+    instance_count: atomic<u32>,
+    first_index: u32,
+    base_vertex: i32,
+    first_instance: u32,
+}
+
+@group(0) @binding(0)
+var<uniform> cull: Cull;
+@group(0) @binding(1)
+var<storage, read> instances: array<CullInstance>;
+@group(0) @binding(2)
+var<storage, read> batches: array<Batch>;
+@group(0) @binding(3)
+var<storage, read> draws: array<DrawInfo>;
+@group(0) @binding(4)
+var<storage, read_write> args: array<DrawArgs>;
+@group(0) @binding(5)
+var<storage, read_write> visible: array<InstanceRaw>;
+
+fn in_frustum(center: vec3<f32>, radius: f32) -> bool {
+    for (var i = 0u; i < 6u; i++) {
+        let plane = cull.planes[i];
+        if (dot(plane.xyz, center) + plane.w < -radius) {
+            return false;
+        }
+    }
+    return true;
+}
+
+@compute @workgroup_size(64)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (id.x >= cull.instance_count) {
+        return;
+    }
+    let instance = instances[id.x];
+    let batch = batches[instance.batch];
+    let model = instance.raw.model;
+    let scale = max(length(model[0].xyz), max(length(model[1].xyz), length(model[2].xyz)));
+
+    for (var i = 0u; i < batch.draw_count; i++) {
+        let d = batch.first_draw + i * batch.draw_stride;
+        let draw = draws[d];
+        let center = (model * vec4<f32>(draw.center, 1.0)).xyz;
+        let distance = length(center - cull.eye.xyz);
+        var metric = distance;
+        if (draw.screen_space != 0u) {
+            let view_height = cull.view_height_scale * distance + cull.view_height_bias;
+            metric = view_height / (2.0 * draw.lod_radius * scale);
+        }
+        if (metric < draw.lod_min || metric >= draw.lod_max + draw.fade_max) {
+            continue;
+        }
+        if (!in_frustum(center, draw.radius * scale)) {
+            continue;
+        }
+        var raw = instance.raw;
+        raw.lod_fade = 0.0;
+        if (metric >= draw.lod_max) {
+            raw.lod_fade = max(1.0 - (metric - draw.lod_max) / draw.fade_max, 1e-4);
+        } else if (metric < draw.lod_min + draw.fade_min) {
+            raw.lod_fade = -(1.0 - (metric - draw.lod_min) / draw.fade_min);
+        }
+        let slot = atomicAdd(&args[d].instance_count, 1u);
+        visible[draw.output_offset + slot] = raw;
+    }
+}
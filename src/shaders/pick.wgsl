@@ -0,0 +1,63 @@
+// Picking pass: every pixel gets the id of the instance covering it
+
+struct Camera {
+    view_proj: mat4x4<f32>,
+}
+@group(1) @binding(0)
+var<uniform> camera: Camera;
+
+// Group index + 1, so that 0 means nothing was drawn, and the mesh index.
+struct Draw {
+    group: u32,
+    mesh: u32,
+    _pad0: u32,
+    _pad1: u32,
+}
+@group(0) @binding(0)
+var<uniform> draw: Draw;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+}
+struct InstanceInput {
+    @location(5) model_matrix_0: vec4<f32>,
+    @location(6) model_matrix_1: vec4<f32>,
+    @location(7) model_matrix_2: vec4<f32>,
+    @location(8) model_matrix_3: vec4<f32>,
+    @location(13) id: vec4<u32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) @interpolate(flat) id: vec4<u32>,
+}
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+    instance: InstanceInput,
+) -> VertexOutput {
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+    var out: VertexOutput;
+    out.id = instance.id;
+    out.clip_position = camera.view_proj * model_matrix * vec4<f32>(model.position, 1.0);
+    return out;
+}
+
+struct PickOutput {
+    @location(0) id: vec4<u32>,
+    @location(1) info: vec2<u32>,
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> PickOutput {
+    var out: PickOutput;
+    out.id = in.id;
+    out.info = vec2<u32>(draw.group, draw.mesh);
+    return out;
+}
@@ -0,0 +1,161 @@
+// Debug render modes: the engine's instanced meshes drawn as wireframe,
+// normals, UV checker, depth or overdraw instead of shaded
+
+struct Camera {
+    view_proj: mat4x4<f32>,
+}
+@group(1) @binding(0)
+var<uniform> camera: Camera;
+
+struct ModeView {
+    eye: vec4<f32>,
+    // Near and far distance of the depth view, overdraw added per surface.
+    params: vec4<f32>,
+    wire_color: vec4<f32>,
+}
+@group(2) @binding(0)
+var<uniform> view: ModeView;
+
+struct VertexInput {
+    @builtin(vertex_index) index: u32,
+    @location(0) position: vec3<f32>,
+    @location(1) tex_coords: vec2<f32>,
+    @location(2) normal: vec3<f32>,
+}
+struct InstanceInput {
+    @location(5) model_matrix_0: vec4<f32>,
+    @location(6) model_matrix_1: vec4<f32>,
+    @location(7) model_matrix_2: vec4<f32>,
+    @location(8) model_matrix_3: vec4<f32>,
+    @location(9) lod_fade: f32,
+    @location(10) normal_matrix_0: vec3<f32>,
+    @location(11) normal_matrix_1: vec3<f32>,
+    @location(12) normal_matrix_2: vec3<f32>,
+    @location(13) id: vec4<u32>,
+    @location(14) uv_rect: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) @interpolate(flat) lod_fade: f32,
+    // Not normalized, so missing normals stay zero.
+    @location(2) world_normal: vec3<f32>,
+    @location(3) world_position: vec3<f32>,
+    // Corner of the triangle, only meaningful for de-indexed meshes.
+    @location(4) barycentric: vec3<f32>,
+}
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+    instance: InstanceInput,
+) -> VertexOutput {
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+    let normal_matrix = mat3x3<f32>(
+        instance.normal_matrix_0,
+        instance.normal_matrix_1,
+        instance.normal_matrix_2,
+    );
+    let world = model_matrix * vec4<f32>(model.position, 1.0);
+    var out: VertexOutput;
+    out.tex_coords = instance.uv_rect.xy + model.tex_coords * instance.uv_rect.zw;
+    out.world_normal = normal_matrix * model.normal;
+    out.world_position = world.xyz;
+    out.lod_fade = instance.lod_fade;
+    let corner = model.index % 3u;
+    out.barycentric = vec3<f32>(f32(corner == 0u), f32(corner == 1u), f32(corner == 2u));
+    out.clip_position = camera.view_proj * world;
+    return out;
+}
+
+// Same cross-fade between LODs as the shaded pipeline
+fn bayer4(pixel: vec2<f32>) -> f32 {
+    let p = vec2<u32>(pixel) % vec2<u32>(4u);
+    var m = array<f32, 16>(
+        0.0, 8.0, 2.0, 10.0,
+        12.0, 4.0, 14.0, 6.0,
+        3.0, 11.0, 1.0, 9.0,
+        15.0, 7.0, 13.0, 5.0,
+    );
+    return (m[p.y * 4u + p.x] + 0.5) / 16.0;
+}
+
+fn faded(in: VertexOutput) -> bool {
+    let dither = bayer4(in.clip_position.xy);
+    return (in.lod_fade > 0.0 && dither >= in.lod_fade) || (in.lod_fade < 0.0 && dither < -in.lod_fade);
+}
+
+// World space normals mapped to colors, magenta where a mesh has none.
+@fragment
+fn fs_normals(in: VertexOutput) -> @location(0) vec4<f32> {
+    if faded(in) {
+        discard;
+    }
+    let length = length(in.world_normal);
+    if length < 0.0001 {
+        return vec4<f32>(1.0, 0.0, 1.0, 1.0);
+    }
+    return vec4<f32>(in.world_normal / length * 0.5 + 0.5, 1.0);
+}
+
+// Eight checks per UV unit, tinted by the UVs so their direction shows.
+@fragment
+fn fs_uv_checker(in: VertexOutput) -> @location(0) vec4<f32> {
+    if faded(in) {
+        discard;
+    }
+    let cell = vec2<i32>(floor(in.tex_coords * 8.0));
+    let check = select(0.35, 1.0, ((cell.x + cell.y) & 1) == 0);
+    let tint = vec3<f32>(fract(in.tex_coords), 1.0);
+    return vec4<f32>(tint * check, 1.0);
+}
+
+// White at the near distance to black at the far one, on a log scale.
+@fragment
+fn fs_depth(in: VertexOutput) -> @location(0) vec4<f32> {
+    if faded(in) {
+        discard;
+    }
+    let near = max(view.params.x, 0.0001);
+    let far = max(view.params.y, near * 1.01);
+    let distance = max(length(in.world_position - view.eye.xyz), near);
+    let t = clamp(log(distance / near) / log(far / near), 0.0, 1.0);
+    return vec4<f32>(vec3<f32>(1.0 - t), 1.0);
+}
+
+// Added up for every surface drawn over a pixel.
+@fragment
+fn fs_overdraw(in: VertexOutput) -> @location(0) vec4<f32> {
+    if faded(in) {
+        discard;
+    }
+    let step = view.params.z;
+    return vec4<f32>(step, step * 0.4, step * 0.1, 1.0);
+}
+
+// Rasterized as lines already.
+@fragment
+fn fs_wire(in: VertexOutput) -> @location(0) vec4<f32> {
+    if faded(in) {
+        discard;
+    }
+    return view.wire_color;
+}
+
+// Edges from the distance to the closest triangle side, about a pixel wide.
+@fragment
+fn fs_barycentric(in: VertexOutput) -> @location(0) vec4<f32> {
+    let width = fwidth(in.barycentric);
+    let edge = smoothstep(vec3<f32>(0.0), width * 1.5, in.barycentric);
+    let alpha = 1.0 - min(min(edge.x, edge.y), edge.z);
+    if alpha <= 0.0 || faded(in) {
+        discard;
+    }
+    return vec4<f32>(view.wire_color.rgb, view.wire_color.a * alpha);
+}
@@ -9,17 +9,26 @@ var<uniform> camera: Camera;
 struct VertexInput {
     @location(0) position: vec3<f32>,
     @location(1) tex_coords: vec2<f32>,
+    @location(2) normal: vec3<f32>,
 }
 struct InstanceInput {
     @location(5) model_matrix_0: vec4<f32>,
     @location(6) model_matrix_1: vec4<f32>,
     @location(7) model_matrix_2: vec4<f32>,
     @location(8) model_matrix_3: vec4<f32>,
+    @location(9) lod_fade: f32,
+    @location(10) normal_matrix_0: vec3<f32>,
+    @location(11) normal_matrix_1: vec3<f32>,
+    @location(12) normal_matrix_2: vec3<f32>,
+    @location(13) id: vec4<u32>,
+    @location(14) uv_rect: vec4<f32>,
 }
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) tex_coords: vec2<f32>,
+    @location(1) @interpolate(flat) lod_fade: f32,
+    @location(2) world_normal: vec3<f32>,
 }
 
 @vertex
@@ -33,8 +42,16 @@ fn vs_main(
         instance.model_matrix_2,
         instance.model_matrix_3,
     );
+    // Inverse transpose of the model matrix, correct under non-uniform scale
+    let normal_matrix = mat3x3<f32>(
+        instance.normal_matrix_0,
+        instance.normal_matrix_1,
+        instance.normal_matrix_2,
+    );
     var out: VertexOutput;
-    out.tex_coords = model.tex_coords;
+    out.tex_coords = instance.uv_rect.xy + model.tex_coords * instance.uv_rect.zw;
+    out.world_normal = normalize(normal_matrix * model.normal);
+    out.lod_fade = instance.lod_fade;
     out.clip_position = camera.view_proj * model_matrix * vec4<f32>(model.position, 1.0);
     return out;
 }
@@ -46,7 +63,23 @@ var t_diffuse: texture_2d<f32>;
 @group(0)@binding(1)
 var s_diffuse: sampler;
 
+// Ordered dither pattern used to cross-fade between LODs
+fn bayer4(pixel: vec2<f32>) -> f32 {
+    let p = vec2<u32>(pixel) % vec2<u32>(4u);
+    var m = array<f32, 16>(
+        0.0, 8.0, 2.0, 10.0,
+        12.0, 4.0, 14.0, 6.0,
+        3.0, 11.0, 1.0, 9.0,
+        15.0, 7.0, 13.0, 5.0,
+    );
+    return (m[p.y * 4u + p.x] + 0.5) / 16.0;
+}
+
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let dither = bayer4(in.clip_position.xy);
+    if (in.lod_fade > 0.0 && dither >= in.lod_fade) || (in.lod_fade < 0.0 && dither < -in.lod_fade) {
+        discard;
+    }
     return textureSample(t_diffuse, s_diffuse, in.tex_coords);
 }
\ No newline at end of file
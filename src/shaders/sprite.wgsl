@@ -0,0 +1,59 @@
+// Sprite batches: quads already placed in world space by the CPU
+
+struct Camera {
+    view_proj: mat4x4<f32>,
+}
+@group(1) @binding(0)
+var<uniform> camera: Camera;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) tex_coords: vec2<f32>,
+    @location(2) color: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) color: vec4<f32>,
+}
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.tex_coords = in.tex_coords;
+    out.color = in.color;
+    out.clip_position = camera.view_proj * vec4<f32>(in.position, 1.0);
+    return out;
+}
+
+@group(0) @binding(0)
+var t_sprite: texture_2d<f32>;
+@group(0) @binding(1)
+var s_sprite: sampler;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(t_sprite, s_sprite, in.tex_coords) * in.color;
+    if color.a <= 0.0 {
+        discard;
+    }
+    return color;
+}
+
+// Screen sprites: pixels from the top left of the view's viewport, y down
+struct ScreenView {
+    viewport: vec4<f32>,
+}
+@group(2) @binding(0)
+var<uniform> view: ScreenView;
+
+@vertex
+fn vs_screen(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.tex_coords = in.tex_coords;
+    out.color = in.color;
+    let ndc = vec2<f32>(in.position.x / view.viewport.x * 2.0 - 1.0, 1.0 - in.position.y / view.viewport.y * 2.0);
+    out.clip_position = vec4<f32>(ndc, 0.0, 1.0);
+    return out;
+}
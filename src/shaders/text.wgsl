@@ -0,0 +1,72 @@
+// Text: glyph quads from the glyph cache, plain coverage or distance field
+
+struct Camera {
+    view_proj: mat4x4<f32>,
+}
+@group(1) @binding(0)
+var<uniform> camera: Camera;
+
+struct TextView {
+    right: vec4<f32>,
+    up: vec4<f32>,
+    viewport: vec4<f32>,
+}
+@group(2) @binding(0)
+var<uniform> view: TextView;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) offset: vec2<f32>,
+    @location(2) tex_coords: vec2<f32>,
+    @location(3) color: vec4<f32>,
+    @location(4) sdf: u32,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) color: vec4<f32>,
+    @location(2) @interpolate(flat) sdf: u32,
+}
+
+// World space, billboards are moved along the camera's right and up.
+@vertex
+fn vs_world(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.tex_coords = in.tex_coords;
+    out.color = in.color;
+    out.sdf = in.sdf;
+    let world = in.position + view.right.xyz * in.offset.x + view.up.xyz * in.offset.y;
+    out.clip_position = camera.view_proj * vec4<f32>(world, 1.0);
+    return out;
+}
+
+// Pixels from the viewport's top left.
+@vertex
+fn vs_screen(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.tex_coords = in.tex_coords;
+    out.color = in.color;
+    out.sdf = in.sdf;
+    let ndc = vec2<f32>(in.position.x / view.viewport.x * 2.0 - 1.0, 1.0 - in.position.y / view.viewport.y * 2.0);
+    out.clip_position = vec4<f32>(ndc, 0.0, 1.0);
+    return out;
+}
+
+@group(0) @binding(0)
+var t_glyphs: texture_2d<f32>;
+@group(0) @binding(1)
+var s_glyphs: sampler;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let sample = textureSample(t_glyphs, s_glyphs, in.tex_coords).r;
+    // The outline is at 0.5, smoothed over about a pixel at any scale.
+    let width = max(fwidth(sample) * 0.7, 0.001);
+    let distance_alpha = smoothstep(0.5 - width, 0.5 + width, sample);
+    let alpha = select(sample, distance_alpha, in.sdf != 0u) * in.color.a;
+    if alpha <= 0.0 {
+        discard;
+    }
+    return vec4<f32>(in.color.rgb, alpha);
+}
@@ -0,0 +1,52 @@
+// Tile layers: chunk meshes in pixels, placed in the world per layer
+
+struct Camera {
+    view_proj: mat4x4<f32>,
+}
+@group(1) @binding(0)
+var<uniform> camera: Camera;
+
+struct Layer {
+    offset: vec2<f32>,
+    scale: vec2<f32>,
+    color: vec4<f32>,
+    z: f32,
+}
+@group(2) @binding(0)
+var<uniform> layer: Layer;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) tex_coords: vec2<f32>,
+    @location(2) color: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) color: vec4<f32>,
+}
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.tex_coords = in.tex_coords;
+    out.color = in.color * layer.color;
+    let world = vec3<f32>(layer.offset + in.position.xy * layer.scale, layer.z);
+    out.clip_position = camera.view_proj * vec4<f32>(world, 1.0);
+    return out;
+}
+
+@group(0) @binding(0)
+var t_tiles: texture_2d<f32>;
+@group(0) @binding(1)
+var s_tiles: sampler;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(t_tiles, s_tiles, in.tex_coords) * in.color;
+    if color.a <= 0.0 {
+        discard;
+    }
+    return color;
+}
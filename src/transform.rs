@@ -1,8 +1,10 @@
-use ultraviolet::{Rotor3, Vec2, Vec3};
+use ultraviolet::{Lerp, Mat3, Mat4, Rotor3, Slerp, Vec2, Vec3};
 
-use crate::wgpu_engine::{instance::Instance, InstanceAble};
+use crate::wgpu_engine::{atlas::UvRect, instance::Instance, InstanceAble};
 
-#[derive(Debug, Clone, Copy)]
+// Yaw turns around +Y, pitch around +X and roll around +Z, applied in the
+// order roll, pitch, yaw.
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct EulerRotation {
     pub yaw: f32,
     pub pitch: f32,
@@ -13,8 +15,28 @@ impl EulerRotation {
     pub fn new(yaw: f32, pitch: f32, roll: f32) -> Self {
         Self { yaw, pitch, roll }
     }
+
     pub fn rotor3(&self) -> Rotor3 {
-        Rotor3::from_euler_angles(self.yaw, self.pitch, self.roll)
+        Rotor3::from_euler_angles(self.roll, self.pitch, self.yaw)
+    }
+
+    // Pitch is kept in [-pi/2, pi/2], at the poles all of the turn goes to yaw.
+    pub fn from_rotor3(rotor: Rotor3) -> Self {
+        let m = rotor.into_matrix();
+        let pitch = (-m[2][1]).clamp(-1.0, 1.0).asin();
+        if m[2][1].abs() < 0.9999 {
+            Self {
+                yaw: (-m[2][0]).atan2(m[2][2]),
+                pitch,
+                roll: m[0][1].atan2(m[1][1]),
+            }
+        } else {
+            Self {
+                yaw: m[0][2].atan2(m[0][0]),
+                pitch,
+                roll: 0.0,
+            }
+        }
     }
 }
 
@@ -24,30 +46,135 @@ impl Default for EulerRotation {
     }
 }
 
+// Translation, rotation and non-uniform scale, applied as scale, then rotation,
+// then translation.
+#[derive(Debug, Clone, Copy)]
 pub struct Transform {
     pub position: Vec3,
-    pub euler_rotation: EulerRotation,
-    pub scale: f32,
+    pub rotation: Rotor3,
+    pub scale: Vec3,
     pub id: u128,
 }
 
 impl Transform {
-    pub fn new(position: Vec3, euler_rotation: EulerRotation, scale: f32, id: u128) -> Self {
+    pub fn new(position: Vec3, rotation: Rotor3, scale: Vec3, id: u128) -> Self {
         Self {
             position,
-            euler_rotation,
+            rotation,
             scale,
             id,
         }
     }
+
+    pub fn from_euler(position: Vec3, euler_rotation: EulerRotation, scale: Vec3, id: u128) -> Self {
+        Self::new(position, euler_rotation.rotor3(), scale, id)
+    }
+
+    pub fn euler(&self) -> EulerRotation {
+        EulerRotation::from_rotor3(self.rotation)
+    }
+
+    pub fn set_euler(&mut self, euler_rotation: EulerRotation) {
+        self.rotation = euler_rotation.rotor3();
+    }
+
+    pub fn matrix(&self) -> Mat4 {
+        Mat4::from_translation(self.position)
+            * self.rotation.into_matrix().into_homogeneous()
+            * Mat4::from_nonuniform_scale(self.scale)
+    }
+
+    // Inverse transpose of the upper 3x3, for transforming normals.
+    pub fn normal_matrix(&self) -> Mat3 {
+        self.rotation.into_matrix() * Mat3::from_nonuniform_scale(recip(self.scale))
+    }
+
+    pub fn transform_point(&self, point: Vec3) -> Vec3 {
+        self.position + self.transform_vector(point)
+    }
+
+    pub fn transform_vector(&self, vector: Vec3) -> Vec3 {
+        self.rotation * (vector * self.scale)
+    }
+
+    // `self * child`: the child's transform expressed in the space `self` is in.
+    // Exact unless `self` scales non-uniformly and `child` is rotated, where the
+    // resulting shear can't be represented and is dropped.
+    pub fn compose(&self, child: &Transform) -> Transform {
+        Transform {
+            position: self.transform_point(child.position),
+            rotation: (self.rotation * child.rotation).normalized(),
+            scale: self.scale * child.scale,
+            id: child.id,
+        }
+    }
+
+    // Same caveat as `compose` for rotated non-uniform scales.
+    pub fn inverse(&self) -> Transform {
+        let rotation = self.rotation.reversed();
+        let scale = recip(self.scale);
+        Transform {
+            position: -(rotation * self.position) * scale,
+            rotation,
+            scale,
+            id: self.id,
+        }
+    }
+
+    // Turns -Z towards `target` with +Y as close to `up` as possible.
+    pub fn look_at(&mut self, target: Vec3, up: Vec3) {
+        let forward = target - self.position;
+        if forward.mag_sq() <= f32::EPSILON {
+            return;
+        }
+        let back = -forward.normalized();
+        let mut right = up.cross(back);
+        if right.mag_sq() <= f32::EPSILON {
+            // `up` is parallel to the view direction, any perpendicular will do.
+            right = Vec3::unit_x().cross(back);
+            if right.mag_sq() <= f32::EPSILON {
+                right = Vec3::unit_z().cross(back);
+            }
+        }
+        let right = right.normalized();
+        let up = back.cross(right);
+        self.rotation = Mat3::new(right, up, back).into_rotor3().normalized();
+    }
+
+    pub fn looking_at(mut self, target: Vec3, up: Vec3) -> Self {
+        self.look_at(target, up);
+        self
+    }
+
+    // Linear interpolation of every component, rotation included (normalized).
+    pub fn lerp(&self, other: &Transform, t: f32) -> Transform {
+        let mut rotation = other.rotation;
+        if self.rotation.dot(rotation) < 0.0 {
+            rotation *= -1.0;
+        }
+        Transform {
+            position: self.position.lerp(other.position, t),
+            rotation: self.rotation.lerp(rotation, t).normalized(),
+            scale: self.scale.lerp(other.scale, t),
+            id: self.id,
+        }
+    }
+
+    // Like `lerp`, but the rotation turns at a constant angular speed.
+    pub fn slerp(&self, other: &Transform, t: f32) -> Transform {
+        Transform {
+            rotation: self.rotation.slerp(other.rotation, t).normalized(),
+            ..self.lerp(other, t)
+        }
+    }
 }
 
 impl Default for Transform {
     fn default() -> Self {
         Self {
             position: Vec3::zero(),
-            euler_rotation: EulerRotation::default(),
-            scale: 1.0,
+            rotation: Rotor3::identity(),
+            scale: Vec3::one(),
             id: 0,
         }
     }
@@ -57,13 +184,19 @@ impl InstanceAble for Transform {
     fn to_instance(&self) -> Instance {
         Instance {
             position: self.position,
-            rotation: self.euler_rotation.rotor3(),
+            rotation: self.rotation,
             scale: self.scale,
             id: self.id,
+            uv: UvRect::FULL,
         }
     }
 }
 
+fn recip(v: Vec3) -> Vec3 {
+    Vec3::new(1.0 / v.x, 1.0 / v.y, 1.0 / v.z)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Transform2d {
     pub position: Vec2,
     pub scale: f32,
@@ -97,9 +230,155 @@ impl InstanceAble for Transform2d {
     fn to_instance(&self) -> Instance {
         Instance {
             position: Vec3::new(self.position.x, self.position.y, 0.0),
-            rotation: Rotor3::from_euler_angles(0.0, 0.0, self.rotation),
-            scale: self.scale,
+            rotation: EulerRotation::new(0.0, 0.0, self.rotation).rotor3(),
+            scale: Vec3::new(self.scale, self.scale, 1.0),
             id: self.id,
+            uv: UvRect::FULL,
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::{FRAC_PI_2, PI};
+
+    use super::*;
+
+    fn assert_near(a: Vec3, b: Vec3) {
+        assert!((a - b).mag() < 1e-4, "{:?} != {:?}", a, b);
+    }
+
+    // Same rotation, the rotor's sign aside.
+    fn assert_same_rotation(a: Rotor3, b: Rotor3) {
+        for v in [Vec3::unit_x(), Vec3::unit_y(), Vec3::unit_z()] {
+            assert_near(a * v, b * v);
+        }
+    }
+
+    fn turned() -> Transform {
+        let rotation = EulerRotation::new(0.7, -0.3, 1.1).rotor3();
+        Transform::new(Vec3::new(1.0, -2.0, 3.0), rotation, Vec3::new(2.0, 0.5, 3.0), 9)
+    }
+
+    #[test]
+    fn euler_angles_turn_around_their_own_axes() {
+        // Yaw around +Y and roll around +Z, not the other way round.
+        let yaw = EulerRotation::new(FRAC_PI_2, 0.0, 0.0).rotor3();
+        assert_near(yaw * Vec3::unit_y(), Vec3::unit_y());
+        assert_near(yaw * Vec3::unit_x(), Vec3::unit_z());
+        let pitch = EulerRotation::new(0.0, FRAC_PI_2, 0.0).rotor3();
+        assert_near(pitch * Vec3::unit_x(), Vec3::unit_x());
+        assert_near(pitch * Vec3::unit_y(), Vec3::unit_z());
+        let roll = EulerRotation::new(0.0, 0.0, FRAC_PI_2).rotor3();
+        assert_near(roll * Vec3::unit_z(), Vec3::unit_z());
+        assert_near(roll * Vec3::unit_x(), Vec3::unit_y());
+
+        // Roll first, then pitch, then yaw.
+        let euler = EulerRotation::new(0.3, -0.4, 0.5);
+        let steps = Rotor3::from_rotation_xz(0.3) * Rotor3::from_rotation_yz(-0.4) * Rotor3::from_rotation_xy(0.5);
+        assert_same_rotation(euler.rotor3(), steps);
+    }
+
+    #[test]
+    fn euler_angles_round_trip_through_rotors() {
+        for (yaw, pitch, roll) in [(0.0, 0.0, 0.0), (0.3, -0.4, 0.5), (-2.5, 1.2, 3.0), (PI - 0.1, -1.5, -0.2)] {
+            let euler = EulerRotation::from_rotor3(EulerRotation::new(yaw, pitch, roll).rotor3());
+            assert!((euler.yaw - yaw).abs() < 1e-4, "yaw {} != {}", euler.yaw, yaw);
+            assert!((euler.pitch - pitch).abs() < 1e-4, "pitch {} != {}", euler.pitch, pitch);
+            assert!((euler.roll - roll).abs() < 1e-4, "roll {} != {}", euler.roll, roll);
+        }
+
+        // At the poles yaw and roll turn around the same axis, only the rotation is kept.
+        let pole = EulerRotation::new(0.4, FRAC_PI_2, 0.3).rotor3();
+        let euler = EulerRotation::from_rotor3(pole);
+        assert_eq!(euler.roll, 0.0);
+        assert_same_rotation(euler.rotor3(), pole);
+    }
+
+    #[test]
+    fn compose_with_inverse_is_identity() {
+        let t = turned();
+        let identity = t.compose(&t.inverse());
+        assert_near(identity.position, Vec3::zero());
+        assert_near(identity.scale, Vec3::one());
+        assert_same_rotation(identity.rotation, Rotor3::identity());
+        assert_eq!(identity.id, t.id);
+
+        // Without rotation the inverse also undoes non-uniform scale on points.
+        let t = Transform::new(Vec3::new(1.0, 2.0, 3.0), Rotor3::identity(), Vec3::new(2.0, 0.5, 4.0), 0);
+        let point = Vec3::new(-3.0, 5.0, 0.5);
+        assert_near(t.inverse().transform_point(t.transform_point(point)), point);
+    }
+
+    #[test]
+    fn compose_matches_matrix_product() {
+        let parent = Transform::new(Vec3::new(0.0, 1.0, -2.0), Rotor3::from_rotation_xz(0.8), Vec3::broadcast(2.0), 0);
+        let child = turned();
+        let composed = parent.compose(&child);
+        assert_eq!(composed.id, child.id);
+        let point = Vec3::new(0.5, -1.0, 2.0);
+        let expected = (parent.matrix() * child.matrix()).transform_point3(point);
+        assert_near(composed.transform_point(point), expected);
+        assert_near(composed.matrix().transform_point3(point), expected);
+    }
+
+    #[test]
+    fn normal_matrix_keeps_normals_perpendicular() {
+        let t = turned();
+        let normal = Vec3::new(1.0, 1.0, 0.0).normalized();
+        let tangent = Vec3::new(1.0, -1.0, 2.0);
+        assert!(normal.dot(tangent).abs() < 1e-6);
+        let moved_normal = t.normal_matrix() * normal;
+        let moved_tangent = t.transform_vector(tangent);
+        assert!(moved_normal.dot(moved_tangent).abs() < 1e-4);
+        // A plain rotation leaves the normal as the rotated vector.
+        let rotated = Transform::new(Vec3::zero(), t.rotation, Vec3::one(), 0);
+        assert_near(rotated.normal_matrix() * normal, t.rotation * normal);
+    }
+
+    #[test]
+    fn lerp_and_slerp_hit_their_endpoints() {
+        let a = Transform::new(Vec3::zero(), Rotor3::identity(), Vec3::one(), 1);
+        let b = Transform::new(Vec3::new(4.0, 0.0, -2.0), Rotor3::from_rotation_xz(FRAC_PI_2), Vec3::broadcast(3.0), 2);
+        for t in [Transform::lerp, Transform::slerp] {
+            let start = t(&a, &b, 0.0);
+            let end = t(&a, &b, 1.0);
+            assert_near(start.position, a.position);
+            assert_same_rotation(start.rotation, a.rotation);
+            assert_near(end.position, b.position);
+            assert_near(end.scale, b.scale);
+            assert_same_rotation(end.rotation, b.rotation);
+            let mid = t(&a, &b, 0.5);
+            assert_near(mid.position, Vec3::new(2.0, 0.0, -1.0));
+            assert_near(mid.scale, Vec3::broadcast(2.0));
+            assert_same_rotation(mid.rotation, Rotor3::from_rotation_xz(FRAC_PI_2 / 2.0));
+            assert_eq!(mid.id, a.id);
+        }
+
+        // The other sign of the same rotation doesn't send lerp the long way round.
+        let flipped = Transform { rotation: b.rotation * -1.0, ..b };
+        assert_same_rotation(a.lerp(&flipped, 0.5).rotation, Rotor3::from_rotation_xz(FRAC_PI_2 / 2.0));
+    }
+
+    #[test]
+    fn look_at_points_forward_at_the_target() {
+        let target = Vec3::new(3.0, 4.0, -5.0);
+        let t = Transform::new(Vec3::new(1.0, 0.0, 0.0), Rotor3::identity(), Vec3::one(), 0).looking_at(target, Vec3::unit_y());
+        let forward = t.rotation * -Vec3::unit_z();
+        assert_near(forward, (target - t.position).normalized());
+        // Level: right stays horizontal, up stays on the up side.
+        assert!((t.rotation * Vec3::unit_x()).y.abs() < 1e-5);
+        assert!((t.rotation * Vec3::unit_y()).y > 0.0);
+
+        // Looking straight along `up` still gives a valid rotation.
+        let t = Transform::default().looking_at(Vec3::new(0.0, 5.0, 0.0), Vec3::unit_y());
+        assert_near(t.rotation * -Vec3::unit_z(), Vec3::unit_y());
+        assert!((t.rotation.mag() - 1.0).abs() < 1e-5);
+
+        // Looking at itself changes nothing.
+        let mut t = turned();
+        let rotation = t.rotation;
+        t.look_at(t.position, Vec3::unit_y());
+        assert_eq!(t.rotation, rotation);
+    }
+}
@@ -0,0 +1,417 @@
+use std::ops::RangeInclusive;
+
+use anyhow::{anyhow, Result};
+
+use super::{
+    atlas::{SpriteSheet, TagDirection},
+    sprite::Sprite,
+};
+
+// What a clip does after its last frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlayMode {
+    Loop,
+    // Stops on the last frame.
+    Once,
+    // Plays back to the first frame and forth again, without showing the
+    // end frames twice in a row.
+    PingPong,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClipFrame {
+    // Index into `SpriteSheet::frames`.
+    pub frame: usize,
+    // Seconds.
+    pub duration: f32,
+}
+
+// Fired when the clip enters frame `position` of its frames.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClipEvent {
+    pub position: usize,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnimationClip {
+    pub name: String,
+    pub frames: Vec<ClipFrame>,
+    pub mode: PlayMode,
+    pub events: Vec<ClipEvent>,
+}
+
+impl AnimationClip {
+    pub fn new(name: &str, frames: Vec<ClipFrame>, mode: PlayMode) -> Self {
+        Self {
+            name: name.to_string(),
+            frames,
+            mode,
+            events: Vec::new(),
+        }
+    }
+
+    // Frames `range` of `sheet` for as long as the sheet says, or `1 / fps`
+    // seconds for frames without a duration.
+    pub fn from_sheet(sheet: &SpriteSheet, name: &str, range: RangeInclusive<usize>, fps: f32, mode: PlayMode) -> Result<Self> {
+        if *range.end() >= sheet.frames.len() {
+            return Err(anyhow!("Frames {:?} are not in a sheet of {}", range, sheet.frames.len()));
+        }
+        let frames = range
+            .map(|frame| {
+                let duration = sheet.frames[frame].duration;
+                ClipFrame {
+                    frame,
+                    duration: if duration > 0.0 { duration } else { 1.0 / fps },
+                }
+            })
+            .collect();
+        Ok(Self::new(name, frames, mode))
+    }
+
+    // The frames of an Aseprite tag, played the way the tag's direction says.
+    pub fn from_tag(sheet: &SpriteSheet, tag: &str, fps: f32) -> Result<Self> {
+        let tag = sheet.tag(tag).ok_or_else(|| anyhow!("Sprite sheet has no tag {:?}", tag))?;
+        let mode = match tag.direction {
+            TagDirection::Forward | TagDirection::Reverse => PlayMode::Loop,
+            TagDirection::PingPong | TagDirection::PingPongReverse => PlayMode::PingPong,
+        };
+        let mut clip = Self::from_sheet(sheet, &tag.name, tag.from..=tag.to, fps, mode)?;
+        if matches!(tag.direction, TagDirection::Reverse | TagDirection::PingPongReverse) {
+            clip.frames.reverse();
+        }
+        Ok(clip)
+    }
+
+    // Every tag of `sheet` as a clip.
+    pub fn from_tags(sheet: &SpriteSheet, fps: f32) -> Result<Vec<Self>> {
+        sheet.tags.iter().map(|tag| Self::from_tag(sheet, &tag.name, fps)).collect()
+    }
+
+    // Fires `name` every time frame `position` of the clip comes up, e.g. for
+    // footstep sounds.
+    pub fn with_event(mut self, position: usize, name: &str) -> Self {
+        self.events.push(ClipEvent {
+            position,
+            name: name.to_string(),
+        });
+        self
+    }
+
+    // Seconds for one pass through the frames.
+    pub fn duration(&self) -> f32 {
+        self.frames.iter().map(|f| f.duration).sum()
+    }
+}
+
+// An event reported by `SpriteAnimator::update`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnimationEvent {
+    pub clip: String,
+    pub name: String,
+    // Index into `SpriteSheet::frames`.
+    pub frame: usize,
+}
+
+// Plays one clip at a time. Only the `dt` given to `update` moves it on, so
+// the same steps always show the same frames.
+#[derive(Debug, Clone)]
+pub struct SpriteAnimator {
+    pub speed: f32,
+    pub playing: bool,
+    clip: AnimationClip,
+    position: usize,
+    // Seconds into the current frame.
+    time: f32,
+    backwards: bool,
+    finished: bool,
+    // The current frame's events haven't been reported yet.
+    entered: bool,
+}
+
+impl SpriteAnimator {
+    pub fn new(clip: AnimationClip) -> Self {
+        Self {
+            speed: 1.0,
+            playing: true,
+            clip,
+            position: 0,
+            time: 0.0,
+            backwards: false,
+            finished: false,
+            entered: true,
+        }
+    }
+
+    pub fn clip(&self) -> &AnimationClip {
+        &self.clip
+    }
+
+    // Switches to `clip` from its first frame, unless it is the clip playing
+    // already, so this can be called every frame with the wanted state.
+    pub fn play(&mut self, clip: &AnimationClip) {
+        if self.clip.name != clip.name {
+            self.clip = clip.clone();
+            self.restart();
+        }
+        self.playing = true;
+    }
+
+    pub fn restart(&mut self) {
+        self.position = 0;
+        self.time = 0.0;
+        self.backwards = false;
+        self.finished = false;
+        self.entered = true;
+    }
+
+    // Position in the clip's frames.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    // The sheet frame showing, if the clip has any.
+    pub fn frame(&self) -> Option<usize> {
+        self.clip.frames.get(self.position).map(|f| f.frame)
+    }
+
+    // A `PlayMode::Once` clip reached the end of its last frame.
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+
+    // Moves on by `dt` seconds, returning the events of every frame entered
+    // on the way, in order.
+    pub fn update(&mut self, dt: f32) -> Vec<AnimationEvent> {
+        let mut events = Vec::new();
+        if self.clip.frames.is_empty() {
+            return events;
+        }
+        if self.entered {
+            self.entered = false;
+            self.fire(&mut events);
+        }
+        if !self.playing || self.finished || self.clip.duration() <= 0.0 {
+            return events;
+        }
+
+        self.time += dt * self.speed.max(0.0);
+        while self.time >= self.clip.frames[self.position].duration {
+            if !self.advance() {
+                self.time = self.clip.frames[self.position].duration;
+                self.finished = true;
+                break;
+            }
+            self.time -= self.clip.frames[self.position].duration;
+            self.fire(&mut events);
+        }
+        events
+    }
+
+    // Shows the current frame on `sprite`.
+    pub fn apply(&self, sheet: &SpriteSheet, sprite: &mut Sprite) {
+        if let Some(frame) = self.frame() {
+            sheet.set_frame(sprite, frame);
+        }
+    }
+
+    // Steps to the next frame, false at the end of a clip played once.
+    fn advance(&mut self) -> bool {
+        let last = self.clip.frames.len() - 1;
+        match self.clip.mode {
+            PlayMode::Loop => self.position = if self.position == last { 0 } else { self.position + 1 },
+            PlayMode::Once if self.position == last => return false,
+            PlayMode::Once => self.position += 1,
+            PlayMode::PingPong if last == 0 => (),
+            PlayMode::PingPong => {
+                if self.backwards && self.position == 0 || !self.backwards && self.position == last {
+                    self.backwards = !self.backwards;
+                }
+                self.position = if self.backwards { self.position - 1 } else { self.position + 1 };
+            }
+        }
+        true
+    }
+
+    fn fire(&self, events: &mut Vec<AnimationEvent>) {
+        let frame = self.clip.frames[self.position].frame;
+        for event in self.clip.events.iter().filter(|e| e.position == self.position) {
+            events.push(AnimationEvent {
+                clip: self.clip.name.clone(),
+                name: event.name.clone(),
+                frame,
+            });
+        }
+    }
+}
+
+// A sprite that shows whatever frame its animator is on.
+#[derive(Debug, Clone)]
+pub struct AnimatedSprite {
+    pub sprite: Sprite,
+    pub animator: SpriteAnimator,
+}
+
+impl AnimatedSprite {
+    pub fn new(sheet: &SpriteSheet, clip: AnimationClip) -> Result<Self> {
+        let animator = SpriteAnimator::new(clip);
+        let frame = animator.frame().ok_or_else(|| anyhow!("Clip {:?} has no frames", animator.clip().name))?;
+        let mut sprite = Sprite::new(sheet.pages[sheet.frames[frame].page], ultraviolet::Vec2::zero());
+        sheet.set_frame(&mut sprite, frame);
+        Ok(Self { sprite, animator })
+    }
+
+    pub fn update(&mut self, sheet: &SpriteSheet, dt: f32) -> Vec<AnimationEvent> {
+        let events = self.animator.update(dt);
+        self.animator.apply(sheet, &mut self.sprite);
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ultraviolet::Vec2;
+
+    use super::*;
+    use crate::wgpu_engine::{
+        atlas::{FrameTag, SpriteFrame},
+        sprite::SpriteTextureId,
+    };
+
+    // Binary fractions of a second, so stepping adds up exactly.
+    fn clip(count: usize, mode: PlayMode) -> AnimationClip {
+        let frames = (0..count).map(|i| ClipFrame { frame: 10 + i, duration: 0.25 }).collect();
+        AnimationClip::new("clip", frames, mode)
+    }
+
+    fn positions(animator: &mut SpriteAnimator, steps: usize) -> Vec<usize> {
+        (0..steps)
+            .map(|_| {
+                animator.update(0.25);
+                animator.position()
+            })
+            .collect()
+    }
+
+    fn names(events: &[AnimationEvent]) -> Vec<&str> {
+        events.iter().map(|e| e.name.as_str()).collect()
+    }
+
+    #[test]
+    fn loop_wraps_to_the_first_frame() {
+        let mut animator = SpriteAnimator::new(clip(3, PlayMode::Loop));
+        assert_eq!(animator.frame(), Some(10));
+        assert_eq!(positions(&mut animator, 5), [1, 2, 0, 1, 2]);
+        // Part way through a frame stays on it.
+        animator.update(0.125);
+        assert_eq!(animator.position(), 2);
+        animator.update(0.125);
+        assert_eq!(animator.position(), 0);
+        assert!(!animator.finished());
+    }
+
+    #[test]
+    fn once_stops_on_the_last_frame() {
+        let mut animator = SpriteAnimator::new(clip(3, PlayMode::Once).with_event(2, "end"));
+        assert_eq!(positions(&mut animator, 2), [1, 2]);
+        assert!(!animator.finished());
+        assert!(animator.update(0.25).is_empty());
+        assert!(animator.finished());
+        assert_eq!(animator.frame(), Some(12));
+        assert!(animator.update(10.0).is_empty());
+
+        animator.restart();
+        assert_eq!(names(&animator.update(10.0)), ["end"]);
+        assert_eq!(animator.position(), 2);
+        assert!(animator.finished());
+    }
+
+    #[test]
+    fn ping_pong_turns_without_repeating_the_ends() {
+        let mut animator = SpriteAnimator::new(clip(4, PlayMode::PingPong));
+        assert_eq!(positions(&mut animator, 9), [1, 2, 3, 2, 1, 0, 1, 2, 3]);
+
+        let mut single = SpriteAnimator::new(clip(1, PlayMode::PingPong));
+        assert_eq!(positions(&mut single, 3), [0, 0, 0]);
+    }
+
+    #[test]
+    fn events_fire_on_entering_their_frame() {
+        let clip = clip(3, PlayMode::Loop).with_event(0, "start").with_event(2, "step");
+        let mut animator = SpriteAnimator::new(clip);
+        // The first frame is entered on the first update.
+        assert_eq!(names(&animator.update(0.0)), ["start"]);
+        assert!(animator.update(0.0).is_empty());
+        assert!(animator.update(0.25).is_empty());
+        assert_eq!(
+            animator.update(0.25),
+            [AnimationEvent {
+                clip: "clip".to_string(),
+                name: "step".to_string(),
+                frame: 12,
+            }]
+        );
+
+        // Every frame crossed by one long step fires, in order.
+        assert_eq!(names(&animator.update(1.5)), ["start", "step", "start", "step"]);
+        assert_eq!(animator.position(), 2);
+    }
+
+    #[test]
+    fn speed_and_pausing_scale_time() {
+        let mut animator = SpriteAnimator::new(clip(3, PlayMode::Loop));
+        animator.speed = 2.0;
+        animator.update(0.125);
+        assert_eq!(animator.position(), 1);
+        animator.playing = false;
+        animator.update(1.0);
+        assert_eq!(animator.position(), 1);
+
+        // Playing the same clip again doesn't restart it.
+        let same = animator.clip().clone();
+        animator.play(&same);
+        assert_eq!(animator.position(), 1);
+        animator.play(&AnimationClip { name: "other".to_string(), ..same });
+        assert_eq!(animator.position(), 0);
+    }
+
+    #[test]
+    fn tags_play_in_their_direction() {
+        let frames = (0..5)
+            .map(|i| SpriteFrame {
+                // Frames without a duration get 1 / fps.
+                duration: if i == 2 { 0.5 } else { 0.0 },
+                ..SpriteFrame::new(&i.to_string(), 0, i * 4, 0, 4, 4)
+            })
+            .collect();
+        let mut sheet = SpriteSheet::new(&[(SpriteTextureId(0), Vec2::new(20.0, 4.0))], frames).unwrap();
+        let tag = |name: &str, direction| FrameTag {
+            name: name.to_string(),
+            from: 1,
+            to: 3,
+            direction,
+        };
+        sheet.tags = vec![
+            tag("forward", TagDirection::Forward),
+            tag("reverse", TagDirection::Reverse),
+            tag("pingpong", TagDirection::PingPong),
+            tag("pingpong_reverse", TagDirection::PingPongReverse),
+        ];
+
+        let order = |clip: &AnimationClip| clip.frames.iter().map(|f| f.frame).collect::<Vec<_>>();
+        let forward = AnimationClip::from_tag(&sheet, "forward", 4.0).unwrap();
+        assert_eq!((order(&forward), forward.mode), (vec![1, 2, 3], PlayMode::Loop));
+        assert_eq!(forward.frames[0].duration, 0.25);
+        assert_eq!(forward.frames[1].duration, 0.5);
+        let reverse = AnimationClip::from_tag(&sheet, "reverse", 4.0).unwrap();
+        assert_eq!((order(&reverse), reverse.mode), (vec![3, 2, 1], PlayMode::Loop));
+        let ping_pong = AnimationClip::from_tag(&sheet, "pingpong", 4.0).unwrap();
+        assert_eq!((order(&ping_pong), ping_pong.mode), (vec![1, 2, 3], PlayMode::PingPong));
+        let ping_pong_reverse = AnimationClip::from_tag(&sheet, "pingpong_reverse", 4.0).unwrap();
+        assert_eq!((order(&ping_pong_reverse), ping_pong_reverse.mode), (vec![3, 2, 1], PlayMode::PingPong));
+
+        assert_eq!(AnimationClip::from_tags(&sheet, 4.0).unwrap().len(), 4);
+        assert!(AnimationClip::from_tag(&sheet, "missing", 4.0).is_err());
+        assert!(AnimationClip::from_sheet(&sheet, "past", 3..=5, 4.0, PlayMode::Loop).is_err());
+    }
+}
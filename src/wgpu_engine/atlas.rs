@@ -0,0 +1,678 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Context, Result};
+use image::RgbaImage;
+use serde::Deserialize;
+use serde_json::Value;
+use ultraviolet::Vec2;
+
+use super::{
+    resources,
+    sprite::{Sprite, SpriteTextureId},
+};
+
+// Part of a texture in UV coordinates, v pointing down.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UvRect {
+    pub min: Vec2,
+    pub max: Vec2,
+}
+
+impl UvRect {
+    pub const FULL: UvRect = UvRect {
+        min: Vec2 { x: 0.0, y: 0.0 },
+        max: Vec2 { x: 1.0, y: 1.0 },
+    };
+
+    // A rectangle of pixels of a texture of `texture_size` pixels.
+    pub fn from_pixels(x: f32, y: f32, width: f32, height: f32, texture_size: Vec2) -> Self {
+        Self {
+            min: Vec2::new(x / texture_size.x, y / texture_size.y),
+            max: Vec2::new((x + width) / texture_size.x, (y + height) / texture_size.y),
+        }
+    }
+
+    pub fn size(&self) -> Vec2 {
+        self.max - self.min
+    }
+}
+
+impl Default for UvRect {
+    fn default() -> Self {
+        Self::FULL
+    }
+}
+
+// One image of a sheet or atlas.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpriteFrame {
+    pub name: String,
+    // Index into `SpriteSheet::pages`.
+    pub page: usize,
+    // Pixels of the page, from its top left.
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    // Filled in by `SpriteSheet::new` from the page size.
+    pub uv: UvRect,
+    // Like `Sprite::pivot`. For trimmed frames it is moved so that the
+    // untrimmed image would still sit in the same place.
+    pub pivot: Vec2,
+    // Seconds to show the frame for when animated, 0 if the sheet has none.
+    pub duration: f32,
+}
+
+impl SpriteFrame {
+    pub fn new(name: &str, page: usize, x: u32, y: u32, width: u32, height: u32) -> Self {
+        Self {
+            name: name.to_string(),
+            page,
+            x,
+            y,
+            width,
+            height,
+            uv: UvRect::FULL,
+            pivot: Vec2::new(0.5, 0.5),
+            duration: 0.0,
+        }
+    }
+}
+
+// Named frames on one or more sprite textures. The same frame UVs work for
+// models too: put a page on a model's material, e.g. with
+// `WgpuEngine::add_atlas_page_model`, and set `Instance::uv` to a frame's.
+#[derive(Debug, Clone)]
+pub struct SpriteSheet {
+    pub pages: Vec<SpriteTextureId>,
+    pub frames: Vec<SpriteFrame>,
+    // Named frame ranges, e.g. Aseprite tags, see `AnimationClip::from_tag`.
+    pub tags: Vec<FrameTag>,
+    // Sprites are this many pixels of the sheet per world unit.
+    pub pixels_per_unit: f32,
+    names: HashMap<String, usize>,
+}
+
+impl SpriteSheet {
+    // `pages` pairs each texture with its size in pixels.
+    pub fn new(pages: &[(SpriteTextureId, Vec2)], mut frames: Vec<SpriteFrame>) -> Result<Self> {
+        let mut names = HashMap::new();
+        for (index, frame) in frames.iter_mut().enumerate() {
+            let (_, size) = pages
+                .get(frame.page)
+                .ok_or_else(|| anyhow!("Frame {:?} is on page {} of {}", frame.name, frame.page, pages.len()))?;
+            frame.uv = UvRect::from_pixels(frame.x as f32, frame.y as f32, frame.width as f32, frame.height as f32, *size);
+            names.insert(frame.name.clone(), index);
+        }
+        Ok(Self {
+            pages: pages.iter().map(|(id, _)| *id).collect(),
+            frames,
+            tags: Vec::new(),
+            pixels_per_unit: 1.0,
+            names,
+        })
+    }
+
+    pub fn index(&self, name: &str) -> Option<usize> {
+        self.names.get(name).copied()
+    }
+
+    pub fn frame(&self, name: &str) -> Option<&SpriteFrame> {
+        self.index(name).map(|i| &self.frames[i])
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&FrameTag> {
+        self.tags.iter().find(|t| t.name == name)
+    }
+
+    // A sprite showing the named frame at its size in pixels.
+    pub fn sprite(&self, name: &str) -> Result<Sprite> {
+        let index = self.index(name).ok_or_else(|| anyhow!("Sprite frame {:?} not found", name))?;
+        let mut sprite = Sprite::new(self.pages[self.frames[index].page], Vec2::zero());
+        self.set_frame(&mut sprite, index);
+        Ok(sprite)
+    }
+
+    // Switches `sprite` to frame `index`, keeping everything but its texture,
+    // UVs, size and pivot.
+    pub fn set_frame(&self, sprite: &mut Sprite, index: usize) {
+        let frame = &self.frames[index];
+        sprite.texture = self.pages[frame.page];
+        sprite.uv = frame.uv;
+        sprite.size = Vec2::new(frame.width as f32, frame.height as f32) / self.pixels_per_unit;
+        sprite.pivot = frame.pivot;
+    }
+}
+
+// Cells of a sheet laid out on a regular grid, named by their index in
+// reading order.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SheetGrid {
+    pub cell_width: u32,
+    pub cell_height: u32,
+    // Pixels around the grid and between cells.
+    pub margin: u32,
+    pub spacing: u32,
+    // Stop after this many cells, for a partly filled last row.
+    pub count: Option<usize>,
+    // Seconds per frame.
+    pub duration: f32,
+}
+
+impl SheetGrid {
+    pub fn new(cell_width: u32, cell_height: u32) -> Self {
+        Self {
+            cell_width,
+            cell_height,
+            margin: 0,
+            spacing: 0,
+            count: None,
+            duration: 0.0,
+        }
+    }
+
+    // The frames of a `width` by `height` pixel page.
+    pub fn frames(&self, width: u32, height: u32) -> Result<Vec<SpriteFrame>> {
+        if self.cell_width == 0 || self.cell_height == 0 {
+            return Err(anyhow!("Grid cells must not be empty"));
+        }
+        let fit = |size: u32, cell: u32| (size.saturating_sub(self.margin * 2) + self.spacing) / (cell + self.spacing);
+        let (columns, rows) = (fit(width, self.cell_width), fit(height, self.cell_height));
+        let total = (columns * rows) as usize;
+        let count = self.count.unwrap_or(total);
+        if count > total {
+            return Err(anyhow!("A {}x{} sheet holds {} cells, not {}", width, height, total, count));
+        }
+        Ok((0..count)
+            .map(|i| {
+                let (column, row) = (i as u32 % columns, i as u32 / columns);
+                let mut frame = SpriteFrame::new(
+                    &i.to_string(),
+                    0,
+                    self.margin + column * (self.cell_width + self.spacing),
+                    self.margin + row * (self.cell_height + self.spacing),
+                    self.cell_width,
+                    self.cell_height,
+                );
+                frame.duration = self.duration;
+                frame
+            })
+            .collect())
+    }
+}
+
+// Which way the frames of a tag play, as in Aseprite.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TagDirection {
+    Forward,
+    Reverse,
+    PingPong,
+    PingPongReverse,
+}
+
+// Frames `from` to `to`, both included, of a sheet.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameTag {
+    pub name: String,
+    pub from: usize,
+    pub to: usize,
+    pub direction: TagDirection,
+}
+
+// A sheet described by a JSON file, before its image is loaded.
+#[derive(Debug, Clone)]
+pub struct SheetData {
+    // Relative to the JSON file.
+    pub image: String,
+    pub frames: Vec<SpriteFrame>,
+    // Aseprite's `frameTags`.
+    pub tags: Vec<FrameTag>,
+    // Everything under `meta`, for anything format specific not read above.
+    pub meta: Value,
+}
+
+// Reads the JSON written by TexturePacker and Aseprite, with frames either
+// as an object keyed by name ("Hash") or as an array ("Array"). Rotated
+// frames aren't supported.
+pub fn parse_sheet_json(text: &str) -> Result<SheetData> {
+    let root: SheetJson = serde_json::from_str(text)?;
+    let frames = match root.frames {
+        Value::Array(items) => items
+            .into_iter()
+            .enumerate()
+            .map(|(i, item)| frame_from_json(None, item).with_context(|| format!("frames[{}]", i)))
+            .collect::<Result<Vec<_>>>()?,
+        Value::Object(items) => items
+            .into_iter()
+            .map(|(name, item)| frame_from_json(Some(&name), item).with_context(|| format!("frames.{}", name)))
+            .collect::<Result<Vec<_>>>()?,
+        _ => return Err(anyhow!("frames must be an array or an object")),
+    };
+    let meta = MetaJson::deserialize(&root.meta).context("meta")?;
+    let tags = meta
+        .frame_tags
+        .into_iter()
+        .enumerate()
+        .map(|(i, tag)| tag_from_json(tag, frames.len()).with_context(|| format!("meta.frameTags[{}]", i)))
+        .collect::<Result<_>>()?;
+    Ok(SheetData {
+        image: meta.image,
+        frames,
+        tags,
+        meta: root.meta,
+    })
+}
+
+#[derive(Deserialize)]
+struct SheetJson {
+    frames: Value,
+    #[serde(default)]
+    meta: Value,
+}
+
+#[derive(Deserialize)]
+struct MetaJson {
+    image: String,
+    #[serde(default, rename = "frameTags")]
+    frame_tags: Vec<TagJson>,
+}
+
+#[derive(Deserialize)]
+struct TagJson {
+    name: String,
+    from: usize,
+    to: usize,
+    #[serde(default)]
+    direction: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FrameJson {
+    // Only in the "Array" format.
+    filename: Option<String>,
+    frame: RectJson,
+    #[serde(default)]
+    rotated: bool,
+    sprite_source_size: Option<RectJson>,
+    source_size: Option<SizeJson>,
+    pivot: Option<PointJson>,
+    #[serde(default)]
+    duration: f32,
+}
+
+#[derive(Deserialize)]
+struct RectJson {
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+}
+
+#[derive(Deserialize)]
+struct SizeJson {
+    w: u32,
+    h: u32,
+}
+
+#[derive(Deserialize)]
+struct PointJson {
+    x: f32,
+    y: f32,
+}
+
+fn tag_from_json(tag: TagJson, frame_count: usize) -> Result<FrameTag> {
+    let direction = match tag.direction.as_deref().unwrap_or("forward") {
+        "forward" => TagDirection::Forward,
+        "reverse" => TagDirection::Reverse,
+        "pingpong" => TagDirection::PingPong,
+        "pingpong_reverse" => TagDirection::PingPongReverse,
+        other => return Err(anyhow!("Unknown tag direction {:?}", other)),
+    };
+    if tag.from > tag.to || tag.to >= frame_count {
+        return Err(anyhow!("Frames {}..={} are not in a sheet of {}", tag.from, tag.to, frame_count));
+    }
+    Ok(FrameTag {
+        name: tag.name,
+        from: tag.from,
+        to: tag.to,
+        direction,
+    })
+}
+
+// Frames of the "Array" format are named by their `filename`.
+fn frame_from_json(name: Option<&str>, value: Value) -> Result<SpriteFrame> {
+    let value = FrameJson::deserialize(value)?;
+    let name = name.or(value.filename.as_deref()).ok_or_else(|| anyhow!("Frame has no filename"))?;
+    if value.rotated {
+        return Err(anyhow!("Rotated frames are not supported, export without rotation"));
+    }
+    let RectJson { x, y, w: width, h: height } = value.frame;
+    let mut frame = SpriteFrame::new(name, 0, x, y, width, height);
+
+    // TexturePacker pivots count from the top left of the untrimmed image.
+    let pivot = match value.pivot {
+        Some(p) => Vec2::new(p.x, 1.0 - p.y),
+        None => Vec2::new(0.5, 0.5),
+    };
+    frame.pivot = match (value.sprite_source_size, value.source_size) {
+        (Some(trim), Some(source)) if width > 0 && height > 0 => {
+            let source = Vec2::new(source.w as f32, source.h as f32);
+            // The trimmed rectangle's bottom left within the untrimmed image, y up.
+            let corner = Vec2::new(trim.x as f32, source.y - trim.y as f32 - height as f32);
+            (pivot * source - corner) / Vec2::new(width as f32, height as f32)
+        }
+        _ => pivot,
+    };
+    // Aseprite durations are in milliseconds.
+    frame.duration = value.duration / 1000.0;
+    Ok(frame)
+}
+
+// `image` relative to the directory of `file_name`, as an asset path.
+pub fn sibling_path(file_name: &str, image: &str) -> String {
+    match file_name.rfind('/') {
+        Some(slash) => format!("{}/{}", &file_name[..slash], image),
+        None => image.to_string(),
+    }
+}
+
+// Images packed onto as few pages as fit, not uploaded yet.
+#[derive(Debug, Clone)]
+pub struct PackedAtlas {
+    pub pages: Vec<RgbaImage>,
+    pub frames: Vec<SpriteFrame>,
+}
+
+// Packs many small images into a few large ones at load time, so sprites
+// using them batch into fewer draws. Images are placed on shelves, tallest
+// first, and a new page is started once one is `max_size` square.
+#[derive(Debug, Clone)]
+pub struct AtlasBuilder {
+    pub max_size: u32,
+    // Pixels left around each image. They are filled with copies of the
+    // image's edge when `extrude` is set, so filtering doesn't pick up the
+    // neighbours' colors.
+    pub padding: u32,
+    pub extrude: bool,
+    images: Vec<(String, RgbaImage)>,
+}
+
+impl AtlasBuilder {
+    pub fn new(max_size: u32) -> Self {
+        Self {
+            max_size,
+            padding: 1,
+            extrude: true,
+            images: Vec::new(),
+        }
+    }
+
+    pub fn add_image(&mut self, name: &str, image: RgbaImage) {
+        self.images.push((name.to_string(), image));
+    }
+
+    // Loads an image from the asset directory, named by its file name.
+    pub async fn load_image(&mut self, file_name: &str) -> Result<()> {
+        let data = resources::load_binary(file_name).await?;
+        let image = image::load_from_memory(&data).with_context(|| file_name.to_string())?;
+        self.add_image(file_name, image.to_rgba8());
+        Ok(())
+    }
+
+    pub fn build(&self) -> Result<PackedAtlas> {
+        let pad = self.padding;
+        let mut order: Vec<usize> = (0..self.images.len()).collect();
+        // Stable, so images of the same height keep the order they were added in.
+        order.sort_by_key(|&i| std::cmp::Reverse(self.images[i].1.height()));
+
+        let mut pages: Vec<ShelfPage> = Vec::new();
+        let mut placements = vec![(0, 0, 0); self.images.len()];
+        for i in order {
+            let (name, image) = &self.images[i];
+            // There'd be no edge to extrude, and no frame to draw.
+            if image.width() == 0 || image.height() == 0 {
+                return Err(anyhow!("Image {:?} is empty", name));
+            }
+            let (width, height) = (image.width() + pad * 2, image.height() + pad * 2);
+            if width > self.max_size || height > self.max_size {
+                return Err(anyhow!(
+                    "Image {:?} is {}x{}, too big for a {} pixel atlas",
+                    name,
+                    image.width(),
+                    image.height(),
+                    self.max_size
+                ));
+            }
+            let placed = pages
+                .iter_mut()
+                .enumerate()
+                .find_map(|(page, shelves)| shelves.place(width, height, self.max_size).map(|(x, y)| (page, x, y)));
+            placements[i] = match placed {
+                Some(placement) => placement,
+                None => {
+                    let mut page = ShelfPage::default();
+                    let (x, y) = page.place(width, height, self.max_size).unwrap();
+                    pages.push(page);
+                    (pages.len() - 1, x, y)
+                }
+            };
+        }
+
+        let mut images: Vec<RgbaImage> = pages.iter().map(|p| RgbaImage::new(p.width.max(1), p.height.max(1))).collect();
+        let mut frames = Vec::with_capacity(self.images.len());
+        for ((name, image), &(page, x, y)) in self.images.iter().zip(&placements) {
+            let target = &mut images[page];
+            let (left, top) = (x + pad, y + pad);
+            let extrude = if self.extrude { pad as i64 } else { 0 };
+            for ty in -extrude..image.height() as i64 + extrude {
+                for tx in -extrude..image.width() as i64 + extrude {
+                    let sx = tx.clamp(0, image.width() as i64 - 1) as u32;
+                    let sy = ty.clamp(0, image.height() as i64 - 1) as u32;
+                    let pixel = *image.get_pixel(sx, sy);
+                    target.put_pixel((left as i64 + tx) as u32, (top as i64 + ty) as u32, pixel);
+                }
+            }
+            frames.push(SpriteFrame::new(name, page, left, top, image.width(), image.height()));
+        }
+        Ok(PackedAtlas { pages: images, frames })
+    }
+}
+
+// Rows of images on one page, each as tall as its first, tallest, image.
+#[derive(Debug, Default)]
+pub(super) struct ShelfPage {
+    // (y, height, used width) per shelf.
+    shelves: Vec<(u32, u32, u32)>,
+    width: u32,
+    height: u32,
+}
+
+impl ShelfPage {
+    pub(super) fn place(&mut self, width: u32, height: u32, max_size: u32) -> Option<(u32, u32)> {
+        let (x, y) = match self
+            .shelves
+            .iter_mut()
+            .find(|(_, shelf_height, used)| height <= *shelf_height && used + width <= max_size)
+        {
+            Some((y, _, used)) => {
+                let x = *used;
+                *used += width;
+                (x, *y)
+            }
+            None if self.height + height <= max_size => {
+                self.shelves.push((self.height, height, width));
+                self.height += height;
+                (0, self.height - height)
+            }
+            None => return None,
+        };
+        self.width = self.width.max(x + width);
+        Some((x, y))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+
+    fn assert_near(a: Vec2, b: Vec2) {
+        assert!((a - b).mag() < 1e-5, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn shelves_fill_left_to_right_then_stack() {
+        let mut page = ShelfPage::default();
+        assert_eq!(page.place(10, 8, 32), Some((0, 0)));
+        assert_eq!(page.place(12, 6, 32), Some((10, 0)));
+        // Too wide for what's left of the first shelf.
+        assert_eq!(page.place(12, 8, 32), Some((0, 8)));
+        // Shorter images go on the first shelf they fit on.
+        assert_eq!(page.place(10, 4, 32), Some((22, 0)));
+        assert_eq!(page.place(32, 16, 32), Some((0, 16)));
+        assert_eq!((page.width, page.height), (32, 32));
+        assert_eq!(page.place(21, 1, 32), None);
+        assert_eq!(page.place(20, 1, 32), Some((12, 8)));
+        assert_eq!(page.place(1, 40, 64), None);
+    }
+
+    #[test]
+    fn grid_frames_skip_margin_and_spacing() {
+        let grid = SheetGrid {
+            margin: 2,
+            spacing: 1,
+            duration: 0.1,
+            ..SheetGrid::new(8, 4)
+        };
+        // (37 - 4 + 1) / 9 = 3 columns, (14 - 4 + 1) / 5 = 2 rows.
+        let frames = grid.frames(37, 14).unwrap();
+        assert_eq!(frames.len(), 6);
+        assert_eq!(frames[0], SpriteFrame { duration: 0.1, ..SpriteFrame::new("0", 0, 2, 2, 8, 4) });
+        assert_eq!((frames[2].x, frames[2].y), (20, 2));
+        assert_eq!((frames[4].name.as_str(), frames[4].x, frames[4].y), ("4", 11, 7));
+
+        let partial = SheetGrid { count: Some(4), ..grid }.frames(37, 14).unwrap();
+        assert_eq!(partial.len(), 4);
+        assert!(SheetGrid { count: Some(7), ..grid }.frames(37, 14).is_err());
+        assert!(SheetGrid::new(0, 4).frames(37, 14).is_err());
+    }
+
+    #[test]
+    fn texture_packer_pivots_survive_trimming() {
+        let sheet = parse_sheet_json(
+            r#"{
+                "frames": {
+                    "hero": {
+                        "frame": {"x": 1, "y": 2, "w": 16, "h": 20},
+                        "rotated": false,
+                        "trimmed": true,
+                        "spriteSourceSize": {"x": 8, "y": 4, "w": 16, "h": 20},
+                        "sourceSize": {"w": 32, "h": 32},
+                        "pivot": {"x": 0.5, "y": 0.5}
+                    },
+                    "feet": {
+                        "frame": {"x": 20, "y": 2, "w": 16, "h": 20},
+                        "spriteSourceSize": {"x": 8, "y": 4, "w": 16, "h": 20},
+                        "sourceSize": {"w": 32, "h": 32},
+                        "pivot": {"x": 0.5, "y": 1.0}
+                    },
+                    "plain": {"frame": {"x": 40, "y": 0, "w": 4, "h": 4}, "pivot": {"x": 0.0, "y": 0.0}}
+                },
+                "meta": {"image": "sheet.png"}
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(sheet.image, "sheet.png");
+        let names: Vec<_> = sheet.frames.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, ["hero", "feet", "plain"]);
+
+        let hero = &sheet.frames[0];
+        assert_eq!((hero.x, hero.y, hero.width, hero.height), (1, 2, 16, 20));
+        // The untrimmed centre (16, 16) from the trimmed rectangle's bottom left (8, 8).
+        assert_near(hero.pivot, Vec2::new(0.5, 0.4));
+        // The untrimmed bottom edge is 8 pixels below the trimmed one.
+        assert_near(sheet.frames[1].pivot, Vec2::new(0.5, -0.4));
+        // Y flipped to count from the bottom.
+        assert_near(sheet.frames[2].pivot, Vec2::new(0.0, 1.0));
+    }
+
+    #[test]
+    fn aseprite_arrays_have_durations_tags_and_trim() {
+        let sheet = parse_sheet_json(
+            r#"{
+                "frames": [
+                    {
+                        "filename": "run 0",
+                        "frame": {"x": 0, "y": 0, "w": 10, "h": 12},
+                        "spriteSourceSize": {"x": 3, "y": 0, "w": 10, "h": 12},
+                        "sourceSize": {"w": 16, "h": 16},
+                        "duration": 100
+                    },
+                    {"filename": "run 1", "frame": {"x": 10, "y": 0, "w": 16, "h": 16}, "duration": 150}
+                ],
+                "meta": {
+                    "image": "run.png",
+                    "frameTags": [{"name": "run", "from": 0, "to": 1, "direction": "pingpong_reverse"}]
+                }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(sheet.frames[0].name, "run 0");
+        assert!((sheet.frames[0].duration - 0.1).abs() < 1e-6);
+        assert!((sheet.frames[1].duration - 0.15).abs() < 1e-6);
+        // Centre (8, 8) of the image from the trimmed bottom left (3, 4).
+        assert_near(sheet.frames[0].pivot, Vec2::new(0.5, 4.0 / 12.0));
+        assert_near(sheet.frames[1].pivot, Vec2::new(0.5, 0.5));
+        assert_eq!(
+            sheet.tags,
+            [FrameTag {
+                name: "run".to_string(),
+                from: 0,
+                to: 1,
+                direction: TagDirection::PingPongReverse,
+            }]
+        );
+        assert_eq!(sheet.meta["image"], "run.png");
+    }
+
+    #[test]
+    fn rejects_unsupported_sheets() {
+        let rotated = r#"{"frames": {"a": {"frame": {"x": 0, "y": 0, "w": 1, "h": 1}, "rotated": true}}}"#;
+        assert!(parse_sheet_json(rotated).is_err());
+        let unnamed = r#"{"frames": [{"frame": {"x": 0, "y": 0, "w": 1, "h": 1}}]}"#;
+        assert!(parse_sheet_json(unnamed).is_err());
+        let tag = r#"{"frames": [], "meta": {"frameTags": [{"name": "a", "from": 0, "to": 0}]}}"#;
+        assert!(parse_sheet_json(tag).is_err());
+    }
+
+    #[test]
+    fn atlas_extrudes_edges_into_the_padding() {
+        let mut builder = AtlasBuilder::new(16);
+        builder.add_image("short", RgbaImage::from_pixel(2, 1, Rgba([1, 2, 3, 4])));
+        builder.add_image("tall", RgbaImage::from_fn(1, 2, |_, y| Rgba([y as u8 * 100; 4])));
+        let atlas = builder.build().unwrap();
+        assert_eq!(atlas.pages.len(), 1);
+        // Added order is kept, though the taller image is placed first.
+        assert_eq!(atlas.frames[1], SpriteFrame::new("tall", 0, 1, 1, 1, 2));
+        assert_eq!(atlas.frames[0], SpriteFrame::new("short", 0, 4, 1, 2, 1));
+        let page = &atlas.pages[0];
+        assert_eq!(page.dimensions(), (7, 4));
+        assert_eq!(*page.get_pixel(1, 0), Rgba([0; 4]));
+        assert_eq!(*page.get_pixel(2, 3), Rgba([100; 4]));
+        assert_eq!(*page.get_pixel(6, 2), Rgba([1, 2, 3, 4]));
+    }
+
+    #[test]
+    fn atlas_rejects_empty_and_oversized_images() {
+        for extrude in [false, true] {
+            let mut builder = AtlasBuilder { extrude, ..AtlasBuilder::new(16) };
+            builder.add_image("empty", RgbaImage::new(0, 0));
+            assert!(builder.build().is_err());
+        }
+        let mut builder = AtlasBuilder::new(16);
+        builder.add_image("wide", RgbaImage::new(15, 1));
+        assert!(builder.build().is_err());
+    }
+}
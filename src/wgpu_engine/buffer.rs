@@ -0,0 +1,63 @@
+// Buffer helpers shared by the renderers that rebuild their data every frame.
+use std::mem::size_of;
+
+// A buffer that can also be filled with `Queue::write_buffer`.
+pub(super) fn create_buffer(device: &wgpu::Device, label: &str, size: u64, usage: wgpu::BufferUsages) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some(label),
+        size,
+        usage: usage | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+// Doubles `buffer` until `size` bytes fit, keeping its usage but not its
+// contents. Returns whether it was recreated, so bind groups of it can be too.
+pub(super) fn grow_buffer(device: &wgpu::Device, buffer: &mut wgpu::Buffer, label: &str, size: u64) -> bool {
+    let mut buffer_size = buffer.size().max(4);
+    if size <= buffer_size {
+        return false;
+    }
+    while size > buffer_size {
+        buffer_size *= 2;
+    }
+    let usage = buffer.usage();
+    buffer.destroy();
+    *buffer = create_buffer(device, label, buffer_size, usage);
+    true
+}
+
+// Bytes between consecutive `T`s in a uniform buffer bound with dynamic
+// offsets, one per view or layer.
+pub(super) fn uniform_stride<T>(device: &wgpu::Device) -> u64 {
+    let alignment = device.limits().min_uniform_buffer_offset_alignment as u64;
+    (size_of::<T>() as u64).div_ceil(alignment) * alignment
+}
+
+// Appends `uniform` to `bytes` at the next multiple of `stride`.
+pub(super) fn push_uniform<T: bytemuck::Pod>(bytes: &mut Vec<u8>, stride: u64, uniform: &T) {
+    let slot = bytes.len().div_ceil(stride as usize) * stride as usize;
+    bytes.resize(slot, 0);
+    bytes.extend_from_slice(bytemuck::bytes_of(uniform));
+}
+
+// Binds one `T` of `buffer` at binding 0, picked with a dynamic offset.
+pub(super) fn create_uniform_bind_group<T>(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    buffer: &wgpu::Buffer,
+    label: &str,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                buffer,
+                offset: 0,
+                size: wgpu::BufferSize::new(size_of::<T>() as u64),
+            }),
+        }],
+        label: Some(label),
+    })
+}
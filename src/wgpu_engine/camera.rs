@@ -1,8 +1,9 @@
 use std::fmt::Debug;
 
-use sdl2::{event, keyboard::Keycode};
 use wgpu::util::DeviceExt;
 
+use super::{scene::{CameraDesc, ProjectionDesc}, WindowSize};
+
 #[derive(Debug)]
 pub struct LookAt {
     pub eye: ultraviolet::Vec3,
@@ -18,11 +19,11 @@ impl LookAt {
         ultraviolet::Mat4::look_at(self.eye, self.target, self.up)
     }
 
-    fn replace(&mut self, pos: ultraviolet::Vec3) {
+    pub fn replace(&mut self, pos: ultraviolet::Vec3) {
         self.eye = pos;
     }
 
-    fn go_forward(&mut self, speed: f32) {
+    pub fn go_forward(&mut self, speed: f32) {
         let forward = self.target - self.eye;
         let forward_mag = forward.mag();
         let forward = forward.normalized();
@@ -32,12 +33,12 @@ impl LookAt {
         self.eye += forward * speed;
     }
 
-    fn rotate_eye(&mut self, rot: ultraviolet::Rotor3) {
+    pub fn rotate_eye(&mut self, rot: ultraviolet::Rotor3) {
         let forward = self.target - self.eye;
         self.eye = self.target - forward.rotated_by(rot) ;
     }
 
-    fn rotate_target(&mut self, rot: ultraviolet::Rotor3) {
+    pub fn rotate_target(&mut self, rot: ultraviolet::Rotor3) {
         let forward = self.target - self.eye;
         self.target = forward.rotated_by(rot) + self.eye;
     }
@@ -47,6 +48,9 @@ impl LookAt {
 pub struct Camera {
     view: LookAt,
     pub projection: Box<dyn Projection>,
+    // Moves the camera in its own space on top of `view`, for effects like
+    // shake that controllers reading the view shouldn't see.
+    pub view_offset: ultraviolet::Isometry3,
     bind_group: wgpu::BindGroup,
     buffer: wgpu::Buffer,
     uniform: CameraUniform,
@@ -76,15 +80,19 @@ impl Camera {
         Self {
             view,
             projection,
+            view_offset: ultraviolet::Isometry3::identity(),
             bind_group: camera_bind_group,
             buffer: camera_buffer,
             uniform: camera_uniform,
         }
     }
     fn build_view_proj_matrix(&self) -> ultraviolet::Mat4 {
-        let view = self.view.view_mat();
-        let proj = self.projection.proj_matrix();
-        proj * view
+        self.view_projection().matrix
+    }
+
+    // The camera's current matrix for converting between screen and world.
+    pub fn view_projection(&self) -> ViewProjection {
+        ViewProjection::new(&self.view, self.view_offset, self.projection.as_ref())
     }
 
     pub fn update(&mut self, queue: &wgpu::Queue) {
@@ -92,14 +100,337 @@ impl Camera {
         queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.uniform]));
     }
 
+    pub fn view(&self) -> &LookAt {
+        &self.view
+    }
+
+    pub fn view_mut(&mut self) -> &mut LookAt {
+        &mut self.view
+    }
+
     pub fn bind_group(&self) -> &wgpu::BindGroup {
         &self.bind_group
     }
+
+    pub fn eye(&self) -> ultraviolet::Vec3 {
+        self.view.eye
+    }
+
+    pub fn view_height(&self, distance: f32) -> f32 {
+        self.projection.view_height(distance)
+    }
+
+    pub fn frustum(&self) -> Frustum {
+        Frustum::from_view_proj(&self.build_view_proj_matrix())
+    }
+
+    pub fn view_proj(&self) -> ultraviolet::Mat4 {
+        self.build_view_proj_matrix()
+    }
+
+    pub fn depth_mode(&self) -> DepthMode {
+        self.projection.depth_mode()
+    }
+
+    // See `ViewProjection::screen_to_ray`.
+    pub fn screen_to_ray(&self, pixel: ultraviolet::Vec2, size: WindowSize) -> Ray {
+        self.view_projection().screen_to_ray(pixel, size)
+    }
+
+    // See `ViewProjection::screen_to_world`.
+    pub fn screen_to_world(&self, pixel: ultraviolet::Vec2, depth: f32, size: WindowSize) -> ultraviolet::Vec3 {
+        self.view_projection().screen_to_world(pixel, depth, size)
+    }
+
+    // See `ViewProjection::world_to_screen`.
+    pub fn world_to_screen(&self, point: ultraviolet::Vec3, size: WindowSize) -> Option<ultraviolet::Vec3> {
+        self.view_projection().world_to_screen(point, size)
+    }
+
+    // See `ViewProjection::frustum_corners`.
+    pub fn frustum_corners(&self) -> [ultraviolet::Vec3; 8] {
+        self.view_projection().frustum_corners()
+    }
+
+    pub fn desc(&self) -> CameraDesc {
+        CameraDesc {
+            eye: self.view.eye,
+            target: self.view.target,
+            up: self.view.up,
+            projection: self.projection.desc(),
+        }
+    }
+
+    pub fn set_desc(&mut self, desc: &CameraDesc, size: WindowSize) {
+        self.view = LookAt::new(desc.eye, desc.target, desc.up);
+        self.projection = desc.projection.build(size);
+    }
+
+    // Like `set_desc` but keeps the aspect ratio, for animating a camera the
+    // engine fits to its viewport. Switching between perspective and
+    // orthographic starts from a square one until the next `WgpuEngine::update`.
+    pub fn apply_desc(&mut self, desc: &CameraDesc) {
+        self.view = LookAt::new(desc.eye, desc.target, desc.up);
+        if !self.projection.set_desc(&desc.projection) {
+            self.projection = desc.projection.build(WindowSize { width: 1, height: 1 });
+        }
+    }
+}
+
+// A camera's view and projection matrix without its GPU uniform, for
+// converting between screen and world positions.
+#[derive(Debug, Clone, Copy)]
+pub struct ViewProjection {
+    pub matrix: ultraviolet::Mat4,
+    pub depth: DepthMode,
+}
+
+impl ViewProjection {
+    pub fn new(view: &LookAt, view_offset: ultraviolet::Isometry3, projection: &dyn Projection) -> Self {
+        let view = view_offset.inversed().into_homogeneous_matrix() * view.view_mat();
+        Self {
+            matrix: projection.proj_matrix() * view,
+            depth: projection.depth_mode(),
+        }
+    }
+
+    // The ray through `pixel` of a screen of `size`, pixels measured from the
+    // top left corner. It starts on the near plane, so with an orthographic
+    // projection every pixel gets its own origin and the same direction.
+    pub fn screen_to_ray(&self, pixel: ultraviolet::Vec2, size: WindowSize) -> Ray {
+        let ndc = pixel_to_ndc(pixel, size);
+        let inverse = self.matrix.inversed();
+        let near = unproject(&inverse, ndc.x, ndc.y, self.depth.near());
+        // Half way in depth is on the ray too and never at infinity.
+        let further = unproject(&inverse, ndc.x, ndc.y, 0.5);
+        Ray::new(near, further - near)
+    }
+
+    // The world position at `pixel` and `depth`, a depth buffer value as
+    // given by `world_to_screen`, see `DepthMode`.
+    pub fn screen_to_world(&self, pixel: ultraviolet::Vec2, depth: f32, size: WindowSize) -> ultraviolet::Vec3 {
+        let ndc = pixel_to_ndc(pixel, size);
+        unproject(&self.matrix.inversed(), ndc.x, ndc.y, depth)
+    }
+
+    // Pixel position of `point` on a screen of `size` with its depth in z, or
+    // None if it is behind the camera. Points outside the view give pixels
+    // off the screen.
+    pub fn world_to_screen(&self, point: ultraviolet::Vec3, size: WindowSize) -> Option<ultraviolet::Vec3> {
+        let clip = self.matrix * point.into_homogeneous_point();
+        if clip.w <= f32::EPSILON {
+            return None;
+        }
+        let ndc = clip.xyz() / clip.w;
+        Some(ultraviolet::Vec3::new(
+            (ndc.x + 1.0) * 0.5 * size.width as f32,
+            (1.0 - ndc.y) * 0.5 * size.height as f32,
+            ndc.z,
+        ))
+    }
+
+    // Near plane corners followed by far plane corners, each in the order
+    // bottom left, bottom right, top right, top left. The far corners aren't
+    // finite with an infinite far plane.
+    pub fn frustum_corners(&self) -> [ultraviolet::Vec3; 8] {
+        let inverse = self.matrix.inversed();
+        let mode = self.depth;
+        let corner = |i: usize| {
+            let (x, y) = corner_ndc(i);
+            let depth = if i < 4 { mode.near() } else { mode.far() };
+            unproject(&inverse, x, y, depth)
+        };
+        [corner(0), corner(1), corner(2), corner(3), corner(4), corner(5), corner(6), corner(7)]
+    }
+
+    // Like `frustum_corners`, but far corners more than `distance` from their
+    // near corner, or at infinity, are pulled back along the edge to it.
+    pub fn frustum_corners_within(&self, distance: f32) -> [ultraviolet::Vec3; 8] {
+        let inverse = self.matrix.inversed();
+        let mut corners = self.frustum_corners();
+        for i in 0..4 {
+            let edge = corners[i + 4] - corners[i];
+            let length = edge.mag();
+            if length.is_finite() && length <= distance {
+                continue;
+            }
+            // Same trick as `screen_to_ray`, half way in depth is finite.
+            let (x, y) = corner_ndc(i);
+            let direction = (unproject(&inverse, x, y, 0.5) - corners[i]).normalized();
+            corners[i + 4] = corners[i] + direction * distance;
+        }
+        corners
+    }
+}
+
+// Bottom left, bottom right, top right, top left, repeated for the far plane.
+fn corner_ndc(i: usize) -> (f32, f32) {
+    let x = if i % 4 == 1 || i % 4 == 2 { 1.0 } else { -1.0 };
+    let y = if i % 4 >= 2 { 1.0 } else { -1.0 };
+    (x, y)
+}
+
+fn pixel_to_ndc(pixel: ultraviolet::Vec2, size: WindowSize) -> ultraviolet::Vec2 {
+    ultraviolet::Vec2::new(
+        2.0 * pixel.x / size.width.max(1) as f32 - 1.0,
+        1.0 - 2.0 * pixel.y / size.height.max(1) as f32,
+    )
+}
+
+fn unproject(inverse_view_proj: &ultraviolet::Mat4, x: f32, y: f32, depth: f32) -> ultraviolet::Vec3 {
+    let point = *inverse_view_proj * ultraviolet::Vec4::new(x, y, depth, 1.0);
+    point.xyz() / point.w
+}
+
+// A half line from `origin`, `direction` is always normalized.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ray {
+    pub origin: ultraviolet::Vec3,
+    pub direction: ultraviolet::Vec3,
+}
+
+impl Ray {
+    pub fn new(origin: ultraviolet::Vec3, direction: ultraviolet::Vec3) -> Self {
+        Self {
+            origin,
+            direction: direction.normalized(),
+        }
+    }
+
+    pub fn at(&self, distance: f32) -> ultraviolet::Vec3 {
+        self.origin + self.direction * distance
+    }
+
+    // Distance to the plane through `point` with `normal`, None if the ray is
+    // parallel to it or points away from it.
+    pub fn intersect_plane(&self, point: ultraviolet::Vec3, normal: ultraviolet::Vec3) -> Option<f32> {
+        let denom = normal.dot(self.direction);
+        if denom.abs() <= f32::EPSILON {
+            return None;
+        }
+        let distance = normal.dot(point - self.origin) / denom;
+        (distance >= 0.0).then_some(distance)
+    }
+
+    // Distance to the first hit on the sphere, 0 if the ray starts inside it.
+    pub fn intersect_sphere(&self, center: ultraviolet::Vec3, radius: f32) -> Option<f32> {
+        let to_center = center - self.origin;
+        let along = to_center.dot(self.direction);
+        let d2 = to_center.mag_sq() - along * along;
+        let r2 = radius * radius;
+        if d2 > r2 {
+            return None;
+        }
+        let half_chord = (r2 - d2).sqrt();
+        let (near, far) = (along - half_chord, along + half_chord);
+        if far < 0.0 {
+            None
+        } else {
+            Some(near.max(0.0))
+        }
+    }
+
+    // Slab test against an axis aligned box, 0 if the ray starts inside it.
+    pub fn intersect_aabb(&self, min: ultraviolet::Vec3, max: ultraviolet::Vec3) -> Option<f32> {
+        let inverse = ultraviolet::Vec3::one() / self.direction;
+        let t0 = (min - self.origin) * inverse;
+        let t1 = (max - self.origin) * inverse;
+        let near = t0.min_by_component(t1).component_max();
+        let far = t0.max_by_component(t1).component_min();
+        if far < near.max(0.0) {
+            None
+        } else {
+            Some(near.max(0.0))
+        }
+    }
+}
+
+// How a projection maps distance to depth. Pipelines and depth clears have
+// to agree with the projection of the camera they draw with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DepthMode {
+    // 0 at the near plane, 1 at the far plane.
+    Standard,
+    // 1 at the near plane, 0 at the far plane. Spreads float precision
+    // evenly over distance, which with an infinite far plane avoids
+    // z-fighting far away.
+    Reversed,
+}
+
+impl DepthMode {
+    pub fn near(&self) -> f32 {
+        match self {
+            DepthMode::Standard => 0.0,
+            DepthMode::Reversed => 1.0,
+        }
+    }
+
+    pub fn far(&self) -> f32 {
+        1.0 - self.near()
+    }
+
+    // Depth buffers are cleared to the far value.
+    pub fn clear_value(&self) -> f32 {
+        self.far()
+    }
+
+    pub fn compare(&self) -> wgpu::CompareFunction {
+        match self {
+            DepthMode::Standard => wgpu::CompareFunction::Less,
+            DepthMode::Reversed => wgpu::CompareFunction::Greater,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Frustum {
+    // left, right, bottom, top, near, far, near and far swapped with reversed
+    // depth; xyz is the inward normal, w the distance. An infinite far plane
+    // is all zeros but w, which passes everything.
+    pub planes: [ultraviolet::Vec4; 6],
+}
+
+impl Frustum {
+    pub fn from_view_proj(view_proj: &ultraviolet::Mat4) -> Self {
+        let row = |i: usize| {
+            ultraviolet::Vec4::new(
+                view_proj.cols[0][i],
+                view_proj.cols[1][i],
+                view_proj.cols[2][i],
+                view_proj.cols[3][i],
+            )
+        };
+        let (r0, r1, r2, r3) = (row(0), row(1), row(2), row(3));
+        // wgpu clip space has depth in [0, 1], so the near plane is just the z row.
+        let mut planes = [r3 + r0, r3 - r0, r3 + r1, r3 - r1, r2, r3 - r2];
+        for plane in planes.iter_mut() {
+            let len = plane.xyz().mag();
+            if len > 0.0 {
+                *plane /= len;
+            }
+        }
+        Self { planes }
+    }
+
+    pub fn intersects_sphere(&self, center: ultraviolet::Vec3, radius: f32) -> bool {
+        self.planes
+            .iter()
+            .all(|p| p.xyz().dot(center) + p.w >= -radius)
+    }
 }
 
 pub trait Projection: Debug {
     fn proj_matrix(&self) -> ultraviolet::Mat4;
     fn resize(&mut self, width: f32, height: f32);
+    // World space height covered by the view at `distance` from the eye.
+    fn view_height(&self, distance: f32) -> f32;
+    fn desc(&self) -> ProjectionDesc;
+    fn depth_mode(&self) -> DepthMode {
+        DepthMode::Standard
+    }
+    // Takes over the parameters in `desc`, false if it describes another kind
+    // of projection.
+    fn set_desc(&mut self, desc: &ProjectionDesc) -> bool;
 }
 
 #[derive(Debug)]
@@ -108,6 +439,9 @@ pub struct PerspectiveProjection {
     fovy: f32,
     znear: f32,
     zfar: f32,
+    pub reverse_z: bool,
+    // Ignores `zfar` and draws everything in front of the near plane.
+    pub infinite: bool,
 }
 
 impl PerspectiveProjection {
@@ -117,63 +451,187 @@ impl PerspectiveProjection {
             fovy: fovy.to_radians(),
             znear,
             zfar,
+            reverse_z: false,
+            infinite: false,
+        }
+    }
+
+    // Reverse-Z with an infinite far plane, for large outdoor scenes.
+    pub fn infinite_reversed(aspect: f32, fovy: f32, znear: f32) -> Self {
+        Self {
+            reverse_z: true,
+            infinite: true,
+            ..Self::new(aspect, fovy, znear, f32::MAX)
         }
     }
 }
 
 impl Projection for PerspectiveProjection {
     fn proj_matrix(&self) -> ultraviolet::Mat4 {
-        ultraviolet::projection::perspective_wgpu_dx(
-            self.fovy,
-            self.aspect,
-            self.znear,
-            self.zfar,
-        )
+        use ultraviolet::projection::*;
+        match (self.reverse_z, self.infinite) {
+            (false, false) => perspective_wgpu_dx(self.fovy, self.aspect, self.znear, self.zfar),
+            (false, true) => perspective_infinite_z_wgpu_dx(self.fovy, self.aspect, self.znear),
+            (true, false) => perspective_reversed_z_wgpu_dx_gl(self.fovy, self.aspect, self.znear, self.zfar),
+            (true, true) => perspective_reversed_infinite_z_wgpu_dx_gl(self.fovy, self.aspect, self.znear),
+        }
     }
 
     fn resize(&mut self, width: f32, height: f32) {
         self.aspect = width / height;
     }
+
+    fn view_height(&self, distance: f32) -> f32 {
+        2.0 * distance * (self.fovy * 0.5).tan()
+    }
+
+    fn desc(&self) -> ProjectionDesc {
+        ProjectionDesc::Perspective {
+            fovy: self.fovy.to_degrees(),
+            znear: self.znear,
+            zfar: self.zfar,
+            reverse_z: self.reverse_z,
+            infinite: self.infinite,
+        }
+    }
+
+    fn depth_mode(&self) -> DepthMode {
+        if self.reverse_z {
+            DepthMode::Reversed
+        } else {
+            DepthMode::Standard
+        }
+    }
+
+    fn set_desc(&mut self, desc: &ProjectionDesc) -> bool {
+        match *desc {
+            ProjectionDesc::Perspective { fovy, znear, zfar, reverse_z, infinite } => {
+                self.fovy = fovy.to_radians();
+                self.znear = znear;
+                self.zfar = zfar;
+                self.reverse_z = reverse_z;
+                self.infinite = infinite;
+                true
+            }
+            _ => false,
+        }
+    }
 }
 
+// Looks at the view from `anchor`, a point of the viewport in fractions of
+// its size from the bottom left: (0, 0) keeps the camera in the bottom left
+// corner, (0.5, 0.5) centers it. One world unit covers `pixels_per_unit`
+// pixels times `zoom`.
 #[derive(Debug)]
 pub struct OrthographicProjection {
-    left: f32,
-    right: f32,
-    bottom: f32,
-    top: f32,
+    // Viewport size in pixels.
+    width: f32,
+    height: f32,
     znear: f32,
     zfar: f32,
+    pub anchor: ultraviolet::Vec2,
+    pub zoom: f32,
+    pub pixels_per_unit: f32,
+    // Rounds the zoom to a whole number of pixels per texel, or whole texels
+    // per pixel when zoomed out, and snaps the edges to the pixel grid so
+    // sprites stay crisp. Keep the camera on whole pixels as well.
+    pub pixel_perfect: bool,
 }
 
 impl OrthographicProjection {
-    pub fn new(size: super::WindowSize, znear: f32, zfar: f32) -> Self {
+    // One unit per pixel with the origin in the bottom left corner.
+    pub fn new(size: WindowSize, znear: f32, zfar: f32) -> Self {
         Self {
-            left: 0.0,
-            right: size.width as f32,
-            bottom: 0.0,
-            top: size.height as f32,
+            width: size.width as f32,
+            height: size.height as f32,
             znear,
             zfar,
+            anchor: ultraviolet::Vec2::zero(),
+            zoom: 1.0,
+            pixels_per_unit: 1.0,
+            pixel_perfect: false,
+        }
+    }
+
+    // The camera in the middle of the view, typical for 2D games.
+    pub fn centered(size: WindowSize, pixels_per_unit: f32, znear: f32, zfar: f32) -> Self {
+        Self {
+            anchor: ultraviolet::Vec2::new(0.5, 0.5),
+            pixels_per_unit,
+            ..Self::new(size, znear, zfar)
+        }
+    }
+
+    // The zoom actually used, see `pixel_perfect`.
+    pub fn effective_zoom(&self) -> f32 {
+        let zoom = self.zoom.max(f32::EPSILON);
+        if !self.pixel_perfect {
+            zoom
+        } else if zoom >= 1.0 {
+            zoom.round()
+        } else {
+            1.0 / (1.0 / zoom).round()
         }
     }
+
+    // Size of a screen pixel in world units.
+    pub fn unit_per_pixel(&self) -> f32 {
+        1.0 / (self.pixels_per_unit * self.effective_zoom())
+    }
+
+    // left, right, bottom, top in world units relative to the camera.
+    pub fn extents(&self) -> (f32, f32, f32, f32) {
+        let pixel = self.unit_per_pixel();
+        let (width, height) = (self.width * pixel, self.height * pixel);
+        let mut left = -self.anchor.x * width;
+        let mut bottom = -self.anchor.y * height;
+        if self.pixel_perfect {
+            left = (left / pixel).round() * pixel;
+            bottom = (bottom / pixel).round() * pixel;
+        }
+        (left, left + width, bottom, bottom + height)
+    }
 }
 
 impl Projection for OrthographicProjection {
     fn proj_matrix(&self) -> ultraviolet::Mat4 {
-        ultraviolet::projection::orthographic_wgpu_dx(
-            self.left,
-            self.right,
-            self.bottom,
-            self.top,
-            self.znear,
-            self.zfar,
-        )
+        let (left, right, bottom, top) = self.extents();
+        ultraviolet::projection::orthographic_wgpu_dx(left, right, bottom, top, self.znear, self.zfar)
     }
 
     fn resize(&mut self, width: f32, height: f32) {
-        self.right = width;
-        self.top = height;
+        self.width = width;
+        self.height = height;
+    }
+
+    fn view_height(&self, _distance: f32) -> f32 {
+        self.height * self.unit_per_pixel()
+    }
+
+    fn desc(&self) -> ProjectionDesc {
+        ProjectionDesc::Orthographic {
+            znear: self.znear,
+            zfar: self.zfar,
+            anchor: self.anchor.into(),
+            zoom: self.zoom,
+            pixels_per_unit: self.pixels_per_unit,
+            pixel_perfect: self.pixel_perfect,
+        }
+    }
+
+    fn set_desc(&mut self, desc: &ProjectionDesc) -> bool {
+        match *desc {
+            ProjectionDesc::Orthographic { znear, zfar, anchor, zoom, pixels_per_unit, pixel_perfect } => {
+                self.znear = znear;
+                self.zfar = zfar;
+                self.anchor = anchor.into();
+                self.zoom = zoom;
+                self.pixels_per_unit = pixels_per_unit;
+                self.pixel_perfect = pixel_perfect;
+                true
+            }
+            _ => false,
+        }
     }
 }
 
@@ -183,6 +641,12 @@ pub struct CameraUniform {
     view_proj: [[f32; 4]; 4],
 }
 
+impl Default for CameraUniform {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl CameraUniform {
     pub fn new() -> Self {
         Self {
@@ -195,111 +659,175 @@ impl CameraUniform {
     }
 }
 
-pub struct CameraController {
-    speed: f32,
-    is_up_pressed: bool,
-    is_down_pressed: bool,
-    is_forward_pressed: bool,
-    is_backward_pressed: bool,
-    is_left_pressed: bool,
-    is_right_pressed: bool,
-}
+#[cfg(test)]
+mod tests {
+    use ultraviolet::{Isometry3, Vec2, Vec3};
 
-impl CameraController {
-    pub fn new(speed: f32) -> Self {
-        Self {
-            speed,
-            is_up_pressed: false,
-            is_down_pressed: false,
-            is_forward_pressed: false,
-            is_backward_pressed: false,
-            is_left_pressed: false,
-            is_right_pressed: false,
-        }
-    }
-
-    pub fn process_events(&mut self, event: &event::Event) -> bool {
-        match event {
-            event::Event::KeyDown { keycode , .. } => {
-                match keycode {
-                    Some(Keycode::Space) => {
-                        self.is_up_pressed = true;
-                        true
-                    }
-                    Some(Keycode::LShift) => {
-                        self.is_down_pressed = true;
-                        true
-                    }
-                    Some(Keycode::W) | Some(Keycode::Up) => {
-                        self.is_forward_pressed = true;
-                        true
-                    }
-                    Some(Keycode::A) | Some(Keycode::Left) => {
-                        self.is_left_pressed = true;
-                        true
-                    }
-                    Some(Keycode::S) | Some(Keycode::Down) => {
-                        self.is_backward_pressed = true;
-                        true
-                    }
-                    Some(Keycode::D) | Some(Keycode::Right) => {
-                        self.is_right_pressed = true;
-                        true
-                    }
-                    _ => false,
-                }
-            }
-            event::Event::KeyUp { keycode, ..} => {
-                match keycode {
-                    Some(Keycode::Space) => {
-                        self.is_up_pressed = false;
-                        true
-                    }
-                    Some(Keycode::LShift) => {
-                        self.is_down_pressed = false;
-                        true
-                    }
-                    Some(Keycode::W) | Some(Keycode::Up) => {
-                        self.is_forward_pressed = false;
-                        true
-                    }
-                    Some(Keycode::A) | Some(Keycode::Left) => {
-                        self.is_left_pressed = false;
-                        true
-                    }
-                    Some(Keycode::S) | Some(Keycode::Down) => {
-                        self.is_backward_pressed = false;
-                        true
-                    }
-                    Some(Keycode::D) | Some(Keycode::Right) => {
-                        self.is_right_pressed = false;
-                        true
-                    },
-                    _ => false,
-                }
+    use super::*;
+
+    const SIZE: WindowSize = WindowSize { width: 800, height: 600 };
+
+    fn look_at(eye: Vec3) -> LookAt {
+        LookAt::new(eye, Vec3::zero(), Vec3::unit_y())
+    }
+
+    fn perspective(reverse_z: bool, infinite: bool) -> ViewProjection {
+        let projection = PerspectiveProjection {
+            reverse_z,
+            infinite,
+            ..PerspectiveProjection::new(SIZE.width as f32 / SIZE.height as f32, 45.0, 0.1, 100.0)
+        };
+        ViewProjection::new(&look_at(Vec3::new(0.0, 3.0, 10.0)), Isometry3::identity(), &projection)
+    }
+
+    // 50 pixels per unit, the camera in the middle of the view.
+    fn orthographic() -> ViewProjection {
+        let projection = OrthographicProjection::centered(SIZE, 50.0, 0.1, 100.0);
+        ViewProjection::new(&look_at(Vec3::new(0.0, 0.0, 10.0)), Isometry3::identity(), &projection)
+    }
+
+    fn assert_near(a: Vec3, b: Vec3, tolerance: f32) {
+        assert!((a - b).mag() < tolerance, "{:?} != {:?}", a, b);
+    }
+
+    const PIXELS: [(f32, f32); 5] = [(400.0, 300.0), (0.0, 0.0), (799.0, 599.0), (123.0, 456.0), (700.0, 50.0)];
+
+    // Pixel to ray to a hit on a wall behind the origin and back to the pixel.
+    fn assert_round_trips(view_proj: &ViewProjection) {
+        for (x, y) in PIXELS {
+            let pixel = Vec2::new(x, y);
+            let ray = view_proj.screen_to_ray(pixel, SIZE);
+            let distance = ray.intersect_plane(Vec3::new(0.0, 0.0, -5.0), Vec3::unit_z()).expect("ray misses the wall");
+            let hit = ray.at(distance);
+            let screen = view_proj.world_to_screen(hit, SIZE).expect("hit behind the camera");
+            assert!((screen.xy() - pixel).mag() < 0.05, "{:?} != {:?}", screen.xy(), pixel);
+            assert_near(view_proj.screen_to_world(pixel, screen.z, SIZE), hit, 1e-2);
+        }
+    }
+
+    #[test]
+    fn perspective_pixels_round_trip_through_rays() {
+        for (reverse_z, infinite) in [(false, false), (false, true), (true, false), (true, true)] {
+            let view_proj = perspective(reverse_z, infinite);
+            assert_round_trips(&view_proj);
+
+            // Rays fan out from the eye, the middle one towards the target.
+            let eye = Vec3::new(0.0, 3.0, 10.0);
+            let center = view_proj.screen_to_ray(Vec2::new(400.0, 300.0), SIZE);
+            assert_near(center.direction, -eye.normalized(), 1e-4);
+            for (x, y) in PIXELS {
+                let ray = view_proj.screen_to_ray(Vec2::new(x, y), SIZE);
+                let back_to_eye = (eye - ray.origin).normalized();
+                assert_near(back_to_eye, -ray.direction, 1e-3);
             }
-            _ => false,
         }
     }
 
-    pub fn update_camera(&self, camera: &mut Camera) {
+    #[test]
+    fn orthographic_pixels_round_trip_through_rays() {
+        let view_proj = orthographic();
+        assert_round_trips(&view_proj);
 
-        // Prevents glitching when camera gets too close to the
-        // center of the scene.
-        if self.is_forward_pressed{
-            camera.view.go_forward(self.speed);
+        // Same direction everywhere, 50 pixels to a unit from the middle.
+        for (x, y) in PIXELS {
+            let ray = view_proj.screen_to_ray(Vec2::new(x, y), SIZE);
+            assert_near(ray.direction, -Vec3::unit_z(), 1e-5);
+            assert_near(ray.origin, Vec3::new((x - 400.0) / 50.0, (300.0 - y) / 50.0, 9.9), 1e-3);
         }
-        if self.is_backward_pressed {
-            camera.view.go_forward(-self.speed);
+    }
+
+    #[test]
+    fn depth_follows_the_depth_mode() {
+        let eye = Vec3::new(0.0, 3.0, 10.0);
+        let forward = -eye.normalized();
+        let depth_at = |view_proj: &ViewProjection, distance: f32| view_proj.world_to_screen(eye + forward * distance, SIZE).unwrap().z;
+        let standard = perspective(false, false);
+        assert!(depth_at(&standard, 0.1).abs() < 1e-4);
+        assert!((depth_at(&standard, 100.0) - 1.0).abs() < 1e-4);
+        let reversed = perspective(true, false);
+        assert!((depth_at(&reversed, 0.1) - 1.0).abs() < 1e-4);
+        assert!(depth_at(&reversed, 100.0).abs() < 1e-4);
+
+        // Infinite far planes never reach the far value, however far away.
+        let infinite = perspective(true, true);
+        assert!((depth_at(&infinite, 0.1) - 1.0).abs() < 1e-4);
+        let far = depth_at(&infinite, 1.0e6);
+        assert!(far > 0.0 && far < 1e-6);
+
+        // Behind the camera there's no pixel.
+        assert!(standard.world_to_screen(eye - forward, SIZE).is_none());
+    }
+
+    #[test]
+    fn frustum_corners_lie_on_the_near_and_far_planes() {
+        let eye = Vec3::new(0.0, 3.0, 10.0);
+        let forward = -eye.normalized();
+        for view_proj in [perspective(false, false), perspective(true, false)] {
+            let corners = view_proj.frustum_corners();
+            for (i, corner) in corners.iter().enumerate() {
+                let distance = (*corner - eye).dot(forward);
+                let expected = if i < 4 { 0.1 } else { 100.0 };
+                assert!((distance - expected).abs() < expected * 1e-3, "corner {} at {}", i, distance);
+            }
+            // Bottom left, bottom right, top right, top left on screen.
+            let pixels = corners[..4].iter().map(|c| view_proj.world_to_screen(*c, SIZE).unwrap().xy()).collect::<Vec<_>>();
+            let expected = [Vec2::new(0.0, 600.0), Vec2::new(800.0, 600.0), Vec2::new(800.0, 0.0), Vec2::new(0.0, 0.0)];
+            for (pixel, expected) in pixels.iter().zip(expected) {
+                assert!((*pixel - expected).mag() < 0.1, "{:?} != {:?}", pixel, expected);
+            }
         }
-        if self.is_right_pressed {
-            // Rescale the distance between the target and eye so
-            // that it doesn't change. The eye therefore still
-            // lies on the circle made by the target and eye.
-            camera.view.rotate_eye(ultraviolet::Rotor3::from_rotation_xz(self.speed));
+
+        let corners = perspective(true, true).frustum_corners();
+        assert!(corners[..4].iter().all(|c| c.x.is_finite() && c.y.is_finite() && c.z.is_finite()));
+        assert!(corners[4..].iter().all(|c| !(c.x.is_finite() && c.y.is_finite() && c.z.is_finite())));
+    }
+
+    #[test]
+    fn frustum_corners_within_clamp_far_edges() {
+        // Infinite far corners land `distance` along the edges through them.
+        let infinite = perspective(true, true);
+        let clamped = infinite.frustum_corners_within(50.0);
+        let near = infinite.frustum_corners();
+        for i in 0..4 {
+            assert_near(clamped[i], near[i], 1e-5);
+            assert!(((clamped[i + 4] - clamped[i]).mag() - 50.0).abs() < 1e-2);
+            let pixel = infinite.world_to_screen(clamped[i + 4], SIZE).unwrap();
+            let expected = infinite.world_to_screen(near[i], SIZE).unwrap();
+            assert!((pixel.xy() - expected.xy()).mag() < 0.1, "{:?} != {:?}", pixel, expected);
         }
-        if self.is_left_pressed {
-            camera.view.rotate_eye(ultraviolet::Rotor3::from_rotation_xz(-self.speed));
+
+        // Finite ones closer than `distance` are left alone.
+        let finite = perspective(false, false);
+        let corners = finite.frustum_corners();
+        let within = finite.frustum_corners_within(1000.0);
+        for (a, b) in corners.iter().zip(within) {
+            assert_near(*a, b, 1e-4);
         }
+        let shortened = finite.frustum_corners_within(10.0);
+        assert!(shortened[4..].iter().zip(&corners[..4]).all(|(far, near)| ((*far - *near).mag() - 10.0).abs() < 1e-3));
+    }
+
+    #[test]
+    fn ray_intersections() {
+        let ray = Ray::new(Vec3::zero(), Vec3::new(0.0, 0.0, -2.0));
+        assert_eq!(ray.direction, -Vec3::unit_z());
+        assert_eq!(ray.at(3.0), Vec3::new(0.0, 0.0, -3.0));
+
+        assert_eq!(ray.intersect_plane(Vec3::new(0.0, 0.0, -4.0), Vec3::unit_z()), Some(4.0));
+        // Either side of the plane facing the ray.
+        assert_eq!(ray.intersect_plane(Vec3::new(0.0, 0.0, -4.0), -Vec3::unit_z()), Some(4.0));
+        assert_eq!(ray.intersect_plane(Vec3::new(0.0, 0.0, 4.0), Vec3::unit_z()), None);
+        assert_eq!(ray.intersect_plane(Vec3::new(0.0, 1.0, 0.0), Vec3::unit_y()), None);
+
+        assert_eq!(ray.intersect_sphere(Vec3::new(0.0, 0.0, -5.0), 1.0), Some(4.0));
+        assert_eq!(ray.intersect_sphere(Vec3::new(0.0, 0.0, -0.5), 1.0), Some(0.0));
+        assert_eq!(ray.intersect_sphere(Vec3::new(0.0, 2.0, -5.0), 1.0), None);
+        assert_eq!(ray.intersect_sphere(Vec3::new(0.0, 0.0, 5.0), 1.0), None);
+
+        let (min, max) = (Vec3::new(-1.0, -1.0, -6.0), Vec3::new(1.0, 1.0, -4.0));
+        assert_eq!(ray.intersect_aabb(min, max), Some(4.0));
+        assert_eq!(ray.intersect_aabb(Vec3::broadcast(-1.0), Vec3::one()), Some(0.0));
+        assert_eq!(ray.intersect_aabb(min + Vec3::unit_x() * 3.0, max + Vec3::unit_x() * 3.0), None);
+        assert_eq!(ray.intersect_aabb(-max, -min), None);
     }
 }
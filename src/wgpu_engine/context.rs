@@ -1,6 +1,6 @@
 use anyhow::{anyhow, Result};
 
-use super::WindowSize;
+use super::{indirect::IndirectRenderer, render_mode::RenderModes, WindowSize};
 
 pub struct WgpuContext<'w> {
     #[allow(dead_code)]
@@ -47,11 +47,13 @@ impl<'w> WgpuContext<'w> {
             .await
             .unwrap();
         log::warn!("device and queue");
+        // Optional features are enabled when the adapter has them, callers check device.features().
+        let optional_features = adapter.features() & (IndirectRenderer::REQUIRED_FEATURES | RenderModes::OPTIONAL_FEATURES);
         let (device, queue) = adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
                     label: None,
-                    required_features: wgpu::Features::empty(),
+                    required_features: optional_features,
                     // WebGL doesn't support all of wgpu's features, so if
                     // we're building for the web we'll have to disable some.
                     required_limits: if cfg!(target_arch = "wasm32") {
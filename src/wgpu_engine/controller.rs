@@ -0,0 +1,352 @@
+use sdl2::{
+    event::Event,
+    keyboard::Keycode,
+    mouse::{MouseButton, MouseWheelDirection},
+};
+use ultraviolet::Vec3;
+
+use super::camera::{Camera, LookAt};
+
+// Drives a camera from SDL input. Events are collected as they arrive and
+// applied once per frame in `update_camera`, scaled by the frame time `dt`
+// in seconds so that speeds don't depend on the frame rate.
+pub trait CameraController {
+    // Returns true if the event was used by the controller.
+    fn process_events(&mut self, event: &Event) -> bool;
+    fn update_camera(&mut self, camera: &mut Camera, dt: f32);
+    // Whether the mouse should be captured with SDL relative mouse mode, see
+    // `sdl2::mouse::MouseUtil::set_relative_mouse_mode`.
+    fn relative_mouse(&self) -> bool {
+        false
+    }
+}
+
+// Held movement keys: WASD or the arrows to move, Space and left Shift for up
+// and down, Q and E to roll and left Ctrl to move faster.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MoveKeys {
+    pub forward: bool,
+    pub backward: bool,
+    pub left: bool,
+    pub right: bool,
+    pub up: bool,
+    pub down: bool,
+    pub roll_left: bool,
+    pub roll_right: bool,
+    pub fast: bool,
+}
+
+impl MoveKeys {
+    pub fn process_events(&mut self, event: &Event) -> bool {
+        let (keycode, pressed) = match event {
+            Event::KeyDown { keycode: Some(k), .. } => (*k, true),
+            Event::KeyUp { keycode: Some(k), .. } => (*k, false),
+            _ => return false,
+        };
+        let key = match keycode {
+            Keycode::W | Keycode::Up => &mut self.forward,
+            Keycode::S | Keycode::Down => &mut self.backward,
+            Keycode::A | Keycode::Left => &mut self.left,
+            Keycode::D | Keycode::Right => &mut self.right,
+            Keycode::Space => &mut self.up,
+            Keycode::LShift => &mut self.down,
+            Keycode::Q => &mut self.roll_left,
+            Keycode::E => &mut self.roll_right,
+            Keycode::LCtrl => &mut self.fast,
+            _ => return false,
+        };
+        *key = pressed;
+        true
+    }
+
+    // x right, y up, z forward; each component is -1, 0 or 1.
+    pub fn direction(&self) -> Vec3 {
+        let axis = |pos: bool, neg: bool| pos as i32 as f32 - neg as i32 as f32;
+        Vec3::new(
+            axis(self.right, self.left),
+            axis(self.up, self.down),
+            axis(self.forward, self.backward),
+        )
+    }
+
+    pub fn roll(&self) -> f32 {
+        (self.roll_right as i32 - self.roll_left as i32) as f32
+    }
+
+    pub fn speed_scale(&self) -> f32 {
+        if self.fast {
+            4.0
+        } else {
+            1.0
+        }
+    }
+}
+
+// Mouse movement since the last update, in pixels.
+#[derive(Debug, Default, Clone, Copy)]
+struct MouseDelta {
+    x: f32,
+    y: f32,
+    wheel: f32,
+}
+
+impl MouseDelta {
+    fn take(&mut self) -> MouseDelta {
+        std::mem::take(self)
+    }
+}
+
+fn wheel_amount(y: i32, direction: MouseWheelDirection) -> f32 {
+    match direction {
+        MouseWheelDirection::Flipped => -y as f32,
+        _ => y as f32,
+    }
+}
+
+// Unconstrained flight: the mouse turns the camera around its own axes, Q/E
+// roll it, and Space/Shift move along its up axis.
+#[derive(Debug)]
+pub struct FreeFlyController {
+    pub keys: MoveKeys,
+    // World units per second.
+    pub speed: f32,
+    // Radians per pixel of mouse movement.
+    pub sensitivity: f32,
+    // Radians per second.
+    pub roll_speed: f32,
+    mouse: MouseDelta,
+}
+
+impl FreeFlyController {
+    pub fn new(speed: f32, sensitivity: f32) -> Self {
+        Self {
+            keys: MoveKeys::default(),
+            speed,
+            sensitivity,
+            roll_speed: std::f32::consts::FRAC_PI_2,
+            mouse: MouseDelta::default(),
+        }
+    }
+}
+
+impl CameraController for FreeFlyController {
+    fn process_events(&mut self, event: &Event) -> bool {
+        match event {
+            Event::MouseMotion { xrel, yrel, .. } => {
+                self.mouse.x += *xrel as f32;
+                self.mouse.y += *yrel as f32;
+                true
+            }
+            _ => self.keys.process_events(event),
+        }
+    }
+
+    fn update_camera(&mut self, camera: &mut Camera, dt: f32) {
+        let view = camera.view_mut();
+        let mouse = self.mouse.take();
+        let (forward, right, up) = basis(view, view.up);
+        let distance = (view.target - view.eye).mag().max(1.0);
+
+        let yaw = -mouse.x * self.sensitivity;
+        let pitch = -mouse.y * self.sensitivity;
+        let roll = self.keys.roll() * self.roll_speed * dt;
+        let forward = rotate_about(forward, up, yaw);
+        let right = rotate_about(right, up, yaw);
+        let forward = rotate_about(forward, right, pitch).normalized();
+        let up = rotate_about(rotate_about(up, right, pitch), forward, roll).normalized();
+        let right = forward.cross(up);
+
+        let direction = self.keys.direction();
+        let step = self.speed * self.keys.speed_scale() * dt;
+        view.eye += (right * direction.x + up * direction.y + forward * direction.z) * step;
+        view.target = view.eye + forward * distance;
+        view.up = up;
+    }
+
+    fn relative_mouse(&self) -> bool {
+        true
+    }
+}
+
+// Walking camera: yaw around the world up axis, pitch clamped short of the
+// poles, and WASD moving on the horizontal plane.
+#[derive(Debug)]
+pub struct FpsController {
+    pub keys: MoveKeys,
+    pub speed: f32,
+    pub sensitivity: f32,
+    pub max_pitch: f32,
+    pub world_up: Vec3,
+    mouse: MouseDelta,
+}
+
+impl FpsController {
+    pub fn new(speed: f32, sensitivity: f32) -> Self {
+        Self {
+            keys: MoveKeys::default(),
+            speed,
+            sensitivity,
+            max_pitch: 89f32.to_radians(),
+            world_up: Vec3::unit_y(),
+            mouse: MouseDelta::default(),
+        }
+    }
+}
+
+impl CameraController for FpsController {
+    fn process_events(&mut self, event: &Event) -> bool {
+        match event {
+            Event::MouseMotion { xrel, yrel, .. } => {
+                self.mouse.x += *xrel as f32;
+                self.mouse.y += *yrel as f32;
+                true
+            }
+            _ => self.keys.process_events(event),
+        }
+    }
+
+    fn update_camera(&mut self, camera: &mut Camera, dt: f32) {
+        let view = camera.view_mut();
+        let mouse = self.mouse.take();
+        let (yaw, pitch) = yaw_pitch(view, self.world_up);
+        let yaw = yaw - mouse.x * self.sensitivity;
+        let pitch = (pitch - mouse.y * self.sensitivity).clamp(-self.max_pitch, self.max_pitch);
+
+        let (flat_forward, forward) = yaw_pitch_forward(yaw, pitch, self.world_up);
+        let right = flat_forward.cross(self.world_up);
+
+        let direction = self.keys.direction();
+        let step = self.speed * self.keys.speed_scale() * dt;
+        view.eye += (right * direction.x + self.world_up * direction.y + flat_forward * direction.z) * step;
+        view.target = view.eye + forward;
+        view.up = self.world_up;
+    }
+
+    fn relative_mouse(&self) -> bool {
+        true
+    }
+}
+
+// Orbits `LookAt::target`: drag with the left button to turn around it, the
+// wheel zooms and WASD/Space/Shift move the target itself.
+#[derive(Debug)]
+pub struct OrbitController {
+    pub keys: MoveKeys,
+    pub speed: f32,
+    pub sensitivity: f32,
+    // Fraction of the distance zoomed per wheel step.
+    pub zoom_speed: f32,
+    pub min_distance: f32,
+    pub max_distance: f32,
+    pub max_pitch: f32,
+    pub world_up: Vec3,
+    dragging: bool,
+    mouse: MouseDelta,
+}
+
+impl OrbitController {
+    pub fn new(speed: f32, sensitivity: f32) -> Self {
+        Self {
+            keys: MoveKeys::default(),
+            speed,
+            sensitivity,
+            zoom_speed: 0.1,
+            min_distance: 0.5,
+            max_distance: 500.0,
+            max_pitch: 89f32.to_radians(),
+            world_up: Vec3::unit_y(),
+            dragging: false,
+            mouse: MouseDelta::default(),
+        }
+    }
+}
+
+impl CameraController for OrbitController {
+    fn process_events(&mut self, event: &Event) -> bool {
+        match event {
+            Event::MouseButtonDown { mouse_btn: MouseButton::Left, .. } => {
+                self.dragging = true;
+                true
+            }
+            Event::MouseButtonUp { mouse_btn: MouseButton::Left, .. } => {
+                self.dragging = false;
+                true
+            }
+            Event::MouseMotion { xrel, yrel, .. } if self.dragging => {
+                self.mouse.x += *xrel as f32;
+                self.mouse.y += *yrel as f32;
+                true
+            }
+            Event::MouseWheel { y, direction, .. } => {
+                self.mouse.wheel += wheel_amount(*y, *direction);
+                true
+            }
+            _ => self.keys.process_events(event),
+        }
+    }
+
+    fn update_camera(&mut self, camera: &mut Camera, dt: f32) {
+        let view = camera.view_mut();
+        let mouse = self.mouse.take();
+        let distance = (view.target - view.eye).mag();
+        let distance = (distance * (1.0 - self.zoom_speed).powf(mouse.wheel)).clamp(self.min_distance, self.max_distance);
+
+        // The scene follows the mouse, so the eye moves the other way around the target.
+        let (yaw, pitch) = yaw_pitch(view, self.world_up);
+        let yaw = yaw - mouse.x * self.sensitivity;
+        let pitch = (pitch - mouse.y * self.sensitivity).clamp(-self.max_pitch, self.max_pitch);
+        let (flat_forward, forward) = yaw_pitch_forward(yaw, pitch, self.world_up);
+        let right = flat_forward.cross(self.world_up);
+
+        let direction = self.keys.direction();
+        let step = self.speed * self.keys.speed_scale() * dt;
+        view.target += (right * direction.x + self.world_up * direction.y + flat_forward * direction.z) * step;
+        view.eye = view.target - forward * distance;
+        view.up = self.world_up;
+    }
+}
+
+// Right-handed rotation of `v` by `angle` around the unit vector `axis`.
+fn rotate_about(v: Vec3, axis: Vec3, angle: f32) -> Vec3 {
+    let (sin, cos) = angle.sin_cos();
+    v * cos + axis.cross(v) * sin + axis * axis.dot(v) * (1.0 - cos)
+}
+
+// Forward, right and up unit vectors of the view.
+fn basis(view: &LookAt, up: Vec3) -> (Vec3, Vec3, Vec3) {
+    let forward = (view.target - view.eye).normalized();
+    let mut right = forward.cross(up);
+    if right.mag_sq() <= f32::EPSILON {
+        right = forward.cross(Vec3::unit_z());
+    }
+    let right = right.normalized();
+    (forward, right, right.cross(forward))
+}
+
+// Yaw is measured from -Z towards -X around `world_up`, pitch up from the horizon.
+fn yaw_pitch(view: &LookAt, world_up: Vec3) -> (f32, f32) {
+    let forward = (view.target - view.eye).normalized();
+    let (x, z) = horizontal_axes(world_up);
+    let pitch = forward.dot(world_up).clamp(-1.0, 1.0).asin();
+    let yaw = (-forward.dot(x)).atan2(-forward.dot(z));
+    (yaw, pitch)
+}
+
+// The horizontal forward direction and the full forward direction.
+fn yaw_pitch_forward(yaw: f32, pitch: f32, world_up: Vec3) -> (Vec3, Vec3) {
+    let (x, z) = horizontal_axes(world_up);
+    let flat = -(x * yaw.sin() + z * yaw.cos());
+    (flat, flat * pitch.cos() + world_up * pitch.sin())
+}
+
+// Two axes spanning the plane perpendicular to `world_up`, matching X and Z
+// when `world_up` is +Y.
+fn horizontal_axes(world_up: Vec3) -> (Vec3, Vec3) {
+    let reference = if world_up.cross(Vec3::unit_x()).mag_sq() > f32::EPSILON {
+        Vec3::unit_x()
+    } else {
+        Vec3::unit_z()
+    };
+    let z = reference.cross(world_up).normalized();
+    (world_up.cross(z), z)
+}
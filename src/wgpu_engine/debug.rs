@@ -0,0 +1,473 @@
+use std::f32::consts::TAU;
+
+use ultraviolet::{Rotor3, Vec2, Vec3};
+
+use super::{
+    buffer::{create_buffer, grow_buffer},
+    camera::{Camera, DepthMode},
+    layouts::BindGroupLayouts,
+    model::Bounds,
+    text::{FontId, TextPlacement, TextRenderer, TextStyle},
+    texture::Texture,
+};
+
+// Segments per circle.
+const CIRCLE_SEGMENTS: usize = 32;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct DebugVertex {
+    position: [f32; 3],
+    color: [f32; 4],
+}
+
+#[derive(Debug, Clone, Copy)]
+struct DebugLine {
+    from: Vec3,
+    to: Vec3,
+    color: [f32; 4],
+    depth_test: bool,
+    // Seconds left to show it; it goes at the end of the frame it runs out.
+    remaining: f32,
+}
+
+#[derive(Debug, Clone)]
+struct DebugLabel {
+    position: Vec3,
+    text: String,
+    color: [f32; 4],
+    remaining: f32,
+}
+
+// Immediate mode lines for seeing what the code thinks is going on: bounds,
+// frusta, light ranges, paths. Shapes are added any time during a frame
+// through `WgpuEngine::debug_mut` and drawn over the scene of every view by
+// the `DebugRenderer`. `depth_test` and `duration` apply to the shapes added
+// while they are set; a duration of 0 shows a shape for the frame it was
+// added in only, longer ones count down with `update`.
+pub struct DebugDraw {
+    pub enabled: bool,
+    // Hidden behind the scene, or drawn on top of it.
+    pub depth_test: bool,
+    // Seconds.
+    pub duration: f32,
+    // Labels need a font and are left out without one. They are depth tested
+    // like all world text.
+    pub font: Option<FontId>,
+    // World units.
+    pub label_size: f32,
+    // How far `frustum` draws from the near plane, infinite far planes
+    // included.
+    pub frustum_distance: f32,
+    lines: Vec<DebugLine>,
+    labels: Vec<DebugLabel>,
+}
+
+impl Default for DebugDraw {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            depth_test: true,
+            duration: 0.0,
+            font: None,
+            label_size: 0.25,
+            frustum_distance: 1000.0,
+            lines: Vec::new(),
+            labels: Vec::new(),
+        }
+    }
+}
+
+impl DebugDraw {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn line(&mut self, from: Vec3, to: Vec3, color: [f32; 4]) {
+        // Frustum corners at infinity and the like can't be drawn.
+        let finite = |p: Vec3| p.x.is_finite() && p.y.is_finite() && p.z.is_finite();
+        if !finite(from) || !finite(to) {
+            return;
+        }
+        self.lines.push(DebugLine {
+            from,
+            to,
+            color,
+            depth_test: self.depth_test,
+            remaining: self.duration,
+        });
+    }
+
+    // Lines through `points`, back to the first with `closed`.
+    pub fn polyline(&mut self, points: &[Vec3], closed: bool, color: [f32; 4]) {
+        for pair in points.windows(2) {
+            self.line(pair[0], pair[1], color);
+        }
+        if let (true, Some(&first), Some(&last)) = (closed && points.len() > 2, points.first(), points.last()) {
+            self.line(last, first, color);
+        }
+    }
+
+    pub fn arrow(&mut self, from: Vec3, to: Vec3, color: [f32; 4]) {
+        self.line(from, to, color);
+        let direction = to - from;
+        let length = direction.mag();
+        if length <= 0.0 {
+            return;
+        }
+        let direction = direction / length;
+        let (side, up) = perpendiculars(direction);
+        let head = length * 0.15;
+        for offset in [side, -side, up, -up] {
+            self.line(to, to - direction * head + offset * head * 0.4, color);
+        }
+    }
+
+    pub fn ray(&mut self, origin: Vec3, direction: Vec3, length: f32, color: [f32; 4]) {
+        self.arrow(origin, origin + direction.normalized() * length, color);
+    }
+
+    // A box with corners in the order of `Camera::frustum_corners`: one face
+    // counter-clockwise, then the opposite face in the same order.
+    pub fn hexahedron(&mut self, corners: [Vec3; 8], color: [f32; 4]) {
+        for i in 0..4 {
+            let next = (i + 1) % 4;
+            self.line(corners[i], corners[next], color);
+            self.line(corners[i + 4], corners[next + 4], color);
+            self.line(corners[i], corners[i + 4], color);
+        }
+    }
+
+    pub fn wire_box(&mut self, min: Vec3, max: Vec3, color: [f32; 4]) {
+        self.hexahedron(
+            [
+                Vec3::new(min.x, min.y, min.z),
+                Vec3::new(max.x, min.y, min.z),
+                Vec3::new(max.x, max.y, min.z),
+                Vec3::new(min.x, max.y, min.z),
+                Vec3::new(min.x, min.y, max.z),
+                Vec3::new(max.x, min.y, max.z),
+                Vec3::new(max.x, max.y, max.z),
+                Vec3::new(min.x, max.y, max.z),
+            ],
+            color,
+        );
+    }
+
+    pub fn bounds(&mut self, bounds: &Bounds, color: [f32; 4]) {
+        self.wire_box(bounds.min, bounds.max, color);
+    }
+
+    // A box of `half_extents` turned by `rotation` around its center.
+    pub fn oriented_box(&mut self, center: Vec3, half_extents: Vec3, rotation: Rotor3, color: [f32; 4]) {
+        let corner = |x: f32, y: f32, z: f32| center + (half_extents * Vec3::new(x, y, z)).rotated_by(rotation);
+        self.hexahedron(
+            [
+                corner(-1.0, -1.0, -1.0),
+                corner(1.0, -1.0, -1.0),
+                corner(1.0, 1.0, -1.0),
+                corner(-1.0, 1.0, -1.0),
+                corner(-1.0, -1.0, 1.0),
+                corner(1.0, -1.0, 1.0),
+                corner(1.0, 1.0, 1.0),
+                corner(-1.0, 1.0, 1.0),
+            ],
+            color,
+        );
+    }
+
+    // What `camera` sees, up to `frustum_distance`.
+    pub fn frustum(&mut self, camera: &Camera, color: [f32; 4]) {
+        self.hexahedron(camera.view_projection().frustum_corners_within(self.frustum_distance), color);
+    }
+
+    pub fn circle(&mut self, center: Vec3, normal: Vec3, radius: f32, color: [f32; 4]) {
+        let (u, v) = perpendiculars(normal.normalized());
+        let points: Vec<Vec3> = (0..CIRCLE_SEGMENTS)
+            .map(|i| {
+                let (sin, cos) = (i as f32 / CIRCLE_SEGMENTS as f32 * TAU).sin_cos();
+                center + (u * cos + v * sin) * radius
+            })
+            .collect();
+        self.polyline(&points, true, color);
+    }
+
+    // Circles around the three axes, e.g. for a point light's range.
+    pub fn wire_sphere(&mut self, center: Vec3, radius: f32, color: [f32; 4]) {
+        for normal in [Vec3::unit_x(), Vec3::unit_y(), Vec3::unit_z()] {
+            self.circle(center, normal, radius, color);
+        }
+    }
+
+    // Red, green and blue arrows along the local X, Y and Z axes.
+    pub fn axes(&mut self, position: Vec3, rotation: Rotor3, size: f32) {
+        let axes = [
+            (Vec3::unit_x(), [1.0, 0.2, 0.2, 1.0]),
+            (Vec3::unit_y(), [0.2, 1.0, 0.2, 1.0]),
+            (Vec3::unit_z(), [0.2, 0.4, 1.0, 1.0]),
+        ];
+        for (axis, color) in axes {
+            self.arrow(position, position + axis.rotated_by(rotation) * size, color);
+        }
+    }
+
+    // `divisions` cells a side on the XZ plane, `size` world units across.
+    pub fn grid(&mut self, center: Vec3, size: f32, divisions: u32, color: [f32; 4]) {
+        let divisions = divisions.max(1);
+        let half = size * 0.5;
+        for i in 0..=divisions {
+            let t = i as f32 / divisions as f32 * size - half;
+            self.line(center + Vec3::new(t, 0.0, -half), center + Vec3::new(t, 0.0, half), color);
+            self.line(center + Vec3::new(-half, 0.0, t), center + Vec3::new(half, 0.0, t), color);
+        }
+    }
+
+    // Text facing the camera at `position`, see `font`.
+    pub fn label(&mut self, position: Vec3, text: &str, color: [f32; 4]) {
+        self.labels.push(DebugLabel {
+            position,
+            text: text.to_string(),
+            color,
+            remaining: self.duration,
+        });
+    }
+
+    // Counts down the shapes that were given a duration.
+    pub fn update(&mut self, dt: f32) {
+        for line in &mut self.lines {
+            line.remaining -= dt;
+        }
+        for label in &mut self.labels {
+            label.remaining -= dt;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.lines.clear();
+        self.labels.clear();
+    }
+
+    // Drops the shapes whose time is up.
+    pub fn finish_frame(&mut self) {
+        self.lines.retain(|line| line.remaining > 0.0);
+        self.labels.retain(|label| label.remaining > 0.0);
+    }
+}
+
+// Draws a `DebugDraw`'s shapes.
+pub struct DebugRenderer {
+    pipeline: wgpu::RenderPipeline,
+    reverse_z_pipeline: wgpu::RenderPipeline,
+    overlay_pipeline: wgpu::RenderPipeline,
+    vertex_buffer: wgpu::Buffer,
+    // Vertices of depth tested lines, the ones drawn on top follow them.
+    tested_vertices: u32,
+    vertex_count: u32,
+}
+
+impl DebugRenderer {
+    pub fn new(device: &wgpu::Device, layouts: &BindGroupLayouts, format: wgpu::TextureFormat) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("debug.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/debug.wgsl").into()),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Debug Pipeline Layout"),
+            bind_group_layouts: &[&layouts.camera],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |depth_compare| create_pipeline(device, &layout, &shader, format, depth_compare);
+
+        Self {
+            pipeline: pipeline(DepthMode::Standard.compare()),
+            reverse_z_pipeline: pipeline(DepthMode::Reversed.compare()),
+            overlay_pipeline: pipeline(wgpu::CompareFunction::Always),
+            vertex_buffer: create_buffer(device, "Debug Vertex Buffer", 4096, wgpu::BufferUsages::VERTEX),
+            tested_vertices: 0,
+            vertex_count: 0,
+        }
+    }
+
+    // Queues `debug`'s labels as text and uploads its lines, once per frame
+    // before any view draws them.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, text: &mut TextRenderer, debug: &DebugDraw) {
+        self.vertex_count = 0;
+        if !debug.enabled {
+            return;
+        }
+        if let Some(font) = debug.font {
+            for label in &debug.labels {
+                let style = TextStyle {
+                    color: label.color,
+                    anchor: Vec2::new(0.5, 1.0),
+                    sdf: true,
+                    ..TextStyle::new(font, debug.label_size)
+                };
+                text.draw(&label.text, TextPlacement::Billboard(label.position), &style);
+            }
+        }
+
+        let tested = debug.lines.iter().filter(|l| l.depth_test);
+        let overlay = debug.lines.iter().filter(|l| !l.depth_test);
+        let vertices: Vec<DebugVertex> = tested
+            .chain(overlay)
+            .flat_map(|line| {
+                [
+                    DebugVertex {
+                        position: line.from.into(),
+                        color: line.color,
+                    },
+                    DebugVertex {
+                        position: line.to.into(),
+                        color: line.color,
+                    },
+                ]
+            })
+            .collect();
+        self.tested_vertices = debug.lines.iter().filter(|l| l.depth_test).count() as u32 * 2;
+        self.vertex_count = vertices.len() as u32;
+        if vertices.is_empty() {
+            return;
+        }
+        grow_buffer(device, &mut self.vertex_buffer, "Debug Vertex Buffer", size_of_val(vertices.as_slice()) as u64);
+        queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, camera_bind_group: &'a wgpu::BindGroup, depth: DepthMode) {
+        if self.vertex_count == 0 {
+            return;
+        }
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        if self.tested_vertices > 0 {
+            render_pass.set_pipeline(match depth {
+                DepthMode::Standard => &self.pipeline,
+                DepthMode::Reversed => &self.reverse_z_pipeline,
+            });
+            render_pass.draw(0..self.tested_vertices, 0..1);
+        }
+        if self.vertex_count > self.tested_vertices {
+            render_pass.set_pipeline(&self.overlay_pipeline);
+            render_pass.draw(self.tested_vertices..self.vertex_count, 0..1);
+        }
+    }
+}
+
+// Two unit vectors at right angles to `direction` and each other.
+fn perpendiculars(direction: Vec3) -> (Vec3, Vec3) {
+    let helper = if direction.y.abs() < 0.9 { Vec3::unit_y() } else { Vec3::unit_x() };
+    let u = direction.cross(helper).normalized();
+    (u, direction.cross(u))
+}
+
+fn create_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+    depth_compare: wgpu::CompareFunction,
+) -> wgpu::RenderPipeline {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 2] = wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x4];
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Debug Pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            buffers: &[wgpu::VertexBufferLayout {
+                array_stride: size_of::<DebugVertex>() as wgpu::BufferAddress,
+                step_mode: wgpu::VertexStepMode::Vertex,
+                attributes: &ATTRIBUTES,
+            }],
+            compilation_options: Default::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: "fs_main",
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: Default::default(),
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::LineList,
+            ..Default::default()
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: Texture::DEPTH_FORMAT,
+            depth_write_enabled: false,
+            depth_compare,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+        cache: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: [f32; 4] = [1.0; 4];
+
+    fn line(debug: &mut DebugDraw) {
+        debug.line(Vec3::zero(), Vec3::unit_x(), WHITE);
+    }
+
+    #[test]
+    fn shapes_without_a_duration_last_one_frame() {
+        let mut debug = DebugDraw::new();
+        line(&mut debug);
+        debug.label(Vec3::zero(), "origin", WHITE);
+        debug.update(0.016);
+        assert_eq!((debug.lines.len(), debug.labels.len()), (1, 1));
+        debug.finish_frame();
+        assert_eq!((debug.lines.len(), debug.labels.len()), (0, 0));
+
+        // Even without an `update` in between.
+        line(&mut debug);
+        debug.finish_frame();
+        assert!(debug.lines.is_empty());
+    }
+
+    #[test]
+    fn shapes_with_a_duration_count_down() {
+        let mut debug = DebugDraw::new();
+        debug.duration = 0.05;
+        line(&mut debug);
+        debug.label(Vec3::zero(), "origin", WHITE);
+        debug.duration = 0.0;
+        line(&mut debug);
+
+        // The one frame shape goes, the timed ones stay until time is up.
+        debug.update(0.02);
+        debug.finish_frame();
+        assert_eq!((debug.lines.len(), debug.labels.len()), (1, 1));
+        debug.update(0.02);
+        debug.finish_frame();
+        assert_eq!((debug.lines.len(), debug.labels.len()), (1, 1));
+        debug.update(0.02);
+        debug.finish_frame();
+        assert_eq!((debug.lines.len(), debug.labels.len()), (0, 0));
+    }
+
+    #[test]
+    fn settings_apply_to_shapes_added_while_set() {
+        let mut debug = DebugDraw::new();
+        line(&mut debug);
+        debug.depth_test = false;
+        line(&mut debug);
+        assert!(debug.lines[0].depth_test);
+        assert!(!debug.lines[1].depth_test);
+
+        // Lines that can't be drawn are dropped, `clear` drops the rest.
+        debug.line(Vec3::zero(), Vec3::broadcast(f32::INFINITY), WHITE);
+        assert_eq!(debug.lines.len(), 2);
+        debug.clear();
+        assert!(debug.lines.is_empty());
+    }
+}
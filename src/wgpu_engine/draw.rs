@@ -1,5 +1,5 @@
 use std::ops::Range;
-use super::{instance::InstanceManager, model::{Material, Mesh, Model}};
+use super::{dynamic_mesh::DynamicMesh, indirect::{DrawIndexedIndirect, IndirectRenderer}, instance::InstanceManager, model::{Material, Mesh, Model}};
 
 pub trait DrawModel<'a> {
     #[allow(unused)]
@@ -17,6 +17,24 @@ pub trait DrawModel<'a> {
         camera_bind_group: &'a wgpu::BindGroup,
     );
 
+    // Draws the mesh once at its `transform`.
+    fn draw_dynamic_mesh(
+        &mut self,
+        mesh: &'a DynamicMesh,
+        material: &'a Material,
+        camera_bind_group: &'a wgpu::BindGroup,
+    );
+    // `instance_buffer` holds the `InstanceRaw`s for `instances`.
+    #[allow(unused)]
+    fn draw_dynamic_mesh_instanced(
+        &mut self,
+        mesh: &'a DynamicMesh,
+        material: &'a Material,
+        instance_buffer: &'a wgpu::Buffer,
+        instances: Range<u32>,
+        camera_bind_group: &'a wgpu::BindGroup,
+    );
+
     #[allow(unused)]
     fn draw_model(&mut self, model: &'a Model, camera_bind_group: &'a wgpu::BindGroup);
     fn draw_model_instanced(
@@ -26,7 +44,34 @@ pub trait DrawModel<'a> {
         camera_bind_group: &'a wgpu::BindGroup,
     );
 
+    #[allow(unused)]
     fn draw_instances(&mut self, instance_manager: &'a InstanceManager, camera_bind_group: &'a wgpu::BindGroup);
+
+    fn draw_mesh_indirect(
+        &mut self,
+        mesh: &'a Mesh,
+        material: &'a Material,
+        indirect_buffer: &'a wgpu::Buffer,
+        indirect_offset: wgpu::BufferAddress,
+        camera_bind_group: &'a wgpu::BindGroup,
+    );
+    // Requires Features::MULTI_DRAW_INDIRECT
+    fn multi_draw_mesh_indirect(
+        &mut self,
+        mesh: &'a Mesh,
+        material: &'a Material,
+        indirect_buffer: &'a wgpu::Buffer,
+        indirect_offset: wgpu::BufferAddress,
+        count: u32,
+        camera_bind_group: &'a wgpu::BindGroup,
+    );
+
+    fn draw_instances_indirect(
+        &mut self,
+        renderer: &'a IndirectRenderer,
+        to_draw: &'a [InstanceManager],
+        camera_bind_group: &'a wgpu::BindGroup,
+    );
 }
 
 impl<'a, 'b> DrawModel<'b> for wgpu::RenderPass<'a>
@@ -56,6 +101,34 @@ where
         self.draw_indexed(0..mesh.num_elements, 0, instances);
     }
 
+    fn draw_dynamic_mesh(
+        &mut self,
+        mesh: &'b DynamicMesh,
+        material: &'b Material,
+        camera_bind_group: &'b wgpu::BindGroup,
+    ) {
+        self.draw_dynamic_mesh_instanced(mesh, material, mesh.instance_buffer(), 0..1, camera_bind_group);
+    }
+
+    fn draw_dynamic_mesh_instanced(
+        &mut self,
+        mesh: &'b DynamicMesh,
+        material: &'b Material,
+        instance_buffer: &'b wgpu::Buffer,
+        instances: Range<u32>,
+        camera_bind_group: &'b wgpu::BindGroup,
+    ) {
+        if mesh.num_elements() == 0 {
+            return;
+        }
+        self.set_vertex_buffer(0, mesh.vertex_buffer().slice(..));
+        self.set_vertex_buffer(1, instance_buffer.slice(..));
+        self.set_index_buffer(mesh.index_buffer().slice(..), wgpu::IndexFormat::Uint32);
+        self.set_bind_group(0, &material.bind_group, &[]);
+        self.set_bind_group(1, camera_bind_group, &[]);
+        self.draw_indexed(0..mesh.num_elements(), 0, instances);
+    }
+
     fn draw_model(&mut self, model: &'b Model, camera_bind_group: &'b wgpu::BindGroup) {
         self.draw_model_instanced(model, 0..1, camera_bind_group);
     }
@@ -76,4 +149,71 @@ where
         self.set_vertex_buffer(1, instance_manager.instance_buffer.slice(..));
         self.draw_model_instanced(&instance_manager.model, 0..instance_manager.instances.len() as u32, camera_bind_group);
     }
+
+    fn draw_mesh_indirect(
+        &mut self,
+        mesh: &'b Mesh,
+        material: &'b Material,
+        indirect_buffer: &'b wgpu::Buffer,
+        indirect_offset: wgpu::BufferAddress,
+        camera_bind_group: &'b wgpu::BindGroup,
+    ) {
+        self.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+        self.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        self.set_bind_group(0, &material.bind_group, &[]);
+        self.set_bind_group(1, camera_bind_group, &[]);
+        self.draw_indexed_indirect(indirect_buffer, indirect_offset);
+    }
+
+    fn multi_draw_mesh_indirect(
+        &mut self,
+        mesh: &'b Mesh,
+        material: &'b Material,
+        indirect_buffer: &'b wgpu::Buffer,
+        indirect_offset: wgpu::BufferAddress,
+        count: u32,
+        camera_bind_group: &'b wgpu::BindGroup,
+    ) {
+        self.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+        self.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        self.set_bind_group(0, &material.bind_group, &[]);
+        self.set_bind_group(1, camera_bind_group, &[]);
+        self.multi_draw_indexed_indirect(indirect_buffer, indirect_offset, count);
+    }
+
+    fn draw_instances_indirect(
+        &mut self,
+        renderer: &'b IndirectRenderer,
+        to_draw: &'b [InstanceManager],
+        camera_bind_group: &'b wgpu::BindGroup,
+    ) {
+        for group in renderer.groups() {
+            let model = &to_draw[group.manager].model;
+            let mesh = &model.lod_meshes(group.lod)[group.mesh];
+            let material = &model.materials[mesh.material];
+            if renderer.is_gpu_driven() {
+                self.set_vertex_buffer(1, renderer.visible_buffer().slice(..));
+                self.multi_draw_mesh_indirect(
+                    mesh,
+                    material,
+                    renderer.args_buffer(),
+                    group.first_draw as u64 * DrawIndexedIndirect::SIZE,
+                    group.draw_count,
+                    camera_bind_group,
+                );
+            } else {
+                // Without first_instance support each draw binds its own slice of instances.
+                for draw in group.first_draw..group.first_draw + group.draw_count {
+                    self.set_vertex_buffer(1, renderer.visible_buffer().slice(renderer.visible_offset(draw)..));
+                    self.draw_mesh_indirect(
+                        mesh,
+                        material,
+                        renderer.args_buffer(),
+                        draw as u64 * DrawIndexedIndirect::SIZE,
+                        camera_bind_group,
+                    );
+                }
+            }
+        }
+    }
 }
\ No newline at end of file
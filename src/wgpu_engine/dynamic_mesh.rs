@@ -0,0 +1,183 @@
+use std::ops::Range;
+
+use anyhow::{anyhow, Result};
+
+use super::{
+    buffer::{create_buffer, grow_buffer},
+    instance::{InstanceAble, InstanceRaw},
+    model::{Bounds, MeshData, ModelVertex},
+};
+use crate::transform::Transform;
+
+const INITIAL_CAPACITY: u64 = 1024;
+
+// Handle to a dynamic mesh owned by the `WgpuEngine`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DynamicMeshId(pub(super) u32);
+
+// One set of GPU buffers. Edits are tracked per slot so that a slot which was
+// skipped while other frames were in flight catches up when it comes around.
+struct Slot {
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    // `transform` as of the flush that made this slot current.
+    instance_buffer: wgpu::Buffer,
+    vertex_dirty: Option<Range<usize>>,
+    index_dirty: Option<Range<usize>>,
+    num_elements: u32,
+}
+
+// A mesh rebuilt or edited at runtime. Geometry lives on the CPU and is
+// streamed into one of `buffering` buffer sets each frame, so the GPU can
+// still read the previous frames' buffers while the next one is written.
+pub struct DynamicMesh {
+    pub name: String,
+    pub material: usize,
+    // Where the mesh is drawn by `DrawModel::draw_dynamic_mesh`.
+    pub transform: Transform,
+    data: MeshData,
+    slots: Vec<Slot>,
+    current: usize,
+}
+
+impl DynamicMesh {
+    pub fn new(device: &wgpu::Device, name: &str, material: usize, buffering: usize) -> Self {
+        let slots = (0..buffering.max(1))
+            .map(|i| Slot {
+                vertex_buffer: create_buffer(device, &format!("{:?} Dynamic Vertex Buffer {}", name, i), INITIAL_CAPACITY, wgpu::BufferUsages::VERTEX),
+                index_buffer: create_buffer(device, &format!("{:?} Dynamic Index Buffer {}", name, i), INITIAL_CAPACITY, wgpu::BufferUsages::INDEX),
+                instance_buffer: create_buffer(
+                    device,
+                    &format!("{:?} Dynamic Instance Buffer {}", name, i),
+                    size_of::<InstanceRaw>() as u64,
+                    wgpu::BufferUsages::VERTEX,
+                ),
+                vertex_dirty: None,
+                index_dirty: None,
+                num_elements: 0,
+            })
+            .collect();
+
+        Self {
+            name: name.to_string(),
+            material,
+            transform: Transform::default(),
+            data: MeshData::default(),
+            slots,
+            current: 0,
+        }
+    }
+
+    pub fn data(&self) -> &MeshData {
+        &self.data
+    }
+
+    pub fn bounds(&self) -> Bounds {
+        self.data.bounds()
+    }
+
+    pub fn set(&mut self, data: MeshData) {
+        self.data = data;
+        self.mark_all_dirty();
+    }
+
+    pub fn clear(&mut self) {
+        self.data.vertices.clear();
+        self.data.indices.clear();
+        self.mark_all_dirty();
+    }
+
+    // Appends geometry, `indices` are relative to `vertices`.
+    pub fn push(&mut self, vertices: &[ModelVertex], indices: &[u32]) {
+        let vertex_start = self.data.vertices.len();
+        let index_start = self.data.indices.len();
+        self.data.vertices.extend_from_slice(vertices);
+        self.data.indices.extend(indices.iter().map(|i| i + vertex_start as u32));
+        self.mark_dirty(vertex_start..self.data.vertices.len(), index_start..self.data.indices.len());
+    }
+
+    pub fn update_vertices(&mut self, offset: usize, vertices: &[ModelVertex]) -> Result<()> {
+        let range = offset..offset + vertices.len();
+        if range.end > self.data.vertices.len() {
+            return Err(anyhow!("Vertex range {:?} out of bounds", range));
+        }
+        self.data.vertices[range.clone()].copy_from_slice(vertices);
+        self.mark_dirty(range, 0..0);
+        Ok(())
+    }
+
+    pub fn update_indices(&mut self, offset: usize, indices: &[u32]) -> Result<()> {
+        let range = offset..offset + indices.len();
+        if range.end > self.data.indices.len() {
+            return Err(anyhow!("Index range {:?} out of bounds", range));
+        }
+        self.data.indices[range.clone()].copy_from_slice(indices);
+        self.mark_dirty(0..0, range);
+        Ok(())
+    }
+
+    // Moves on to the next buffer set and uploads whatever it is missing.
+    // Call once per frame before drawing.
+    pub fn flush(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        self.current = (self.current + 1) % self.slots.len();
+        let slot = &mut self.slots[self.current];
+
+        let vertices: &[u8] = bytemuck::cast_slice(&self.data.vertices);
+        if grow_buffer(device, &mut slot.vertex_buffer, "Dynamic Vertex Buffer", vertices.len() as u64) {
+            slot.vertex_dirty = Some(0..self.data.vertices.len());
+        }
+        if let Some(range) = slot.vertex_dirty.take() {
+            let size = size_of::<ModelVertex>();
+            queue.write_buffer(&slot.vertex_buffer, (range.start * size) as u64, &vertices[range.start * size..range.end * size]);
+        }
+
+        let indices: &[u8] = bytemuck::cast_slice(&self.data.indices);
+        if grow_buffer(device, &mut slot.index_buffer, "Dynamic Index Buffer", indices.len() as u64) {
+            slot.index_dirty = Some(0..self.data.indices.len());
+        }
+        if let Some(range) = slot.index_dirty.take() {
+            let size = size_of::<u32>();
+            queue.write_buffer(&slot.index_buffer, (range.start * size) as u64, &indices[range.start * size..range.end * size]);
+        }
+
+        slot.num_elements = self.data.indices.len() as u32;
+        queue.write_buffer(&slot.instance_buffer, 0, bytemuck::bytes_of(&self.transform.to_raw()));
+    }
+
+    pub fn vertex_buffer(&self) -> &wgpu::Buffer {
+        &self.slots[self.current].vertex_buffer
+    }
+
+    pub fn index_buffer(&self) -> &wgpu::Buffer {
+        &self.slots[self.current].index_buffer
+    }
+
+    pub fn instance_buffer(&self) -> &wgpu::Buffer {
+        &self.slots[self.current].instance_buffer
+    }
+
+    pub fn num_elements(&self) -> u32 {
+        self.slots[self.current].num_elements
+    }
+
+    fn mark_dirty(&mut self, vertices: Range<usize>, indices: Range<usize>) {
+        for slot in self.slots.iter_mut() {
+            merge_range(&mut slot.vertex_dirty, vertices.clone());
+            merge_range(&mut slot.index_dirty, indices.clone());
+        }
+    }
+
+    fn mark_all_dirty(&mut self) {
+        self.mark_dirty(0..self.data.vertices.len(), 0..self.data.indices.len());
+    }
+}
+
+fn merge_range(dirty: &mut Option<Range<usize>>, range: Range<usize>) {
+    if range.is_empty() {
+        return;
+    }
+    *dirty = Some(match dirty.take() {
+        Some(d) => d.start.min(range.start)..d.end.max(range.end),
+        None => range,
+    });
+}
@@ -0,0 +1,471 @@
+use std::sync::Arc;
+
+use ab_glyph::{point, OutlineCurve, OutlinedGlyph, PxScaleFactor};
+use anyhow::{anyhow, Result};
+use owned_ttf_parser::{self as ttf, gpos::PairAdjustment, gpos::PositioningSubtable, AsFaceRef, OwnedFace};
+use ultraviolet::Vec2;
+
+// Glyph index of a font, 0 is the font's "missing character" glyph.
+pub type GlyphId = u16;
+
+// One step of a glyph outline, in font units with y up.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PathCommand {
+    MoveTo(Vec2),
+    LineTo(Vec2),
+    // Control point, end point.
+    QuadTo(Vec2, Vec2),
+    // Two control points, end point. Only CFF outlines have these.
+    CurveTo(Vec2, Vec2, Vec2),
+    Close,
+}
+
+// A rasterized glyph: one byte of coverage, or of distance for SDF glyphs,
+// per pixel in rows from the top.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct GlyphBitmap {
+    pub width: u32,
+    pub height: u32,
+    // Pixels from the pen position to the bitmap's left edge, and up from the
+    // baseline to its top edge.
+    pub left: f32,
+    pub top: f32,
+    pub data: Vec<u8>,
+}
+
+// A font from a .ttf or .otf file, or the first font of a .ttc collection,
+// with TrueType or CFF outlines. Kerning comes from pair adjustments of the
+// GPOS `kern` feature, or the `kern` table without them; no other shaping is
+// done, so scripts that need it come out as separate glyphs. Clones share
+// the font's data.
+#[derive(Debug, Clone)]
+pub struct Font {
+    face: Arc<OwnedFace>,
+    pub units_per_em: f32,
+    // Font units above and below (negative) the baseline, and between lines.
+    pub ascender: f32,
+    pub descender: f32,
+    pub line_gap: f32,
+    // GPOS lookups of the `kern` feature with pair adjustments.
+    kern_lookups: Vec<u16>,
+}
+
+impl Font {
+    pub fn from_bytes(data: Vec<u8>) -> Result<Font> {
+        let face = OwnedFace::from_vec(data, 0).map_err(|e| anyhow!("Not a TrueType or OpenType font: {}", e))?;
+        let tables = face.as_face_ref().tables();
+        let unicode = tables.cmap.is_some_and(|cmap| cmap.subtables.into_iter().any(|s| s.is_unicode()));
+        if !unicode {
+            return Err(anyhow!("Font has no Unicode character map"));
+        }
+
+        let mut kern_lookups = Vec::new();
+        if let Some(gpos) = tables.gpos {
+            for feature in gpos.features.into_iter().filter(|f| f.tag == ttf::Tag::from_bytes(b"kern")) {
+                kern_lookups.extend(feature.lookup_indices);
+            }
+            kern_lookups.sort_unstable();
+            kern_lookups.dedup();
+            kern_lookups.retain(|&i| {
+                gpos.lookups.get(i).is_some_and(|lookup| {
+                    lookup.subtables.into_iter::<PositioningSubtable>().any(|s| matches!(s, PositioningSubtable::Pair(_)))
+                })
+            });
+        }
+
+        let face_ref = face.as_face_ref();
+        Ok(Font {
+            units_per_em: face_ref.units_per_em() as f32,
+            ascender: face_ref.ascender() as f32,
+            descender: face_ref.descender() as f32,
+            line_gap: face_ref.line_gap() as f32,
+            kern_lookups,
+            face: Arc::new(face),
+        })
+    }
+
+    fn face(&self) -> &ttf::Face<'_> {
+        self.face.as_face_ref()
+    }
+
+    pub fn glyph_count(&self) -> u16 {
+        self.face().number_of_glyphs()
+    }
+
+    // The glyph drawn for `c`, 0 if the font doesn't have one.
+    pub fn glyph_index(&self, c: char) -> GlyphId {
+        self.face().glyph_index(c).map_or(0, |g| g.0)
+    }
+
+    // Font units the pen moves on after `glyph`.
+    pub fn advance(&self, glyph: GlyphId) -> f32 {
+        self.face().glyph_hor_advance(ttf::GlyphId(glyph)).unwrap_or(0) as f32
+    }
+
+    // Font units added to the advance of `left` when `right` follows it.
+    pub fn kerning(&self, left: GlyphId, right: GlyphId) -> f32 {
+        let (left, right) = (ttf::GlyphId(left), ttf::GlyphId(right));
+        let tables = self.face().tables();
+        // Fonts with both repeat the `kern` pairs in GPOS, which wins.
+        if let Some(gpos) = tables.gpos.filter(|_| !self.kern_lookups.is_empty()) {
+            // The first subtable of a lookup covering the pair is the one that applies.
+            return self
+                .kern_lookups
+                .iter()
+                .filter_map(|&i| gpos.lookups.get(i))
+                .filter_map(|lookup| {
+                    lookup
+                        .subtables
+                        .into_iter::<PositioningSubtable>()
+                        .find_map(|subtable| pair_adjustment(subtable, left, right))
+                })
+                .map(f32::from)
+                .sum();
+        }
+        tables
+            .kern
+            .into_iter()
+            .flat_map(|kern| kern.subtables)
+            .filter(|s| s.horizontal && !s.variable && !s.has_cross_stream)
+            .find_map(|s| s.glyphs_kerning(left, right))
+            .map_or(0.0, f32::from)
+    }
+
+    // Pixels per font unit for text `size` pixels high, the em square.
+    pub fn scale(&self, size: f32) -> f32 {
+        size / self.units_per_em
+    }
+
+    // Font units from one baseline to the next.
+    pub fn line_height(&self) -> f32 {
+        self.ascender - self.descender + self.line_gap
+    }
+
+    // The glyph's contours, empty for glyphs like the space.
+    pub fn outline(&self, glyph: GlyphId) -> Result<Vec<PathCommand>> {
+        Ok(self.outline_with_bounds(glyph)?.0)
+    }
+
+    fn outline_with_bounds(&self, glyph: GlyphId) -> Result<(Vec<PathCommand>, Option<ttf::Rect>)> {
+        if glyph >= self.glyph_count() {
+            return Err(anyhow!("Glyph {} is not in the font", glyph));
+        }
+        let mut path = PathBuilder(Vec::new());
+        let bounds = self.face().outline_glyph(ttf::GlyphId(glyph), &mut path);
+        Ok((path.0, bounds))
+    }
+
+    // Rasterizes `glyph` at `size` pixels per em. With `sdf_spread` the
+    // bitmap holds signed distances instead of coverage: 128 on the outline,
+    // 255 `sdf_spread` pixels inside it and 0 as far outside. Empty glyphs
+    // give an empty bitmap.
+    pub fn rasterize(&self, glyph: GlyphId, size: f32, sdf_spread: Option<f32>) -> Result<GlyphBitmap> {
+        let (path, bounds) = match self.outline_with_bounds(glyph)? {
+            (path, Some(bounds)) if !path.is_empty() => (path, bounds),
+            _ => return Ok(GlyphBitmap::default()),
+        };
+        let scale = self.scale(size);
+        let outline = ab_glyph::Outline {
+            // Top left and bottom right, y up.
+            bounds: ab_glyph::Rect {
+                min: point(bounds.x_min as f32, bounds.y_max as f32),
+                max: point(bounds.x_max as f32, bounds.y_min as f32),
+            },
+            curves: curves(&path),
+        };
+        let glyph = OutlinedGlyph::new(
+            ab_glyph::GlyphId(glyph).with_scale(size),
+            outline,
+            PxScaleFactor {
+                horizontal: scale,
+                vertical: scale,
+            },
+        );
+
+        // Pixels from the pen position, y down.
+        let pixels = glyph.px_bounds();
+        let pad = sdf_spread.map_or(1, |spread| spread.ceil() as u32 + 1);
+        let width = pixels.width() as u32 + pad * 2;
+        let height = pixels.height() as u32 + pad * 2;
+        let mut data = vec![0; (width * height) as usize];
+        glyph.draw(|x, y, c| data[((y + pad) * width + x + pad) as usize] = (c.min(1.0) * 255.0).round() as u8);
+        if let Some(spread) = sdf_spread {
+            data = signed_distances(&data, width as usize, height as usize, spread);
+        }
+        Ok(GlyphBitmap {
+            width,
+            height,
+            left: pixels.min.x - pad as f32,
+            top: -pixels.min.y + pad as f32,
+            data,
+        })
+    }
+}
+
+// The x advance adjustment of the first glyph of the pair, if `subtable`
+// covers it.
+fn pair_adjustment(subtable: PositioningSubtable, left: ttf::GlyphId, right: ttf::GlyphId) -> Option<i16> {
+    let values = match subtable {
+        PositioningSubtable::Pair(PairAdjustment::Format1 { coverage, sets }) => sets.get(coverage.get(left)?)?.get(right)?,
+        PositioningSubtable::Pair(PairAdjustment::Format2 { coverage, classes, matrix }) => {
+            coverage.get(left)?;
+            matrix.get((classes.0.get(left), classes.1.get(right)))?
+        }
+        _ => return None,
+    };
+    Some(values.0.x_advance)
+}
+
+struct PathBuilder(Vec<PathCommand>);
+
+impl ttf::OutlineBuilder for PathBuilder {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.0.push(PathCommand::MoveTo(Vec2::new(x, y)));
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.0.push(PathCommand::LineTo(Vec2::new(x, y)));
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        self.0.push(PathCommand::QuadTo(Vec2::new(x1, y1), Vec2::new(x, y)));
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        self.0.push(PathCommand::CurveTo(Vec2::new(x1, y1), Vec2::new(x2, y2), Vec2::new(x, y)));
+    }
+
+    fn close(&mut self) {
+        self.0.push(PathCommand::Close);
+    }
+}
+
+// The segments of `path` for ab_glyph, every contour closed.
+fn curves(path: &[PathCommand]) -> Vec<OutlineCurve> {
+    let to_point = |p: Vec2| point(p.x, p.y);
+    let mut curves = Vec::new();
+    let (mut start, mut current) = (Vec2::zero(), Vec2::zero());
+    let close = |curves: &mut Vec<OutlineCurve>, start: Vec2, current: Vec2| {
+        if current != start {
+            curves.push(OutlineCurve::Line(to_point(current), to_point(start)));
+        }
+    };
+    for command in path {
+        match *command {
+            PathCommand::MoveTo(p) => {
+                close(&mut curves, start, current);
+                start = p;
+                current = p;
+            }
+            PathCommand::LineTo(p) => {
+                curves.push(OutlineCurve::Line(to_point(current), to_point(p)));
+                current = p;
+            }
+            PathCommand::QuadTo(c, p) => {
+                curves.push(OutlineCurve::Quad(to_point(current), to_point(c), to_point(p)));
+                current = p;
+            }
+            PathCommand::CurveTo(c1, c2, p) => {
+                curves.push(OutlineCurve::Cubic(to_point(current), to_point(c1), to_point(c2), to_point(p)));
+                current = p;
+            }
+            PathCommand::Close => {
+                close(&mut curves, start, current);
+                current = start;
+            }
+        }
+    }
+    close(&mut curves, start, current);
+    curves
+}
+
+// Signed distances to the outline from its coverage, as in TinySDF: each
+// pixel's squared distance to the nearest pixel inside, and to the nearest
+// outside, with partly covered pixels counted as that far from the edge.
+fn signed_distances(coverage: &[u8], width: usize, height: usize, spread: f32) -> Vec<u8> {
+    const FAR: f64 = 1e20;
+    let mut to_inside: Vec<f64> = coverage
+        .iter()
+        .map(|&c| match c as f64 / 255.0 {
+            c if c >= 1.0 => 0.0,
+            c if c <= 0.0 => FAR,
+            c => (0.5 - c).max(0.0).powi(2),
+        })
+        .collect();
+    let mut to_outside: Vec<f64> = coverage
+        .iter()
+        .map(|&c| match c as f64 / 255.0 {
+            c if c >= 1.0 => FAR,
+            c if c <= 0.0 => 0.0,
+            c => (c - 0.5).max(0.0).powi(2),
+        })
+        .collect();
+    distance_transform(&mut to_inside, width, height);
+    distance_transform(&mut to_outside, width, height);
+
+    to_inside
+        .iter()
+        .zip(&to_outside)
+        .map(|(&to_inside, &to_outside)| {
+            // Positive inside the outline.
+            let distance = to_outside.sqrt() - to_inside.sqrt();
+            ((0.5 + distance / (2.0 * spread as f64)).clamp(0.0, 1.0) * 255.0).round() as u8
+        })
+        .collect()
+}
+
+// Replaces each value of the grid with the smallest of
+// `value + squared distance` over all pixels: columns, then rows.
+fn distance_transform(grid: &mut [f64], width: usize, height: usize) {
+    let size = width.max(height);
+    let mut line = vec![0.0; size];
+    let mut out = vec![0.0; size];
+    let mut parabolas = vec![0; size];
+    let mut bounds = vec![0.0; size + 1];
+    for x in 0..width {
+        for y in 0..height {
+            line[y] = grid[y * width + x];
+        }
+        distance_transform_1d(&line[..height], &mut out, &mut parabolas, &mut bounds);
+        for y in 0..height {
+            grid[y * width + x] = out[y];
+        }
+    }
+    for y in 0..height {
+        line[..width].copy_from_slice(&grid[y * width..(y + 1) * width]);
+        distance_transform_1d(&line[..width], &mut out, &mut parabolas, &mut bounds);
+        grid[y * width..(y + 1) * width].copy_from_slice(&out[..width]);
+    }
+}
+
+// Felzenszwalb and Huttenlocher's lower envelope of the parabolas rooted at
+// each value of `f`.
+fn distance_transform_1d(f: &[f64], out: &mut [f64], parabolas: &mut [usize], bounds: &mut [f64]) {
+    let mut k = 0;
+    parabolas[0] = 0;
+    bounds[0] = f64::NEG_INFINITY;
+    bounds[1] = f64::INFINITY;
+    for q in 1..f.len() {
+        let mut s;
+        loop {
+            let r = parabolas[k];
+            // The far values first, they would swallow the squares.
+            s = (f[q] - f[r] + (q * q - r * r) as f64) / (2 * (q - r)) as f64;
+            // The first bound is minus infinity, so this stops at k = 0.
+            if s > bounds[k] {
+                break;
+            }
+            k -= 1;
+        }
+        k += 1;
+        parabolas[k] = q;
+        bounds[k] = s;
+        bounds[k + 1] = f64::INFINITY;
+    }
+    k = 0;
+    for (q, out) in out.iter_mut().enumerate().take(f.len()) {
+        while bounds[k + 1] < q as f64 {
+            k += 1;
+        }
+        let r = parabolas[k];
+        *out = (q as f64 - r as f64).powi(2) + f[r];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn font(data: &[u8]) -> Font {
+        Font::from_bytes(data.to_vec()).unwrap()
+    }
+
+    #[test]
+    fn cmap_maps_characters_to_glyphs() {
+        let hack = font(epaint_default_fonts::HACK_REGULAR);
+        let a = hack.glyph_index('A');
+        assert_ne!(a, 0);
+        assert_ne!(hack.glyph_index('B'), a);
+        assert_eq!(hack.glyph_index('\u{E000}'), 0);
+        assert!(hack.advance(a) > 0.0);
+
+        // Beyond ASCII, in the Basic Multilingual Plane and past it.
+        let ubuntu = font(epaint_default_fonts::UBUNTU_LIGHT);
+        for c in ['ü', 'п', '€'] {
+            assert_ne!(ubuntu.glyph_index(c), 0, "{}", c);
+        }
+        let emoji = font(epaint_default_fonts::NOTO_EMOJI_REGULAR);
+        assert_ne!(emoji.glyph_index('😀'), 0);
+    }
+
+    #[test]
+    fn kerning_moves_pairs_closer() {
+        let ubuntu = font(epaint_default_fonts::UBUNTU_LIGHT);
+        let (a, v) = (ubuntu.glyph_index('A'), ubuntu.glyph_index('V'));
+        assert!(ubuntu.kerning(a, v) < 0.0);
+
+        // Monospace.
+        let hack = font(epaint_default_fonts::HACK_REGULAR);
+        assert_eq!(hack.kerning(hack.glyph_index('A'), hack.glyph_index('V')), 0.0);
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert!(Font::from_bytes(b"not a font".to_vec()).is_err());
+        assert!(font(epaint_default_fonts::HACK_REGULAR).outline(u16::MAX).is_err());
+    }
+
+    #[test]
+    fn cff_outlines_have_cubic_curves() {
+        let cff = font(include_bytes!("../../tests/fixtures/fonts/cff-square.otf"));
+        assert_eq!((cff.units_per_em, cff.ascender, cff.descender), (1000.0, 800.0, -200.0));
+        let glyph = cff.glyph_index('A');
+        assert_eq!(glyph, 1);
+        assert_eq!(cff.advance(glyph), 600.0);
+
+        let outline = cff.outline(glyph).unwrap();
+        assert_eq!(outline.first(), Some(&PathCommand::MoveTo(Vec2::new(100.0, 0.0))));
+        assert!(outline.contains(&PathCommand::CurveTo(
+            Vec2::new(400.0, 500.0),
+            Vec2::new(200.0, 500.0),
+            Vec2::new(100.0, 400.0)
+        )));
+        assert!(cff.outline(0).unwrap().is_empty());
+        assert_eq!(cff.rasterize(0, 48.0, None).unwrap(), GlyphBitmap::default());
+    }
+
+    #[test]
+    fn coverage_fills_the_glyph() {
+        // 400 by 500 units with the curve's control points: 40 by 50 pixels at
+        // 100 per em, and a pixel of padding.
+        let cff = font(include_bytes!("../../tests/fixtures/fonts/cff-square.otf"));
+        let bitmap = cff.rasterize(1, 100.0, None).unwrap();
+        assert_eq!((bitmap.width, bitmap.height), (42, 52));
+        assert_eq!((bitmap.left, bitmap.top), (9.0, 51.0));
+        let at = |x: u32, y: u32| bitmap.data[(y * bitmap.width + x) as usize];
+        assert_eq!(at(0, 0), 0);
+        assert_eq!(at(21, 25), 255);
+        // The rounded top leaves the corners empty.
+        assert_eq!(at(1, 2), 0);
+        assert_eq!(at(1, 40), 255);
+    }
+
+    #[test]
+    fn distances_are_signed_around_the_outline() {
+        let cff = font(include_bytes!("../../tests/fixtures/fonts/cff-square.otf"));
+        let bitmap = cff.rasterize(1, 100.0, Some(4.0)).unwrap();
+        assert_eq!((bitmap.width, bitmap.height), (50, 60));
+        let at = |x: u32, y: u32| bitmap.data[(y * bitmap.width + x) as usize];
+        // The baseline is 5 pixels of padding up, the left edge 5 in: the
+        // pixels either side of it are as far below and above 128.
+        let baseline = bitmap.height - 5;
+        assert_eq!((at(25, baseline - 1), at(25, baseline)), (159, 96));
+        assert_eq!((at(5, 30), at(4, 30)), (159, 96));
+        assert_eq!(at(25, baseline + 1), 64);
+        assert_eq!(at(25, baseline + 3), 0);
+        assert_eq!(at(25, 0), 0);
+        // The top of the curve, 47.5 pixels up, halves its row.
+        assert_eq!(at(25, baseline - 48), 127);
+        assert_eq!(at(25, 30), 255);
+    }
+}
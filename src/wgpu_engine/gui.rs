@@ -0,0 +1,321 @@
+use std::time::Instant;
+
+use sdl2::{
+    clipboard::ClipboardUtil,
+    event::{Event, WindowEvent},
+    keyboard::{Keycode, Mod, Scancode},
+    mouse::{Cursor, MouseButton, MouseWheelDirection, SystemCursor},
+    video::Window,
+};
+
+use super::{texture::Texture, WindowSize};
+
+// What `run` produced, drawn by the next `render`.
+struct GuiFrame {
+    primitives: Vec<egui::ClippedPrimitive>,
+    textures: egui::TexturesDelta,
+    pixels_per_point: f32,
+}
+
+// egui drawn over the scene, for in-game tools and debug menus. Feed it the
+// SDL events with `handle_event` and build the UI each frame with `run`.
+pub struct Gui {
+    // When false, events pass through and nothing is drawn.
+    pub enabled: bool,
+    context: egui::Context,
+    renderer: egui_wgpu::Renderer,
+    input: egui::RawInput,
+    modifiers: egui::Modifiers,
+    window_id: u32,
+    clipboard: ClipboardUtil,
+    // Kept alive while it is the cursor, SDL doesn't own it.
+    cursor: Option<(egui::CursorIcon, Cursor)>,
+    start: Instant,
+    frame: Option<GuiFrame>,
+    // Textures egui is done with, freed after the frame is submitted.
+    freed: Vec<egui::TextureId>,
+}
+
+impl Gui {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, window: &Window) -> Self {
+        Self {
+            enabled: true,
+            context: egui::Context::default(),
+            renderer: egui_wgpu::Renderer::new(device, format, None, 1, false),
+            input: egui::RawInput::default(),
+            modifiers: egui::Modifiers::default(),
+            window_id: window.id(),
+            clipboard: window.subsystem().clipboard(),
+            cursor: None,
+            start: Instant::now(),
+            frame: None,
+            freed: Vec::new(),
+        }
+    }
+
+    // The egui context, e.g. for styles and fonts.
+    pub fn context(&self) -> &egui::Context {
+        &self.context
+    }
+
+    // Whether the UI is using the pointer, i.e. clicks shouldn't reach the game.
+    pub fn wants_pointer(&self) -> bool {
+        self.enabled && (self.context.wants_pointer_input() || self.context.is_pointer_over_area())
+    }
+
+    // Whether a UI widget has keyboard focus, e.g. a text field.
+    pub fn wants_keyboard(&self) -> bool {
+        self.enabled && self.context.wants_keyboard_input()
+    }
+
+    // Translates an SDL event into UI input. Returns true when the UI uses
+    // it, so the game can skip it.
+    pub fn handle_event(&mut self, event: &Event) -> bool {
+        if !self.enabled || event.get_window_id().is_some_and(|id| id != self.window_id) {
+            return false;
+        }
+        match event {
+            Event::MouseMotion { x, y, .. } => {
+                self.input.events.push(egui::Event::PointerMoved(egui::pos2(*x as f32, *y as f32)));
+                self.wants_pointer()
+            }
+            Event::MouseButtonDown { mouse_btn, x, y, .. } | Event::MouseButtonUp { mouse_btn, x, y, .. } => {
+                let button = match mouse_btn {
+                    MouseButton::Left => egui::PointerButton::Primary,
+                    MouseButton::Right => egui::PointerButton::Secondary,
+                    MouseButton::Middle => egui::PointerButton::Middle,
+                    MouseButton::X1 => egui::PointerButton::Extra1,
+                    MouseButton::X2 => egui::PointerButton::Extra2,
+                    MouseButton::Unknown => return false,
+                };
+                self.input.events.push(egui::Event::PointerButton {
+                    pos: egui::pos2(*x as f32, *y as f32),
+                    button,
+                    pressed: matches!(event, Event::MouseButtonDown { .. }),
+                    modifiers: self.modifiers,
+                });
+                self.wants_pointer()
+            }
+            Event::MouseWheel { precise_x, precise_y, direction, .. } => {
+                // SDL's x is the wheel's direction, egui's is the content's.
+                let flip = if *direction == MouseWheelDirection::Flipped { -1.0 } else { 1.0 };
+                self.input.events.push(egui::Event::MouseWheel {
+                    unit: egui::MouseWheelUnit::Line,
+                    delta: egui::vec2(-precise_x, *precise_y) * flip,
+                    modifiers: self.modifiers,
+                });
+                self.wants_pointer()
+            }
+            Event::TextInput { text, .. } => {
+                if !text.chars().all(char::is_control) {
+                    self.input.events.push(egui::Event::Text(text.clone()));
+                }
+                self.wants_keyboard()
+            }
+            Event::KeyDown { keycode, scancode, keymod, repeat, .. } => {
+                self.modifiers = modifiers(*keymod);
+                if self.modifiers.command && !*repeat {
+                    match keycode {
+                        Some(Keycode::C) => self.input.events.push(egui::Event::Copy),
+                        Some(Keycode::X) => self.input.events.push(egui::Event::Cut),
+                        Some(Keycode::V) => {
+                            if let Ok(text) = self.clipboard.clipboard_text() {
+                                self.input.events.push(egui::Event::Paste(text));
+                            }
+                        }
+                        _ => (),
+                    }
+                }
+                self.push_key(*keycode, *scancode, true, *repeat);
+                self.wants_keyboard()
+            }
+            Event::KeyUp { keycode, scancode, keymod, .. } => {
+                self.modifiers = modifiers(*keymod);
+                self.push_key(*keycode, *scancode, false, false);
+                self.wants_keyboard()
+            }
+            Event::Window { win_event, .. } => {
+                match win_event {
+                    WindowEvent::FocusGained => self.input.events.push(egui::Event::WindowFocused(true)),
+                    WindowEvent::FocusLost => self.input.events.push(egui::Event::WindowFocused(false)),
+                    WindowEvent::Leave => self.input.events.push(egui::Event::PointerGone),
+                    _ => (),
+                }
+                false
+            }
+            _ => false,
+        }
+    }
+
+    fn push_key(&mut self, keycode: Option<Keycode>, scancode: Option<Scancode>, pressed: bool, repeat: bool) {
+        let key = match keycode.and_then(|k| egui::Key::from_name(&k.name())) {
+            Some(key) => key,
+            None => return,
+        };
+        self.input.events.push(egui::Event::Key {
+            key,
+            physical_key: scancode.and_then(|s| egui::Key::from_name(s.name())),
+            pressed,
+            repeat,
+            modifiers: self.modifiers,
+        });
+    }
+
+    // Builds this frame's UI. `screen` is the window's size in points and
+    // `pixels_per_point` its scale on high DPI displays.
+    pub fn run(&mut self, screen: WindowSize, pixels_per_point: f32, ui: impl FnMut(&egui::Context)) {
+        if !self.enabled {
+            self.input.events.clear();
+            return;
+        }
+        self.input.screen_rect = Some(egui::Rect::from_min_size(
+            egui::Pos2::ZERO,
+            egui::vec2(screen.width as f32, screen.height as f32),
+        ));
+        self.input.time = Some(self.start.elapsed().as_secs_f64());
+        self.input.modifiers = self.modifiers;
+        self.input
+            .viewports
+            .entry(egui::ViewportId::ROOT)
+            .or_default()
+            .native_pixels_per_point = Some(pixels_per_point);
+
+        let output = self.context.run(self.input.take(), ui);
+        let platform = output.platform_output;
+        if !platform.copied_text.is_empty() {
+            if let Err(e) = self.clipboard.set_clipboard_text(&platform.copied_text) {
+                log::warn!("Couldn't copy to the clipboard: {}", e);
+            }
+        }
+        self.set_cursor(platform.cursor_icon);
+
+        let primitives = self.context.tessellate(output.shapes, output.pixels_per_point);
+        // Texture changes of a frame that never got drawn still have to be applied.
+        let mut textures = self.frame.take().map(|frame| frame.textures).unwrap_or_default();
+        textures.append(output.textures_delta);
+        self.frame = Some(GuiFrame {
+            primitives,
+            textures,
+            pixels_per_point: output.pixels_per_point,
+        });
+    }
+
+    fn set_cursor(&mut self, icon: egui::CursorIcon) {
+        if self.cursor.as_ref().is_some_and(|(current, _)| *current == icon) {
+            return;
+        }
+        let system = match icon {
+            egui::CursorIcon::Text | egui::CursorIcon::VerticalText => SystemCursor::IBeam,
+            egui::CursorIcon::PointingHand => SystemCursor::Hand,
+            egui::CursorIcon::Crosshair => SystemCursor::Crosshair,
+            egui::CursorIcon::Wait => SystemCursor::Wait,
+            egui::CursorIcon::Progress => SystemCursor::WaitArrow,
+            egui::CursorIcon::NotAllowed | egui::CursorIcon::NoDrop => SystemCursor::No,
+            egui::CursorIcon::Move | egui::CursorIcon::AllScroll | egui::CursorIcon::Grab | egui::CursorIcon::Grabbing => {
+                SystemCursor::SizeAll
+            }
+            egui::CursorIcon::ResizeHorizontal
+            | egui::CursorIcon::ResizeColumn
+            | egui::CursorIcon::ResizeEast
+            | egui::CursorIcon::ResizeWest => SystemCursor::SizeWE,
+            egui::CursorIcon::ResizeVertical
+            | egui::CursorIcon::ResizeRow
+            | egui::CursorIcon::ResizeNorth
+            | egui::CursorIcon::ResizeSouth => SystemCursor::SizeNS,
+            egui::CursorIcon::ResizeNeSw | egui::CursorIcon::ResizeNorthEast | egui::CursorIcon::ResizeSouthWest => {
+                SystemCursor::SizeNESW
+            }
+            egui::CursorIcon::ResizeNwSe | egui::CursorIcon::ResizeNorthWest | egui::CursorIcon::ResizeSouthEast => {
+                SystemCursor::SizeNWSE
+            }
+            _ => SystemCursor::Arrow,
+        };
+        match Cursor::from_system(system) {
+            Ok(cursor) => {
+                cursor.set();
+                self.cursor = Some((icon, cursor));
+            }
+            Err(e) => log::warn!("Couldn't set the cursor: {}", e),
+        }
+    }
+
+    // Makes an engine texture, e.g. a render texture or a material's diffuse
+    // texture, usable in `egui::Image`. It stays registered until `free_texture`.
+    pub fn register_texture(&mut self, device: &wgpu::Device, texture: &Texture, filter: wgpu::FilterMode) -> egui::TextureId {
+        self.renderer.register_native_texture(device, &texture.view, filter)
+    }
+
+    // Points a registered id at another texture, e.g. after a render texture was resized.
+    pub fn update_texture(&mut self, device: &wgpu::Device, id: egui::TextureId, texture: &Texture, filter: wgpu::FilterMode) {
+        self.renderer.update_egui_texture_from_wgpu_texture(device, &texture.view, filter, id);
+    }
+
+    pub fn free_texture(&mut self, id: egui::TextureId) {
+        self.renderer.free_texture(&id);
+    }
+
+    // Uploads the last `run`'s textures and meshes. Returns command buffers
+    // from paint callbacks, to be submitted before `encoder`'s.
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        size: WindowSize,
+    ) -> Vec<wgpu::CommandBuffer> {
+        let frame = match &mut self.frame {
+            Some(frame) if self.enabled => frame,
+            _ => return Vec::new(),
+        };
+        for (id, delta) in &frame.textures.set {
+            self.renderer.update_texture(device, queue, *id, delta);
+        }
+        self.freed.append(&mut frame.textures.free);
+        frame.textures.set.clear();
+        let screen = egui_wgpu::ScreenDescriptor {
+            size_in_pixels: [size.width, size.height],
+            pixels_per_point: frame.pixels_per_point,
+        };
+        self.renderer.update_buffers(device, queue, encoder, &frame.primitives, &screen)
+    }
+
+    // Draws the UI over whatever is in `render_pass`, after `prepare`.
+    pub fn draw(&self, render_pass: &mut wgpu::RenderPass<'static>, size: WindowSize) {
+        if let Some(frame) = self.frame.as_ref().filter(|_| self.enabled) {
+            let screen = egui_wgpu::ScreenDescriptor {
+                size_in_pixels: [size.width, size.height],
+                pixels_per_point: frame.pixels_per_point,
+            };
+            self.renderer.render(render_pass, &frame.primitives, &screen);
+        }
+    }
+
+    // Whether there is a UI to draw this frame.
+    pub fn has_frame(&self) -> bool {
+        self.enabled && self.frame.as_ref().is_some_and(|frame| !frame.primitives.is_empty())
+    }
+
+    // Drops the drawn frame, the UI has to be built again for the next one.
+    // A frame that wasn't prepared keeps its texture changes for later.
+    pub fn finish_frame(&mut self) {
+        if self.frame.as_ref().is_some_and(|frame| frame.textures.set.is_empty()) {
+            self.frame = None;
+        }
+        for id in self.freed.drain(..) {
+            self.renderer.free_texture(&id);
+        }
+    }
+}
+
+fn modifiers(keymod: Mod) -> egui::Modifiers {
+    let ctrl = keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD);
+    let mac_cmd = cfg!(target_os = "macos") && keymod.intersects(Mod::LGUIMOD | Mod::RGUIMOD);
+    egui::Modifiers {
+        alt: keymod.intersects(Mod::LALTMOD | Mod::RALTMOD),
+        ctrl,
+        shift: keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD),
+        mac_cmd,
+        command: if cfg!(target_os = "macos") { mac_cmd } else { ctrl },
+    }
+}
+
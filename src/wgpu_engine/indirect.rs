@@ -0,0 +1,429 @@
+use std::sync::Arc;
+
+use bytemuck::Zeroable;
+
+use super::{
+    buffer::{create_buffer, grow_buffer},
+    camera::Camera,
+    instance::{InstanceManager, InstanceRaw},
+    model::LodMetric,
+};
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct DrawIndexedIndirect {
+    pub index_count: u32,
+    pub instance_count: u32,
+    pub first_index: u32,
+    pub base_vertex: i32,
+    pub first_instance: u32,
+}
+
+impl DrawIndexedIndirect {
+    pub const SIZE: u64 = size_of::<DrawIndexedIndirect>() as u64;
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct CullUniform {
+    planes: [[f32; 4]; 6],
+    eye: [f32; 4],
+    instance_count: u32,
+    view_height_bias: f32,
+    view_height_scale: f32,
+    _padding: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct CullInstance {
+    raw: InstanceRaw,
+    batch: u32,
+    _padding: [u32; 3],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct Batch {
+    first_draw: u32,
+    draw_count: u32,
+    draw_stride: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct DrawInfo {
+    center: [f32; 3],
+    radius: f32,
+    lod_min: f32,
+    lod_max: f32,
+    fade_min: f32,
+    fade_max: f32,
+    lod_radius: f32,
+    screen_space: u32,
+    output_offset: u32,
+    _padding: u32,
+}
+
+// A run of draws sharing one mesh, issued with a single multi draw call.
+#[derive(Debug, Clone, Copy)]
+pub struct DrawGroup {
+    pub manager: usize,
+    pub lod: usize,
+    pub mesh: usize,
+    pub first_draw: u32,
+    pub draw_count: u32,
+}
+
+pub struct IndirectRenderer {
+    gpu_driven: bool,
+    pipeline: wgpu::ComputePipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: Option<wgpu::BindGroup>,
+    uniform_buffer: wgpu::Buffer,
+    instance_buffer: wgpu::Buffer,
+    batch_buffer: wgpu::Buffer,
+    draw_buffer: wgpu::Buffer,
+    args_buffer: wgpu::Buffer,
+    visible_buffer: wgpu::Buffer,
+    instance_count: u32,
+    output_offsets: Vec<u32>,
+    groups: Vec<DrawGroup>,
+}
+
+impl IndirectRenderer {
+    pub const REQUIRED_FEATURES: wgpu::Features =
+        wgpu::Features::MULTI_DRAW_INDIRECT.union(wgpu::Features::INDIRECT_FIRST_INSTANCE);
+
+    pub fn new(device: &wgpu::Device, adapter: &wgpu::Adapter) -> Self {
+        // Culling on the GPU only pays off if the results can be consumed by a
+        // single multi draw per mesh, otherwise the CPU builds the args itself.
+        let gpu_driven = device.features().contains(Self::REQUIRED_FEATURES)
+            && adapter
+                .get_downlevel_capabilities()
+                .flags
+                .contains(wgpu::DownlevelFlags::COMPUTE_SHADERS);
+        log::info!("Indirect rendering is GPU driven: {gpu_driven}");
+
+        let storage_entry = |binding: u32, read_only: bool| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                storage_entry(1, true),
+                storage_entry(2, true),
+                storage_entry(3, true),
+                storage_entry(4, false),
+                storage_entry(5, false),
+            ],
+            label: Some("cull_bind_group_layout"),
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("cull.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/cull.wgsl").into()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Cull Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Cull Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: "cs_main",
+            compilation_options: Default::default(),
+            cache: None,
+        });
+
+        let storage = wgpu::BufferUsages::STORAGE;
+        Self {
+            gpu_driven,
+            pipeline,
+            bind_group_layout,
+            bind_group: None,
+            uniform_buffer: create_buffer(
+                device,
+                "Cull Uniform Buffer",
+                size_of::<CullUniform>() as u64,
+                wgpu::BufferUsages::UNIFORM,
+            ),
+            instance_buffer: create_buffer(device, "Cull Instance Buffer", 256, storage),
+            batch_buffer: create_buffer(device, "Cull Batch Buffer", 256, storage),
+            draw_buffer: create_buffer(device, "Cull Draw Buffer", 256, storage),
+            args_buffer: create_buffer(device, "Indirect Args Buffer", 256, storage | wgpu::BufferUsages::INDIRECT),
+            visible_buffer: create_buffer(device, "Visible Instance Buffer", 256, storage | wgpu::BufferUsages::VERTEX),
+            instance_count: 0,
+            output_offsets: Vec::new(),
+            groups: Vec::new(),
+        }
+    }
+
+    pub fn is_gpu_driven(&self) -> bool {
+        self.gpu_driven
+    }
+
+    pub fn groups(&self) -> &[DrawGroup] {
+        &self.groups
+    }
+
+    pub fn args_buffer(&self) -> &wgpu::Buffer {
+        &self.args_buffer
+    }
+
+    pub fn visible_buffer(&self) -> &wgpu::Buffer {
+        &self.visible_buffer
+    }
+
+    // Start of the visible instances of `draw`, for binding without first_instance.
+    pub fn visible_offset(&self, draw: u32) -> wgpu::BufferAddress {
+        self.output_offsets[draw as usize] as u64 * InstanceRaw::SIZE
+    }
+
+    // Builds the draw list for this frame. Draws are ordered by model, then mesh,
+    // then instance manager so that every mesh ends up with one contiguous run.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, to_draw: &[InstanceManager], camera: &Camera) {
+        self.prepare_filtered(device, queue, to_draw, camera, |_| true)
+    }
+
+    // Like `prepare`, leaving out the managers for which `visible` is false.
+    pub fn prepare_filtered(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        to_draw: &[InstanceManager],
+        camera: &Camera,
+        visible: impl Fn(&InstanceManager) -> bool,
+    ) {
+        let mut by_model: Vec<(*const _, Vec<usize>)> = Vec::new();
+        for (i, manager) in to_draw.iter().enumerate() {
+            if !visible(manager) || manager.live_instances().next().is_none() {
+                continue;
+            }
+            let key = Arc::as_ptr(&manager.model);
+            match by_model.iter_mut().find(|(k, _)| *k == key) {
+                Some((_, managers)) => managers.push(i),
+                None => by_model.push((key, vec![i])),
+            }
+        }
+
+        let mut instances = Vec::new();
+        let mut batches = Vec::new();
+        let mut draws = Vec::new();
+        let mut args = Vec::new();
+        let mut visible_count = 0;
+        self.groups.clear();
+        self.output_offsets.clear();
+
+        for (_, managers) in &by_model {
+            let model = &to_draw[managers[0]].model;
+            let first_draw = draws.len() as u32;
+            let stride = managers.len() as u32;
+            let mut counts = Vec::with_capacity(managers.len());
+
+            for (k, &m) in managers.iter().enumerate() {
+                let batch = batches.len() as u32;
+                batches.push(Batch {
+                    first_draw: first_draw + k as u32,
+                    draw_count: (0..model.lod_count()).map(|lod| model.lod_meshes(lod).len() as u32).sum(),
+                    draw_stride: stride,
+                });
+                let before = instances.len();
+                instances.extend(to_draw[m].live_instances().map(|instance| CullInstance {
+                    raw: instance.to_raw(),
+                    batch,
+                    _padding: [0; 3],
+                }));
+                counts.push((instances.len() - before) as u32);
+            }
+
+            let lod_radius = model.bounds().radius();
+            let screen_space = (model.lod_metric == LodMetric::ScreenSize) as u32;
+            for (lod, j, mesh) in (0..model.lod_count())
+                .flat_map(|lod| model.lod_meshes(lod).iter().enumerate().map(move |(j, mesh)| (lod, j, mesh)))
+            {
+                let (lod_min, lod_max) = model.lod_range(lod);
+                self.groups.push(DrawGroup {
+                    manager: managers[0],
+                    lod,
+                    mesh: j,
+                    first_draw: draws.len() as u32,
+                    draw_count: stride,
+                });
+                // Every draw gets room for all of its batch's instances.
+                for &count in &counts {
+                    let output_offset = visible_count;
+                    visible_count += count;
+                    self.output_offsets.push(output_offset);
+                    draws.push(DrawInfo {
+                        center: mesh.bounds.center().into(),
+                        radius: mesh.bounds.radius(),
+                        lod_min,
+                        lod_max,
+                        fade_min: fade_width(lod_min, model.lod_fade),
+                        fade_max: fade_width(lod_max, model.lod_fade),
+                        lod_radius,
+                        screen_space,
+                        output_offset,
+                        _padding: 0,
+                    });
+                    args.push(DrawIndexedIndirect {
+                        index_count: mesh.num_elements,
+                        instance_count: 0,
+                        first_index: 0,
+                        base_vertex: 0,
+                        first_instance: if self.gpu_driven { output_offset } else { 0 },
+                    });
+                }
+            }
+        }
+        self.instance_count = instances.len() as u32;
+
+        let frustum = camera.frustum();
+        let uniform = CullUniform {
+            planes: frustum.planes.map(|p| p.into()),
+            eye: camera.eye().into_homogeneous_point().into(),
+            instance_count: self.instance_count,
+            view_height_bias: camera.view_height(0.0),
+            view_height_scale: camera.view_height(1.0) - camera.view_height(0.0),
+            _padding: 0,
+        };
+
+        let mut resized = self.bind_group.is_none();
+        resized |= grow_buffer(device, &mut self.instance_buffer, "Cull Instance Buffer", size_of_val(instances.as_slice()) as u64);
+        resized |= grow_buffer(device, &mut self.batch_buffer, "Cull Batch Buffer", size_of_val(batches.as_slice()) as u64);
+        resized |= grow_buffer(device, &mut self.draw_buffer, "Cull Draw Buffer", size_of_val(draws.as_slice()) as u64);
+        resized |= grow_buffer(device, &mut self.args_buffer, "Indirect Args Buffer", size_of_val(args.as_slice()) as u64);
+        resized |= grow_buffer(
+            device,
+            &mut self.visible_buffer,
+            "Visible Instance Buffer",
+            visible_count as u64 * InstanceRaw::SIZE,
+        );
+
+        if self.gpu_driven {
+            queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+            queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(&instances));
+            queue.write_buffer(&self.batch_buffer, 0, bytemuck::cast_slice(&batches));
+            queue.write_buffer(&self.draw_buffer, 0, bytemuck::cast_slice(&draws));
+            queue.write_buffer(&self.args_buffer, 0, bytemuck::cast_slice(&args));
+            if resized {
+                self.bind_group = Some(self.create_bind_group(device));
+            }
+        } else {
+            let eye = camera.eye();
+            let mut visible = vec![InstanceRaw::zeroed(); visible_count as usize];
+            for instance in &instances {
+                let model = instance.raw.model_matrix();
+                let scale = model.cols[0].xyz().mag().max(model.cols[1].xyz().mag()).max(model.cols[2].xyz().mag());
+                let batch = batches[instance.batch as usize];
+                for i in 0..batch.draw_count {
+                    let d = (batch.first_draw + i * batch.draw_stride) as usize;
+                    let draw = draws[d];
+                    let center = model.transform_point3(draw.center.into());
+                    let distance = (center - eye).mag();
+                    let metric = if draw.screen_space != 0 {
+                        camera.view_height(distance) / (2.0 * draw.lod_radius * scale)
+                    } else {
+                        distance
+                    };
+                    if metric < draw.lod_min || metric >= draw.lod_max + draw.fade_max {
+                        continue;
+                    }
+                    if !frustum.intersects_sphere(center, draw.radius * scale) {
+                        continue;
+                    }
+                    let mut raw = instance.raw;
+                    raw.lod_fade = if metric >= draw.lod_max {
+                        (1.0 - (metric - draw.lod_max) / draw.fade_max).max(1e-4)
+                    } else if metric < draw.lod_min + draw.fade_min {
+                        -(1.0 - (metric - draw.lod_min) / draw.fade_min)
+                    } else {
+                        0.0
+                    };
+                    visible[(draw.output_offset + args[d].instance_count) as usize] = raw;
+                    args[d].instance_count += 1;
+                }
+            }
+            queue.write_buffer(&self.visible_buffer, 0, bytemuck::cast_slice(&visible));
+            queue.write_buffer(&self.args_buffer, 0, bytemuck::cast_slice(&args));
+        }
+    }
+
+    // Records the culling pass. Must be submitted before the render pass using the results.
+    pub fn cull(&self, encoder: &mut wgpu::CommandEncoder) {
+        let Some(bind_group) = &self.bind_group else {
+            return;
+        };
+        if !self.gpu_driven || self.instance_count == 0 {
+            return;
+        }
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Cull Pass"),
+            timestamp_writes: None,
+        });
+        compute_pass.set_pipeline(&self.pipeline);
+        compute_pass.set_bind_group(0, bind_group, &[]);
+        compute_pass.dispatch_workgroups(self.instance_count.div_ceil(64), 1, 1);
+    }
+
+    fn create_bind_group(&self, device: &wgpu::Device) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.instance_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.batch_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: self.draw_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: self.args_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: self.visible_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("cull_bind_group"),
+        })
+    }
+}
+
+// Cross-fade band past a LOD threshold, none for the open ends of the range.
+fn fade_width(threshold: f32, fade: f32) -> f32 {
+    if threshold > 0.0 && threshold < f32::MAX {
+        threshold * fade
+    } else {
+        0.0
+    }
+}
@@ -1,25 +1,43 @@
-use std::{collections::HashMap, hash::Hash, sync::Arc};
+use std::{collections::HashMap, sync::Arc};
 
 use anyhow::{anyhow, Ok, Result};
 
-use super::model::Model;
+use super::{atlas::UvRect, model::Model};
 
 #[derive(Debug, Clone, Copy)]
 pub struct Instance {
     pub id: u128,
     pub position: ultraviolet::Vec3,
     pub rotation: ultraviolet::Rotor3,
-    pub scale: f32,
+    pub scale: ultraviolet::Vec3,
+    // Part of the material's texture the model's UVs are mapped into, e.g.
+    // a frame of an atlas. `UvRect::FULL` leaves them as they are.
+    pub uv: UvRect,
 }
 
 impl Instance {
+    pub fn model_matrix(&self) -> ultraviolet::Mat4 {
+        ultraviolet::Mat4::from_translation(self.position)
+            * self.rotation.into_matrix().into_homogeneous()
+            * ultraviolet::Mat4::from_nonuniform_scale(self.scale)
+    }
+
+    // Inverse transpose of the model matrix's upper 3x3, keeps normals
+    // perpendicular to surfaces under non-uniform scale.
+    pub fn normal_matrix(&self) -> ultraviolet::Mat3 {
+        let s = self.scale;
+        self.rotation.into_matrix() * ultraviolet::Mat3::from_nonuniform_scale(ultraviolet::Vec3::new(1.0 / s.x, 1.0 / s.y, 1.0 / s.z))
+    }
+
     pub fn to_raw(&self) -> InstanceRaw {
+        let normal = self.normal_matrix();
         InstanceRaw {
-            model: (
-                ultraviolet::Mat4::from_translation(self.position)
-                * ultraviolet::Mat4::from_angle_plane(self.rotation.s, self.rotation.bv)
-                * ultraviolet::Mat4::from_scale(self.scale)
-            ).into(),
+            model: self.model_matrix().into(),
+            normal: normal.cols.map(|c| [c.x, c.y, c.z, 0.0]),
+            lod_fade: 0.0,
+            _padding: [0.0; 3],
+            id: split_id(self.id),
+            uv_rect: [self.uv.min.x, self.uv.min.y, self.uv.size().x, self.uv.size().y],
         }
     }
 }
@@ -37,11 +55,29 @@ pub trait InstanceAble {
 pub struct InstanceRaw {
     #[allow(dead_code)]
     model: [[f32; 4]; 4],
+    // mat3x3 columns, padded to vec4 like in WGSL.
+    normal: [[f32; 4]; 3],
+    // Written by LOD selection: > 0 while fading out, < 0 while fading in, 0 otherwise.
+    pub lod_fade: f32,
+    // Keeps `id` 16 byte aligned like a vec4 in WGSL storage arrays.
+    _padding: [f32; 3],
+    // `Instance::id`, least significant word first, for the picking pass.
+    id: [u32; 4],
+    // `Instance::uv` as offset and scale.
+    uv_rect: [f32; 4],
 }
 
 impl InstanceRaw {
     pub const SIZE: u64 = size_of::<InstanceRaw>() as u64;
 
+    pub fn model_matrix(&self) -> ultraviolet::Mat4 {
+        self.model.into()
+    }
+
+    pub fn id(&self) -> u128 {
+        join_id(self.id)
+    }
+
     pub fn desc() -> wgpu::VertexBufferLayout<'static> {
         use std::mem;
         wgpu::VertexBufferLayout {
@@ -75,11 +111,53 @@ impl InstanceRaw {
                     shader_location: 8,
                     format: wgpu::VertexFormat::Float32x4,
                 },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 16]>() as wgpu::BufferAddress,
+                    shader_location: 10,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 20]>() as wgpu::BufferAddress,
+                    shader_location: 11,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 24]>() as wgpu::BufferAddress,
+                    shader_location: 12,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 28]>() as wgpu::BufferAddress,
+                    shader_location: 9,
+                    format: wgpu::VertexFormat::Float32,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 32]>() as wgpu::BufferAddress,
+                    shader_location: 13,
+                    format: wgpu::VertexFormat::Uint32x4,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 36]>() as wgpu::BufferAddress,
+                    shader_location: 14,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
             ],
         }
     }
 }
 
+// A u128 as four u32 words, least significant first, the way ids are stored on the GPU.
+pub fn split_id(id: u128) -> [u32; 4] {
+    [id as u32, (id >> 32) as u32, (id >> 64) as u32, (id >> 96) as u32]
+}
+
+pub fn join_id(words: [u32; 4]) -> u128 {
+    words
+        .iter()
+        .rev()
+        .fold(0, |id, &word| (id << 32) | word as u128)
+}
+
 pub struct InstanceManager {
     pub model: Arc<Model>,
     pub instances: Vec<Instance>,
@@ -107,8 +185,7 @@ impl InstanceManager {
     }
 
     pub fn add_instance(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, instance: Instance) {
-        if self.remove_idxs.len() > 0 {
-            let index = self.remove_idxs.pop().unwrap();
+        if let Some(index) = self.remove_idxs.pop() {
             self.id_to_index.insert(instance.id, index);
             self.instances[index] = instance;
             self.update_instance(queue, instance).unwrap();
@@ -146,6 +223,32 @@ impl InstanceManager {
         }
     }
 
+    // Removed slots stay in `instances` until they are reused, so skip them here.
+    pub fn live_instances(&self) -> impl Iterator<Item = &Instance> {
+        self.instances
+            .iter()
+            .enumerate()
+            .filter(move |(index, instance)| self.id_to_index.get(&instance.id) == Some(index))
+            .map(|(_, instance)| instance)
+    }
+
+    // Runs of consecutive live slots in `instance_buffer`, for drawing straight
+    // from it without the removed ones.
+    pub fn live_ranges(&self) -> Vec<std::ops::Range<u32>> {
+        let mut ranges: Vec<std::ops::Range<u32>> = Vec::new();
+        for (index, instance) in self.instances.iter().enumerate() {
+            if self.id_to_index.get(&instance.id) != Some(&index) {
+                continue;
+            }
+            let index = index as u32;
+            match ranges.last_mut() {
+                Some(range) if range.end == index => range.end += 1,
+                _ => ranges.push(index..index + 1),
+            }
+        }
+        ranges
+    }
+
     pub fn remove_instance(&mut self, instance_id: u128) -> Result<Instance> {
         if let Some(index) = self.id_to_index.remove(&instance_id) {
             let instance = self.instances[index];
@@ -0,0 +1,192 @@
+use std::collections::{HashMap, HashSet};
+
+use super::model::{Bounds, LodMetric, ModelVertex};
+
+#[derive(Debug, Clone, Copy)]
+pub struct LodLevel {
+    // Fraction of the source triangles to keep.
+    pub ratio: f32,
+    pub threshold: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct LodSettings {
+    pub metric: LodMetric,
+    pub fade: f32,
+    pub levels: Vec<LodLevel>,
+}
+
+impl LodSettings {
+    pub fn new(metric: LodMetric, levels: Vec<LodLevel>) -> Self {
+        Self {
+            metric,
+            fade: 0.0,
+            levels,
+        }
+    }
+
+    pub fn with_fade(mut self, fade: f32) -> Self {
+        self.fade = fade;
+        self
+    }
+}
+
+// Vertex clustering: vertices are snapped to a grid and merged per cell, and
+// triangles that collapse are dropped. The grid resolution is searched so that
+// the result keeps at most `ratio` of the triangles, as close to it as found.
+// When every grid that small collapses the mesh entirely, the smallest result
+// that still has triangles is used instead.
+pub fn simplify(vertices: &[ModelVertex], indices: &[u32], ratio: f32) -> (Vec<ModelVertex>, Vec<u32>) {
+    let triangle_count = indices.len() / 3;
+    if ratio >= 1.0 || triangle_count == 0 {
+        return (vertices.to_vec(), indices.to_vec());
+    }
+    let target = ((triangle_count as f32 * ratio).ceil() as usize).max(1);
+    let bounds = Bounds::from_positions(vertices.iter().map(|v| &v.position));
+
+    let (mut low, mut high) = (1u32, 1024u32);
+    let mut best = cluster(vertices, indices, &bounds, low);
+    let mut smallest_over: Option<(Vec<ModelVertex>, Vec<u32>)> = None;
+    while low < high {
+        let mid = (low + high).div_ceil(2);
+        let result = cluster(vertices, indices, &bounds, mid);
+        let count = result.1.len() / 3;
+        if count <= target {
+            if count > best.1.len() / 3 {
+                best = result;
+            }
+            low = mid;
+        } else {
+            if count < triangle_count && smallest_over.as_ref().is_none_or(|s| count < s.1.len() / 3) {
+                smallest_over = Some(result);
+            }
+            high = mid - 1;
+        }
+    }
+    match smallest_over {
+        Some(over) if best.1.is_empty() => over,
+        _ => best,
+    }
+}
+
+fn cluster(vertices: &[ModelVertex], indices: &[u32], bounds: &Bounds, resolution: u32) -> (Vec<ModelVertex>, Vec<u32>) {
+    let extent = bounds.max - bounds.min;
+    let cell = extent.component_max().max(f32::EPSILON) / resolution as f32;
+    let cell_of = |p: [f32; 3]| {
+        let p = (ultraviolet::Vec3::from(p) - bounds.min) / cell;
+        (p.x as i32, p.y as i32, p.z as i32)
+    };
+
+    let mut cells = HashMap::new();
+    let mut sums: Vec<(ModelVertex, f32)> = Vec::new();
+    let remap = vertices
+        .iter()
+        .map(|v| {
+            let index = *cells.entry(cell_of(v.position)).or_insert_with(|| {
+                sums.push((
+                    ModelVertex {
+                        position: [0.0; 3],
+                        tex_coords: [0.0; 2],
+                        normal: [0.0; 3],
+                    },
+                    0.0,
+                ));
+                sums.len() as u32 - 1
+            });
+            let (sum, count) = &mut sums[index as usize];
+            for i in 0..3 {
+                sum.position[i] += v.position[i];
+                sum.normal[i] += v.normal[i];
+            }
+            for i in 0..2 {
+                sum.tex_coords[i] += v.tex_coords[i];
+            }
+            *count += 1.0;
+            index
+        })
+        .collect::<Vec<_>>();
+
+    let mut seen = HashSet::new();
+    let mut new_indices = Vec::new();
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| remap[triangle[i] as usize]);
+        if a == b || b == c || c == a {
+            continue;
+        }
+        // Rotate so the smallest index comes first, keeping the winding.
+        let key = if a < b && a < c {
+            (a, b, c)
+        } else if b < c {
+            (b, c, a)
+        } else {
+            (c, a, b)
+        };
+        if seen.insert(key) {
+            new_indices.extend_from_slice(&[a, b, c]);
+        }
+    }
+
+    let new_vertices = sums
+        .into_iter()
+        .map(|(sum, count)| {
+            let normal = ultraviolet::Vec3::from(sum.normal);
+            let normal = if normal.mag_sq() > 0.0 { normal.normalized() } else { normal };
+            ModelVertex {
+                position: sum.position.map(|p| p / count),
+                tex_coords: sum.tex_coords.map(|t| t / count),
+                normal: normal.into(),
+            }
+        })
+        .collect();
+    (new_vertices, new_indices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wgpu_engine::{mesh_builder::MeshBuilder, model::MeshData};
+
+    fn meshes() -> Vec<MeshData> {
+        vec![
+            MeshBuilder::uv_sphere(1.0, 32, 16).into_data(),
+            MeshBuilder::icosphere(1.0, 3).into_data(),
+            MeshBuilder::torus(1.0, 0.3, 48, 16).into_data(),
+            MeshBuilder::plane(4.0, 4.0, 20).into_data(),
+        ]
+    }
+
+    fn assert_valid(vertices: &[ModelVertex], indices: &[u32]) {
+        assert_eq!(indices.len() % 3, 0);
+        assert!(indices.iter().all(|&i| (i as usize) < vertices.len()));
+    }
+
+    #[test]
+    fn simplify_gets_close_to_the_ratio_without_going_over() {
+        for data in meshes() {
+            let count = data.indices.len() / 3;
+            for ratio in [0.75, 0.5, 0.25, 0.1] {
+                let (vertices, indices) = simplify(&data.vertices, &data.indices, ratio);
+                assert_valid(&vertices, &indices);
+                let target = (count as f32 * ratio).ceil();
+                let kept = (indices.len() / 3) as f32;
+                assert!(kept <= target && kept >= target * 0.4, "{} of {} triangles for {}", kept, count, ratio);
+                assert!(vertices.len() < data.vertices.len());
+            }
+        }
+    }
+
+    #[test]
+    fn simplify_keeps_some_triangles_and_never_adds_any() {
+        for data in meshes() {
+            let count = data.indices.len() / 3;
+            let (vertices, indices) = simplify(&data.vertices, &data.indices, 0.001);
+            assert_valid(&vertices, &indices);
+            assert!(!indices.is_empty() && indices.len() / 3 < count);
+
+            let (vertices, indices) = simplify(&data.vertices, &data.indices, 1.0);
+            assert_eq!(indices, data.indices);
+            assert_eq!(vertices.len(), data.vertices.len());
+        }
+        assert!(simplify(&[], &[], 0.5).1.is_empty());
+    }
+}
@@ -0,0 +1,365 @@
+use std::{
+    collections::HashMap,
+    f32::consts::{FRAC_PI_2, PI, TAU},
+};
+
+use ultraviolet::Vec3;
+
+use super::{
+    model::{Material, Mesh, MeshData, Model, ModelVertex},
+    texture,
+};
+
+// One ring of a surface of revolution around the y axis. `normal` is the
+// (radial, y) direction of the surface normal, `v` the texture coordinate.
+struct Ring {
+    radius: f32,
+    y: f32,
+    normal: (f32, f32),
+    v: f32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MeshBuilder {
+    pub vertices: Vec<ModelVertex>,
+    pub indices: Vec<u32>,
+}
+
+impl MeshBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // A quad in the xy plane facing +z, like the sprite quad.
+    pub fn quad(width: f32, height: f32) -> Self {
+        let mut builder = Self::new();
+        builder.grid(
+            Vec3::new(-0.5 * width, -0.5 * height, 0.0),
+            Vec3::unit_x() * width,
+            Vec3::unit_y() * height,
+            1,
+            1,
+        );
+        builder
+    }
+
+    pub fn cube(size: f32) -> Self {
+        let h = 0.5 * size;
+        // (normal, u, v) with u x v = normal so every face winds counter-clockwise.
+        let faces = [
+            (Vec3::unit_x(), -Vec3::unit_z(), Vec3::unit_y()),
+            (-Vec3::unit_x(), Vec3::unit_z(), Vec3::unit_y()),
+            (Vec3::unit_y(), Vec3::unit_x(), -Vec3::unit_z()),
+            (-Vec3::unit_y(), Vec3::unit_x(), Vec3::unit_z()),
+            (Vec3::unit_z(), Vec3::unit_x(), Vec3::unit_y()),
+            (-Vec3::unit_z(), -Vec3::unit_x(), Vec3::unit_y()),
+        ];
+        let mut builder = Self::new();
+        for (normal, u, v) in faces {
+            builder.grid((normal - u - v) * h, u * size, v * size, 1, 1);
+        }
+        builder
+    }
+
+    // A plane in the xz plane facing +y.
+    pub fn plane(width: f32, depth: f32, subdivisions: u32) -> Self {
+        let mut builder = Self::new();
+        builder.grid(
+            Vec3::new(-0.5 * width, 0.0, 0.5 * depth),
+            Vec3::unit_x() * width,
+            -Vec3::unit_z() * depth,
+            subdivisions + 1,
+            subdivisions + 1,
+        );
+        builder
+    }
+
+    pub fn uv_sphere(radius: f32, sectors: u32, stacks: u32) -> Self {
+        let stacks = stacks.max(2);
+        let rings = (0..=stacks)
+            .map(|i| {
+                let phi = PI * i as f32 / stacks as f32;
+                Ring {
+                    radius: radius * phi.sin(),
+                    y: radius * phi.cos(),
+                    normal: (phi.sin(), phi.cos()),
+                    v: i as f32 / stacks as f32,
+                }
+            })
+            .collect::<Vec<_>>();
+        let mut builder = Self::new();
+        builder.lathe(&rings, sectors);
+        builder
+    }
+
+    pub fn icosphere(radius: f32, subdivisions: u32) -> Self {
+        let t = (1.0 + 5.0f32.sqrt()) / 2.0;
+        let mut positions = [
+            (-1.0, t, 0.0),
+            (1.0, t, 0.0),
+            (-1.0, -t, 0.0),
+            (1.0, -t, 0.0),
+            (0.0, -1.0, t),
+            (0.0, 1.0, t),
+            (0.0, -1.0, -t),
+            (0.0, 1.0, -t),
+            (t, 0.0, -1.0),
+            (t, 0.0, 1.0),
+            (-t, 0.0, -1.0),
+            (-t, 0.0, 1.0),
+        ]
+        .map(|(x, y, z)| Vec3::new(x, y, z).normalized())
+        .to_vec();
+        let mut triangles = vec![
+            [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+            [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+            [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+            [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1],
+        ];
+
+        for _ in 0..subdivisions {
+            let mut midpoints = HashMap::new();
+            let mut midpoint = |a: u32, b: u32| {
+                *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                    positions.push(((positions[a as usize] + positions[b as usize]) * 0.5).normalized());
+                    positions.len() as u32 - 1
+                })
+            };
+            triangles = triangles
+                .into_iter()
+                .flat_map(|[a, b, c]| {
+                    let (ab, bc, ca) = (midpoint(a, b), midpoint(b, c), midpoint(c, a));
+                    [[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+                })
+                .collect();
+        }
+
+        let mut builder = Self::new();
+        builder.vertices = positions
+            .iter()
+            .map(|&n| ModelVertex {
+                position: (n * radius).into(),
+                tex_coords: sphere_uv(n),
+                normal: n.into(),
+            })
+            .collect();
+        // Triangles crossing the u seam get copies of their low-u vertices shifted by one.
+        let mut seam_copies = HashMap::new();
+        for triangle in triangles.iter_mut() {
+            let us = triangle.map(|i| builder.vertices[i as usize].tex_coords[0]);
+            if us.iter().cloned().fold(f32::MIN, f32::max) - us.iter().cloned().fold(f32::MAX, f32::min) <= 0.5 {
+                continue;
+            }
+            for (index, u) in triangle.iter_mut().zip(us) {
+                if u < 0.5 {
+                    *index = *seam_copies.entry(*index).or_insert_with(|| {
+                        let mut vertex = builder.vertices[*index as usize];
+                        vertex.tex_coords[0] += 1.0;
+                        builder.vertices.push(vertex);
+                        builder.vertices.len() as u32 - 1
+                    });
+                }
+            }
+        }
+        builder.indices = triangles.into_iter().flatten().collect();
+        builder
+    }
+
+    pub fn cylinder(radius: f32, height: f32, segments: u32) -> Self {
+        let h = 0.5 * height;
+        let mut builder = Self::new();
+        builder.lathe(
+            &[
+                Ring { radius, y: h, normal: (1.0, 0.0), v: 0.0 },
+                Ring { radius, y: -h, normal: (1.0, 0.0), v: 1.0 },
+            ],
+            segments,
+        );
+        builder.disc(radius, h, true, segments);
+        builder.disc(radius, -h, false, segments);
+        builder
+    }
+
+    // A cone with its base centered below the origin and the apex at height / 2.
+    pub fn cone(radius: f32, height: f32, segments: u32) -> Self {
+        let h = 0.5 * height;
+        let slant = (radius * radius + height * height).sqrt();
+        let normal = (height / slant, radius / slant);
+        let mut builder = Self::new();
+        builder.lathe(
+            &[
+                Ring { radius: 0.0, y: h, normal, v: 0.0 },
+                Ring { radius, y: -h, normal, v: 1.0 },
+            ],
+            segments,
+        );
+        builder.disc(radius, -h, false, segments);
+        builder
+    }
+
+    // `height` is the length of the cylindrical part between the two hemispheres.
+    pub fn capsule(radius: f32, height: f32, segments: u32, rings: u32) -> Self {
+        let rings = rings.max(1);
+        let h = 0.5 * height;
+        let total = PI * radius + height;
+        let hemisphere = |i: u32, bottom: bool| {
+            let phi = FRAC_PI_2 * i as f32 / rings as f32 + if bottom { FRAC_PI_2 } else { 0.0 };
+            let arc = phi * radius + if bottom { height } else { 0.0 };
+            Ring {
+                radius: radius * phi.sin(),
+                y: radius * phi.cos() + if bottom { -h } else { h },
+                normal: (phi.sin(), phi.cos()),
+                v: arc / total,
+            }
+        };
+        let rows = (0..=rings)
+            .map(|i| hemisphere(i, false))
+            .chain((0..=rings).map(|i| hemisphere(i, true)))
+            .collect::<Vec<_>>();
+        let mut builder = Self::new();
+        builder.lathe(&rows, segments);
+        builder
+    }
+
+    // A torus around the y axis.
+    pub fn torus(major_radius: f32, minor_radius: f32, major_segments: u32, minor_segments: u32) -> Self {
+        let minor_segments = minor_segments.max(3);
+        let rings = (0..=minor_segments)
+            .map(|k| {
+                // Walk the tube downwards on the outside so the winding faces out.
+                let phi = -TAU * k as f32 / minor_segments as f32;
+                Ring {
+                    radius: major_radius + minor_radius * phi.cos(),
+                    y: minor_radius * phi.sin(),
+                    normal: (phi.cos(), phi.sin()),
+                    v: k as f32 / minor_segments as f32,
+                }
+            })
+            .collect::<Vec<_>>();
+        let mut builder = Self::new();
+        builder.lathe(&rings, major_segments);
+        builder
+    }
+
+    pub fn append(&mut self, other: &MeshBuilder) -> &mut Self {
+        let base = self.vertices.len() as u32;
+        self.vertices.extend_from_slice(&other.vertices);
+        self.indices.extend(other.indices.iter().map(|i| i + base));
+        self
+    }
+
+    pub fn translate(&mut self, offset: Vec3) -> &mut Self {
+        for vertex in self.vertices.iter_mut() {
+            vertex.position = (Vec3::from(vertex.position) + offset).into();
+        }
+        self
+    }
+
+    pub fn build(&self, device: &wgpu::Device, name: &str, material: usize) -> Mesh {
+        Mesh::new(device, name, &self.vertices, &self.indices, material)
+    }
+
+    pub fn into_data(self) -> MeshData {
+        MeshData::new(self.vertices, self.indices)
+    }
+
+    pub fn build_model(
+        &self,
+        device: &wgpu::Device,
+        name: &str,
+        texture: texture::Texture,
+        layout: &wgpu::BindGroupLayout,
+    ) -> Model {
+        let material = Material::new(device, name, texture, layout);
+        Model::new(vec![self.build(device, name, 0)], vec![material])
+    }
+
+    // A grid of quads spanning `u` and `v` from `origin`, facing u x v.
+    fn grid(&mut self, origin: Vec3, u: Vec3, v: Vec3, columns: u32, rows: u32) {
+        let base = self.vertices.len() as u32;
+        let normal = u.cross(v).normalized();
+        for j in 0..=rows {
+            for i in 0..=columns {
+                let (s, t) = (i as f32 / columns as f32, j as f32 / rows as f32);
+                self.vertices.push(ModelVertex {
+                    position: (origin + u * s + v * t).into(),
+                    tex_coords: [s, 1.0 - t],
+                    normal: normal.into(),
+                });
+            }
+        }
+        let stride = columns + 1;
+        for j in 0..rows {
+            for i in 0..columns {
+                let a = base + j * stride + i;
+                let (b, c, d) = (a + 1, a + stride + 1, a + stride);
+                self.indices.extend_from_slice(&[a, b, c, a, c, d]);
+            }
+        }
+    }
+
+    // Revolves `rings`, ordered top to bottom, around the y axis.
+    fn lathe(&mut self, rings: &[Ring], segments: u32) {
+        let segments = segments.max(3);
+        let base = self.vertices.len() as u32;
+        for ring in rings {
+            for j in 0..=segments {
+                let theta = TAU * j as f32 / segments as f32;
+                let (sin, cos) = theta.sin_cos();
+                self.vertices.push(ModelVertex {
+                    position: [ring.radius * cos, ring.y, -ring.radius * sin],
+                    tex_coords: [j as f32 / segments as f32, ring.v],
+                    normal: [ring.normal.0 * cos, ring.normal.1, -ring.normal.0 * sin],
+                });
+            }
+        }
+        let stride = segments + 1;
+        for (i, pair) in rings.windows(2).enumerate() {
+            for j in 0..segments {
+                let upper_left = base + i as u32 * stride + j;
+                let (upper_right, lower_left) = (upper_left + 1, upper_left + stride);
+                let lower_right = lower_left + 1;
+                // Rings of radius 0 are poles, where one of the two triangles collapses.
+                if pair[1].radius > 0.0 {
+                    self.indices.extend_from_slice(&[lower_left, lower_right, upper_right]);
+                }
+                if pair[0].radius > 0.0 {
+                    self.indices.extend_from_slice(&[lower_left, upper_right, upper_left]);
+                }
+            }
+        }
+    }
+
+    fn disc(&mut self, radius: f32, y: f32, up: bool, segments: u32) {
+        let segments = segments.max(3);
+        let base = self.vertices.len() as u32;
+        let normal = if up { [0.0, 1.0, 0.0] } else { [0.0, -1.0, 0.0] };
+        self.vertices.push(ModelVertex {
+            position: [0.0, y, 0.0],
+            tex_coords: [0.5, 0.5],
+            normal,
+        });
+        for j in 0..=segments {
+            let theta = TAU * j as f32 / segments as f32;
+            let (sin, cos) = theta.sin_cos();
+            self.vertices.push(ModelVertex {
+                position: [radius * cos, y, -radius * sin],
+                tex_coords: [0.5 + 0.5 * cos, 0.5 + if up { -0.5 } else { 0.5 } * sin],
+                normal,
+            });
+        }
+        for j in 0..segments {
+            let (a, b) = (base + 1 + j, base + 2 + j);
+            if up {
+                self.indices.extend_from_slice(&[base, a, b]);
+            } else {
+                self.indices.extend_from_slice(&[base, b, a]);
+            }
+        }
+    }
+}
+
+fn sphere_uv(n: Vec3) -> [f32; 2] {
+    let u = (-n.z).atan2(n.x) / TAU;
+    [if u < 0.0 { u + 1.0 } else { u }, n.y.clamp(-1.0, 1.0).acos() / PI]
+}
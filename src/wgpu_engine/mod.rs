@@ -1,31 +1,59 @@
-use std::{iter, sync::Arc};
+use std::{collections::HashMap, iter, sync::Arc};
 
-use anyhow::Result;
-use camera::{Camera, CameraController};
+use anyhow::{anyhow, Context, Result};
+use camera::{Camera, DepthMode};
 use context::WgpuContext;
-use sdl2::{event, video::Window};
+use sdl2::video::Window;
 use instance::{Instance, InstanceManager, InstanceRaw};
+use debug::{DebugDraw, DebugRenderer};
 use draw::DrawModel;
+use dynamic_mesh::{DynamicMesh, DynamicMeshId};
+use gui::Gui;
+use layouts::BindGroupLayouts;
+use picking::{PickHit, PickRect, Picker};
+use render_mode::{RenderMode, RenderModes};
+use atlas::{PackedAtlas, SheetGrid, SpriteSheet};
+use sprite::{SpriteRenderer, SpriteTextureId};
+use text::{FontId, TextRenderer};
+use tilemap::{Tilemap, TilemapRenderer, Tileset};
+use ui::{Ui, UiRect};
+use crate::scene_graph::{InstanceGroups, SceneGraph};
+use scene::{CameraDesc, CameraId, EngineSettings, GroupId, InstanceGroupDesc, LightDesc, ModelId, SceneDesc};
+use view::{CameraView, RenderTarget, Viewport};
 
-mod model;
-mod resources;
-mod texture;
-mod camera;
+mod buffer;
+pub mod model;
+pub mod atlas;
+pub mod animation;
+pub mod resources;
+pub mod texture;
+pub mod camera;
 pub mod instance;
-mod draw;
-mod context;
+pub mod draw;
+pub mod context;
+pub mod dynamic_mesh;
+pub mod indirect;
+pub mod lod;
+pub mod mesh_builder;
+pub mod scene;
+pub mod layouts;
+pub mod controller;
+pub mod view;
+pub mod picking;
+pub mod rig;
+pub mod sprite;
+pub mod tilemap;
+pub mod tiled;
+pub mod font;
+pub mod text;
+pub mod debug;
+pub mod render_mode;
+pub mod gui;
+pub mod ui;
 
-use model::{texture_to_model, Vertex};
-
-const NUM_INSTANCES_PER_ROW: u32 = 10;
-
-pub trait InstanceAble {
-    fn to_instance(&self) -> Instance;
-    fn to_raw(&self) -> InstanceRaw {
-        self.to_instance().to_raw()
-    }
-}
+use model::{Model, Vertex};
 
+pub use instance::InstanceAble;
 
 #[derive(Debug, Clone, Copy)]
 pub struct WindowSize {
@@ -36,172 +64,105 @@ pub struct WindowSize {
 pub struct WgpuEngine<'w> {
     context: WgpuContext<'w>,
     render_pipeline: wgpu::RenderPipeline,
-    camera: Camera,
-    depth_texture: texture::Texture,
+    reverse_z_pipeline: wgpu::RenderPipeline,
+    // The main camera is always `cameras[0]`, `camera_ids[i]` names `cameras[i]`.
+    cameras: Vec<CameraView>,
+    camera_ids: Vec<CameraId>,
+    layouts: BindGroupLayouts,
+    pub settings: EngineSettings,
+    pub lights: Vec<LightDesc>,
+    models: HashMap<ModelId, Arc<Model>>,
+    // Models loaded from assets by file name, so each asset is loaded once.
+    model_assets: HashMap<String, ModelId>,
+    // Kept contiguous for the renderer, `group_ids[i]` names `groups[i]`.
+    groups: Vec<InstanceManager>,
+    group_ids: Vec<GroupId>,
+    // Each drawn with its model's material `DynamicMesh::material`.
+    dynamic_meshes: HashMap<DynamicMeshId, (Arc<Model>, DynamicMesh)>,
+    // Created by the first `pick`.
+    picker: Option<Picker>,
+    sprites: SpriteRenderer,
+    tilemaps: TilemapRenderer,
+    text: TextRenderer,
+    debug: DebugDraw,
+    debug_renderer: DebugRenderer,
+    render_modes: RenderModes,
+    gui: Gui,
+    next_id: u32,
 }
 
 impl<'w> WgpuEngine<'w> {
     pub async fn new(window: &'w Window) -> Result<WgpuEngine<'w>> {
         let context = WgpuContext::new(window).await?;
 
-        let texture_bind_group_layout =
-            context.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            multisampled: false,
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                        count: None,
-                    },
-                ],
-                label: Some("texture_bind_group_layout"),
-            });
-
-        let camera_bind_group_layout =
-        context.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            entries: &[wgpu::BindGroupLayoutEntry {
-                binding: 0,
-                visibility: wgpu::ShaderStages::VERTEX,
-                ty: wgpu::BindingType::Buffer {
-                    ty: wgpu::BufferBindingType::Uniform,
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
-                },
-                count: None,
-            }],
-            label: Some("camera_bind_group_layout"),
-        });
+        let layouts = BindGroupLayouts::new(&context.device);
 
         let camera = Camera::new(
             camera::LookAt::new((0.0, 3.0, 10.0).into(), (0.0, 0.0, 0.0).into(), (0.0, 1.0, 0.0).into()),
             Box::new(camera::PerspectiveProjection::new(context.size.width as f32 / context.size.height as f32, 45.0, 0.1, 100.0)
             ),
             &context.device,
-            &camera_bind_group_layout,
+            &layouts.camera,
         );
 
-        log::warn!("Load model");
-        let obj_model = Arc::new(
-            texture_to_model(
-                resources::load_texture("cube-diffuse.jpg", &context.device, &context.queue).await?,
-                &texture_bind_group_layout,
-                &context.device,
-                "box",
-            ));
-        let mut instance_manager = instance::InstanceManager::new(&context.device, obj_model.clone()); 
-
-        const SPACE_BETWEEN: f32 = 3.0;
-        for i in 0..NUM_INSTANCES_PER_ROW {
-            for j in 0..NUM_INSTANCES_PER_ROW {
-                let x = SPACE_BETWEEN * (i as f32 - NUM_INSTANCES_PER_ROW as f32 / 2.0);
-                let z = SPACE_BETWEEN * (j as f32 - NUM_INSTANCES_PER_ROW as f32 / 2.0);
-
-                let position = ultraviolet::Vec3 { x, y: 0.0, z };
-
-                let rotation = if position.mag() == 0.0 {
-                    ultraviolet::Rotor3::identity()
-                } else {
-                    ultraviolet::Rotor3::from_rotation_between(
-                        ultraviolet::Vec3::unit_z(),
-                        position.normalized(),
-                    )
-                };
-
-                let instance = Instance { position, rotation, id: (i * NUM_INSTANCES_PER_ROW + j) as u128 , scale: 1.0};
-                instance_manager.add_instance(&context.device, &context.queue, instance);
-            }
-        }
-
-
         let shader = context.device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("shader.wgsl"),
             source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/shader.wgsl").into()),
         });
 
-        let depth_texture =
-            texture::Texture::create_depth_texture(&context.device, &context.config, "depth_texture");
-
         let render_pipeline_layout =
             context.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("Render Pipeline Layout"),
-                bind_group_layouts: &[&texture_bind_group_layout, &camera_bind_group_layout],
+                bind_group_layouts: &[&layouts.texture, &layouts.camera],
                 push_constant_ranges: &[],
             });
 
-        let render_pipeline = context.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main",
-                buffers: &[model::ModelVertex::desc(), InstanceRaw::desc()],
-                compilation_options: Default::default(),
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: context.config.format,
-                    blend: Some(wgpu::BlendState {
-                        color: wgpu::BlendComponent::REPLACE,
-                        alpha: wgpu::BlendComponent::REPLACE,
-                    }),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-                compilation_options: Default::default(),
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                // Setting this to anything other than Fill requires Features::POLYGON_MODE_LINE
-                // or Features::POLYGON_MODE_POINT
-                polygon_mode: wgpu::PolygonMode::Fill,
-                // Requires Features::DEPTH_CLIP_CONTROL
-                unclipped_depth: false,
-                // Requires Features::CONSERVATIVE_RASTERIZATION
-                conservative: false,
-            },
-            depth_stencil: Some(wgpu::DepthStencilState {
-                format: texture::Texture::DEPTH_FORMAT,
-                depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::Less,
-                stencil: wgpu::StencilState::default(),
-                bias: wgpu::DepthBiasState::default(),
-            }),
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            // If the pipeline will be used with a multiview render pass, this
-            // indicates how many array layers the attachments will have.
-            multiview: None,
-            // Useful for optimizing shader compilation on Android
-            cache: None,
-        });
+        let render_pipeline =
+            create_render_pipeline(&context.device, &render_pipeline_layout, &shader, context.config.format, DepthMode::Standard);
+        let reverse_z_pipeline =
+            create_render_pipeline(&context.device, &render_pipeline_layout, &shader, context.config.format, DepthMode::Reversed);
+        let sprites = SpriteRenderer::new(&context.device, &layouts, context.config.format);
+        let tilemaps = TilemapRenderer::new(&context.device, &layouts, context.config.format);
+        let text = TextRenderer::new(&context.device, &layouts, context.config.format);
+        let debug_renderer = DebugRenderer::new(&context.device, &layouts, context.config.format);
+        let render_modes = RenderModes::new(&context.device, &layouts, context.config.format);
+        let gui = Gui::new(&context.device, context.config.format, window);
+        let mut main_view = CameraView::new(camera, Viewport::FULL, RenderTarget::Surface, &context.device, &context.adapter);
+        main_view.hud = true;
+
         context.surface.configure(&context.device, &context.config);
         Ok(Self {
             context,
             render_pipeline,
-            camera,
-            depth_texture,
+            reverse_z_pipeline,
+            cameras: vec![main_view],
+            camera_ids: vec![CameraId(0)],
+            layouts,
+            settings: EngineSettings::default(),
+            lights: Vec::new(),
+            models: HashMap::new(),
+            model_assets: HashMap::new(),
+            groups: Vec::new(),
+            group_ids: Vec::new(),
+            dynamic_meshes: HashMap::new(),
+            picker: None,
+            sprites,
+            tilemaps,
+            text,
+            debug: DebugDraw::new(),
+            debug_renderer,
+            render_modes,
+            gui,
+            next_id: 0,
         })
     }
 
     pub fn window(&self) -> &Window {
-        &self.context.window
+        self.context.window
+    }
+
+    pub fn context(&self) -> &WgpuContext<'w> {
+        &self.context
     }
 
     pub fn resize(&mut self, new_size: WindowSize) {
@@ -209,22 +170,541 @@ impl<'w> WgpuEngine<'w> {
             self.context.config.width = new_size.width;
             self.context.config.height = new_size.height;
             self.context.size = new_size;
-            self.camera.projection.resize(self.context.config.width as f32, self.context.config.height as f32);
             self.context.surface.configure(&self.context.device, &self.context.config);
-            self.depth_texture =
-                texture::Texture::create_depth_texture(&self.context.device, &self.context.config, "depth_texture");
         }
     }
 
+    pub fn device(&self) -> &wgpu::Device {
+        &self.context.device
+    }
+
+    pub fn queue(&self) -> &wgpu::Queue {
+        &self.context.queue
+    }
+
+    fn next_id(&mut self) -> u32 {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    // Loads an OBJ or image from the asset directory. Loading the same asset
+    // again returns the model that is already loaded.
+    pub async fn load_model(&mut self, file_name: &str) -> Result<ModelId> {
+        if let Some(id) = self.model_assets.get(file_name) {
+            return Ok(*id);
+        }
+        let model =
+            resources::load_asset_model(file_name, &self.context.device, &self.context.queue, &self.layouts.texture).await?;
+        let id = self.add_model(model);
+        self.model_assets.insert(file_name.to_string(), id);
+        Ok(id)
+    }
+
+    // Registers a model built in code, e.g. with `MeshBuilder`.
+    pub fn add_model(&mut self, model: Model) -> ModelId {
+        let id = ModelId(self.next_id());
+        self.models.insert(id, Arc::new(model));
+        id
+    }
+
+    pub fn model(&self, id: ModelId) -> Result<&Arc<Model>> {
+        self.models.get(&id).ok_or_else(|| anyhow!("Model {:?} not found", id))
+    }
+
+    // Fails while an instance group still uses the model.
+    pub fn remove_model(&mut self, id: ModelId) -> Result<()> {
+        let model = self.model(id)?;
+        if self.groups.iter().any(|g| Arc::ptr_eq(&g.model, model)) {
+            return Err(anyhow!("Model {:?} is still used by an instance group", id));
+        }
+        if self.dynamic_meshes.values().any(|(m, _)| Arc::ptr_eq(m, model)) {
+            return Err(anyhow!("Model {:?} is still used by a dynamic mesh", id));
+        }
+        if self.cameras.iter().any(|c| matches!(c.target, RenderTarget::Material { model, .. } if model == id)) {
+            return Err(anyhow!("Model {:?} is still rendered to by a camera", id));
+        }
+        self.models.remove(&id);
+        self.model_assets.retain(|_, m| *m != id);
+        Ok(())
+    }
+
+    // Layouts for building materials and cameras that work with the engine's pipeline.
+    pub fn layouts(&self) -> &BindGroupLayouts {
+        &self.layouts
+    }
+
+    // The main camera, the one scenes are saved and loaded with.
+    pub fn camera(&self) -> &Camera {
+        &self.cameras[0].camera
+    }
+
+    pub fn camera_mut(&mut self) -> &mut Camera {
+        &mut self.cameras[0].camera
+    }
+
+    pub fn main_camera(&self) -> CameraId {
+        self.camera_ids[0]
+    }
+
+    // Adds a camera drawing to `viewport` of `target`, e.g. one per player for
+    // split-screen or a small one on top for a minimap.
+    pub fn create_camera(&mut self, desc: &CameraDesc, viewport: Viewport, target: RenderTarget) -> Result<CameraId> {
+        let size = target_size(&self.models, self.context.size, target)?;
+        let mut camera = Camera::new(
+            camera::LookAt::new(desc.eye, desc.target, desc.up),
+            desc.projection.build(size),
+            &self.context.device,
+            &self.layouts.camera,
+        );
+        camera.update(&self.context.queue);
+        let id = CameraId(self.next_id());
+        self.cameras
+            .push(CameraView::new(camera, viewport, target, &self.context.device, &self.context.adapter));
+        self.camera_ids.push(id);
+        Ok(id)
+    }
+
+    // The main camera can't be removed.
+    pub fn remove_camera(&mut self, id: CameraId) -> Result<()> {
+        let index = self.camera_index(id)?;
+        if index == 0 {
+            return Err(anyhow!("The main camera can't be removed"));
+        }
+        self.camera_ids.remove(index);
+        self.cameras.remove(index);
+        Ok(())
+    }
+
+    fn camera_index(&self, id: CameraId) -> Result<usize> {
+        self.camera_ids
+            .iter()
+            .position(|c| *c == id)
+            .ok_or_else(|| anyhow!("Camera {:?} not found", id))
+    }
+
+    pub fn camera_view(&self, id: CameraId) -> Result<&CameraView> {
+        Ok(&self.cameras[self.camera_index(id)?])
+    }
+
+    pub fn camera_view_mut(&mut self, id: CameraId) -> Result<&mut CameraView> {
+        let index = self.camera_index(id)?;
+        Ok(&mut self.cameras[index])
+    }
+
+    pub fn camera_ids(&self) -> &[CameraId] {
+        &self.camera_ids
+    }
+
+    // The camera drawn on top at `pixel` of the window, e.g. to know which
+    // split-screen view the mouse is over before calling `CameraView::screen_to_ray`.
+    pub fn camera_at(&self, pixel: ultraviolet::Vec2) -> Option<CameraId> {
+        self.cameras
+            .iter()
+            .zip(&self.camera_ids)
+            .filter(|(view, _)| {
+                view.enabled && view.target == RenderTarget::Surface && view.contains(pixel, self.context.size)
+            })
+            .max_by_key(|(view, _)| view.order)
+            .map(|(_, id)| *id)
+    }
+
+    // A texture in the surface's format for a `RenderTarget::Material`: build
+    // a `Material` from it and a model using that material.
+    pub fn create_render_texture(&self, width: u32, height: u32, label: &str) -> texture::Texture {
+        texture::Texture::create_render_target(&self.context.device, width, height, self.context.config.format, label)
+    }
+
+    pub fn create_group(&mut self, model: ModelId) -> Result<GroupId> {
+        let model = self.model(model)?.clone();
+        let id = GroupId(self.next_id());
+        self.groups.push(InstanceManager::new(&self.context.device, model));
+        self.group_ids.push(id);
+        Ok(id)
+    }
+
+    // Returns the group's instances so they can be moved elsewhere.
+    pub fn destroy_group(&mut self, id: GroupId) -> Result<InstanceManager> {
+        let index = self.group_index(id)?;
+        self.group_ids.remove(index);
+        Ok(self.groups.remove(index))
+    }
+
+    // Position of the group in `groups`, which shifts when earlier groups are destroyed.
+    pub fn group_index(&self, id: GroupId) -> Result<usize> {
+        self.group_ids
+            .iter()
+            .position(|g| *g == id)
+            .ok_or_else(|| anyhow!("Instance group {:?} not found", id))
+    }
+
+    pub fn group(&self, id: GroupId) -> Result<&InstanceManager> {
+        Ok(&self.groups[self.group_index(id)?])
+    }
+
+    pub fn group_mut(&mut self, id: GroupId) -> Result<&mut InstanceManager> {
+        let index = self.group_index(id)?;
+        Ok(&mut self.groups[index])
+    }
+
+    pub fn group_ids(&self) -> &[GroupId] {
+        &self.group_ids
+    }
+
+    // All groups in creation order.
+    pub fn groups_mut(&mut self) -> &mut [InstanceManager] {
+        &mut self.groups
+    }
+
+    // Writes the graph's changed world transforms into the instance groups
+    // their nodes are bound to.
+    pub fn sync_scene_graph(&mut self, graph: &mut SceneGraph) -> Result<()> {
+        graph.sync_instances(self)
+    }
+
+    pub fn add_instance(&mut self, group: GroupId, instance: Instance) -> Result<()> {
+        let index = self.group_index(group)?;
+        self.groups[index].add_instance(&self.context.device, &self.context.queue, instance);
+        Ok(())
+    }
+
+    pub fn update_instance(&mut self, group: GroupId, instance: Instance) -> Result<()> {
+        let index = self.group_index(group)?;
+        self.groups[index].update_instance(&self.context.queue, instance)
+    }
+
+    pub fn remove_instance(&mut self, group: GroupId, instance_id: u128) -> Result<Instance> {
+        self.group_mut(group)?.remove_instance(instance_id)
+    }
+
+    // A mesh drawn with `model`'s material `material`, flushed every frame
+    // with `buffering` buffer sets, see `DynamicMesh::new`.
+    pub fn create_dynamic_mesh(&mut self, name: &str, model: ModelId, material: usize, buffering: usize) -> Result<DynamicMeshId> {
+        let model = self.model(model)?.clone();
+        material_texture(&model, material)?;
+        let mesh = DynamicMesh::new(&self.context.device, name, material, buffering);
+        let id = DynamicMeshId(self.next_id());
+        self.dynamic_meshes.insert(id, (model, mesh));
+        Ok(id)
+    }
+
+    pub fn dynamic_mesh(&self, id: DynamicMeshId) -> Result<&DynamicMesh> {
+        self.dynamic_meshes
+            .get(&id)
+            .map(|(_, mesh)| mesh)
+            .ok_or_else(|| anyhow!("Dynamic mesh {:?} not found", id))
+    }
+
+    pub fn dynamic_mesh_mut(&mut self, id: DynamicMeshId) -> Result<&mut DynamicMesh> {
+        self.dynamic_meshes
+            .get_mut(&id)
+            .map(|(_, mesh)| mesh)
+            .ok_or_else(|| anyhow!("Dynamic mesh {:?} not found", id))
+    }
+
+    pub fn remove_dynamic_mesh(&mut self, id: DynamicMeshId) -> Result<DynamicMesh> {
+        self.dynamic_meshes
+            .remove(&id)
+            .map(|(_, mesh)| mesh)
+            .ok_or_else(|| anyhow!("Dynamic mesh {:?} not found", id))
+    }
+
+    // Destroys every instance group, dynamic mesh and camera but the main one
+    // and forgets every model.
+    pub fn clear_scene(&mut self) {
+        self.cameras.truncate(1);
+        self.camera_ids.truncate(1);
+        self.groups.clear();
+        self.group_ids.clear();
+        self.dynamic_meshes.clear();
+        self.models.clear();
+        self.model_assets.clear();
+    }
+
+    // Replaces the current content with the scene's settings, camera, lights
+    // and instance groups.
+    pub async fn load_scene(&mut self, desc: &SceneDesc) -> Result<Vec<GroupId>> {
+        self.clear_scene();
+        self.settings = desc.settings;
+        self.context.config.present_mode = if desc.settings.vsync {
+            wgpu::PresentMode::AutoVsync
+        } else {
+            wgpu::PresentMode::AutoNoVsync
+        };
+        self.context.surface.configure(&self.context.device, &self.context.config);
+        let size = self.context.size;
+        self.camera_mut().set_desc(&desc.camera, size);
+        self.lights = desc.lights.clone();
+
+        let mut groups = Vec::with_capacity(desc.groups.len());
+        for group in &desc.groups {
+            let model = self.load_model(&group.model).await?;
+            let id = self.create_group(model)?;
+            for instance in &group.instances {
+                self.add_instance(id, *instance)?;
+            }
+            groups.push(id);
+        }
+        Ok(groups)
+    }
+
+    // Captures the current settings, camera, lights and instance groups. Every
+    // group's model must have been loaded from an asset.
+    pub fn scene_desc(&self) -> Result<SceneDesc> {
+        let groups = self
+            .groups
+            .iter()
+            .map(|manager| {
+                let model = manager.model.source.clone().ok_or_else(|| anyhow!("Model was not loaded from an asset"))?;
+                Ok(InstanceGroupDesc {
+                    model,
+                    instances: manager.live_instances().copied().collect(),
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(SceneDesc {
+            settings: self.settings,
+            camera: self.camera().desc(),
+            lights: self.lights.clone(),
+            groups,
+        })
+    }
+
+    // 2D sprites, queue them with `SpriteRenderer::draw` every frame.
+    pub fn sprites(&self) -> &SpriteRenderer {
+        &self.sprites
+    }
+
+    pub fn sprites_mut(&mut self) -> &mut SpriteRenderer {
+        &mut self.sprites
+    }
+
+    // Loads an image from the asset directory for use by sprites.
+    pub async fn load_sprite_texture(&mut self, file_name: &str) -> Result<SpriteTextureId> {
+        let texture = resources::load_texture(file_name, &self.context.device, &self.context.queue).await?;
+        let material = model::Material::new(&self.context.device, file_name, texture, &self.layouts.texture);
+        Ok(self.sprites.add_texture(material))
+    }
+
+    // Loads a TexturePacker or Aseprite JSON sheet and the image it names,
+    // with the Aseprite tags if there are any.
+    pub async fn load_sprite_sheet(&mut self, file_name: &str) -> Result<SpriteSheet> {
+        let text = resources::load_string(file_name).await?;
+        let data = atlas::parse_sheet_json(&text).with_context(|| file_name.to_string())?;
+        let image = atlas::sibling_path(file_name, &data.image);
+        let texture = self.load_sprite_texture(&image).await?;
+        let mut sheet = SpriteSheet::new(&[(texture, self.sprites.texture_size(texture)?)], data.frames)?;
+        sheet.tags = data.tags;
+        Ok(sheet)
+    }
+
+    // Loads an image cut into equal cells.
+    pub async fn load_sprite_grid(&mut self, file_name: &str, grid: &SheetGrid) -> Result<SpriteSheet> {
+        let texture = self.load_sprite_texture(file_name).await?;
+        let size = self.sprites.texture_size(texture)?;
+        SpriteSheet::new(&[(texture, size)], grid.frames(size.x as u32, size.y as u32)?)
+    }
+
+    // Uploads the pages of a packed atlas for use by sprites.
+    pub fn add_sprite_atlas(&mut self, atlas: &PackedAtlas, label: &str) -> Result<SpriteSheet> {
+        let mut pages = Vec::with_capacity(atlas.pages.len());
+        for (i, page) in atlas.pages.iter().enumerate() {
+            let label = format!("{} page {}", label, i);
+            let texture = self.atlas_page_texture(page, &label)?;
+            let material = model::Material::new(&self.context.device, &label, texture, &self.layouts.texture);
+            let size = ultraviolet::Vec2::new(page.width() as f32, page.height() as f32);
+            pages.push((self.sprites.add_texture(material), size));
+        }
+        SpriteSheet::new(&pages, atlas.frames.clone())
+    }
+
+    // A one pixel texture of a single color, e.g. white for `Theme::texture`
+    // to tint into flat UI backgrounds.
+    pub fn add_solid_sprite_texture(&mut self, color: [u8; 4], label: &str) -> Result<SpriteTextureId> {
+        let texture = self.atlas_page_texture(&image::RgbaImage::from_pixel(1, 1, image::Rgba(color)), label)?;
+        let material = model::Material::new(&self.context.device, label, texture, &self.layouts.texture);
+        Ok(self.sprites.add_texture(material))
+    }
+
+    // A quad textured with one page of a packed atlas. Draw single frames
+    // with `Instance::uv` set to theirs, scaled to the frame's aspect.
+    pub fn add_atlas_page_model(&mut self, atlas: &PackedAtlas, page: usize, label: &str) -> Result<ModelId> {
+        let image = atlas.pages.get(page).ok_or_else(|| anyhow!("Atlas has no page {}", page))?;
+        let texture = self.atlas_page_texture(image, label)?;
+        let model = model::texture_to_model(texture, &self.layouts.texture, &self.context.device, label);
+        Ok(self.add_model(model))
+    }
+
+    fn atlas_page_texture(&self, page: &image::RgbaImage, label: &str) -> Result<texture::Texture> {
+        texture::Texture::from_image(
+            &self.context.device,
+            &self.context.queue,
+            &image::DynamicImage::ImageRgba8(page.clone()),
+            Some(label),
+        )
+    }
+
+    // Tilemaps drawn with the sprites, add them with `TilemapRenderer::add`
+    // and call `TilemapRenderer::update` every frame to animate their tiles.
+    pub fn tilemaps(&self) -> &TilemapRenderer {
+        &self.tilemaps
+    }
+
+    pub fn tilemaps_mut(&mut self) -> &mut TilemapRenderer {
+        &mut self.tilemaps
+    }
+
+    // Loads an image from the asset directory cut into tiles of the given size in pixels.
+    pub async fn load_tileset(&mut self, file_name: &str, tile_width: u32, tile_height: u32) -> Result<Tileset> {
+        let texture = self.load_sprite_texture(file_name).await?;
+        let size = self.sprites.texture_size(texture)?;
+        Ok(Tileset::new(file_name, texture, size, tile_width, tile_height))
+    }
+
+    // Loads a Tiled `.tmx` or `.tmj` map and its tileset images, one world
+    // unit per pixel. Add it with `TilemapRenderer::add` to draw it.
+    pub async fn load_tiled_map(&mut self, file_name: &str) -> Result<Tilemap> {
+        let tiled = tiled::TiledMap::load(file_name).await?;
+        let mut textures = Vec::with_capacity(tiled.tilesets.len());
+        for tileset in &tiled.tilesets {
+            let texture = self.load_sprite_texture(&tileset.image).await?;
+            textures.push((texture, self.sprites.texture_size(texture)?));
+        }
+        tiled.build(&textures, 1.0).with_context(|| file_name.to_string())
+    }
+
+    // Text drawn over the scene, queue it with `TextRenderer::draw` every frame.
+    pub fn text(&self) -> &TextRenderer {
+        &self.text
+    }
+
+    pub fn text_mut(&mut self) -> &mut TextRenderer {
+        &mut self.text
+    }
+
+    // Lays out `ui` in the main view's viewport and queues it for this
+    // frame, call every frame before `render`.
+    pub fn draw_ui(&mut self, ui: &mut Ui) -> Result<()> {
+        let view = &self.cameras[0];
+        let (x, y, width, height) = view.viewport.pixels(target_size(&self.models, self.context.size, view.target)?);
+        let screen = UiRect::new(ultraviolet::Vec2::new(x, y), ultraviolet::Vec2::new(width, height));
+        ui.layout(screen, &self.text)?;
+        ui.draw(&mut self.sprites, &mut self.text);
+        Ok(())
+    }
+
+    // Debug lines and labels, see `DebugDraw`.
+    pub fn debug(&self) -> &DebugDraw {
+        &self.debug
+    }
+
+    pub fn debug_mut(&mut self) -> &mut DebugDraw {
+        &mut self.debug
+    }
+
+    // Loads a .ttf, .otf or .ttc font from the asset directory.
+    pub async fn load_font(&mut self, file_name: &str) -> Result<FontId> {
+        let data = resources::load_binary(file_name).await?;
+        let font = font::Font::from_bytes(data).with_context(|| file_name.to_string())?;
+        Ok(self.text.add_font(font))
+    }
+
+    // The instance drawn at pixel (x, y) of the window, as of the last `update`.
+    pub async fn pick(&mut self, x: u32, y: u32) -> Result<Option<PickHit>> {
+        let rect = PickRect {
+            x,
+            y,
+            width: 1,
+            height: 1,
+        };
+        Ok(self.pick_rect(rect).await?.into_iter().next())
+    }
+
+    // Every instance with at least one pixel in `rect`, e.g. for box selection.
+    pub async fn pick_rect(&mut self, rect: PickRect) -> Result<Vec<PickHit>> {
+        let size = self.context.size;
+        let x = rect.x.min(size.width);
+        let y = rect.y.min(size.height);
+        let rect = PickRect {
+            x,
+            y,
+            width: rect.width.min(size.width - x),
+            height: rect.height.min(size.height - y),
+        };
+        let mut views: Vec<&CameraView> = self
+            .cameras
+            .iter()
+            .filter(|view| view.enabled && view.target == RenderTarget::Surface)
+            .collect();
+        if rect.width == 0 || rect.height == 0 || views.is_empty() {
+            return Ok(Vec::new());
+        }
+        views.sort_by_key(|view| view.order);
+
+        let (device, layouts) = (&self.context.device, &self.layouts);
+        let picker = self.picker.get_or_insert_with(|| Picker::new(device, layouts, size));
+        picker.resize(device, size);
+        picker
+            .pick(device, &self.context.queue, &views, &self.groups, &self.group_ids, rect)
+            .await
+    }
+
+    // Switches how instanced meshes are drawn, e.g. to wireframe or normals
+    // for checking imported models.
+    pub fn set_render_mode(&mut self, mode: RenderMode) {
+        self.render_modes.mode = mode;
+    }
+
+    pub fn render_mode(&self) -> RenderMode {
+        self.render_modes.mode
+    }
+
+    pub fn render_modes(&self) -> &RenderModes {
+        &self.render_modes
+    }
+
+    pub fn render_modes_mut(&mut self) -> &mut RenderModes {
+        &mut self.render_modes
+    }
+
+    // The egui layer drawn over every frame, see `Gui`.
+    pub fn gui(&self) -> &Gui {
+        &self.gui
+    }
+
+    pub fn gui_mut(&mut self) -> &mut Gui {
+        &mut self.gui
+    }
+
+    // Builds this frame's egui UI, drawn over the scene by the next `render`.
+    pub fn run_gui(&mut self, ui: impl FnMut(&egui::Context)) {
+        let window = self.context.window;
+        let (width, height) = window.size();
+        let pixels_per_point = window.drawable_size().0 as f32 / width.max(1) as f32;
+        self.gui.run(WindowSize { width, height }, pixels_per_point, ui);
+    }
+
+    // Makes `texture` showable with `egui::Image`, e.g. a render texture as a preview.
+    pub fn register_gui_texture(&mut self, texture: &texture::Texture, filter: wgpu::FilterMode) -> egui::TextureId {
+        self.gui.register_texture(&self.context.device, texture, filter)
+    }
+
+    pub fn update_gui_texture(&mut self, id: egui::TextureId, texture: &texture::Texture, filter: wgpu::FilterMode) {
+        self.gui.update_texture(&self.context.device, id, texture, filter);
+    }
+
     pub fn update(&mut self) -> Result<()> {
-        log::info!("{:?}", self.camera);
-        self.camera.update(&self.context.queue);
+        log::info!("{:?}", self.camera());
+        for view in &mut self.cameras {
+            let size = target_size(&self.models, self.context.size, view.target)?;
+            view.fit(size);
+            view.camera.update(&self.context.queue);
+        }
         Ok(())
     }
 
-    pub fn render(&mut self, to_draw: &[InstanceManager]) -> Result<()> {
+    pub fn render(&mut self) -> Result<()> {
         let output = self.context.surface.get_current_texture()?;
-        let view = output
+        let surface_view = output
             .texture
             .create_view(&wgpu::TextureViewDescriptor::default());
 
@@ -234,26 +714,87 @@ impl<'w> WgpuEngine<'w> {
                 label: Some("Render Encoder"),
             });
 
-        {
+        let clear_color = self.render_modes.clear_color(wgpu::Color {
+            r: self.settings.clear_color[0] as f64,
+            g: self.settings.clear_color[1] as f64,
+            b: self.settings.clear_color[2] as f64,
+            a: self.settings.clear_color[3] as f64,
+        });
+
+        // Sorting is stable, so views with the same order keep their creation order.
+        let mut order: Vec<usize> = (0..self.cameras.len()).filter(|&i| self.cameras[i].enabled).collect();
+        order.sort_by_key(|&i| self.cameras[i].order);
+        let mut cleared: Vec<RenderTarget> = Vec::new();
+        self.tilemaps.prepare(&self.context.device, &self.context.queue, &self.cameras);
+        let viewport_sizes = self
+            .cameras
+            .iter()
+            .map(|view| {
+                let (_, _, width, height) = view.viewport.pixels(target_size(&self.models, self.context.size, view.target)?);
+                Ok(ultraviolet::Vec2::new(width, height))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        self.sprites.prepare(&self.context.device, &self.context.queue, &viewport_sizes);
+        self.debug_renderer.prepare(&self.context.device, &self.context.queue, &mut self.text, &self.debug);
+        self.text.prepare(&self.context.device, &self.context.queue, &self.cameras, &viewport_sizes);
+        self.render_modes.prepare(&self.context.device, &self.context.queue, &self.cameras, &self.groups)?;
+        for (_, mesh) in self.dynamic_meshes.values_mut() {
+            mesh.flush(&self.context.device, &self.context.queue);
+        }
+
+        for i in order {
+            let view = &mut self.cameras[i];
+            let (target_view, size, target_model) = match view.target {
+                RenderTarget::Surface => (&surface_view, self.context.size, None),
+                RenderTarget::Material { model, material } => {
+                    let model = self.models.get(&model).ok_or_else(|| anyhow!("Model {:?} not found", model))?;
+                    let texture = material_texture(model, material)?;
+                    let size = WindowSize {
+                        width: texture.texture.width(),
+                        height: texture.texture.height(),
+                    };
+                    (&texture.view, size, Some(model))
+                }
+            };
+            let ((x, y, width, height), scissor) = match view.pass_rects(size) {
+                Some(rects) => rects,
+                None => continue,
+            };
+
+            // A model can't show a texture while it is being drawn into.
+            view.indirect.prepare_filtered(
+                &self.context.device,
+                &self.context.queue,
+                &self.groups,
+                &view.camera,
+                |manager| target_model.is_none_or(|model| !Arc::ptr_eq(&manager.model, model)),
+            );
+            view.indirect.cull(&mut encoder);
+            view.fit_depth(&self.context.device, size);
+            let depth_mode = view.camera.depth_mode();
+
+            // The first view drawing to a target clears all of it.
+            let load = if cleared.contains(&view.target) {
+                wgpu::LoadOp::Load
+            } else {
+                cleared.push(view.target);
+                wgpu::LoadOp::Clear(clear_color)
+            };
+
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Render Pass"),
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
+                    view: target_view,
                     resolve_target: None,
                     ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.1,
-                            g: 0.2,
-                            b: 0.3,
-                            a: 1.0,
-                        }),
+                        load,
                         store: wgpu::StoreOp::Store,
                     },
                 })],
                 depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                    view: &self.depth_texture.view,
+                    view: &view.depth_texture.view,
                     depth_ops: Some(wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(1.0),
+                        load: wgpu::LoadOp::Clear(depth_mode.clear_value()),
                         store: wgpu::StoreOp::Store,
                     }),
                     stencil_ops: None,
@@ -262,15 +803,188 @@ impl<'w> WgpuEngine<'w> {
                 timestamp_writes: None,
             });
 
-            render_pass.set_pipeline(&self.render_pipeline);
-            for i in to_draw {
-                render_pass.draw_instances(i, &self.camera.bind_group());
+            render_pass.set_viewport(x, y, width, height, 0.0, 1.0);
+            render_pass.set_scissor_rect(scissor.0, scissor.1, scissor.2, scissor.3);
+            let draws_meshes = if self.render_modes.mode == RenderMode::Shaded {
+                render_pass.set_pipeline(match depth_mode {
+                    DepthMode::Standard => &self.render_pipeline,
+                    DepthMode::Reversed => &self.reverse_z_pipeline,
+                });
+                render_pass.draw_instances_indirect(&view.indirect, &self.groups, view.camera.bind_group());
+                true
+            } else {
+                self.render_modes
+                    .draw(&mut render_pass, i, &view.indirect, &self.groups, view.camera.bind_group(), depth_mode)
+            };
+            // With the pipeline the instance groups were drawn with.
+            if draws_meshes {
+                for (model, mesh) in self.dynamic_meshes.values() {
+                    if let Some(material) = model.materials.get(mesh.material) {
+                        render_pass.draw_dynamic_mesh(mesh, material, view.camera.bind_group());
+                    }
+                }
+            }
+            if view.sprites {
+                let camera = view.camera.bind_group();
+                self.tilemaps.draw_layers(&mut render_pass, i, camera, depth_mode, &self.sprites, false);
+                self.sprites.draw_batches(&mut render_pass, camera, depth_mode);
+                self.tilemaps.draw_layers(&mut render_pass, i, camera, depth_mode, &self.sprites, true);
+            }
+            self.debug_renderer.draw(&mut render_pass, view.camera.bind_group(), depth_mode);
+            self.text.draw_world(&mut render_pass, i, view.camera.bind_group(), depth_mode);
+            if view.hud {
+                self.sprites.draw_screen_batches(&mut render_pass, i, view.camera.bind_group());
+                self.text.draw_screen(&mut render_pass, i, view.camera.bind_group());
             }
         }
 
-        self.context.queue.submit(iter::once(encoder.finish()));
+        // Nothing drew to the window this frame, it still gets cleared.
+        if !cleared.contains(&RenderTarget::Surface) {
+            encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Clear Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &surface_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(clear_color),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+        }
+
+        // The UI goes over everything drawn to the window.
+        let callbacks = self.gui.prepare(&self.context.device, &self.context.queue, &mut encoder, self.context.size);
+        if self.gui.has_frame() {
+            let mut pass = encoder
+                .begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("GUI Pass"),
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: &surface_view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Load,
+                            store: wgpu::StoreOp::Store,
+                        },
+                    })],
+                    depth_stencil_attachment: None,
+                    occlusion_query_set: None,
+                    timestamp_writes: None,
+                })
+                .forget_lifetime();
+            self.gui.draw(&mut pass, self.context.size);
+        }
+
+        self.context.queue.submit(callbacks.into_iter().chain(iter::once(encoder.finish())));
         output.present();
+        self.sprites.finish_frame();
+        self.text.finish_frame();
+        self.debug.finish_frame();
+        self.gui.finish_frame();
 
         Ok(())
     }
 }
+
+impl InstanceGroups for WgpuEngine<'_> {
+    fn add_instance(&mut self, group: GroupId, instance: Instance) -> Result<()> {
+        WgpuEngine::add_instance(self, group, instance)
+    }
+
+    fn update_instance(&mut self, group: GroupId, instance: Instance) -> Result<()> {
+        WgpuEngine::update_instance(self, group, instance)
+    }
+
+    fn remove_instance(&mut self, group: GroupId, instance_id: u128) -> Result<Instance> {
+        WgpuEngine::remove_instance(self, group, instance_id)
+    }
+}
+
+fn material_texture(model: &Model, material: usize) -> Result<&texture::Texture> {
+    model
+        .materials
+        .get(material)
+        .map(|m| &m.diffuse_texture)
+        .ok_or_else(|| anyhow!("Model has no material {}", material))
+}
+
+// Size in pixels of what a camera draws to.
+fn target_size(models: &HashMap<ModelId, Arc<Model>>, surface: WindowSize, target: RenderTarget) -> Result<WindowSize> {
+    match target {
+        RenderTarget::Surface => Ok(surface),
+        RenderTarget::Material { model, material } => {
+            let model = models.get(&model).ok_or_else(|| anyhow!("Model {:?} not found", model))?;
+            let texture = &material_texture(model, material)?.texture;
+            Ok(WindowSize {
+                width: texture.width(),
+                height: texture.height(),
+            })
+        }
+    }
+}
+
+// The engine's pipeline for cameras whose projection uses `depth`.
+fn create_render_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+    depth: DepthMode,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Render Pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            buffers: &[model::ModelVertex::desc(), InstanceRaw::desc()],
+            compilation_options: Default::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: "fs_main",
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState {
+                    color: wgpu::BlendComponent::REPLACE,
+                    alpha: wgpu::BlendComponent::REPLACE,
+                }),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: Default::default(),
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: Some(wgpu::Face::Back),
+            // Setting this to anything other than Fill requires Features::POLYGON_MODE_LINE
+            // or Features::POLYGON_MODE_POINT
+            polygon_mode: wgpu::PolygonMode::Fill,
+            // Requires Features::DEPTH_CLIP_CONTROL
+            unclipped_depth: false,
+            // Requires Features::CONSERVATIVE_RASTERIZATION
+            conservative: false,
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: texture::Texture::DEPTH_FORMAT,
+            depth_write_enabled: true,
+            depth_compare: depth.compare(),
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState {
+            count: 1,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        // If the pipeline will be used with a multiview render pass, this
+        // indicates how many array layers the attachments will have.
+        multiview: None,
+        // Useful for optimizing shader compilation on Android
+        cache: None,
+    })
+}
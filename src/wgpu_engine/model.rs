@@ -1,6 +1,7 @@
+use anyhow::{anyhow, Result};
 use wgpu::util::DeviceExt;
 
-use super::texture;
+use super::{mesh_builder::MeshBuilder, texture};
 
 pub trait Vertex {
     fn desc() -> wgpu::VertexBufferLayout<'static>;
@@ -49,6 +50,127 @@ pub struct Material {
     pub bind_group: wgpu::BindGroup,
 }
 
+impl Material {
+    pub fn new(
+        device: &wgpu::Device,
+        name: &str,
+        diffuse_texture: texture::Texture,
+        layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
+                },
+            ],
+            label: Some(&format!("{:?} Bind Group", name)),
+        });
+
+        Self {
+            name: name.to_string(),
+            diffuse_texture,
+            bind_group,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Bounds {
+    pub min: ultraviolet::Vec3,
+    pub max: ultraviolet::Vec3,
+}
+
+impl Bounds {
+    pub fn from_positions<'p>(positions: impl IntoIterator<Item = &'p [f32; 3]>) -> Self {
+        let mut min = ultraviolet::Vec3::broadcast(f32::MAX);
+        let mut max = ultraviolet::Vec3::broadcast(f32::MIN);
+        for p in positions {
+            let p = ultraviolet::Vec3::from(*p);
+            min = min.min_by_component(p);
+            max = max.max_by_component(p);
+        }
+        if min.x > max.x {
+            return Self { min: ultraviolet::Vec3::zero(), max: ultraviolet::Vec3::zero() };
+        }
+        Self { min, max }
+    }
+
+    pub fn center(&self) -> ultraviolet::Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    // Radius of the sphere around `center` enclosing the whole box.
+    pub fn radius(&self) -> f32 {
+        (self.max - self.min).mag() * 0.5
+    }
+
+    pub fn union(&self, other: &Bounds) -> Bounds {
+        Bounds {
+            min: self.min.min_by_component(other.min),
+            max: self.max.max_by_component(other.max),
+        }
+    }
+}
+
+// CPU copy of a mesh's geometry, kept on `Mesh` when it is needed for picking,
+// collision or editing.
+#[derive(Debug, Clone, Default)]
+pub struct MeshData {
+    pub vertices: Vec<ModelVertex>,
+    pub indices: Vec<u32>,
+}
+
+impl MeshData {
+    pub fn new(vertices: Vec<ModelVertex>, indices: Vec<u32>) -> Self {
+        Self { vertices, indices }
+    }
+
+    pub fn positions(&self) -> impl Iterator<Item = ultraviolet::Vec3> + '_ {
+        self.vertices.iter().map(|v| v.position.into())
+    }
+
+    pub fn normals(&self) -> impl Iterator<Item = ultraviolet::Vec3> + '_ {
+        self.vertices.iter().map(|v| v.normal.into())
+    }
+
+    pub fn tex_coords(&self) -> impl Iterator<Item = ultraviolet::Vec2> + '_ {
+        self.vertices.iter().map(|v| v.tex_coords.into())
+    }
+
+    pub fn bounds(&self) -> Bounds {
+        Bounds::from_positions(self.vertices.iter().map(|v| &v.position))
+    }
+
+    pub fn triangles(&self) -> impl Iterator<Item = [ModelVertex; 3]> + '_ {
+        self.indices
+            .chunks_exact(3)
+            .map(move |t| [0, 1, 2].map(|i| self.vertices[t[i] as usize]))
+    }
+
+    pub fn append(&mut self, other: &MeshData) {
+        let base = self.vertices.len() as u32;
+        self.vertices.extend_from_slice(&other.vertices);
+        self.indices.extend(other.indices.iter().map(|i| i + base));
+    }
+
+    pub fn transform(&mut self, matrix: &ultraviolet::Mat4) {
+        let normal_matrix = matrix.inversed().transposed();
+        for vertex in self.vertices.iter_mut() {
+            vertex.position = matrix.transform_point3(vertex.position.into()).into();
+            let normal = normal_matrix.transform_vec3(vertex.normal.into());
+            if normal.mag_sq() > 0.0 {
+                vertex.normal = normal.normalized().into();
+            }
+        }
+    }
+}
+
 pub struct Mesh {
     #[allow(unused)]
     pub name: String,
@@ -56,11 +178,171 @@ pub struct Mesh {
     pub index_buffer: wgpu::Buffer,
     pub num_elements: u32,
     pub material: usize,
+    pub bounds: Bounds,
+    pub data: Option<MeshData>,
+    // Bumped by every `upload`, so caches built from the geometry know it changed.
+    generation: u32,
+}
+
+impl Mesh {
+    pub fn new(
+        device: &wgpu::Device,
+        name: &str,
+        vertices: &[ModelVertex],
+        indices: &[u32],
+        material: usize,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            vertex_buffer: create_vertex_buffer(device, name, vertices),
+            index_buffer: create_index_buffer(device, name, indices),
+            num_elements: indices.len() as u32,
+            material,
+            bounds: Bounds::from_positions(vertices.iter().map(|v| &v.position)),
+            data: None,
+            generation: 0,
+        }
+    }
+
+    // Like `new`, but keeps `data` on the mesh.
+    pub fn from_data(device: &wgpu::Device, name: &str, data: MeshData, material: usize) -> Self {
+        let mut mesh = Self::new(device, name, &data.vertices, &data.indices, material);
+        mesh.data = Some(data);
+        mesh
+    }
+
+    // Writes the edited `data` back to the GPU, growing the buffers if needed.
+    pub fn upload(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<()> {
+        let data = self.data.as_ref().ok_or_else(|| anyhow!("Mesh {} has no CPU data", self.name))?;
+        let vertices: &[u8] = bytemuck::cast_slice(&data.vertices);
+        let indices: &[u8] = bytemuck::cast_slice(&data.indices);
+        if vertices.len() as u64 > self.vertex_buffer.size() {
+            self.vertex_buffer.destroy();
+            self.vertex_buffer = create_vertex_buffer(device, &self.name, &data.vertices);
+        } else {
+            queue.write_buffer(&self.vertex_buffer, 0, vertices);
+        }
+        if indices.len() as u64 > self.index_buffer.size() {
+            self.index_buffer.destroy();
+            self.index_buffer = create_index_buffer(device, &self.name, &data.indices);
+        } else {
+            queue.write_buffer(&self.index_buffer, 0, indices);
+        }
+        self.num_elements = data.indices.len() as u32;
+        self.bounds = data.bounds();
+        self.generation += 1;
+        Ok(())
+    }
+
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    // Merges meshes that kept their CPU data into a single mesh.
+    pub fn merge(device: &wgpu::Device, name: &str, meshes: &[&Mesh], material: usize) -> Result<Mesh> {
+        let mut data = MeshData::default();
+        for mesh in meshes {
+            let other = mesh.data.as_ref().ok_or_else(|| anyhow!("Mesh {} has no CPU data", mesh.name))?;
+            data.append(other);
+        }
+        Ok(Self::from_data(device, name, data, material))
+    }
+}
+
+// COPY_SRC lets debug views read meshes back that didn't keep their data.
+fn create_vertex_buffer(device: &wgpu::Device, name: &str, vertices: &[ModelVertex]) -> wgpu::Buffer {
+    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some(&format!("{:?} Vertex Buffer", name)),
+        contents: bytemuck::cast_slice(vertices),
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+    })
+}
+
+fn create_index_buffer(device: &wgpu::Device, name: &str, indices: &[u32]) -> wgpu::Buffer {
+    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some(&format!("{:?} Index Buffer", name)),
+        contents: bytemuck::cast_slice(indices),
+        usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+    })
+}
+
+// How LOD thresholds are measured. Screen size is the height of the model's
+// bounding sphere as a fraction of the view height.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LodMetric {
+    Distance,
+    ScreenSize,
+}
+
+pub struct Lod {
+    pub meshes: Vec<Mesh>,
+    // Distance from which, or screen size below which, this level is used.
+    pub threshold: f32,
 }
 
 pub struct Model {
+    // The full detail meshes, used as LOD 0.
     pub meshes: Vec<Mesh>,
     pub materials: Vec<Material>,
+    // Coarser levels ordered from most to least detailed.
+    pub lods: Vec<Lod>,
+    pub lod_metric: LodMetric,
+    // Width of the cross-fade band relative to each threshold, 0 disables dithering.
+    pub lod_fade: f32,
+    // Asset the model was loaded from, used when saving scenes.
+    pub source: Option<String>,
+}
+
+impl Model {
+    pub fn new(meshes: Vec<Mesh>, materials: Vec<Material>) -> Self {
+        Self {
+            meshes,
+            materials,
+            lods: Vec::new(),
+            lod_metric: LodMetric::Distance,
+            lod_fade: 0.0,
+            source: None,
+        }
+    }
+
+    pub fn with_lods(mut self, lod_metric: LodMetric, lods: Vec<Lod>, lod_fade: f32) -> Self {
+        self.lod_metric = lod_metric;
+        self.lods = lods;
+        self.lod_fade = lod_fade;
+        self
+    }
+
+    pub fn lod_count(&self) -> usize {
+        self.lods.len() + 1
+    }
+
+    pub fn lod_meshes(&self, lod: usize) -> &[Mesh] {
+        if lod == 0 {
+            &self.meshes
+        } else {
+            &self.lods[lod - 1].meshes
+        }
+    }
+
+    // Range of the LOD metric in which `lod` is selected. For screen size the
+    // metric is the inverse of the screen size so that it grows with distance.
+    pub fn lod_range(&self, lod: usize) -> (f32, f32) {
+        let metric = |threshold: f32| match self.lod_metric {
+            LodMetric::Distance => threshold,
+            LodMetric::ScreenSize => 1.0 / threshold,
+        };
+        let min = if lod == 0 { 0.0 } else { metric(self.lods[lod - 1].threshold) };
+        let max = self.lods.get(lod).map_or(f32::MAX, |l| metric(l.threshold));
+        (min, max)
+    }
+
+    pub fn bounds(&self) -> Bounds {
+        self.meshes
+            .iter()
+            .map(|m| m.bounds)
+            .reduce(|a, b| a.union(&b))
+            .unwrap_or(Bounds { min: ultraviolet::Vec3::zero(), max: ultraviolet::Vec3::zero() })
+    }
 }
 
 pub fn texture_to_model (
@@ -70,71 +352,5 @@ pub fn texture_to_model (
     label: &str,
 ) -> Model {
     let aspect = texture.texture.width() as f32 / texture.texture.height() as f32;
-    let vertices= vec![
-        ModelVertex {
-            position: [-0.5 * aspect, -0.5, 0.0],
-            tex_coords: [0.0, 1.0],
-            normal: [0.0, 0.0, 1.0],
-        },
-        ModelVertex {
-            position: [-0.5 * aspect, 0.5, 0.0],
-            tex_coords: [0.0, 0.0],
-            normal: [0.0, 0.0, 1.0],
-        },
-        ModelVertex {
-            position: [0.5 * aspect, 0.5, 0.0],
-            tex_coords: [1.0, 0.0],
-            normal: [0.0, 0.0, 1.0],
-        },
-        ModelVertex {
-            position: [0.5 * aspect, -0.5, 0.0],
-            tex_coords: [1.0, 1.0],
-            normal: [0.0, 0.0, 1.0],
-        },
-    ];
-    let indices = vec![0, 1, 2, 2, 3, 0, /*padding*/ 0];
-    let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        label: Some(&format!("{:?} Vertex Buffer", label)),
-        contents: bytemuck::cast_slice(&vertices),
-        usage: wgpu::BufferUsages::VERTEX,
-    });
-    let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        label: Some(&format!("{:?} Index Buffer", label)),
-        contents: bytemuck::cast_slice(&indices),
-        usage: wgpu::BufferUsages::INDEX,
-    });
-
-    let mesh = Mesh {
-        name: label.to_string(),
-        vertex_buffer,
-        index_buffer,
-        num_elements: indices.len() as u32,
-        material: 0,
-    };
-
-    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-        layout,
-        entries: &[
-            wgpu::BindGroupEntry {
-                binding: 0,
-                resource: wgpu::BindingResource::TextureView(&texture.view),
-            },
-            wgpu::BindGroupEntry {
-                binding: 1,
-                resource: wgpu::BindingResource::Sampler(&texture.sampler),
-            },
-        ],
-        label: Some(&format!("{:?} Bind Group", label)),
-    });
-
-    let material = Material {
-        name: label.to_string(),
-        diffuse_texture: texture,
-        bind_group,
-    };
-
-    Model {
-        meshes: vec![mesh],
-        materials: vec![material],
-    }
-}
\ No newline at end of file
+    MeshBuilder::quad(aspect, 1.0).build_model(device, label, texture, layout)
+}
@@ -0,0 +1,389 @@
+use std::collections::HashSet;
+
+use anyhow::{anyhow, Result};
+
+use super::{
+    buffer::{create_buffer, create_uniform_bind_group, grow_buffer, push_uniform, uniform_stride},
+    camera::DepthMode,
+    instance::{join_id, InstanceManager, InstanceRaw},
+    layouts::BindGroupLayouts,
+    model::{ModelVertex, Vertex},
+    scene::GroupId,
+    texture::Texture,
+    view::CameraView,
+    WindowSize,
+};
+
+// What covers a pixel: the instance, its group and which of the model's
+// meshes. Picking always uses the most detailed LOD.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PickHit {
+    pub group: GroupId,
+    pub id: u128,
+    pub mesh: u32,
+}
+
+// A rectangle in whole pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PickRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct PickDraw {
+    // Group index + 1, 0 is left where nothing was drawn.
+    group: u32,
+    mesh: u32,
+    _padding: [u32; 2],
+}
+
+// Draws the same views as the screen into integer targets holding instance
+// ids, only when asked to. `WgpuEngine::pick` creates one on first use.
+pub struct Picker {
+    pipeline: wgpu::RenderPipeline,
+    reverse_z_pipeline: wgpu::RenderPipeline,
+    draw_layout: wgpu::BindGroupLayout,
+    draw_buffer: wgpu::Buffer,
+    draw_bind_group: wgpu::BindGroup,
+    // Distance between `PickDraw`s, rounded up for dynamic offsets.
+    draw_stride: u64,
+    id_texture: wgpu::Texture,
+    info_texture: wgpu::Texture,
+    depth_texture: Texture,
+    size: WindowSize,
+}
+
+impl Picker {
+    pub const ID_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba32Uint;
+    pub const INFO_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rg32Uint;
+
+    pub fn new(device: &wgpu::Device, layouts: &BindGroupLayouts, size: WindowSize) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("pick.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/pick.wgsl").into()),
+        });
+
+        let draw_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: wgpu::BufferSize::new(size_of::<PickDraw>() as u64),
+                },
+                count: None,
+            }],
+            label: Some("pick_draw_bind_group_layout"),
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Pick Pipeline Layout"),
+            bind_group_layouts: &[&draw_layout, &layouts.camera],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = create_pipeline(device, &layout, &shader, DepthMode::Standard);
+        let reverse_z_pipeline = create_pipeline(device, &layout, &shader, DepthMode::Reversed);
+
+        let draw_stride = uniform_stride::<PickDraw>(device);
+        let draw_buffer = create_buffer(device, "Pick Draw Buffer", draw_stride, wgpu::BufferUsages::UNIFORM);
+        let draw_bind_group = create_uniform_bind_group::<PickDraw>(device, &draw_layout, &draw_buffer, "pick_draw_bind_group");
+
+        Self {
+            pipeline,
+            reverse_z_pipeline,
+            draw_layout,
+            draw_buffer,
+            draw_bind_group,
+            draw_stride,
+            id_texture: create_target(device, size, Self::ID_FORMAT, "Pick Id Texture"),
+            info_texture: create_target(device, size, Self::INFO_FORMAT, "Pick Info Texture"),
+            depth_texture: Texture::create_depth_texture_sized(device, size.width, size.height, "pick_depth_texture"),
+            size,
+        }
+    }
+
+    // Call before `pick` when the window size changes.
+    pub fn resize(&mut self, device: &wgpu::Device, size: WindowSize) {
+        if self.size.width == size.width && self.size.height == size.height {
+            return;
+        }
+        self.id_texture = create_target(device, size, Self::ID_FORMAT, "Pick Id Texture");
+        self.info_texture = create_target(device, size, Self::INFO_FORMAT, "Pick Info Texture");
+        self.depth_texture = Texture::create_depth_texture_sized(device, size.width, size.height, "pick_depth_texture");
+        self.size = size;
+    }
+
+    // Draws `views`, in the order given, into the pick targets and reads
+    // back what covers `rect`. Every hit is reported once, in the order it is
+    // first found going through the rect row by row.
+    pub async fn pick(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        views: &[&CameraView],
+        groups: &[InstanceManager],
+        group_ids: &[GroupId],
+        rect: PickRect,
+    ) -> Result<Vec<PickHit>> {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Pick Encoder"),
+        });
+        self.draw(device, queue, &mut encoder, views, groups);
+
+        let id_buffer = self.copy_rect(device, &mut encoder, &self.id_texture, 16, rect);
+        let info_buffer = self.copy_rect(device, &mut encoder, &self.info_texture, 8, rect);
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let ids = read_buffer(device, &id_buffer).await?;
+        let infos = read_buffer(device, &info_buffer).await?;
+        let id_row = padded_row(rect.width * 16) as usize;
+        let info_row = padded_row(rect.width * 8) as usize;
+
+        let mut hits = Vec::new();
+        let mut seen = HashSet::new();
+        for y in 0..rect.height as usize {
+            for x in 0..rect.width as usize {
+                let info: [u32; 2] = bytemuck::pod_read_unaligned(&infos[y * info_row + x * 8..][..8]);
+                if info[0] == 0 {
+                    continue;
+                }
+                let id: [u32; 4] = bytemuck::pod_read_unaligned(&ids[y * id_row + x * 16..][..16]);
+                let group = *group_ids
+                    .get(info[0] as usize - 1)
+                    .ok_or_else(|| anyhow!("Picked unknown group {}", info[0] - 1))?;
+                let hit = PickHit {
+                    group,
+                    id: join_id(id),
+                    mesh: info[1],
+                };
+                if seen.insert(hit) {
+                    hits.push(hit);
+                }
+            }
+        }
+        Ok(hits)
+    }
+
+    fn draw(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        views: &[&CameraView],
+        groups: &[InstanceManager],
+    ) {
+        // One `PickDraw` per mesh of every group, they are the same for all views.
+        let mut draws = Vec::new();
+        let mut bytes = Vec::new();
+        for (g, manager) in groups.iter().enumerate() {
+            let ranges = manager.live_ranges();
+            if ranges.is_empty() {
+                continue;
+            }
+            for (mesh, _) in manager.model.lod_meshes(0).iter().enumerate() {
+                let draw = PickDraw {
+                    group: g as u32 + 1,
+                    mesh: mesh as u32,
+                    _padding: [0; 2],
+                };
+                push_uniform(&mut bytes, self.draw_stride, &draw);
+                draws.push((g, mesh, (draws.len() as u64 * self.draw_stride) as u32, ranges.clone()));
+            }
+        }
+        if grow_buffer(device, &mut self.draw_buffer, "Pick Draw Buffer", bytes.len() as u64) {
+            self.draw_bind_group = create_uniform_bind_group::<PickDraw>(device, &self.draw_layout, &self.draw_buffer, "pick_draw_bind_group");
+        }
+        queue.write_buffer(&self.draw_buffer, 0, &bytes);
+
+        let id_view = self.id_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let info_view = self.info_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        for (i, view) in views.iter().enumerate() {
+            // Views drawn later cover earlier ones, like on screen.
+            let load = if i == 0 {
+                wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT)
+            } else {
+                wgpu::LoadOp::Load
+            };
+            let depth_mode = view.camera.depth_mode();
+            let attachment = |view| {
+                Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })
+            };
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Pick Pass"),
+                color_attachments: &[attachment(&id_view), attachment(&info_view)],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.depth_texture.view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(depth_mode.clear_value()),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+            let ((x, y, width, height), scissor) = match view.pass_rects(self.size) {
+                Some(rects) => rects,
+                None => continue,
+            };
+            pass.set_viewport(x, y, width, height, 0.0, 1.0);
+            pass.set_scissor_rect(scissor.0, scissor.1, scissor.2, scissor.3);
+            pass.set_pipeline(match depth_mode {
+                DepthMode::Standard => &self.pipeline,
+                DepthMode::Reversed => &self.reverse_z_pipeline,
+            });
+            pass.set_bind_group(1, view.camera.bind_group(), &[]);
+
+            for (g, mesh, offset, ranges) in &draws {
+                let manager = &groups[*g];
+                let mesh = &manager.model.lod_meshes(0)[*mesh];
+                pass.set_bind_group(0, &self.draw_bind_group, &[*offset]);
+                pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+                pass.set_vertex_buffer(1, manager.instance_buffer.slice(..));
+                pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                for range in ranges {
+                    pass.draw_indexed(0..mesh.num_elements, 0, range.clone());
+                }
+            }
+        }
+    }
+
+    fn copy_rect(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+        bytes_per_pixel: u32,
+        rect: PickRect,
+    ) -> wgpu::Buffer {
+        let bytes_per_row = padded_row(rect.width * bytes_per_pixel);
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Pick Readback Buffer"),
+            size: (bytes_per_row * rect.height) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d {
+                    x: rect.x,
+                    y: rect.y,
+                    z: 0,
+                },
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(bytes_per_row),
+                    rows_per_image: Some(rect.height),
+                },
+            },
+            wgpu::Extent3d {
+                width: rect.width,
+                height: rect.height,
+                depth_or_array_layers: 1,
+            },
+        );
+        buffer
+    }
+}
+
+// Texture to buffer copies need rows aligned to 256 bytes.
+fn padded_row(bytes: u32) -> u32 {
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    bytes.div_ceil(align) * align
+}
+
+async fn read_buffer(device: &wgpu::Device, buffer: &wgpu::Buffer) -> Result<Vec<u8>> {
+    let slice = buffer.slice(..);
+    let (sender, receiver) = tokio::sync::oneshot::channel();
+    slice.map_async(wgpu::MapMode::Read, move |result| {
+        let _ = sender.send(result);
+    });
+    device.poll(wgpu::Maintain::Wait);
+    receiver.await??;
+    let data = slice.get_mapped_range().to_vec();
+    buffer.unmap();
+    Ok(data)
+}
+
+fn create_target(device: &wgpu::Device, size: WindowSize, format: wgpu::TextureFormat, label: &str) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d {
+            width: size.width.max(1),
+            height: size.height.max(1),
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    })
+}
+
+fn create_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    depth: DepthMode,
+) -> wgpu::RenderPipeline {
+    let target = |format| {
+        Some(wgpu::ColorTargetState {
+            format,
+            blend: None,
+            write_mask: wgpu::ColorWrites::ALL,
+        })
+    };
+device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Pick Pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            buffers: &[ModelVertex::desc(), InstanceRaw::desc()],
+            compilation_options: Default::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: "fs_main",
+            targets: &[target(Picker::ID_FORMAT), target(Picker::INFO_FORMAT)],
+            compilation_options: Default::default(),
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: Some(wgpu::Face::Back),
+            ..Default::default()
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: Texture::DEPTH_FORMAT,
+            depth_write_enabled: true,
+            depth_compare: depth.compare(),
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+        cache: None,
+    })
+}
@@ -0,0 +1,424 @@
+use std::{collections::HashMap, mem::size_of, sync::mpsc};
+
+use anyhow::{anyhow, Result};
+use wgpu::util::DeviceExt;
+
+use super::{
+    buffer::{create_buffer, create_uniform_bind_group, grow_buffer, push_uniform, uniform_stride},
+    camera::DepthMode,
+    indirect::{DrawIndexedIndirect, IndirectRenderer},
+    instance::{InstanceManager, InstanceRaw},
+    layouts::BindGroupLayouts,
+    model::{Mesh, ModelVertex, Vertex},
+    texture,
+    view::CameraView,
+};
+
+// How the engine's instanced meshes are drawn. Everything but `Shaded` is
+// for debugging, e.g. `Normals` shows meshes without normals in magenta.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum RenderMode {
+    #[default]
+    Shaded,
+    Wireframe,
+    Normals,
+    UvChecker,
+    // Distance from the camera, white near to black far.
+    Depth,
+    // Brighter where more surfaces are drawn over each other.
+    Overdraw,
+}
+
+impl RenderMode {
+    pub const ALL: [RenderMode; 6] = [
+        RenderMode::Shaded,
+        RenderMode::Wireframe,
+        RenderMode::Normals,
+        RenderMode::UvChecker,
+        RenderMode::Depth,
+        RenderMode::Overdraw,
+    ];
+
+    // The mode after this one, for cycling through them with a key.
+    pub fn next(self) -> RenderMode {
+        let i = Self::ALL.iter().position(|m| *m == self).unwrap_or(0);
+        Self::ALL[(i + 1) % Self::ALL.len()]
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ModeViewUniform {
+    eye: [f32; 4],
+    params: [f32; 4],
+    wire_color: [f32; 4],
+}
+
+// A mesh drawn as separate triangles, for wireframes from barycentric
+// coordinates when the adapter can't rasterize lines.
+struct WireMesh {
+    vertex_buffer: wgpu::Buffer,
+    // `Mesh::generation` the wire mesh was built from.
+    generation: u32,
+}
+
+pub struct RenderModes {
+    pub mode: RenderMode,
+    pub wire_color: [f32; 4],
+    // Distances shown from white to black by `RenderMode::Depth`.
+    pub depth_range: (f32, f32),
+    // Brightness added per surface by `RenderMode::Overdraw`.
+    pub overdraw_step: f32,
+    pipelines: HashMap<(RenderMode, DepthMode), wgpu::RenderPipeline>,
+    // Whether wireframes use Features::POLYGON_MODE_LINE.
+    native_wireframe: bool,
+    wire_meshes: HashMap<wgpu::Id<wgpu::Buffer>, WireMesh>,
+    // 0, 1, 2, ... so indirect draws of the meshes' index counts work on wire meshes.
+    sequence_buffer: wgpu::Buffer,
+    view_layout: wgpu::BindGroupLayout,
+    view_buffer: wgpu::Buffer,
+    view_bind_group: wgpu::BindGroup,
+    view_stride: u64,
+}
+
+impl RenderModes {
+    // Enabled when the adapter has them, see `WgpuContext::new`.
+    pub const OPTIONAL_FEATURES: wgpu::Features = wgpu::Features::POLYGON_MODE_LINE;
+
+    pub fn new(device: &wgpu::Device, layouts: &BindGroupLayouts, format: wgpu::TextureFormat) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("render_mode.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/render_mode.wgsl").into()),
+        });
+        let view_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: wgpu::BufferSize::new(size_of::<ModeViewUniform>() as u64),
+                },
+                count: None,
+            }],
+            label: Some("render_mode_view_bind_group_layout"),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Render Mode Pipeline Layout"),
+            bind_group_layouts: &[&layouts.texture, &layouts.camera, &view_layout],
+            push_constant_ranges: &[],
+        });
+
+        let native_wireframe = device.features().contains(wgpu::Features::POLYGON_MODE_LINE);
+        let mut pipelines = HashMap::new();
+        for mode in RenderMode::ALL {
+            if mode == RenderMode::Shaded {
+                continue;
+            }
+            for depth in [DepthMode::Standard, DepthMode::Reversed] {
+                let pipeline = create_pipeline(device, &layout, &shader, format, mode, native_wireframe, depth);
+                pipelines.insert((mode, depth), pipeline);
+            }
+        }
+
+        let view_stride = uniform_stride::<ModeViewUniform>(device);
+        let view_buffer = create_buffer(device, "Render Mode View Buffer", view_stride, wgpu::BufferUsages::UNIFORM);
+        let view_bind_group = create_uniform_bind_group::<ModeViewUniform>(device, &view_layout, &view_buffer, "render_mode_view_bind_group");
+        Self {
+            mode: RenderMode::Shaded,
+            wire_color: [0.1, 1.0, 0.4, 1.0],
+            depth_range: (0.1, 100.0),
+            overdraw_step: 0.1,
+            pipelines,
+            native_wireframe,
+            wire_meshes: HashMap::new(),
+            sequence_buffer: create_sequence_buffer(device, 0),
+            view_layout,
+            view_buffer,
+            view_bind_group,
+            view_stride,
+        }
+    }
+
+    // False when wireframes fall back to barycentric edges on de-indexed copies of the meshes.
+    pub fn native_wireframe(&self) -> bool {
+        self.native_wireframe
+    }
+
+    // What the scene is cleared to, overdraw needs black to add up from.
+    pub fn clear_color(&self, clear_color: wgpu::Color) -> wgpu::Color {
+        match self.mode {
+            RenderMode::Overdraw | RenderMode::Depth => wgpu::Color::BLACK,
+            _ => clear_color,
+        }
+    }
+
+    // Writes the views' uniforms and, for fallback wireframes, builds wire
+    // meshes for any mesh that doesn't have one yet. Once per frame before
+    // any view draws.
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        views: &[CameraView],
+        groups: &[InstanceManager],
+    ) -> Result<()> {
+        if self.mode != RenderMode::Wireframe || self.native_wireframe {
+            self.wire_meshes.clear();
+        } else {
+            self.prepare_wire_meshes(device, queue, groups)?;
+        }
+        if self.mode == RenderMode::Shaded {
+            return Ok(());
+        }
+
+        let mut bytes = Vec::new();
+        for view in views {
+            let eye = view.camera.view().eye;
+            let uniform = ModeViewUniform {
+                eye: [eye.x, eye.y, eye.z, 1.0],
+                params: [self.depth_range.0, self.depth_range.1, self.overdraw_step, 0.0],
+                wire_color: self.wire_color,
+            };
+            push_uniform(&mut bytes, self.view_stride, &uniform);
+        }
+        if bytes.is_empty() {
+            return Ok(());
+        }
+        if grow_buffer(device, &mut self.view_buffer, "Render Mode View Buffer", bytes.len() as u64) {
+            self.view_bind_group =
+                create_uniform_bind_group::<ModeViewUniform>(device, &self.view_layout, &self.view_buffer, "render_mode_view_bind_group");
+        }
+        queue.write_buffer(&self.view_buffer, 0, &bytes);
+        Ok(())
+    }
+
+    fn prepare_wire_meshes(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, groups: &[InstanceManager]) -> Result<()> {
+        let meshes: Vec<&Mesh> = groups
+            .iter()
+            .flat_map(|group| {
+                let model = &group.model;
+                (0..model.lod_count()).flat_map(move |lod| model.lod_meshes(lod).iter())
+            })
+            .collect();
+        // Meshes that were removed, replaced their buffers or uploaded new
+        // geometry get rebuilt.
+        self.wire_meshes.retain(|id, wire| {
+            meshes
+                .iter()
+                .any(|mesh| mesh.index_buffer.global_id() == *id && mesh.generation() == wire.generation)
+        });
+
+        let mut longest = self.sequence_buffer.size() / size_of::<u32>() as u64;
+        for mesh in meshes {
+            let id = mesh.index_buffer.global_id();
+            if self.wire_meshes.contains_key(&id) {
+                continue;
+            }
+            let vertices = match &mesh.data {
+                Some(data) => data.triangles().flatten().collect::<Vec<_>>(),
+                None => {
+                    let (vertices, indices) = read_mesh(device, queue, mesh)?;
+                    indices.iter().map(|&i| vertices[i as usize]).collect()
+                }
+            };
+            let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(&format!("{:?} Wire Vertex Buffer", mesh.name)),
+                contents: bytemuck::cast_slice(&vertices),
+                usage: wgpu::BufferUsages::VERTEX,
+            });
+            let vertex_count = vertices.len() as u32;
+            longest = longest.max(vertex_count as u64);
+            self.wire_meshes.insert(id, WireMesh { vertex_buffer, generation: mesh.generation() });
+        }
+        if longest * size_of::<u32>() as u64 > self.sequence_buffer.size() {
+            self.sequence_buffer = create_sequence_buffer(device, longest.next_power_of_two() as u32);
+        }
+        Ok(())
+    }
+
+    // Draws a view's visible instances in the current mode, in place of the
+    // shaded pipeline. `view` is the view's index in the engine's cameras.
+    // Returns whether the mode's pipeline is left set for drawing other
+    // meshes, which fallback wireframes can't.
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        view: usize,
+        renderer: &'a IndirectRenderer,
+        to_draw: &'a [InstanceManager],
+        camera_bind_group: &'a wgpu::BindGroup,
+        depth: DepthMode,
+    ) -> bool {
+        use super::draw::DrawModel;
+
+        let pipeline = match self.pipelines.get(&(self.mode, depth)) {
+            Some(pipeline) => pipeline,
+            None => return false,
+        };
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(2, &self.view_bind_group, &[(view as u64 * self.view_stride) as u32]);
+        if self.mode != RenderMode::Wireframe || self.native_wireframe {
+            render_pass.draw_instances_indirect(renderer, to_draw, camera_bind_group);
+            return true;
+        }
+
+        // Same draws as `draw_instances_indirect`, on the wire meshes.
+        render_pass.set_bind_group(1, camera_bind_group, &[]);
+        render_pass.set_index_buffer(self.sequence_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        for group in renderer.groups() {
+            let model = &to_draw[group.manager].model;
+            let mesh = &model.lod_meshes(group.lod)[group.mesh];
+            let wire = match self.wire_meshes.get(&mesh.index_buffer.global_id()) {
+                Some(wire) if wire.generation == mesh.generation() => wire,
+                _ => continue,
+            };
+            render_pass.set_bind_group(0, &model.materials[mesh.material].bind_group, &[]);
+            render_pass.set_vertex_buffer(0, wire.vertex_buffer.slice(..));
+            if renderer.is_gpu_driven() {
+                render_pass.set_vertex_buffer(1, renderer.visible_buffer().slice(..));
+                render_pass.multi_draw_indexed_indirect(
+                    renderer.args_buffer(),
+                    group.first_draw as u64 * DrawIndexedIndirect::SIZE,
+                    group.draw_count,
+                );
+            } else {
+                for draw in group.first_draw..group.first_draw + group.draw_count {
+                    render_pass.set_vertex_buffer(1, renderer.visible_buffer().slice(renderer.visible_offset(draw)..));
+                    render_pass.draw_indexed_indirect(renderer.args_buffer(), draw as u64 * DrawIndexedIndirect::SIZE);
+                }
+            }
+        }
+        false
+    }
+}
+
+// Copies a mesh's geometry back from the GPU, for meshes loaded without
+// `retain_data`. Blocks until the copy is done.
+fn read_mesh(device: &wgpu::Device, queue: &wgpu::Queue, mesh: &Mesh) -> Result<(Vec<ModelVertex>, Vec<u32>)> {
+    let index_size = mesh.num_elements as u64 * size_of::<u32>() as u64;
+    let vertex_size = mesh.vertex_buffer.size() / size_of::<ModelVertex>() as u64 * size_of::<ModelVertex>() as u64;
+    let staging = |size: u64| {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Wire Mesh Readback Buffer"),
+            size: size.max(4),
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        })
+    };
+    let vertex_staging = staging(vertex_size);
+    let index_staging = staging(index_size);
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Wire Mesh Readback Encoder"),
+    });
+    encoder.copy_buffer_to_buffer(&mesh.vertex_buffer, 0, &vertex_staging, 0, vertex_size);
+    encoder.copy_buffer_to_buffer(&mesh.index_buffer, 0, &index_staging, 0, index_size);
+    queue.submit(std::iter::once(encoder.finish()));
+
+    let vertices: Vec<ModelVertex> = bytemuck::pod_collect_to_vec(&map_read(device, &vertex_staging, vertex_size)?);
+    let indices: Vec<u32> = bytemuck::pod_collect_to_vec(&map_read(device, &index_staging, index_size)?);
+    if indices.iter().any(|&i| i as usize >= vertices.len()) {
+        return Err(anyhow!("Mesh {} has indices past its vertices", mesh.name));
+    }
+    Ok((vertices, indices))
+}
+
+fn map_read(device: &wgpu::Device, buffer: &wgpu::Buffer, size: u64) -> Result<Vec<u8>> {
+    if size == 0 {
+        return Ok(Vec::new());
+    }
+    let slice = buffer.slice(..size);
+    let (sender, receiver) = mpsc::channel();
+    slice.map_async(wgpu::MapMode::Read, move |result| {
+        let _ = sender.send(result);
+    });
+    device.poll(wgpu::Maintain::Wait);
+    receiver.recv()??;
+    let data = slice.get_mapped_range().to_vec();
+    buffer.unmap();
+    Ok(data)
+}
+
+fn create_sequence_buffer(device: &wgpu::Device, count: u32) -> wgpu::Buffer {
+    let indices: Vec<u32> = (0..count.max(3)).collect();
+    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Wire Sequence Index Buffer"),
+        contents: bytemuck::cast_slice(&indices),
+        usage: wgpu::BufferUsages::INDEX,
+    })
+}
+
+fn create_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+    mode: RenderMode,
+    native_wireframe: bool,
+    depth: DepthMode,
+) -> wgpu::RenderPipeline {
+    let (entry_point, blend) = match mode {
+        RenderMode::Wireframe if native_wireframe => ("fs_wire", wgpu::BlendState::REPLACE),
+        RenderMode::Wireframe => ("fs_barycentric", wgpu::BlendState::ALPHA_BLENDING),
+        RenderMode::Overdraw => (
+            "fs_overdraw",
+            wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::One,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: wgpu::BlendComponent::REPLACE,
+            },
+        ),
+        RenderMode::UvChecker => ("fs_uv_checker", wgpu::BlendState::REPLACE),
+        RenderMode::Depth => ("fs_depth", wgpu::BlendState::REPLACE),
+        RenderMode::Normals | RenderMode::Shaded => ("fs_normals", wgpu::BlendState::REPLACE),
+    };
+    // Wireframes show back faces too, overdraw counts every surface whether hidden or not.
+    let wireframe = mode == RenderMode::Wireframe;
+    let overdraw = mode == RenderMode::Overdraw;
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(&format!("{:?} Render Mode Pipeline", mode)),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            buffers: &[ModelVertex::desc(), InstanceRaw::desc()],
+            compilation_options: Default::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point,
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(blend),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: Default::default(),
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: if wireframe || overdraw { None } else { Some(wgpu::Face::Back) },
+            polygon_mode: if wireframe && native_wireframe {
+                wgpu::PolygonMode::Line
+            } else {
+                wgpu::PolygonMode::Fill
+            },
+            unclipped_depth: false,
+            conservative: false,
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: texture::Texture::DEPTH_FORMAT,
+            depth_write_enabled: !overdraw,
+            depth_compare: if overdraw { wgpu::CompareFunction::Always } else { depth.compare() },
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+        cache: None,
+    })
+}
@@ -1,9 +1,8 @@
 use std::io::{BufReader, Cursor};
 
 use cfg_if::cfg_if;
-use wgpu::util::DeviceExt;
 
-use super::{model::{self, texture_to_model}, texture};
+use super::{lod::{self, LodSettings}, model::{self, texture_to_model}, texture};
 
 pub async fn load_string(file_name: &str) -> anyhow::Result<String> {
     cfg_if! {
@@ -60,7 +59,26 @@ pub async fn load_sprite(
     layout: &wgpu::BindGroupLayout,
 ) -> anyhow::Result<model::Model> {
     let texture = load_texture(file_name, device, queue).await?;
-    Ok(texture_to_model(texture, layout, device, file_name))
+    let mut model = texture_to_model(texture, layout, device, file_name);
+    model.source = Some(file_name.to_string());
+    Ok(model)
+}
+
+// Loads an OBJ as a model and anything else as an image on a quad.
+pub async fn load_asset_model(
+    file_name: &str,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    layout: &wgpu::BindGroupLayout,
+) -> anyhow::Result<model::Model> {
+    let is_obj = std::path::Path::new(file_name)
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("obj"));
+    if is_obj {
+        load_model(file_name, device, queue, layout).await
+    } else {
+        load_sprite(file_name, device, queue, layout).await
+    }
 }
 
 pub async fn load_model(
@@ -68,6 +86,24 @@ pub async fn load_model(
     device: &wgpu::Device,
     queue: &wgpu::Queue,
     layout: &wgpu::BindGroupLayout,
+) -> anyhow::Result<model::Model> {
+    load_model_with_options(file_name, device, queue, layout, &ModelLoadOptions::default()).await
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ModelLoadOptions {
+    // Coarser levels generated from the OBJ with `lod::simplify`.
+    pub lods: Option<LodSettings>,
+    // Keep a CPU copy of every mesh in `Mesh::data`.
+    pub retain_data: bool,
+}
+
+pub async fn load_model_with_options(
+    file_name: &str,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    layout: &wgpu::BindGroupLayout,
+    options: &ModelLoadOptions,
 ) -> anyhow::Result<model::Model> {
     let obj_text = load_string(file_name).await?;
     let obj_cursor = Cursor::new(obj_text);
@@ -90,29 +126,10 @@ pub async fn load_model(
     let mut materials = Vec::new();
     for m in obj_materials? {
         let diffuse_texture = load_texture(&m.diffuse_texture, device, queue).await?;
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
-                },
-            ],
-            label: None,
-        });
-
-        materials.push(model::Material {
-            name: m.name,
-            diffuse_texture,
-            bind_group,
-        })
+        materials.push(model::Material::new(device, &m.name, diffuse_texture, layout));
     }
 
-    let meshes = models
+    let mesh_data = models
         .into_iter()
         .map(|m| {
             let vertices = (0..m.mesh.positions.len() / 3)
@@ -145,27 +162,45 @@ pub async fn load_model(
             })
             .collect::<Vec<_>>();
 
-            let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some(&format!("{:?} Vertex Buffer", file_name)),
-                contents: bytemuck::cast_slice(&vertices),
-                usage: wgpu::BufferUsages::VERTEX,
-            });
-            let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some(&format!("{:?} Index Buffer", file_name)),
-                contents: bytemuck::cast_slice(&m.mesh.indices),
-                usage: wgpu::BufferUsages::INDEX,
-            });
-
             log::info!("Mesh: {}", m.name);
-            model::Mesh {
-                name: file_name.to_string(),
-                vertex_buffer,
-                index_buffer,
-                num_elements: m.mesh.indices.len() as u32,
-                material: m.mesh.material_id.unwrap_or(0),
-            }
+            (vertices, m.mesh.indices, m.mesh.material_id.unwrap_or(0))
         })
         .collect::<Vec<_>>();
 
-    Ok(model::Model { meshes, materials })
+    let create_mesh = |vertices: Vec<model::ModelVertex>, indices: Vec<u32>, material: usize| {
+        if options.retain_data {
+            model::Mesh::from_data(device, file_name, model::MeshData::new(vertices, indices), material)
+        } else {
+            model::Mesh::new(device, file_name, &vertices, &indices, material)
+        }
+    };
+
+    let mut lod_levels = Vec::new();
+    if let Some(lods) = &options.lods {
+        for level in &lods.levels {
+            let meshes = mesh_data
+                .iter()
+                .map(|(vertices, indices, material)| {
+                    let (vertices, indices) = lod::simplify(vertices, indices, level.ratio);
+                    create_mesh(vertices, indices, *material)
+                })
+                .collect();
+            lod_levels.push(model::Lod {
+                meshes,
+                threshold: level.threshold,
+            });
+        }
+    }
+
+    let meshes = mesh_data
+        .into_iter()
+        .map(|(vertices, indices, material)| create_mesh(vertices, indices, material))
+        .collect::<Vec<_>>();
+
+    let mut model = model::Model::new(meshes, materials);
+    model.source = Some(file_name.to_string());
+    Ok(match &options.lods {
+        Some(lods) => model.with_lods(lods.metric, lod_levels, lods.fade),
+        None => model,
+    })
 }
\ No newline at end of file
@@ -0,0 +1,469 @@
+use sdl2::event::Event;
+use ultraviolet::{Isometry3, Lerp, Rotor3, Vec3};
+
+use super::{camera::Camera, controller::CameraController, scene::CameraDesc};
+use crate::transform::Transform;
+
+// Camera rigs move the camera on their own instead of from input. They only
+// depend on the `dt` they're given, so replaying the same steps gives the
+// same shots.
+
+// Shapes the progress of a transition, `t` and the result are in [0, 1].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Easing {
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+}
+
+impl Easing {
+    pub fn apply(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => t * (2.0 - t),
+            Easing::EaseInOut => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+// Fraction of the way to close in one step of `dt` so that half of the
+// distance is left after `half_life` seconds, whatever the frame rate.
+pub fn damp_factor(half_life: f32, dt: f32) -> f32 {
+    if half_life <= 0.0 {
+        1.0
+    } else {
+        1.0 - (-std::f32::consts::LN_2 * dt / half_life).exp()
+    }
+}
+
+// Keeps `target` in view from `offset`, given in the target's own space so
+// the camera stays behind it when it turns. Set `target` every frame.
+#[derive(Debug)]
+pub struct FollowRig {
+    pub target: Transform,
+    pub offset: Vec3,
+    // Looked at point relative to the target, in world space.
+    pub look_offset: Vec3,
+    // Seconds for the camera to close half the distance to where it should be.
+    pub position_half_life: f32,
+    pub look_half_life: f32,
+    // The target can move this far before the camera starts following.
+    pub dead_zone: f32,
+    pub up: Vec3,
+    focus: Option<Vec3>,
+    eye: Option<Vec3>,
+    look: Option<Vec3>,
+}
+
+impl FollowRig {
+    pub fn new(target: Transform, offset: Vec3) -> Self {
+        Self {
+            target,
+            offset,
+            look_offset: Vec3::zero(),
+            position_half_life: 0.15,
+            look_half_life: 0.05,
+            dead_zone: 0.0,
+            up: Vec3::unit_y(),
+            focus: None,
+            eye: None,
+            look: None,
+        }
+    }
+
+    // Jumps straight to the target on the next update, e.g. after a teleport.
+    pub fn snap(&mut self) {
+        self.focus = None;
+        self.eye = None;
+        self.look = None;
+    }
+}
+
+impl CameraController for FollowRig {
+    fn process_events(&mut self, _event: &Event) -> bool {
+        false
+    }
+
+    fn update_camera(&mut self, camera: &mut Camera, dt: f32) {
+        let position = self.target.position;
+        let focus = match self.focus {
+            Some(focus) => {
+                let away = position - focus;
+                let distance = away.mag();
+                if distance > self.dead_zone {
+                    focus + away * ((distance - self.dead_zone) / distance)
+                } else {
+                    focus
+                }
+            }
+            None => position,
+        };
+        self.focus = Some(focus);
+
+        let eye_goal = focus + self.offset.rotated_by(self.target.rotation);
+        let look_goal = focus + self.look_offset;
+        let eye = match self.eye {
+            Some(eye) => eye.lerp(eye_goal, damp_factor(self.position_half_life, dt)),
+            None => eye_goal,
+        };
+        let look = match self.look {
+            Some(look) => look.lerp(look_goal, damp_factor(self.look_half_life, dt)),
+            None => look_goal,
+        };
+        self.eye = Some(eye);
+        self.look = Some(look);
+
+        let view = camera.view_mut();
+        view.eye = eye;
+        view.target = look;
+        view.up = self.up;
+    }
+}
+
+// A camera state to pass through at `time` seconds from the start of a path.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CameraKeyframe {
+    pub time: f32,
+    pub desc: CameraDesc,
+}
+
+// Flies through keyframes on Catmull-Rom splines for the eye and target,
+// blending the projection in between, e.g. for cutscenes.
+#[derive(Debug, Clone)]
+pub struct CameraPath {
+    // Sorted by time.
+    pub keyframes: Vec<CameraKeyframe>,
+    pub looping: bool,
+    pub speed: f32,
+    pub time: f32,
+}
+
+impl CameraPath {
+    pub fn new(mut keyframes: Vec<CameraKeyframe>) -> Self {
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+        Self {
+            keyframes,
+            looping: false,
+            speed: 1.0,
+            time: 0.0,
+        }
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map_or(0.0, |k| k.time)
+    }
+
+    pub fn finished(&self) -> bool {
+        !self.looping && self.time >= self.duration()
+    }
+
+    // The camera state at `time`, held at the ends of the path.
+    pub fn sample(&self, time: f32) -> Option<CameraDesc> {
+        let keys = &self.keyframes;
+        let last = keys.len().checked_sub(1)?;
+        let i = keys.iter().rposition(|k| k.time <= time).unwrap_or(0);
+        if i == last || time <= keys[0].time {
+            return Some(keys[i].desc);
+        }
+        let (a, b) = (&keys[i], &keys[i + 1]);
+        let span = b.time - a.time;
+        let t = if span > 0.0 { (time - a.time) / span } else { 1.0 };
+        let before = &keys[i.saturating_sub(1)].desc;
+        let after = &keys[(i + 2).min(last)].desc;
+
+        let mut desc = a.desc.lerp(&b.desc, t);
+        desc.eye = catmull_rom(before.eye, a.desc.eye, b.desc.eye, after.eye, t);
+        desc.target = catmull_rom(before.target, a.desc.target, b.desc.target, after.target, t);
+        Some(desc)
+    }
+}
+
+impl CameraController for CameraPath {
+    fn process_events(&mut self, _event: &Event) -> bool {
+        false
+    }
+
+    fn update_camera(&mut self, camera: &mut Camera, dt: f32) {
+        let duration = self.duration();
+        self.time += dt * self.speed;
+        if self.looping && duration > 0.0 {
+            self.time = self.time.rem_euclid(duration);
+        } else {
+            self.time = self.time.min(duration);
+        }
+        if let Some(desc) = self.sample(self.time) {
+            camera.apply_desc(&desc);
+        }
+    }
+}
+
+// Uniform Catmull-Rom between `p1` and `p2`.
+fn catmull_rom(p0: Vec3, p1: Vec3, p2: Vec3, p3: Vec3, t: f32) -> Vec3 {
+    let (t2, t3) = (t * t, t * t * t);
+    ((p1 * 2.0)
+        + (p2 - p0) * t
+        + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * t2
+        + (p3 - p0 + (p1 - p2) * 3.0) * t3)
+        * 0.5
+}
+
+// Moves a camera from one state to another over `duration` seconds,
+// including its field of view and clip planes.
+#[derive(Debug, Clone)]
+pub struct CameraTransition {
+    pub from: CameraDesc,
+    pub to: CameraDesc,
+    pub duration: f32,
+    pub easing: Easing,
+    pub time: f32,
+}
+
+impl CameraTransition {
+    pub fn new(from: CameraDesc, to: CameraDesc, duration: f32, easing: Easing) -> Self {
+        Self {
+            from,
+            to,
+            duration,
+            easing,
+            time: 0.0,
+        }
+    }
+
+    // From wherever `camera` is now.
+    pub fn from_camera(camera: &Camera, to: CameraDesc, duration: f32, easing: Easing) -> Self {
+        Self::new(camera.desc(), to, duration, easing)
+    }
+
+    pub fn finished(&self) -> bool {
+        self.time >= self.duration
+    }
+
+    pub fn sample(&self, time: f32) -> CameraDesc {
+        let t = if self.duration > 0.0 { time / self.duration } else { 1.0 };
+        self.from.lerp(&self.to, self.easing.apply(t))
+    }
+}
+
+impl CameraController for CameraTransition {
+    fn process_events(&mut self, _event: &Event) -> bool {
+        false
+    }
+
+    fn update_camera(&mut self, camera: &mut Camera, dt: f32) {
+        self.time = (self.time + dt).min(self.duration);
+        camera.apply_desc(&self.sample(self.time));
+    }
+}
+
+// Trauma based shake: hits add trauma which wears off over time, and the
+// shake grows with its square so small hits stay subtle. The motion comes
+// from smooth noise so it is the same for the same seed and steps. Only
+// `Camera::view_offset` is touched, so it can run after any other controller.
+#[derive(Debug, Clone)]
+pub struct CameraShake {
+    // In [0, 1].
+    pub trauma: f32,
+    // Trauma lost per second.
+    pub decay: f32,
+    // Largest offset in world units and largest angles in radians.
+    pub max_offset: f32,
+    pub max_angle: f32,
+    // Noise samples per second, higher shakes faster.
+    pub frequency: f32,
+    pub seed: u32,
+    time: f32,
+}
+
+impl CameraShake {
+    pub fn new(seed: u32) -> Self {
+        Self {
+            trauma: 0.0,
+            decay: 1.0,
+            max_offset: 0.3,
+            max_angle: 3f32.to_radians(),
+            frequency: 15.0,
+            seed,
+            time: 0.0,
+        }
+    }
+
+    pub fn add_trauma(&mut self, amount: f32) {
+        self.trauma = (self.trauma + amount).clamp(0.0, 1.0);
+    }
+
+    // Moves the noise on and wears the trauma off, `update_camera` does this
+    // before applying the offset.
+    pub fn advance(&mut self, dt: f32) {
+        self.time += dt;
+        self.trauma = (self.trauma - self.decay * dt).max(0.0);
+    }
+
+    // Offset in the camera's space for the current trauma.
+    pub fn offset(&self) -> Isometry3 {
+        let shake = self.trauma * self.trauma;
+        let noise = |channel: u32| shake * smooth_noise(self.seed.wrapping_add(channel), self.time * self.frequency);
+        let translation = Vec3::new(noise(0), noise(1), noise(2)) * self.max_offset;
+        let rotation =
+            Rotor3::from_euler_angles(noise(3) * self.max_angle, noise(4) * self.max_angle, noise(5) * self.max_angle);
+        Isometry3::new(translation, rotation)
+    }
+}
+
+impl CameraController for CameraShake {
+    fn process_events(&mut self, _event: &Event) -> bool {
+        false
+    }
+
+    fn update_camera(&mut self, camera: &mut Camera, dt: f32) {
+        self.advance(dt);
+        camera.view_offset = self.offset();
+    }
+}
+
+// Value noise in [-1, 1], smooth in `x`.
+fn smooth_noise(seed: u32, x: f32) -> f32 {
+    let cell = x.floor();
+    let t = x - cell;
+    let t = t * t * (3.0 - 2.0 * t);
+    let a = hash_unit(seed, cell as i32);
+    let b = hash_unit(seed, cell as i32 + 1);
+    a + (b - a) * t
+}
+
+fn hash_unit(seed: u32, i: i32) -> f32 {
+    let mut h = seed.wrapping_mul(0x9e37_79b9) ^ (i as u32).wrapping_mul(0x85eb_ca6b);
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x7feb_352d);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x846c_a68b);
+    h ^= h >> 16;
+    h as f32 / u32::MAX as f32 * 2.0 - 1.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wgpu_engine::scene::ProjectionDesc;
+
+    fn assert_near(a: Vec3, b: Vec3) {
+        assert!((a - b).mag() < 1e-4, "{:?} != {:?}", a, b);
+    }
+
+    fn keyframe(time: f32, eye: Vec3, fovy: f32) -> CameraKeyframe {
+        let mut desc = CameraDesc {
+            eye,
+            target: eye + Vec3::new(0.0, 0.0, -1.0),
+            ..CameraDesc::default()
+        };
+        if let ProjectionDesc::Perspective { fovy: f, .. } = &mut desc.projection {
+            *f = fovy;
+        }
+        CameraKeyframe { time, desc }
+    }
+
+    fn fovy(desc: &CameraDesc) -> f32 {
+        match desc.projection {
+            ProjectionDesc::Perspective { fovy, .. } => fovy,
+            ProjectionDesc::Orthographic { .. } => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn damping_is_frame_rate_independent() {
+        let step = |value: f32, dt: f32| value + (10.0 - value) * damp_factor(0.2, dt);
+        let once = step(0.0, 0.1);
+        let twice = step(step(0.0, 0.05), 0.05);
+        assert!((once - twice).abs() < 1e-5, "{} != {}", once, twice);
+        let tenths = (0..10).fold(0.0, |value, _| step(value, 0.01));
+        assert!((once - tenths).abs() < 1e-4, "{} != {}", once, tenths);
+
+        // Half the distance is left after the half life, none without one.
+        assert!((step(0.0, 0.2) - 5.0).abs() < 1e-5);
+        assert_eq!(damp_factor(0.0, 0.016), 1.0);
+        assert_eq!(damp_factor(0.2, 0.0), 0.0);
+    }
+
+    #[test]
+    fn catmull_rom_passes_through_its_inner_points() {
+        let (p0, p1, p2, p3) = (Vec3::new(-1.0, 2.0, 0.0), Vec3::zero(), Vec3::new(1.0, 1.0, 3.0), Vec3::new(4.0, 0.0, 1.0));
+        assert_near(catmull_rom(p0, p1, p2, p3, 0.0), p1);
+        assert_near(catmull_rom(p0, p1, p2, p3, 1.0), p2);
+        // Evenly spaced points on a line stay on it at an even pace.
+        let x = |x: f32| Vec3::new(x, 0.0, 0.0);
+        assert_near(catmull_rom(x(0.0), x(1.0), x(2.0), x(3.0), 0.25), x(1.25));
+    }
+
+    #[test]
+    fn path_passes_through_keyframes_and_holds_at_the_ends() {
+        let keys = vec![
+            keyframe(2.0, Vec3::new(4.0, 0.0, 0.0), 60.0),
+            keyframe(0.0, Vec3::zero(), 40.0),
+            keyframe(1.0, Vec3::new(1.0, 1.0, 0.0), 50.0),
+        ];
+        let path = CameraPath::new(keys);
+        assert_eq!(path.duration(), 2.0);
+        for key in &path.keyframes {
+            assert_eq!(path.sample(key.time), Some(key.desc));
+        }
+        assert_eq!(path.sample(-1.0), Some(path.keyframes[0].desc));
+        assert_eq!(path.sample(5.0), Some(path.keyframes[2].desc));
+
+        let mid = path.sample(0.5).unwrap();
+        assert!((fovy(&mid) - 45.0).abs() < 1e-4);
+        // Curves through the middle keyframe rather than cutting the corner.
+        assert!(mid.eye.y > 0.5);
+        assert_eq!(path.sample(0.5), path.sample(0.5));
+
+        assert_eq!(CameraPath::new(Vec::new()).sample(1.0), None);
+    }
+
+    #[test]
+    fn transition_eases_between_its_ends() {
+        let from = keyframe(0.0, Vec3::zero(), 40.0).desc;
+        let to = keyframe(0.0, Vec3::new(2.0, 0.0, 0.0), 80.0).desc;
+        for easing in [Easing::Linear, Easing::EaseIn, Easing::EaseOut, Easing::EaseInOut] {
+            let transition = CameraTransition::new(from, to, 2.0, easing);
+            assert_eq!(transition.sample(0.0), from);
+            assert_eq!(transition.sample(2.0), to);
+            assert_eq!(transition.sample(3.0), to);
+            assert_eq!(easing.apply(0.0), 0.0);
+            assert_eq!(easing.apply(1.0), 1.0);
+        }
+        let halfway = CameraTransition::new(from, to, 2.0, Easing::EaseInOut).sample(1.0);
+        assert_near(halfway.eye, Vec3::new(1.0, 0.0, 0.0));
+        assert!((fovy(&halfway) - 60.0).abs() < 1e-4);
+        let early = CameraTransition::new(from, to, 2.0, Easing::EaseIn).sample(0.5);
+        assert_near(early.eye, Vec3::new(0.125, 0.0, 0.0));
+        assert_eq!(CameraTransition::new(from, to, 0.0, Easing::Linear).sample(0.0), to);
+    }
+
+    #[test]
+    fn shake_is_deterministic() {
+        let shaken = |seed: u32, steps: &[f32]| {
+            let mut shake = CameraShake::new(seed);
+            shake.decay = 0.5;
+            shake.add_trauma(1.0);
+            for dt in steps {
+                shake.advance(*dt);
+            }
+            shake.offset()
+        };
+        let a = shaken(7, &[0.1, 0.1, 0.1]);
+        assert_eq!(a, shaken(7, &[0.1, 0.1, 0.1]));
+        // One long step ends up at the same point as several short ones.
+        assert_near(a.translation, shaken(7, &[0.3]).translation);
+        assert_ne!(a.translation, shaken(8, &[0.1, 0.1, 0.1]).translation);
+        assert!(a.translation.mag() > 0.0);
+        assert!(a.translation.mag() <= 0.3 * 3f32.sqrt());
+
+        // Without trauma there's no shake.
+        let mut calm = CameraShake::new(7);
+        calm.add_trauma(0.5);
+        calm.advance(1.0);
+        assert_eq!(calm.trauma, 0.0);
+        assert_eq!(calm.offset().translation, Vec3::zero());
+    }
+}
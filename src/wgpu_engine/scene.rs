@@ -0,0 +1,526 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use ultraviolet::{Lerp, Vec3};
+
+use super::{
+    atlas::UvRect,
+    camera::{OrthographicProjection, PerspectiveProjection, Projection},
+    instance::Instance,
+    WindowSize,
+};
+
+// Handles to models, instance groups and cameras owned by `WgpuEngine`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ModelId(pub(super) u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct GroupId(pub(crate) u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CameraId(pub(super) u32);
+
+// Bump when the file layout changes and add the upgrade step to `migrate`.
+pub const SCENE_VERSION: u32 = 1;
+
+// Everything needed to rebuild a scene, saved as JSON for level designers to
+// edit and diff by hand. Fields other than the group models and instance ids
+// may be left out of a file and take their default values.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SceneDesc {
+    pub settings: EngineSettings,
+    pub camera: CameraDesc,
+    pub lights: Vec<LightDesc>,
+    pub groups: Vec<InstanceGroupDesc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EngineSettings {
+    pub clear_color: [f32; 4],
+    pub vsync: bool,
+}
+
+impl Default for EngineSettings {
+    fn default() -> Self {
+        Self {
+            clear_color: [0.1, 0.2, 0.3, 1.0],
+            vsync: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CameraDesc {
+    #[serde(with = "vec3")]
+    pub eye: Vec3,
+    #[serde(with = "vec3")]
+    pub target: Vec3,
+    #[serde(with = "vec3")]
+    pub up: Vec3,
+    pub projection: ProjectionDesc,
+}
+
+impl Default for CameraDesc {
+    fn default() -> Self {
+        Self {
+            eye: Vec3::new(0.0, 3.0, 10.0),
+            target: Vec3::zero(),
+            up: Vec3::unit_y(),
+            projection: ProjectionDesc::Perspective {
+                fovy: 45.0,
+                znear: 0.1,
+                zfar: 100.0,
+                reverse_z: false,
+                infinite: false,
+            },
+        }
+    }
+}
+
+impl CameraDesc {
+    // Eye, target and up move in straight lines, see `ProjectionDesc::lerp`
+    // for the projection.
+    pub fn lerp(&self, other: &CameraDesc, t: f32) -> CameraDesc {
+        CameraDesc {
+            eye: self.eye.lerp(other.eye, t),
+            target: self.target.lerp(other.target, t),
+            up: self.up.lerp(other.up, t).normalized(),
+            projection: self.projection.lerp(&other.projection, t),
+        }
+    }
+}
+
+// The aspect ratio and orthographic extents follow the window, so only the
+// settings that don't are stored. `fovy` is in degrees, see
+// `PerspectiveProjection` and `OrthographicProjection` for the rest.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ProjectionDesc {
+    Perspective {
+        fovy: f32,
+        znear: f32,
+        zfar: f32,
+        #[serde(default)]
+        reverse_z: bool,
+        #[serde(default)]
+        infinite: bool,
+    },
+    Orthographic {
+        znear: f32,
+        zfar: f32,
+        #[serde(default)]
+        anchor: [f32; 2],
+        #[serde(default = "one")]
+        zoom: f32,
+        #[serde(default = "one")]
+        pixels_per_unit: f32,
+        #[serde(default)]
+        pixel_perfect: bool,
+    },
+}
+
+impl ProjectionDesc {
+    pub fn build(&self, size: WindowSize) -> Box<dyn Projection> {
+        let mut projection: Box<dyn Projection> = match *self {
+            ProjectionDesc::Perspective { fovy, znear, zfar, .. } => Box::new(PerspectiveProjection::new(
+                size.width as f32 / size.height.max(1) as f32,
+                fovy,
+                znear,
+                zfar,
+            )),
+            ProjectionDesc::Orthographic { znear, zfar, .. } => Box::new(OrthographicProjection::new(size, znear, zfar)),
+        };
+        projection.set_desc(self);
+        projection
+    }
+}
+
+impl ProjectionDesc {
+    // Blends the parameters of two projections of the same kind, otherwise
+    // switches from one to the other half way.
+    pub fn lerp(&self, other: &ProjectionDesc, t: f32) -> ProjectionDesc {
+        let mix = |a: f32, b: f32| a + (b - a) * t;
+        // Flags can't be blended, they switch half way too.
+        let pick = |a, b| if t < 0.5 { a } else { b };
+        match (*self, *other) {
+            (
+                ProjectionDesc::Perspective { fovy, znear, zfar, reverse_z, infinite },
+                ProjectionDesc::Perspective {
+                    fovy: fovy2,
+                    znear: znear2,
+                    zfar: zfar2,
+                    reverse_z: reverse_z2,
+                    infinite: infinite2,
+                },
+            ) => ProjectionDesc::Perspective {
+                fovy: mix(fovy, fovy2),
+                znear: mix(znear, znear2),
+                zfar: mix(zfar, zfar2),
+                reverse_z: pick(reverse_z, reverse_z2),
+                infinite: pick(infinite, infinite2),
+            },
+            (
+                ProjectionDesc::Orthographic { znear, zfar, anchor, zoom, pixels_per_unit, pixel_perfect },
+                ProjectionDesc::Orthographic {
+                    znear: znear2,
+                    zfar: zfar2,
+                    anchor: anchor2,
+                    zoom: zoom2,
+                    pixels_per_unit: pixels_per_unit2,
+                    pixel_perfect: pixel_perfect2,
+                },
+            ) => ProjectionDesc::Orthographic {
+                znear: mix(znear, znear2),
+                zfar: mix(zfar, zfar2),
+                anchor: [mix(anchor[0], anchor2[0]), mix(anchor[1], anchor2[1])],
+                // Zooming feels even when the scale changes by the same factor each step.
+                zoom: zoom * (zoom2 / zoom).powf(t),
+                pixels_per_unit: mix(pixels_per_unit, pixels_per_unit2),
+                pixel_perfect: pick(pixel_perfect, pixel_perfect2),
+            },
+            _ if t < 0.5 => *self,
+            _ => *other,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LightKind {
+    Directional,
+    Point,
+}
+
+// Lights are stored and handed back by the engine, shading them is up to the
+// pipelines that want them.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LightDesc {
+    #[serde(rename = "type")]
+    pub kind: LightKind,
+    #[serde(default, with = "vec3")]
+    pub position: Vec3,
+    #[serde(default = "down", with = "vec3")]
+    pub direction: Vec3,
+    #[serde(default = "white")]
+    pub color: [f32; 3],
+    #[serde(default = "one")]
+    pub intensity: f32,
+}
+
+// One `InstanceManager`: the asset its model is loaded from and its instances.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstanceGroupDesc {
+    pub model: String,
+    #[serde(default, with = "instances")]
+    pub instances: Vec<Instance>,
+}
+
+impl SceneDesc {
+    pub fn read(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).with_context(|| format!("Reading scene {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("Loading scene {}", path.display()))
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        std::fs::write(path, self.to_json_string()?).with_context(|| format!("Writing scene {}", path.display()))
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let SceneHeader { version } = serde_json::from_str(text)?;
+        if version == SCENE_VERSION {
+            // Straight from the text, so instance ids keep all 128 bits.
+            return Ok(serde_json::from_str(text)?);
+        }
+        Ok(serde_json::from_value(migrate(serde_json::from_str(text)?, version)?)?)
+    }
+
+    pub fn to_json_string(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(&SceneFile { version: SCENE_VERSION, scene: self })?)
+    }
+}
+
+// The version is read first to know how to read the rest.
+#[derive(Deserialize)]
+struct SceneHeader {
+    version: u32,
+}
+
+#[derive(Serialize)]
+struct SceneFile<'a> {
+    version: u32,
+    #[serde(flatten)]
+    scene: &'a SceneDesc,
+}
+
+// Upgrades a document of an older `version` to the current layout.
+fn migrate(value: serde_json::Value, version: u32) -> Result<serde_json::Value> {
+    match version {
+        SCENE_VERSION => Ok(value),
+        v if v > SCENE_VERSION => Err(anyhow!(
+            "Scene version {} is newer than the supported version {}",
+            v,
+            SCENE_VERSION
+        )),
+        v => Err(anyhow!("Unknown scene version {}", v)),
+    }
+}
+
+fn one() -> f32 {
+    1.0
+}
+
+fn down() -> Vec3 {
+    -Vec3::unit_y()
+}
+
+fn white() -> [f32; 3] {
+    [1.0; 3]
+}
+
+// Vectors are written as [x, y, z].
+mod vec3 {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use ultraviolet::Vec3;
+
+    pub fn serialize<S: Serializer>(v: &Vec3, serializer: S) -> Result<S::Ok, S::Error> {
+        [v.x, v.y, v.z].serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec3, D::Error> {
+        Ok(<[f32; 3]>::deserialize(deserializer)?.into())
+    }
+}
+
+// Rotations are written as Euler angles in degrees, which are easier to edit
+// than a rotor, and UVs as [min x, min y, max x, max y].
+mod instances {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use ultraviolet::{Vec2, Vec3};
+
+    use super::{vec3, Instance, UvRect};
+    use crate::transform::EulerRotation;
+
+    #[derive(Serialize, Deserialize)]
+    struct InstanceFile {
+        id: u128,
+        #[serde(default, with = "vec3")]
+        position: Vec3,
+        #[serde(default)]
+        rotation: Degrees,
+        #[serde(default = "one", with = "vec3")]
+        scale: Vec3,
+        #[serde(default = "full")]
+        uv: [f32; 4],
+    }
+
+    #[derive(Default, Serialize, Deserialize)]
+    #[serde(default)]
+    struct Degrees {
+        yaw: f32,
+        pitch: f32,
+        roll: f32,
+    }
+
+    fn one() -> Vec3 {
+        Vec3::one()
+    }
+
+    fn full() -> [f32; 4] {
+        [0.0, 0.0, 1.0, 1.0]
+    }
+
+    pub fn serialize<S: Serializer>(instances: &[Instance], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(instances.iter().map(|instance| {
+            let euler = EulerRotation::from_rotor3(instance.rotation);
+            InstanceFile {
+                id: instance.id,
+                position: instance.position,
+                rotation: Degrees {
+                    yaw: euler.yaw.to_degrees(),
+                    pitch: euler.pitch.to_degrees(),
+                    roll: euler.roll.to_degrees(),
+                },
+                scale: instance.scale,
+                uv: [instance.uv.min.x, instance.uv.min.y, instance.uv.max.x, instance.uv.max.y],
+            }
+        }))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Instance>, D::Error> {
+        let instances = Vec::<InstanceFile>::deserialize(deserializer)?;
+        Ok(instances
+            .into_iter()
+            .map(|file| {
+                let Degrees { yaw, pitch, roll } = file.rotation;
+                let [min_x, min_y, max_x, max_y] = file.uv;
+                Instance {
+                    id: file.id,
+                    position: file.position,
+                    rotation: EulerRotation::new(yaw.to_radians(), pitch.to_radians(), roll.to_radians()).rotor3(),
+                    scale: file.scale,
+                    uv: UvRect {
+                        min: Vec2::new(min_x, min_y),
+                        max: Vec2::new(max_x, max_y),
+                    },
+                }
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ultraviolet::{Rotor3, Vec2};
+
+    use super::*;
+
+    fn scene() -> SceneDesc {
+        SceneDesc {
+            settings: EngineSettings {
+                clear_color: [0.5, 0.25, 0.0, 1.0],
+                vsync: false,
+            },
+            camera: CameraDesc {
+                eye: Vec3::new(1.0, 2.0, 3.0),
+                target: Vec3::new(0.0, 1.0, 0.0),
+                up: Vec3::unit_y(),
+                projection: ProjectionDesc::Orthographic {
+                    znear: 0.5,
+                    zfar: 50.0,
+                    anchor: [0.5, 1.0],
+                    zoom: 2.0,
+                    pixels_per_unit: 16.0,
+                    pixel_perfect: true,
+                },
+            },
+            lights: vec![LightDesc {
+                kind: LightKind::Point,
+                position: Vec3::new(4.0, 5.0, 6.0),
+                direction: -Vec3::unit_y(),
+                color: [1.0, 0.5, 0.25],
+                intensity: 3.0,
+            }],
+            groups: vec![InstanceGroupDesc {
+                model: "cube.obj".to_string(),
+                instances: vec![Instance {
+                    // More than fits in a u64 or an f64.
+                    id: u128::MAX - 1,
+                    position: Vec3::new(-1.0, 0.0, 2.5),
+                    rotation: Rotor3::from_euler_angles(0.1, 0.2, 0.3),
+                    scale: Vec3::new(1.0, 2.0, 3.0),
+                    uv: UvRect {
+                        min: Vec2::new(0.25, 0.5),
+                        max: Vec2::new(0.5, 0.75),
+                    },
+                }],
+            }],
+        }
+    }
+
+    #[test]
+    fn round_trips_through_json() {
+        let original = scene();
+        let text = original.to_json_string().unwrap();
+        let parsed = SceneDesc::parse(&text).unwrap();
+
+        assert_eq!(parsed.settings, original.settings);
+        assert_eq!(parsed.camera, original.camera);
+        assert_eq!(parsed.lights, original.lights);
+        assert_eq!(parsed.groups.len(), 1);
+        assert_eq!(parsed.groups[0].model, "cube.obj");
+        let (a, b) = (&parsed.groups[0].instances[0], &original.groups[0].instances[0]);
+        assert_eq!(a.id, b.id);
+        assert_eq!(a.position, b.position);
+        assert_eq!(a.scale, b.scale);
+        assert_eq!(a.uv, b.uv);
+        // Through Euler angles in degrees, so only close.
+        let (p, q) = (a.rotation * Vec3::unit_x(), b.rotation * Vec3::unit_x());
+        assert!((p - q).mag() < 1e-5, "{:?} != {:?}", p, q);
+    }
+
+    #[test]
+    fn writes_the_current_version() {
+        let value: serde_json::Value = serde_json::from_str(&scene().to_json_string().unwrap()).unwrap();
+        assert_eq!(value["version"], SCENE_VERSION);
+        assert_eq!(value["camera"]["projection"]["type"], "orthographic");
+        assert_eq!(value["lights"][0]["type"], "point");
+    }
+
+    #[test]
+    fn fills_in_left_out_fields() {
+        let text = r#"{
+            "version": 1,
+            "camera": { "projection": { "type": "perspective", "fovy": 60, "znear": 0.1, "zfar": 10 } },
+            "lights": [{ "type": "directional" }],
+            "groups": [{ "model": "tree.obj", "instances": [{ "id": 7 }] }]
+        }"#;
+        let scene = SceneDesc::parse(text).unwrap();
+        assert_eq!(scene.settings, EngineSettings::default());
+        assert_eq!(scene.camera.eye, CameraDesc::default().eye);
+        assert_eq!(
+            scene.camera.projection,
+            ProjectionDesc::Perspective {
+                fovy: 60.0,
+                znear: 0.1,
+                zfar: 10.0,
+                reverse_z: false,
+                infinite: false,
+            }
+        );
+        assert_eq!(scene.lights[0].direction, -Vec3::unit_y());
+        assert_eq!(scene.lights[0].intensity, 1.0);
+        let instance = &scene.groups[0].instances[0];
+        assert_eq!(instance.id, 7);
+        assert_eq!(instance.scale, Vec3::one());
+        assert_eq!(instance.uv, UvRect::FULL);
+    }
+
+    #[test]
+    fn migrates_only_known_versions() {
+        let current = serde_json::json!({ "version": SCENE_VERSION, "groups": [] });
+        assert_eq!(migrate(current.clone(), SCENE_VERSION).unwrap(), current);
+
+        let newer = format!(r#"{{ "version": {} }}"#, SCENE_VERSION + 1);
+        let error = SceneDesc::parse(&newer).unwrap_err().to_string();
+        assert!(error.contains("newer"), "{}", error);
+        let error = SceneDesc::parse(r#"{ "version": 0 }"#).unwrap_err().to_string();
+        assert!(error.contains("Unknown scene version 0"), "{}", error);
+        assert!(migrate(serde_json::json!({}), SCENE_VERSION + 1).is_err());
+    }
+
+    #[test]
+    fn requires_a_whole_version_number() {
+        assert!(SceneDesc::parse(r#"{ "groups": [] }"#).is_err());
+        assert!(SceneDesc::parse(r#"{ "version": "1" }"#).is_err());
+        assert!(SceneDesc::parse(r#"{ "version": 1.5 }"#).is_err());
+        assert!(SceneDesc::parse(r#"{ "version": -1 }"#).is_err());
+    }
+
+    #[test]
+    fn keeps_instance_ids_exact() {
+        for id in [u128::MAX, u64::MAX as u128 + 1, (1 << 100) + 3] {
+            let text = format!(r#"{{ "version": 1, "groups": [{{ "model": "a.obj", "instances": [{{ "id": {} }}] }}] }}"#, id);
+            let scene = SceneDesc::parse(&text).unwrap();
+            assert_eq!(scene.groups[0].instances[0].id, id);
+            let written = SceneDesc::parse(&scene.to_json_string().unwrap()).unwrap();
+            assert_eq!(written.groups[0].instances[0].id, id);
+        }
+        let too_big = format!(r#"{{ "version": 1, "groups": [{{ "model": "a.obj", "instances": [{{ "id": {}0 }}] }}] }}"#, u128::MAX);
+        assert!(SceneDesc::parse(&too_big).is_err());
+    }
+
+    #[test]
+    fn rejects_what_the_layout_does_not_allow() {
+        assert!(SceneDesc::parse(r#"{ "version": 1, "lights": [{ "type": "spot" }] }"#).is_err());
+        assert!(SceneDesc::parse(r#"{ "version": 1, "camera": { "projection": { "type": "fisheye" } } }"#).is_err());
+        assert!(SceneDesc::parse(r#"{ "version": 1, "groups": [{ "instances": [] }] }"#).is_err());
+        assert!(SceneDesc::parse(r#"{ "version": 1, "groups": [{ "model": "a.obj", "instances": [{}] }] }"#).is_err());
+        assert!(SceneDesc::parse(r#"{ "version": 1, "camera": { "eye": [1, 2] } }"#).is_err());
+    }
+}
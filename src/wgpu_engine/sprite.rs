@@ -0,0 +1,432 @@
+use anyhow::{anyhow, Result};
+use ultraviolet::{Vec2, Vec3};
+
+use super::{
+    atlas::UvRect,
+    buffer::{create_buffer, create_uniform_bind_group, grow_buffer, push_uniform, uniform_stride},
+    camera::DepthMode, layouts::BindGroupLayouts, model::Material, model::Vertex, texture::Texture,
+};
+use crate::transform::Transform2d;
+
+// Handle to a texture registered with the `SpriteRenderer`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SpriteTextureId(pub(super) u32);
+
+// One textured quad on the z = 0 plane. `size` is in world units before the
+// transform's scale, `pivot` is the point of the quad at the transform's
+// position in fractions of its size from the bottom left, and sprites are
+// drawn by ascending `layer`, then `z`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sprite {
+    pub texture: SpriteTextureId,
+    pub transform: Transform2d,
+    pub size: Vec2,
+    pub uv: UvRect,
+    // Multiplies the texture, linear RGBA.
+    pub color: [f32; 4],
+    pub pivot: Vec2,
+    pub flip_x: bool,
+    pub flip_y: bool,
+    pub layer: i32,
+    pub z: f32,
+}
+
+impl Sprite {
+    pub fn new(texture: SpriteTextureId, size: Vec2) -> Self {
+        Self {
+            texture,
+            transform: Transform2d::default(),
+            size,
+            uv: UvRect::FULL,
+            color: [1.0; 4],
+            pivot: Vec2::new(0.5, 0.5),
+            flip_x: false,
+            flip_y: false,
+            layer: 0,
+            z: 0.0,
+        }
+    }
+
+    // Corners in world space and their UVs: bottom left, bottom right, top
+    // right, top left.
+    pub fn corners(&self) -> [(Vec3, Vec2); 4] {
+        // v points down the texture, y up the world.
+        self.quad(self.uv.max.y, self.uv.min.y)
+    }
+
+    // Corners in pixels for `SpriteRenderer::draw_screen`, where y points
+    // down like v and `pivot` is measured from the top left.
+    pub fn screen_corners(&self) -> [(Vec3, Vec2); 4] {
+        self.quad(self.uv.min.y, self.uv.max.y)
+    }
+
+    // `v0` is the v at y = 0 of the quad.
+    fn quad(&self, v0: f32, v1: f32) -> [(Vec3, Vec2); 4] {
+        let (sin, cos) = self.transform.rotation.sin_cos();
+        let scale = self.transform.scale;
+        let (mut u0, mut u1) = (self.uv.min.x, self.uv.max.x);
+        let (mut v0, mut v1) = (v0, v1);
+        if self.flip_x {
+            std::mem::swap(&mut u0, &mut u1);
+        }
+        if self.flip_y {
+            std::mem::swap(&mut v0, &mut v1);
+        }
+        let corner = |x: f32, y: f32, uv: Vec2| {
+            let local = (Vec2::new(x, y) - self.pivot) * self.size * scale;
+            let rotated = Vec2::new(local.x * cos - local.y * sin, local.x * sin + local.y * cos);
+            let world = self.transform.position + rotated;
+            (Vec3::new(world.x, world.y, 0.0), uv)
+        };
+        [
+            corner(0.0, 0.0, Vec2::new(u0, v0)),
+            corner(1.0, 0.0, Vec2::new(u1, v0)),
+            corner(1.0, 1.0, Vec2::new(u1, v1)),
+            corner(0.0, 1.0, Vec2::new(u0, v1)),
+        ]
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct SpriteVertex {
+    pub position: [f32; 3],
+    pub tex_coords: [f32; 2],
+    pub color: [f32; 4],
+}
+
+impl Vertex for SpriteVertex {
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        const ATTRIBUTES: [wgpu::VertexAttribute; 3] =
+            wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x2, 2 => Float32x4];
+        wgpu::VertexBufferLayout {
+            array_stride: size_of::<SpriteVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &ATTRIBUTES,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ScreenViewUniform {
+    viewport: [f32; 4],
+}
+
+// A run of sprites sharing a texture, drawn with one call.
+#[derive(Debug, Clone, Copy)]
+struct SpriteBatch {
+    texture: usize,
+    first_index: u32,
+    index_count: u32,
+}
+
+// Draws the sprites queued with `draw` since the last frame. Sprites are
+// sorted, written into one vertex buffer and drawn in as few calls as
+// there are texture changes, after the 3D scene of every camera view with
+// `CameraView::sprites` set. Use an orthographic camera looking down -Z for
+// plain 2D. Sprites queued with `draw_screen` are drawn in pixels over the
+// views with `CameraView::hud` set instead, e.g. for game UI.
+pub struct SpriteRenderer {
+    pipeline: wgpu::RenderPipeline,
+    reverse_z_pipeline: wgpu::RenderPipeline,
+    screen_pipeline: wgpu::RenderPipeline,
+    textures: Vec<(SpriteTextureId, Material)>,
+    sprites: Vec<Sprite>,
+    screen_sprites: Vec<Sprite>,
+    batches: Vec<SpriteBatch>,
+    // Screen sprites go after the world's in the same buffers.
+    screen_batches: Vec<SpriteBatch>,
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    view_layout: wgpu::BindGroupLayout,
+    view_buffer: wgpu::Buffer,
+    view_bind_group: wgpu::BindGroup,
+    view_stride: u64,
+    next_id: u32,
+}
+
+impl SpriteRenderer {
+    pub fn new(device: &wgpu::Device, layouts: &BindGroupLayouts, format: wgpu::TextureFormat) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("sprite.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/sprite.wgsl").into()),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Sprite Pipeline Layout"),
+            bind_group_layouts: &[&layouts.texture, &layouts.camera],
+            push_constant_ranges: &[],
+        });
+        let view_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: wgpu::BufferSize::new(size_of::<ScreenViewUniform>() as u64),
+                },
+                count: None,
+            }],
+            label: Some("sprite_view_bind_group_layout"),
+        });
+        let screen_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Screen Sprite Pipeline Layout"),
+            bind_group_layouts: &[&layouts.texture, &layouts.camera, &view_layout],
+            push_constant_ranges: &[],
+        });
+
+        let view_stride = uniform_stride::<ScreenViewUniform>(device);
+        let view_buffer = create_buffer(device, "Sprite View Buffer", view_stride, wgpu::BufferUsages::UNIFORM);
+        let view_bind_group = create_uniform_bind_group::<ScreenViewUniform>(device, &view_layout, &view_buffer, "sprite_view_bind_group");
+
+        let pipeline = |label: &str, layout: &wgpu::PipelineLayout, entry_point: &str, depth_compare: wgpu::CompareFunction| {
+            create_pipeline(device, label, layout, &shader, entry_point, format, depth_compare)
+        };
+        Self {
+            pipeline: pipeline("Sprite Pipeline", &layout, "vs_main", DepthMode::Standard.compare()),
+            reverse_z_pipeline: pipeline("Sprite Pipeline", &layout, "vs_main", DepthMode::Reversed.compare()),
+            screen_pipeline: pipeline("Screen Sprite Pipeline", &screen_layout, "vs_screen", wgpu::CompareFunction::Always),
+            textures: Vec::new(),
+            sprites: Vec::new(),
+            screen_sprites: Vec::new(),
+            batches: Vec::new(),
+            screen_batches: Vec::new(),
+            vertex_buffer: create_buffer(device, "Sprite Vertex Buffer", 1024, wgpu::BufferUsages::VERTEX),
+            index_buffer: create_buffer(device, "Sprite Index Buffer", 1024, wgpu::BufferUsages::INDEX),
+            view_layout,
+            view_buffer,
+            view_bind_group,
+            view_stride,
+            next_id: 0,
+        }
+    }
+
+    // `material` must have been created with `BindGroupLayouts::texture`.
+    pub fn add_texture(&mut self, material: Material) -> SpriteTextureId {
+        self.next_id += 1;
+        let id = SpriteTextureId(self.next_id);
+        self.textures.push((id, material));
+        id
+    }
+
+    pub fn remove_texture(&mut self, id: SpriteTextureId) -> Result<Material> {
+        let index = self.texture_index(id)?;
+        Ok(self.textures.remove(index).1)
+    }
+
+    fn texture_index(&self, id: SpriteTextureId) -> Result<usize> {
+        self.textures
+            .iter()
+            .position(|(t, _)| *t == id)
+            .ok_or_else(|| anyhow!("Sprite texture {:?} not found", id))
+    }
+
+    pub(super) fn bind_group(&self, id: SpriteTextureId) -> Option<&wgpu::BindGroup> {
+        self.textures.iter().find(|(t, _)| *t == id).map(|(_, m)| &m.bind_group)
+    }
+
+    pub fn texture(&self, id: SpriteTextureId) -> Result<&Texture> {
+        Ok(&self.textures[self.texture_index(id)?].1.diffuse_texture)
+    }
+
+    // Size of the texture in pixels.
+    pub fn texture_size(&self, id: SpriteTextureId) -> Result<Vec2> {
+        let texture = &self.texture(id)?.texture;
+        Ok(Vec2::new(texture.width() as f32, texture.height() as f32))
+    }
+
+    // Queues a sprite for the next frame only.
+    pub fn draw(&mut self, sprite: Sprite) {
+        self.sprites.push(sprite);
+    }
+
+    pub fn draw_all(&mut self, sprites: impl IntoIterator<Item = Sprite>) {
+        self.sprites.extend(sprites);
+    }
+
+    // Queues a sprite in pixels from the top left of the views' viewports,
+    // y down, for the next frame only. Drawn over the scene and under
+    // screen text.
+    pub fn draw_screen(&mut self, sprite: Sprite) {
+        self.screen_sprites.push(sprite);
+    }
+
+    pub fn queued(&self) -> usize {
+        self.sprites.len() + self.screen_sprites.len()
+    }
+
+    // Sorts the queued sprites and uploads them, once per frame before any
+    // view draws them. `viewport_sizes` are the pixel sizes of the views'
+    // viewports, in the engine's camera order. Sprites with a texture that
+    // was removed are dropped.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, viewport_sizes: &[Vec2]) {
+        let mut vertices = Vec::with_capacity((self.sprites.len() + self.screen_sprites.len()) * 4);
+        let mut indices = Vec::with_capacity((self.sprites.len() + self.screen_sprites.len()) * 6);
+        self.batches = batch(&mut self.sprites, &self.textures, &mut vertices, &mut indices, Sprite::corners);
+        self.screen_batches = batch(&mut self.screen_sprites, &self.textures, &mut vertices, &mut indices, Sprite::screen_corners);
+
+        grow_buffer(device, &mut self.vertex_buffer, "Sprite Vertex Buffer", size_of_val(vertices.as_slice()) as u64);
+        grow_buffer(device, &mut self.index_buffer, "Sprite Index Buffer", size_of_val(indices.as_slice()) as u64);
+        queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+        queue.write_buffer(&self.index_buffer, 0, bytemuck::cast_slice(&indices));
+
+        if self.screen_batches.is_empty() {
+            return;
+        }
+        let mut bytes = Vec::new();
+        for size in viewport_sizes {
+            let uniform = ScreenViewUniform {
+                viewport: [size.x.max(1.0), size.y.max(1.0), 0.0, 0.0],
+            };
+            push_uniform(&mut bytes, self.view_stride, &uniform);
+        }
+        if bytes.is_empty() {
+            return;
+        }
+        if grow_buffer(device, &mut self.view_buffer, "Sprite View Buffer", bytes.len() as u64) {
+            self.view_bind_group = create_uniform_bind_group::<ScreenViewUniform>(device, &self.view_layout, &self.view_buffer, "sprite_view_bind_group");
+        }
+        queue.write_buffer(&self.view_buffer, 0, &bytes);
+    }
+
+    pub fn draw_batches<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+        depth: DepthMode,
+    ) {
+        if self.batches.is_empty() {
+            return;
+        }
+        render_pass.set_pipeline(match depth {
+            DepthMode::Standard => &self.pipeline,
+            DepthMode::Reversed => &self.reverse_z_pipeline,
+        });
+        render_pass.set_bind_group(1, camera_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        for batch in &self.batches {
+            render_pass.set_bind_group(0, &self.textures[batch.texture].1.bind_group, &[]);
+            render_pass.draw_indexed(batch.first_index..batch.first_index + batch.index_count, 0, 0..1);
+        }
+    }
+
+    // Draws the screen sprites over view `view`, the view's index in the engine's cameras.
+    pub fn draw_screen_batches<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        view: usize,
+        camera_bind_group: &'a wgpu::BindGroup,
+    ) {
+        if self.screen_batches.is_empty() {
+            return;
+        }
+        render_pass.set_pipeline(&self.screen_pipeline);
+        render_pass.set_bind_group(1, camera_bind_group, &[]);
+        render_pass.set_bind_group(2, &self.view_bind_group, &[(view as u64 * self.view_stride) as u32]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        for batch in &self.screen_batches {
+            render_pass.set_bind_group(0, &self.textures[batch.texture].1.bind_group, &[]);
+            render_pass.draw_indexed(batch.first_index..batch.first_index + batch.index_count, 0, 0..1);
+        }
+    }
+
+    // Forgets this frame's sprites.
+    pub fn finish_frame(&mut self) {
+        self.sprites.clear();
+        self.screen_sprites.clear();
+    }
+}
+
+// Sorts sprites and appends their quads, returning the batches drawing them.
+fn batch(
+    sprites: &mut [Sprite],
+    textures: &[(SpriteTextureId, Material)],
+    vertices: &mut Vec<SpriteVertex>,
+    indices: &mut Vec<u32>,
+    corners: fn(&Sprite) -> [(Vec3, Vec2); 4],
+) -> Vec<SpriteBatch> {
+    // Stable, so sprites on the same layer and z keep the order they were
+    // queued in and overlapping translucent ones blend as submitted. Runs
+    // of the same texture still share a batch.
+    sprites.sort_by(|a, b| a.layer.cmp(&b.layer).then(a.z.total_cmp(&b.z)));
+
+    let mut batches: Vec<SpriteBatch> = Vec::new();
+    for sprite in sprites.iter() {
+        let texture = match textures.iter().position(|(t, _)| *t == sprite.texture) {
+            Some(texture) => texture,
+            None => continue,
+        };
+        let base = vertices.len() as u32;
+        for (position, uv) in corners(sprite).iter() {
+            vertices.push(SpriteVertex {
+                position: (*position).into(),
+                tex_coords: (*uv).into(),
+                color: sprite.color,
+            });
+        }
+        let first_index = indices.len() as u32;
+        indices.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+        match batches.last_mut() {
+            Some(batch) if batch.texture == texture => batch.index_count += 6,
+            _ => batches.push(SpriteBatch {
+                texture,
+                first_index,
+                index_count: 6,
+            }),
+        }
+    }
+    batches
+}
+
+// Alpha blended quads that test depth but don't write it, for 2D drawing.
+pub(super) fn create_pipeline(
+    device: &wgpu::Device,
+    label: &str,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    entry_point: &str,
+    format: wgpu::TextureFormat,
+    depth_compare: wgpu::CompareFunction,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(label),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point,
+            buffers: &[SpriteVertex::desc()],
+            compilation_options: Default::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: "fs_main",
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: Default::default(),
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            front_face: wgpu::FrontFace::Ccw,
+            // Negative scales turn quads around.
+            cull_mode: None,
+            ..Default::default()
+        },
+        // Tested against the 3D scene but not written, sprites are ordered by sorting.
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: Texture::DEPTH_FORMAT,
+            depth_write_enabled: false,
+            depth_compare,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+        cache: None,
+    })
+}
@@ -0,0 +1,833 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use ultraviolet::{Vec2, Vec3};
+
+use super::{
+    atlas::{ShelfPage, UvRect},
+    buffer::{create_buffer, create_uniform_bind_group, grow_buffer, push_uniform, uniform_stride},
+    camera::DepthMode,
+    font::{Font, GlyphBitmap, GlyphId},
+    instance::Instance,
+    layouts::BindGroupLayouts,
+    texture::Texture,
+    view::CameraView,
+};
+
+// Size of the glyph cache texture in pixels.
+const ATLAS_SIZE: u32 = 1024;
+// Distance field glyphs are rasterized once at this size and scaled.
+const SDF_SIZE: f32 = 48.0;
+const SDF_SPREAD: f32 = 6.0;
+// Plain world space glyphs are rasterized at this size.
+const WORLD_SIZE: f32 = 64.0;
+// Empty pixels kept around each glyph in the cache so filtering doesn't
+// pick up its neighbours.
+const GLYPH_PADDING: u32 = 1;
+
+// Handle to a font registered with the `TextRenderer`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FontId(pub(super) u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextAlign {
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextStyle {
+    pub font: FontId,
+    // Height of the em square: pixels for screen text, world units otherwise.
+    pub size: f32,
+    // Linear RGBA.
+    pub color: [f32; 4],
+    // Lines within the text's box.
+    pub align: TextAlign,
+    // Lines wrap at spaces, or inside words too long for a line, to stay
+    // this wide, in the same units as `size`.
+    pub max_width: Option<f32>,
+    // Multiplies the font's distance between baselines.
+    pub line_spacing: f32,
+    // Point of the text's box put at its position, in fractions of its size
+    // from the top left.
+    pub anchor: Vec2,
+    // Draws from a signed distance field, which stays sharp at any scale.
+    // Plain screen text is rasterized at its exact size instead.
+    pub sdf: bool,
+}
+
+impl TextStyle {
+    pub fn new(font: FontId, size: f32) -> Self {
+        Self {
+            font,
+            size,
+            color: [1.0; 4],
+            align: TextAlign::Left,
+            max_width: None,
+            line_spacing: 1.0,
+            anchor: Vec2::zero(),
+            sdf: false,
+        }
+    }
+}
+
+// Where queued text is drawn.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextPlacement {
+    // Pixels from the top left of every view with `CameraView::hud` set.
+    Screen(Vec2),
+    // On the world's XY plane, reading along +X, seen from +Z.
+    World(Vec3),
+    // Turned to face the camera of each view, e.g. a name over a character.
+    Billboard(Vec3),
+}
+
+impl TextPlacement {
+    // A billboard `height` world units above an instance's origin.
+    pub fn above(instance: &Instance, height: f32) -> Self {
+        TextPlacement::Billboard(instance.position + Vec3::unit_y() * height)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LayoutGlyph {
+    pub glyph: GlyphId,
+    // Byte index of the glyph's character in the text.
+    pub index: usize,
+    // Pen position on the baseline, from the top left of the text's box with
+    // y down, in the style's units.
+    pub position: Vec2,
+    pub advance: f32,
+    pub whitespace: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LayoutLine {
+    // Range of `TextLayout::glyphs` on this line.
+    pub first_glyph: usize,
+    pub glyph_count: usize,
+    // Without the spaces it ends in.
+    pub width: f32,
+    // Down from the top of the box.
+    pub baseline: f32,
+}
+
+// Positioned glyphs of a text, see `TextRenderer::layout`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TextLayout {
+    pub glyphs: Vec<LayoutGlyph>,
+    pub lines: Vec<LayoutLine>,
+    // Width of the widest line, height of all lines.
+    pub size: Vec2,
+}
+
+impl TextLayout {
+    // Lays out `text` in `font`, breaking lines at '\n' and wherever
+    // `style.max_width` says. Control characters take no room.
+    pub fn new(font: &Font, text: &str, style: &TextStyle) -> Self {
+        let scale = font.scale(style.size);
+        let line_height = font.line_height() * scale * style.line_spacing;
+        let ascent = font.ascender * scale;
+        let mut lines: Vec<(Vec<LayoutGlyph>, f32)> = Vec::new();
+
+        let mut offset = 0;
+        for paragraph in text.split('\n') {
+            let mut line: Vec<LayoutGlyph> = Vec::new();
+            // Index in `line` of the last space a line can break after.
+            let mut last_break = None;
+            let mut pen = 0.0;
+            for (i, c) in paragraph.char_indices() {
+                if c.is_control() {
+                    continue;
+                }
+                let glyph = font.glyph_index(c);
+                let kerning = line.last().map_or(0.0, |previous| font.kerning(previous.glyph, glyph) * scale);
+                let advance = font.advance(glyph) * scale;
+                let mut x = pen + kerning;
+                let too_wide = style.max_width.is_some_and(|max| x + advance > max);
+                if too_wide && !c.is_whitespace() && !line.is_empty() {
+                    let rest = match last_break.take() {
+                        Some(space) => line.split_off(space + 1),
+                        None => Vec::new(),
+                    };
+                    let shift = rest.first().map_or(x, |g| g.position.x);
+                    let finished = std::mem::replace(&mut line, rest);
+                    let width = line_width(&finished);
+                    lines.push((finished, width));
+                    for g in &mut line {
+                        g.position.x -= shift;
+                    }
+                    x = if line.is_empty() { 0.0 } else { x - shift };
+                }
+                line.push(LayoutGlyph {
+                    glyph,
+                    index: offset + i,
+                    position: Vec2::new(x, 0.0),
+                    advance,
+                    whitespace: c.is_whitespace(),
+                });
+                if c.is_whitespace() {
+                    last_break = Some(line.len() - 1);
+                }
+                pen = x + advance;
+            }
+            let width = line_width(&line);
+            lines.push((line, width));
+            offset += paragraph.len() + 1;
+        }
+
+        let width = lines.iter().map(|(_, w)| *w).fold(0.0, f32::max);
+        let mut layout = TextLayout {
+            glyphs: Vec::new(),
+            lines: Vec::with_capacity(lines.len()),
+            size: Vec2::new(width, line_height * lines.len() as f32),
+        };
+        for (row, (glyphs, line_width)) in lines.into_iter().enumerate() {
+            let x = match style.align {
+                TextAlign::Left => 0.0,
+                TextAlign::Center => (width - line_width) * 0.5,
+                TextAlign::Right => width - line_width,
+            };
+            let baseline = ascent + row as f32 * line_height;
+            layout.lines.push(LayoutLine {
+                first_glyph: layout.glyphs.len(),
+                glyph_count: glyphs.len(),
+                width: line_width,
+                baseline,
+            });
+            layout.glyphs.extend(glyphs.into_iter().map(|mut g| {
+                g.position += Vec2::new(x, baseline);
+                g
+            }));
+        }
+        layout
+    }
+}
+
+// Right edge of the last glyph that isn't a space.
+fn line_width(line: &[LayoutGlyph]) -> f32 {
+    line.iter()
+        .rev()
+        .find(|g| !g.whitespace)
+        .map_or(0.0, |g| g.position.x + g.advance)
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct TextVertex {
+    // Pixels from the view's top left for screen text, world space otherwise.
+    pub position: [f32; 3],
+    // World units along the camera's right and up from `position`, for billboards.
+    pub offset: [f32; 2],
+    pub tex_coords: [f32; 2],
+    pub color: [f32; 4],
+    // 1 for distance field glyphs.
+    pub sdf: u32,
+}
+
+impl TextVertex {
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        const ATTRIBUTES: [wgpu::VertexAttribute; 5] = wgpu::vertex_attr_array![
+            0 => Float32x3, 1 => Float32x2, 2 => Float32x2, 3 => Float32x4, 4 => Uint32
+        ];
+        wgpu::VertexBufferLayout {
+            array_stride: size_of::<TextVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &ATTRIBUTES,
+        }
+    }
+}
+
+// Per view: the camera's right and up for billboards, the viewport's size in
+// pixels for screen text.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct TextViewUniform {
+    right: [f32; 4],
+    up: [f32; 4],
+    viewport: [f32; 4],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct GlyphKey {
+    font: FontId,
+    glyph: GlyphId,
+    // Pixels per em it was rasterized at.
+    size: u32,
+    sdf: bool,
+}
+
+// A glyph in the cache texture, in pixels of its rasterized size.
+#[derive(Debug, Clone, Copy)]
+struct CachedGlyph {
+    uv: UvRect,
+    size: Vec2,
+    left: f32,
+    top: f32,
+}
+
+struct QueuedText {
+    text: String,
+    placement: TextPlacement,
+    style: TextStyle,
+}
+
+// The cache texture ran out of room.
+struct AtlasFull;
+
+// Draws the text queued with `draw` since the last frame. Glyphs are
+// rasterized on first use into a cache texture shared by all fonts; when it
+// fills up it is cleared and refilled with what the frame needs. World text
+// is depth tested against the scene of every view, screen text is drawn
+// over everything in views with `CameraView::hud`.
+pub struct TextRenderer {
+    world_pipeline: wgpu::RenderPipeline,
+    reverse_z_pipeline: wgpu::RenderPipeline,
+    screen_pipeline: wgpu::RenderPipeline,
+    atlas: Texture,
+    atlas_bind_group: wgpu::BindGroup,
+    shelves: ShelfPage,
+    glyphs: HashMap<GlyphKey, Option<CachedGlyph>>,
+    // Rasterized glyphs waiting to be written into the cache texture.
+    uploads: Vec<(u32, u32, GlyphBitmap)>,
+    fonts: Vec<(FontId, Font)>,
+    queued: Vec<QueuedText>,
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    // Index ranges of this frame's world and screen text.
+    world_indices: std::ops::Range<u32>,
+    screen_indices: std::ops::Range<u32>,
+    view_layout: wgpu::BindGroupLayout,
+    view_buffer: wgpu::Buffer,
+    view_bind_group: wgpu::BindGroup,
+    view_stride: u64,
+    next_id: u32,
+}
+
+impl TextRenderer {
+    pub fn new(device: &wgpu::Device, layouts: &BindGroupLayouts, format: wgpu::TextureFormat) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("text.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/text.wgsl").into()),
+        });
+        let view_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: wgpu::BufferSize::new(size_of::<TextViewUniform>() as u64),
+                },
+                count: None,
+            }],
+            label: Some("text_view_bind_group_layout"),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Text Pipeline Layout"),
+            bind_group_layouts: &[&layouts.texture, &layouts.camera, &view_layout],
+            push_constant_ranges: &[],
+        });
+
+        let atlas = create_atlas(device);
+        let atlas_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &layouts.texture,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&atlas.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&atlas.sampler),
+                },
+            ],
+            label: Some("glyph_atlas_bind_group"),
+        });
+
+        let view_stride = uniform_stride::<TextViewUniform>(device);
+        let view_buffer = create_buffer(device, "Text View Buffer", view_stride, wgpu::BufferUsages::UNIFORM);
+        let view_bind_group = create_uniform_bind_group::<TextViewUniform>(device, &view_layout, &view_buffer, "text_view_bind_group");
+
+        let pipeline = |label: &str, entry_point: &str, depth_compare: wgpu::CompareFunction| {
+            create_pipeline(device, label, &layout, &shader, entry_point, format, depth_compare)
+        };
+        Self {
+            world_pipeline: pipeline("Text Pipeline", "vs_world", DepthMode::Standard.compare()),
+            reverse_z_pipeline: pipeline("Text Pipeline", "vs_world", DepthMode::Reversed.compare()),
+            screen_pipeline: pipeline("Screen Text Pipeline", "vs_screen", wgpu::CompareFunction::Always),
+            atlas,
+            atlas_bind_group,
+            shelves: ShelfPage::default(),
+            glyphs: HashMap::new(),
+            uploads: Vec::new(),
+            fonts: Vec::new(),
+            queued: Vec::new(),
+            vertex_buffer: create_buffer(device, "Text Vertex Buffer", 1024, wgpu::BufferUsages::VERTEX),
+            index_buffer: create_buffer(device, "Text Index Buffer", 1024, wgpu::BufferUsages::INDEX),
+            world_indices: 0..0,
+            screen_indices: 0..0,
+            view_layout,
+            view_buffer,
+            view_bind_group,
+            view_stride,
+            next_id: 0,
+        }
+    }
+
+    pub fn add_font(&mut self, font: Font) -> FontId {
+        self.next_id += 1;
+        let id = FontId(self.next_id);
+        self.fonts.push((id, font));
+        id
+    }
+
+    pub fn remove_font(&mut self, id: FontId) -> Result<Font> {
+        let index = self
+            .fonts
+            .iter()
+            .position(|(f, _)| *f == id)
+            .ok_or_else(|| anyhow!("Font {:?} not found", id))?;
+        self.glyphs.retain(|key, _| key.font != id);
+        Ok(self.fonts.remove(index).1)
+    }
+
+    pub fn font(&self, id: FontId) -> Result<&Font> {
+        self.fonts
+            .iter()
+            .find(|(f, _)| *f == id)
+            .map(|(_, font)| font)
+            .ok_or_else(|| anyhow!("Font {:?} not found", id))
+    }
+
+    pub fn layout(&self, text: &str, style: &TextStyle) -> Result<TextLayout> {
+        Ok(TextLayout::new(self.font(style.font)?, text, style))
+    }
+
+    // Size of the text's box in the style's units.
+    pub fn measure(&self, text: &str, style: &TextStyle) -> Result<Vec2> {
+        Ok(self.layout(text, style)?.size)
+    }
+
+    // Queues text for the next frame only.
+    pub fn draw(&mut self, text: &str, placement: TextPlacement, style: &TextStyle) {
+        self.queued.push(QueuedText {
+            text: text.to_string(),
+            placement,
+            style: *style,
+        });
+    }
+
+    pub fn queued(&self) -> usize {
+        self.queued.len()
+    }
+
+    // Lays out the queued text and uploads it with any new glyphs, once per
+    // frame before any view draws it. `viewport_sizes` are the pixel sizes of
+    // the views' viewports, in the same order as `views`. Text in a font
+    // that was removed is dropped.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, views: &[CameraView], viewport_sizes: &[Vec2]) {
+        let mut world = (Vec::new(), Vec::new());
+        let mut screen = (Vec::new(), Vec::new());
+        let built = self.build(&mut world, &mut screen);
+        if built.is_err() {
+            // Start over with only this frame's glyphs in the cache.
+            self.glyphs.clear();
+            self.uploads.clear();
+            self.shelves = ShelfPage::default();
+            world = (Vec::new(), Vec::new());
+            screen = (Vec::new(), Vec::new());
+            if self.build(&mut world, &mut screen).is_err() {
+                log::warn!("Glyph cache is too small for this frame's text, some is left out");
+            }
+        }
+
+        for (x, y, bitmap) in self.uploads.drain(..) {
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    texture: &self.atlas.texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d { x, y, z: 0 },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                &bitmap.data,
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(bitmap.width),
+                    rows_per_image: Some(bitmap.height),
+                },
+                wgpu::Extent3d {
+                    width: bitmap.width,
+                    height: bitmap.height,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+
+        // Screen text goes after world text in the same buffers.
+        let (mut vertices, mut indices) = world;
+        let world_end = indices.len() as u32;
+        let base = vertices.len() as u32;
+        vertices.extend(screen.0);
+        indices.extend(screen.1.iter().map(|i| i + base));
+        self.world_indices = 0..world_end;
+        self.screen_indices = world_end..indices.len() as u32;
+        if !indices.is_empty() {
+            grow_buffer(device, &mut self.vertex_buffer, "Text Vertex Buffer", size_of_val(vertices.as_slice()) as u64);
+            grow_buffer(device, &mut self.index_buffer, "Text Index Buffer", size_of_val(indices.as_slice()) as u64);
+            queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+            queue.write_buffer(&self.index_buffer, 0, bytemuck::cast_slice(&indices));
+        }
+
+        let mut bytes = Vec::new();
+        for (view, size) in views.iter().zip(viewport_sizes) {
+            let look = view.camera.view();
+            let forward = (look.target - look.eye).normalized();
+            let right = forward.cross(look.up).normalized();
+            let up = right.cross(forward);
+            let uniform = TextViewUniform {
+                right: [right.x, right.y, right.z, 0.0],
+                up: [up.x, up.y, up.z, 0.0],
+                viewport: [size.x.max(1.0), size.y.max(1.0), 0.0, 0.0],
+            };
+            push_uniform(&mut bytes, self.view_stride, &uniform);
+        }
+        if bytes.is_empty() {
+            return;
+        }
+        if grow_buffer(device, &mut self.view_buffer, "Text View Buffer", bytes.len() as u64) {
+            self.view_bind_group = create_uniform_bind_group::<TextViewUniform>(device, &self.view_layout, &self.view_buffer, "text_view_bind_group");
+        }
+        queue.write_buffer(&self.view_buffer, 0, &bytes);
+    }
+
+    // Quads for every queued text, world and screen text apart.
+    fn build(
+        &mut self,
+        world: &mut (Vec<TextVertex>, Vec<u32>),
+        screen: &mut (Vec<TextVertex>, Vec<u32>),
+    ) -> Result<(), AtlasFull> {
+        let queued = std::mem::take(&mut self.queued);
+        let result = queued.iter().try_for_each(|text| {
+            let (vertices, indices) = match text.placement {
+                TextPlacement::Screen(_) => &mut *screen,
+                _ => &mut *world,
+            };
+            self.build_text(text, vertices, indices)
+        });
+        self.queued = queued;
+        result
+    }
+
+    fn build_text(&mut self, text: &QueuedText, vertices: &mut Vec<TextVertex>, indices: &mut Vec<u32>) -> Result<(), AtlasFull> {
+        let style = &text.style;
+        let font_index = match self.fonts.iter().position(|(f, _)| *f == style.font) {
+            Some(index) => index,
+            None => return Ok(()),
+        };
+        let layout = TextLayout::new(&self.fonts[font_index].1, &text.text, style);
+        let screen = matches!(text.placement, TextPlacement::Screen(_));
+        let raster_size = if style.sdf {
+            SDF_SIZE
+        } else if screen {
+            style.size.round().max(1.0)
+        } else {
+            WORLD_SIZE
+        };
+        let scale = style.size / raster_size;
+        let origin = layout.size * style.anchor;
+
+        for glyph in &layout.glyphs {
+            let key = GlyphKey {
+                font: style.font,
+                glyph: glyph.glyph,
+                size: raster_size as u32,
+                sdf: style.sdf,
+            };
+            let cached = match self.cached_glyph(font_index, key)? {
+                Some(cached) => cached,
+                None => continue,
+            };
+            // Top left of the quad from the top left of the box, y down.
+            let mut top_left = glyph.position - origin + Vec2::new(cached.left, -cached.top) * scale;
+            let size = cached.size * scale;
+            if screen && !style.sdf {
+                // Whole pixels keep plain glyphs as sharp as they were rasterized.
+                top_left = Vec2::new(top_left.x.round(), top_left.y.round());
+            }
+            let base = vertices.len() as u32;
+            let uv = cached.uv;
+            let corners = [
+                (Vec2::new(0.0, size.y), Vec2::new(uv.min.x, uv.max.y)),
+                (size, uv.max),
+                (Vec2::new(size.x, 0.0), Vec2::new(uv.max.x, uv.min.y)),
+                (Vec2::zero(), uv.min),
+            ];
+            for (corner, tex_coords) in corners {
+                let local = top_left + corner;
+                let (position, offset) = match text.placement {
+                    TextPlacement::Screen(at) => (Vec3::new(at.x + local.x, at.y + local.y, 0.0), Vec2::zero()),
+                    TextPlacement::World(at) => (at + Vec3::new(local.x, -local.y, 0.0), Vec2::zero()),
+                    TextPlacement::Billboard(at) => (at, Vec2::new(local.x, -local.y)),
+                };
+                vertices.push(TextVertex {
+                    position: position.into(),
+                    offset: offset.into(),
+                    tex_coords: tex_coords.into(),
+                    color: style.color,
+                    sdf: style.sdf as u32,
+                });
+            }
+            indices.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+        }
+        Ok(())
+    }
+
+    // The glyph from the cache, rasterized and placed on first use. None for
+    // glyphs without pixels.
+    fn cached_glyph(&mut self, font_index: usize, key: GlyphKey) -> Result<Option<CachedGlyph>, AtlasFull> {
+        if let Some(cached) = self.glyphs.get(&key) {
+            return Ok(*cached);
+        }
+        let spread = key.sdf.then_some(SDF_SPREAD);
+        let bitmap = match self.fonts[font_index].1.rasterize(key.glyph, key.size as f32, spread) {
+            Ok(bitmap) => bitmap,
+            Err(e) => {
+                log::warn!("{}", e);
+                GlyphBitmap::default()
+            }
+        };
+        if bitmap.width == 0 || bitmap.height == 0 {
+            self.glyphs.insert(key, None);
+            return Ok(None);
+        }
+        let (x, y) = self
+            .shelves
+            .place(bitmap.width + GLYPH_PADDING * 2, bitmap.height + GLYPH_PADDING * 2, ATLAS_SIZE)
+            .ok_or(AtlasFull)?;
+        let (x, y) = (x + GLYPH_PADDING, y + GLYPH_PADDING);
+        let cached = CachedGlyph {
+            uv: UvRect::from_pixels(x as f32, y as f32, bitmap.width as f32, bitmap.height as f32, Vec2::broadcast(ATLAS_SIZE as f32)),
+            size: Vec2::new(bitmap.width as f32, bitmap.height as f32),
+            left: bitmap.left,
+            top: bitmap.top,
+        };
+        self.uploads.push((x, y, bitmap));
+        self.glyphs.insert(key, Some(cached));
+        Ok(Some(cached))
+    }
+
+    // Draws the world text for the view at `view` in the slice given to `prepare`.
+    pub fn draw_world<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        view: usize,
+        camera_bind_group: &'a wgpu::BindGroup,
+        depth: DepthMode,
+    ) {
+        let pipeline = match depth {
+            DepthMode::Standard => &self.world_pipeline,
+            DepthMode::Reversed => &self.reverse_z_pipeline,
+        };
+        self.draw_range(render_pass, pipeline, view, camera_bind_group, self.world_indices.clone());
+    }
+
+    // Draws the screen text over everything in the view at `view`.
+    pub fn draw_screen<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, view: usize, camera_bind_group: &'a wgpu::BindGroup) {
+        self.draw_range(render_pass, &self.screen_pipeline, view, camera_bind_group, self.screen_indices.clone());
+    }
+
+    fn draw_range<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        pipeline: &'a wgpu::RenderPipeline,
+        view: usize,
+        camera_bind_group: &'a wgpu::BindGroup,
+        indices: std::ops::Range<u32>,
+    ) {
+        if indices.is_empty() {
+            return;
+        }
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, &self.atlas_bind_group, &[]);
+        render_pass.set_bind_group(1, camera_bind_group, &[]);
+        render_pass.set_bind_group(2, &self.view_bind_group, &[(view as u64 * self.view_stride) as u32]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        render_pass.draw_indexed(indices, 0, 0..1);
+    }
+
+    // Forgets this frame's text.
+    pub fn finish_frame(&mut self) {
+        self.queued.clear();
+    }
+}
+
+fn create_atlas(device: &wgpu::Device) -> Texture {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Glyph Atlas"),
+        size: wgpu::Extent3d {
+            width: ATLAS_SIZE,
+            height: ATLAS_SIZE,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::R8Unorm,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+        address_mode_u: wgpu::AddressMode::ClampToEdge,
+        address_mode_v: wgpu::AddressMode::ClampToEdge,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+    });
+    Texture { texture, view, sampler }
+}
+
+fn create_pipeline(
+    device: &wgpu::Device,
+    label: &str,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    vertex_entry_point: &str,
+    format: wgpu::TextureFormat,
+    depth_compare: wgpu::CompareFunction,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(label),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: vertex_entry_point,
+            buffers: &[TextVertex::desc()],
+            compilation_options: Default::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: "fs_main",
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: Default::default(),
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            front_face: wgpu::FrontFace::Ccw,
+            // World text can be read from behind, mirrored, and screen
+            // quads flip over on the way to clip space.
+            cull_mode: None,
+            ..Default::default()
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: Texture::DEPTH_FORMAT,
+            depth_write_enabled: false,
+            depth_compare,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+        cache: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: f32 = 20.0;
+
+    // Hack is monospace: every glyph is `advance` wide and none are kerned.
+    fn hack() -> (Font, f32) {
+        let font = Font::from_bytes(epaint_default_fonts::HACK_REGULAR.to_vec()).unwrap();
+        let advance = font.advance(font.glyph_index('a')) * font.scale(SIZE);
+        (font, advance)
+    }
+
+    fn style(max_width: Option<f32>, align: TextAlign) -> TextStyle {
+        TextStyle {
+            max_width,
+            align,
+            ..TextStyle::new(FontId(0), SIZE)
+        }
+    }
+
+    fn line_text<'a>(text: &'a str, layout: &TextLayout, line: usize) -> Vec<&'a str> {
+        let line = layout.lines[line];
+        layout.glyphs[line.first_glyph..line.first_glyph + line.glyph_count]
+            .iter()
+            .map(|g| &text[g.index..g.index + text[g.index..].chars().next().unwrap().len_utf8()])
+            .collect()
+    }
+
+    #[test]
+    fn wraps_at_spaces() {
+        let (font, advance) = hack();
+        let text = "aaa bbb ccc";
+        let layout = TextLayout::new(&font, text, &style(Some(advance * 8.5), TextAlign::Left));
+        assert_eq!(layout.lines.len(), 2);
+        // The space stays at the end of the first line but takes no width.
+        assert_eq!(line_text(text, &layout, 0).concat(), "aaa bbb ");
+        assert_eq!(layout.lines[0].width, advance * 7.0);
+        assert_eq!(line_text(text, &layout, 1).concat(), "ccc");
+        assert_eq!(layout.glyphs[8].position.x, 0.0);
+        assert_eq!(layout.lines[1].width, advance * 3.0);
+        let line_height = font.line_height() * font.scale(SIZE);
+        assert_eq!(layout.lines[1].baseline - layout.lines[0].baseline, line_height);
+        assert_eq!(layout.size, Vec2::new(advance * 7.0, line_height * 2.0));
+    }
+
+    #[test]
+    fn breaks_words_longer_than_a_line() {
+        let (font, advance) = hack();
+        let text = "aaaaaabb";
+        let layout = TextLayout::new(&font, text, &style(Some(advance * 4.5), TextAlign::Left));
+        assert_eq!(layout.lines.iter().map(|l| l.glyph_count).collect::<Vec<_>>(), vec![4, 4]);
+        assert_eq!(line_text(text, &layout, 1).concat(), "aabb");
+        assert_eq!(layout.glyphs[4].position.x, 0.0);
+        assert_eq!(layout.glyphs[5].position.x, advance);
+    }
+
+    #[test]
+    fn breaks_lines_at_newlines() {
+        let (font, advance) = hack();
+        let layout = TextLayout::new(&font, "ab\n\ncd", &style(None, TextAlign::Left));
+        assert_eq!(layout.lines.len(), 3);
+        assert_eq!(layout.lines[1].glyph_count, 0);
+        assert_eq!(layout.lines[1].width, 0.0);
+        let c = &layout.glyphs[2];
+        assert_eq!((c.index, c.position.x), (4, 0.0));
+        assert_eq!(layout.size.x, advance * 2.0);
+    }
+
+    #[test]
+    fn aligns_lines_in_the_widest() {
+        let (font, advance) = hack();
+        let first_x = |align| TextLayout::new(&font, "a\naaa", &style(None, align)).glyphs[0].position.x;
+        assert_eq!(first_x(TextAlign::Left), 0.0);
+        assert_eq!(first_x(TextAlign::Center), advance);
+        assert_eq!(first_x(TextAlign::Right), advance * 2.0);
+    }
+
+    #[test]
+    fn indexes_glyphs_by_byte() {
+        let (font, advance) = hack();
+        let text = "aé€😀b";
+        let layout = TextLayout::new(&font, text, &style(None, TextAlign::Left));
+        let indices: Vec<usize> = layout.glyphs.iter().map(|g| g.index).collect();
+        assert_eq!(indices, vec![0, 1, 3, 6, 10]);
+        assert_ne!(layout.glyphs[1].glyph, 0);
+        // Characters the font lacks still take the missing glyph's room.
+        assert_eq!(layout.glyphs[3].glyph, 0);
+        assert_eq!(layout.glyphs[4].position.x, advance * 4.0);
+    }
+}
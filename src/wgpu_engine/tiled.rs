@@ -0,0 +1,739 @@
+use std::{collections::HashMap, str::FromStr};
+
+use anyhow::{anyhow, Context, Result};
+use base64::Engine;
+use roxmltree::{Document, Node};
+use serde::Deserialize;
+use ultraviolet::Vec2;
+
+use super::{
+    atlas::sibling_path,
+    resources,
+    sprite::SpriteTextureId,
+    tilemap::{Tile, TileFrame, Tilemap, Tileset},
+};
+
+// Flags in the top bits of Tiled's global tile ids. The bit below them
+// rotates hexagonal tiles and is ignored.
+const FLIP_X: u32 = 0x8000_0000;
+const FLIP_Y: u32 = 0x4000_0000;
+const FLIP_DIAGONAL: u32 = 0x2000_0000;
+const GID_MASK: u32 = 0x0fff_ffff;
+
+// A tileset of a Tiled map, before its image is loaded.
+#[derive(Debug, Clone)]
+pub struct TiledTileset {
+    pub name: String,
+    // Global id of the set's first tile in the map.
+    pub first_gid: u32,
+    // Asset path of the image.
+    pub image: String,
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub margin: u32,
+    pub spacing: u32,
+    pub animations: HashMap<u32, Vec<TileFrame>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TiledLayer {
+    pub name: String,
+    pub visible: bool,
+    // Tint with the opacity in alpha, linear.
+    pub color: [f32; 4],
+    // Pixels, y down.
+    pub offset: Vec2,
+    pub parallax: Vec2,
+    // The layer's `foreground` property.
+    pub foreground: bool,
+    // Global ids with flip flags by cell, 0 cells left out.
+    pub tiles: Vec<(i32, i32, u32)>,
+}
+
+// An orthogonal map saved by Tiled. Tile layers are kept, inside groups
+// too, object and image layers are skipped.
+#[derive(Debug, Clone)]
+pub struct TiledMap {
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub tilesets: Vec<TiledTileset>,
+    // Back to front.
+    pub layers: Vec<TiledLayer>,
+}
+
+impl TiledMap {
+    // Reads a `.tmx` or `.tmj` map from the asset directory with the external
+    // tilesets it uses. Tile data can be CSV or uncompressed Base64.
+    pub async fn load(file_name: &str) -> Result<TiledMap> {
+        let text = resources::load_string(file_name).await?;
+        Self::parse(file_name, &text).await.with_context(|| file_name.to_string())
+    }
+
+    // Reads the `text` of the map at `file_name`, loading the external
+    // tilesets it uses relative to it.
+    pub async fn parse(file_name: &str, text: &str) -> Result<TiledMap> {
+        if is_json(file_name) {
+            Self::load_json(file_name, serde_json::from_str(text)?).await
+        } else {
+            Self::load_xml(file_name, Document::parse(text)?.root_element()).await
+        }
+    }
+
+    async fn load_xml(file_name: &str, root: Node<'_, '_>) -> Result<TiledMap> {
+        check_orientation(root.attribute("orientation").unwrap_or("orthogonal"))?;
+        let mut tilesets = Vec::new();
+        for element in children(root, "tileset") {
+            let first_gid = parse_attr(element, "firstgid", 1)?;
+            tilesets.push(match element.attribute("source") {
+                Some(source) => load_external_tileset(&sibling_path(file_name, source), first_gid).await?,
+                None => tileset_from_xml(element, first_gid, file_name)?,
+            });
+        }
+        let mut layers = Vec::new();
+        layers_from_xml(root, &LayerState::default(), &mut layers)?;
+        Ok(TiledMap {
+            tile_width: parse_attr(root, "tilewidth", 0)?,
+            tile_height: parse_attr(root, "tileheight", 0)?,
+            tilesets,
+            layers,
+        })
+    }
+
+    async fn load_json(file_name: &str, root: MapJson) -> Result<TiledMap> {
+        check_orientation(&root.orientation)?;
+        let mut tilesets = Vec::new();
+        for entry in root.tilesets {
+            tilesets.push(match entry.source {
+                Some(source) => load_external_tileset(&sibling_path(file_name, &source), entry.firstgid).await?,
+                None => tileset_from_json(entry.tileset, entry.firstgid, file_name)?,
+            });
+        }
+        let mut layers = Vec::new();
+        layers_from_json(&root.layers, &LayerState::default(), &mut layers)?;
+        Ok(TiledMap {
+            tile_width: root.tilewidth,
+            tile_height: root.tileheight,
+            tilesets,
+            layers,
+        })
+    }
+
+    // `textures` holds the loaded image of each tileset with its size in
+    // pixels. A layer using tiles of several tilesets becomes one layer per
+    // tileset, in the order of the tilesets, each named after it.
+    pub fn build(&self, textures: &[(SpriteTextureId, Vec2)], pixels_per_unit: f32) -> Result<Tilemap> {
+        if textures.len() != self.tilesets.len() {
+            return Err(anyhow!("{} textures for {} tilesets", textures.len(), self.tilesets.len()));
+        }
+        let mut map = Tilemap::new(self.tile_width, self.tile_height);
+        map.pixels_per_unit = pixels_per_unit;
+        for (tiled, &(texture, size)) in self.tilesets.iter().zip(textures) {
+            let mut tileset = Tileset::new(&tiled.name, texture, size, tiled.tile_width, tiled.tile_height);
+            tileset.margin = tiled.margin;
+            tileset.spacing = tiled.spacing;
+            tileset.animations = tiled.animations.clone();
+            map.add_tileset(tileset);
+        }
+
+        for tiled in &self.layers {
+            let mut by_tileset: Vec<Vec<(i32, i32, u32)>> = vec![Vec::new(); self.tilesets.len().max(1)];
+            for &(x, y, gid) in &tiled.tiles {
+                // The tileset with the highest first id not above a tile's id holds it.
+                let tileset = self
+                    .tilesets
+                    .iter()
+                    .rposition(|t| t.first_gid <= gid & GID_MASK)
+                    .ok_or_else(|| anyhow!("Layer {:?} uses tile {} of no tileset", tiled.name, gid & GID_MASK))?;
+                by_tileset[tileset].push((x, y, gid));
+            }
+            // A layer without tiles is still kept, on the first tileset.
+            let empty = tiled.tiles.is_empty();
+            for (tileset, tiles) in by_tileset.iter().enumerate() {
+                if tiles.is_empty() && !(empty && tileset == 0) {
+                    continue;
+                }
+                let layer = map.add_layer(&tiled.name, tileset);
+                layer.visible = tiled.visible;
+                layer.color = tiled.color;
+                layer.offset = Vec2::new(tiled.offset.x, -tiled.offset.y) / pixels_per_unit;
+                layer.parallax = tiled.parallax;
+                layer.foreground = tiled.foreground;
+                let first_gid = self.tilesets.get(tileset).map_or(1, |t| t.first_gid);
+                for &(x, y, gid) in tiles {
+                    layer.set_tile(
+                        x,
+                        y,
+                        Some(Tile {
+                            index: (gid & GID_MASK) - first_gid,
+                            flip_x: gid & FLIP_X != 0,
+                            flip_y: gid & FLIP_Y != 0,
+                            flip_diagonal: gid & FLIP_DIAGONAL != 0,
+                        }),
+                    );
+                }
+            }
+        }
+        Ok(map)
+    }
+}
+
+fn is_json(file_name: &str) -> bool {
+    let extension = file_name.rsplit('.').next().unwrap_or("");
+    ["tmj", "tsj", "json"].iter().any(|e| extension.eq_ignore_ascii_case(e))
+}
+
+fn check_orientation(orientation: &str) -> Result<()> {
+    if orientation == "orthogonal" {
+        Ok(())
+    } else {
+        Err(anyhow!("Only orthogonal maps are supported, not {}", orientation))
+    }
+}
+
+// `.tsx` or `.tsj`, image paths are relative to the tileset file.
+async fn load_external_tileset(file_name: &str, first_gid: u32) -> Result<TiledTileset> {
+    let text = resources::load_string(file_name).await?;
+    let tileset = if is_json(file_name) {
+        serde_json::from_str(&text).map_err(anyhow::Error::from).and_then(|t| tileset_from_json(t, first_gid, file_name))
+    } else {
+        Document::parse(&text)
+            .map_err(anyhow::Error::from)
+            .and_then(|document| tileset_from_xml(document.root_element(), first_gid, file_name))
+    };
+    tileset.with_context(|| file_name.to_string())
+}
+
+fn tileset_from_xml(element: Node, first_gid: u32, file_name: &str) -> Result<TiledTileset> {
+    let name = element.attribute("name").unwrap_or("").to_string();
+    let image = child(element, "image")
+        .ok_or_else(|| anyhow!("Tileset {:?} has no single image, image collections are not supported", name))?;
+    let mut animations = HashMap::new();
+    for tile in children(element, "tile") {
+        if let Some(animation) = child(tile, "animation") {
+            let frames = children(animation, "frame")
+                .map(|frame| {
+                    Ok(TileFrame {
+                        tile: parse_attr(frame, "tileid", 0)?,
+                        duration: parse_attr(frame, "duration", 0.0f32)? / 1000.0,
+                    })
+                })
+                .collect::<Result<_>>()?;
+            animations.insert(parse_attr(tile, "id", 0)?, frames);
+        }
+    }
+    let source = image.attribute("source").ok_or_else(|| anyhow!("Tileset {:?} image has no source", name))?;
+    Ok(TiledTileset {
+        first_gid,
+        image: sibling_path(file_name, source),
+        tile_width: parse_attr(element, "tilewidth", 0)?,
+        tile_height: parse_attr(element, "tileheight", 0)?,
+        margin: parse_attr(element, "margin", 0)?,
+        spacing: parse_attr(element, "spacing", 0)?,
+        animations,
+        name,
+    })
+}
+
+fn tileset_from_json(value: TilesetJson, first_gid: u32, file_name: &str) -> Result<TiledTileset> {
+    let image = match value.image {
+        Some(image) => image,
+        None => return Err(anyhow!("Tileset {:?} has no single image, image collections are not supported", value.name)),
+    };
+    let animations = value
+        .tiles
+        .into_iter()
+        .filter_map(|tile| {
+            let frames = tile.animation?;
+            let frames = frames
+                .into_iter()
+                .map(|frame| TileFrame {
+                    tile: frame.tileid,
+                    duration: frame.duration / 1000.0,
+                })
+                .collect();
+            Some((tile.id, frames))
+        })
+        .collect();
+    Ok(TiledTileset {
+        first_gid,
+        image: sibling_path(file_name, &image),
+        tile_width: value.tilewidth,
+        tile_height: value.tileheight,
+        margin: value.margin,
+        spacing: value.spacing,
+        animations,
+        name: value.name,
+    })
+}
+
+// What groups pass down to the layers inside them.
+#[derive(Debug, Clone, Copy)]
+struct LayerState {
+    visible: bool,
+    color: [f32; 4],
+    offset: Vec2,
+    parallax: Vec2,
+}
+
+impl Default for LayerState {
+    fn default() -> Self {
+        Self {
+            visible: true,
+            color: [1.0; 4],
+            offset: Vec2::zero(),
+            parallax: Vec2::one(),
+        }
+    }
+}
+
+impl LayerState {
+    // Offsets add up, the rest multiplies.
+    fn nest(&self, visible: bool, opacity: f32, tint: [f32; 4], offset: Vec2, parallax: Vec2) -> LayerState {
+        let mut color = self.color;
+        for (c, t) in color.iter_mut().zip(tint) {
+            *c *= t;
+        }
+        color[3] *= opacity;
+        LayerState {
+            visible: self.visible && visible,
+            color,
+            offset: self.offset + offset,
+            parallax: self.parallax * parallax,
+        }
+    }
+
+    fn layer(&self, name: &str, foreground: bool, tiles: Vec<(i32, i32, u32)>) -> TiledLayer {
+        TiledLayer {
+            name: name.to_string(),
+            visible: self.visible,
+            color: self.color,
+            offset: self.offset,
+            parallax: self.parallax,
+            foreground,
+            tiles,
+        }
+    }
+}
+
+fn layers_from_xml(parent: Node, state: &LayerState, layers: &mut Vec<TiledLayer>) -> Result<()> {
+    for element in parent.children().filter(|c| c.has_tag_name("layer") || c.has_tag_name("group")) {
+        let name = element.attribute("name").unwrap_or("");
+        let state = state.nest(
+            parse_attr(element, "visible", 1)? != 0,
+            parse_attr(element, "opacity", 1.0)?,
+            element.attribute("tintcolor").map_or(Ok([1.0; 4]), parse_color)?,
+            Vec2::new(parse_attr(element, "offsetx", 0.0)?, parse_attr(element, "offsety", 0.0)?),
+            Vec2::new(parse_attr(element, "parallaxx", 1.0)?, parse_attr(element, "parallaxy", 1.0)?),
+        );
+        if element.has_tag_name("group") {
+            layers_from_xml(element, &state, layers)?;
+            continue;
+        }
+
+        let foreground = child(element, "properties")
+            .and_then(|p| children(p, "property").find(|p| p.attribute("name") == Some("foreground")))
+            .is_some_and(|p| p.attribute("value") == Some("true"));
+        let mut tiles = Vec::new();
+        if let Some(data) = child(element, "data") {
+            let (encoding, compression) = (data.attribute("encoding"), data.attribute("compression"));
+            let chunks: Vec<Node> = children(data, "chunk").collect();
+            if chunks.is_empty() {
+                let gids = xml_tile_data(data, encoding, compression)?;
+                push_tiles(&mut tiles, 0, 0, parse_attr(element, "width", 0)?, &gids);
+            }
+            for chunk in chunks {
+                let gids = xml_tile_data(chunk, encoding, compression)?;
+                push_tiles(&mut tiles, parse_attr(chunk, "x", 0)?, parse_attr(chunk, "y", 0)?, parse_attr(chunk, "width", 0)?, &gids);
+            }
+        }
+        layers.push(state.layer(name, foreground, tiles));
+    }
+    Ok(())
+}
+
+fn xml_tile_data(data: Node, encoding: Option<&str>, compression: Option<&str>) -> Result<Vec<u32>> {
+    match encoding {
+        // The oldest format, one element per cell.
+        None => children(data, "tile").map(|tile| parse_attr(tile, "gid", 0)).collect(),
+        Some(encoding) => decode_tile_data(data.text().unwrap_or("").trim(), encoding, compression),
+    }
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|c| c.has_tag_name(name))
+}
+
+fn children<'a, 'input>(node: Node<'a, 'input>, name: &'a str) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children().filter(move |c| c.has_tag_name(name))
+}
+
+// Parses an attribute, `default` if it is missing.
+fn parse_attr<T>(node: Node, name: &str, default: T) -> Result<T>
+where
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    match node.attribute(name) {
+        Some(value) => value
+            .trim()
+            .parse()
+            .map_err(|e| anyhow!("<{}> attribute {:?}: {}", node.tag_name().name(), name, e)),
+        None => Ok(default),
+    }
+}
+
+fn layers_from_json(values: &[LayerJson], state: &LayerState, layers: &mut Vec<TiledLayer>) -> Result<()> {
+    for value in values {
+        if value.kind != "tilelayer" && value.kind != "group" {
+            continue;
+        }
+        let state = state.nest(
+            value.visible,
+            value.opacity,
+            value.tintcolor.as_deref().map_or(Ok([1.0; 4]), parse_color)?,
+            Vec2::new(value.offsetx, value.offsety),
+            Vec2::new(value.parallaxx, value.parallaxy),
+        );
+        if value.kind == "group" {
+            layers_from_json(&value.layers, &state, layers)?;
+            continue;
+        }
+
+        let mut foreground = false;
+        for property in value.properties.iter().filter(|p| p.name == "foreground") {
+            foreground = property.value.as_bool().ok_or_else(|| anyhow!("The foreground property must be a bool"))?;
+        }
+        let mut tiles = Vec::new();
+        let mut read = |region: &RegionJson| -> Result<()> {
+            let gids = match &region.data {
+                Some(DataJson::Text(text)) => decode_tile_data(text, &value.encoding, value.compression.as_deref())?,
+                Some(DataJson::Gids(gids)) => gids.clone(),
+                None => return Err(anyhow!("Tile layer {:?} has no data", value.name)),
+            };
+            push_tiles(&mut tiles, region.x, region.y, region.width, &gids);
+            Ok(())
+        };
+        match &value.chunks {
+            Some(chunks) => chunks.iter().try_for_each(&mut read)?,
+            None => read(&value.region)?,
+        }
+        layers.push(state.layer(&value.name, foreground, tiles));
+    }
+    Ok(())
+}
+
+// The parts of Tiled's JSON format that are read, `.tmj` maps and `.tsj`
+// tilesets. Unknown fields are skipped.
+#[derive(Deserialize)]
+struct MapJson {
+    #[serde(default = "orthogonal")]
+    orientation: String,
+    tilewidth: u32,
+    tileheight: u32,
+    #[serde(default)]
+    tilesets: Vec<TilesetEntryJson>,
+    #[serde(default)]
+    layers: Vec<LayerJson>,
+}
+
+// A tileset of a map, either embedded or in the `source` file.
+#[derive(Deserialize)]
+struct TilesetEntryJson {
+    #[serde(default = "one")]
+    firstgid: u32,
+    source: Option<String>,
+    #[serde(flatten)]
+    tileset: TilesetJson,
+}
+
+#[derive(Deserialize)]
+struct TilesetJson {
+    #[serde(default)]
+    name: String,
+    image: Option<String>,
+    #[serde(default)]
+    tilewidth: u32,
+    #[serde(default)]
+    tileheight: u32,
+    #[serde(default)]
+    margin: u32,
+    #[serde(default)]
+    spacing: u32,
+    #[serde(default)]
+    tiles: Vec<TileJson>,
+}
+
+#[derive(Deserialize)]
+struct TileJson {
+    id: u32,
+    animation: Option<Vec<TileFrameJson>>,
+}
+
+#[derive(Deserialize)]
+struct TileFrameJson {
+    tileid: u32,
+    // Milliseconds.
+    duration: f32,
+}
+
+#[derive(Deserialize)]
+struct LayerJson {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default = "yes")]
+    visible: bool,
+    #[serde(default = "one")]
+    opacity: f32,
+    tintcolor: Option<String>,
+    #[serde(default)]
+    offsetx: f32,
+    #[serde(default)]
+    offsety: f32,
+    #[serde(default = "one")]
+    parallaxx: f32,
+    #[serde(default = "one")]
+    parallaxy: f32,
+    #[serde(default)]
+    properties: Vec<PropertyJson>,
+    #[serde(default = "csv")]
+    encoding: String,
+    compression: Option<String>,
+    // Tiles of a fixed size map, or of an infinite one in `chunks`.
+    #[serde(flatten)]
+    region: RegionJson,
+    chunks: Option<Vec<RegionJson>>,
+    // Of a group.
+    #[serde(default)]
+    layers: Vec<LayerJson>,
+}
+
+#[derive(Deserialize)]
+struct PropertyJson {
+    name: String,
+    value: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+struct RegionJson {
+    data: Option<DataJson>,
+    #[serde(default)]
+    x: i32,
+    #[serde(default)]
+    y: i32,
+    #[serde(default)]
+    width: u32,
+}
+
+// Global ids, or a string in the layer's `encoding`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum DataJson {
+    Gids(Vec<u32>),
+    Text(String),
+}
+
+fn orthogonal() -> String {
+    "orthogonal".to_string()
+}
+
+fn csv() -> String {
+    "csv".to_string()
+}
+
+fn one<T: From<u8>>() -> T {
+    T::from(1)
+}
+
+fn yes() -> bool {
+    true
+}
+
+// Adds the non-empty cells of a `width` wide region starting at (x, y).
+fn push_tiles(tiles: &mut Vec<(i32, i32, u32)>, x: i32, y: i32, width: u32, gids: &[u32]) {
+    let width = width.max(1) as usize;
+    for (i, &gid) in gids.iter().enumerate() {
+        if gid & GID_MASK != 0 {
+            tiles.push((x + (i % width) as i32, y + (i / width) as i32, gid));
+        }
+    }
+}
+
+fn decode_tile_data(text: &str, encoding: &str, compression: Option<&str>) -> Result<Vec<u32>> {
+    if let Some(compression) = compression.filter(|c| !c.is_empty()) {
+        return Err(anyhow!(
+            "{} compressed tile data is not supported, save the map as CSV or uncompressed Base64",
+            compression
+        ));
+    }
+    match encoding {
+        "csv" => text
+            .split(',')
+            .map(|gid| gid.trim().parse().with_context(|| format!("Invalid tile {:?}", gid.trim())))
+            .collect(),
+        "base64" => {
+            // Tiled wraps long data in XML, the line breaks aren't Base64.
+            let text: String = text.split_ascii_whitespace().collect();
+            let bytes = base64::engine::general_purpose::STANDARD
+                .decode(text)
+                .context("Invalid Base64 tile data")?;
+            if bytes.len() % 4 != 0 {
+                return Err(anyhow!("Base64 tile data is {} bytes, not whole tiles", bytes.len()));
+            }
+            Ok(bytes
+                .chunks_exact(4)
+                .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect())
+        }
+        other => Err(anyhow!("Unknown tile data encoding {:?}", other)),
+    }
+}
+
+// "#RRGGBB" or "#AARRGGBB" in sRGB, to linear RGBA.
+fn parse_color(text: &str) -> Result<[f32; 4]> {
+    let hex = text.trim_start_matches('#');
+    let value = u32::from_str_radix(hex, 16).with_context(|| format!("Invalid color {:?}", text))?;
+    let alpha = match hex.len() {
+        6 => 1.0,
+        8 => (value >> 24) as f32 / 255.0,
+        _ => return Err(anyhow!("Invalid color {:?}", text)),
+    };
+    let channel = |shift: u32| {
+        let c = ((value >> shift) & 0xff) as f32 / 255.0;
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    Ok([channel(16), channel(8), channel(0), alpha])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn fixture(name: &str) -> TiledMap {
+        let text = match name {
+            "level.tmx" => include_str!("../../tests/fixtures/tiled/level.tmx"),
+            "level.tmj" => include_str!("../../tests/fixtures/tiled/level.tmj"),
+            "infinite.tmx" => include_str!("../../tests/fixtures/tiled/infinite.tmx"),
+            "infinite.tmj" => include_str!("../../tests/fixtures/tiled/infinite.tmj"),
+            _ => unreachable!(),
+        };
+        TiledMap::parse(&format!("maps/{}", name), text).await.unwrap()
+    }
+
+    fn layer<'a>(map: &'a TiledMap, name: &str) -> &'a TiledLayer {
+        map.layers.iter().find(|l| l.name == name).unwrap()
+    }
+
+    #[tokio::test]
+    async fn reads_fixed_size_maps() {
+        for name in ["level.tmx", "level.tmj"] {
+            let map = fixture(name).await;
+            assert_eq!((map.tile_width, map.tile_height), (16, 16), "{}", name);
+
+            let terrain = &map.tilesets[0];
+            assert_eq!((terrain.name.as_str(), terrain.first_gid), ("terrain", 1));
+            assert_eq!(terrain.image, "maps/../images/terrain.png");
+            assert_eq!((terrain.margin, terrain.spacing), (2, 1));
+            let frames = &terrain.animations[&2];
+            assert_eq!((frames[1].tile, frames[1].duration), (3, 0.25));
+            assert_eq!((map.tilesets[1].first_gid, map.tilesets[1].image.as_str()), (17, "maps/props.png"));
+
+            // Object layers are skipped.
+            let names: Vec<_> = map.layers.iter().map(|l| l.name.as_str()).collect();
+            assert_eq!(names, ["ground", "trees", "legacy"], "{}", name);
+            assert_eq!(
+                layer(&map, "ground").tiles,
+                [(0, 0, 1), (1, 0, 2), (3, 0, 17), (0, 1, 3), (1, 1, 0x8000_0004), (2, 1, 18)],
+                "{}",
+                name
+            );
+
+            // Base64 inside a group that passes on its offset and opacity.
+            let trees = layer(&map, "trees");
+            assert_eq!(trees.tiles, [(1, 0, 5), (3, 1, 19)], "{}", name);
+            assert!(!trees.visible && trees.foreground);
+            assert_eq!(trees.color, [1.0, 0.0, 0.0, 0.5]);
+            assert_eq!(trees.offset, Vec2::new(8.0, -4.0));
+
+            assert_eq!(layer(&map, "legacy").tiles, [(1, 1, 0x4000_0006)], "{}", name);
+        }
+    }
+
+    #[tokio::test]
+    async fn reads_infinite_map_chunks() {
+        for name in ["infinite.tmx", "infinite.tmj"] {
+            let map = fixture(name).await;
+            let walls = layer(&map, "walls");
+            assert_eq!(walls.tiles, [(-2, 0, 1), (-1, 1, 2), (1, -2, 3), (0, -1, 4)], "{}", name);
+            assert_eq!(walls.parallax, Vec2::new(0.5, 0.25));
+            assert_eq!(layer(&map, "water").tiles, [(2, 3, 0x2000_0001)], "{}", name);
+        }
+    }
+
+    #[tokio::test]
+    async fn builds_a_layer_per_tileset_used() {
+        let map = fixture("level.tmx").await;
+        let textures = [(SpriteTextureId(0), Vec2::new(70.0, 70.0)), (SpriteTextureId(1), Vec2::new(32.0, 32.0))];
+        let built = map.build(&textures, 16.0).unwrap();
+        assert_eq!(built.tilesets.len(), 2);
+        assert_eq!(built.tilesets[0].animations.len(), 1);
+
+        let layers: Vec<_> = built.layers.iter().map(|l| (l.name.as_str(), l.tileset)).collect();
+        assert_eq!(layers, [("ground", 0), ("ground", 1), ("trees", 0), ("trees", 1), ("legacy", 0)]);
+        let (terrain, props) = (&built.layers[0], &built.layers[1]);
+        assert_eq!(terrain.tile(0, 0), Some(Tile::new(0)));
+        assert_eq!(terrain.tile(1, 1), Some(Tile { flip_x: true, ..Tile::new(3) }));
+        assert_eq!(terrain.tile(3, 0), None);
+        assert_eq!(props.tile(3, 0), Some(Tile::new(0)));
+        assert_eq!(props.tile(2, 1), Some(Tile::new(1)));
+        assert_eq!(props.tiles().count(), 2);
+
+        // Both halves of a split layer keep its settings, y flipped to point up.
+        for trees in &built.layers[2..4] {
+            assert!(trees.foreground && !trees.visible);
+            assert_eq!(trees.offset, Vec2::new(0.5, 0.25));
+        }
+        assert_eq!(built.layers[4].tile(1, 1), Some(Tile { flip_y: true, ..Tile::new(5) }));
+
+        assert!(map.build(&textures[..1], 16.0).is_err());
+    }
+
+    #[test]
+    fn rejects_unsupported_tile_data() {
+        assert_eq!(decode_tile_data("AQAAAAIAAAA=", "base64", None).unwrap(), [1, 2]);
+        assert_eq!(decode_tile_data("AQAA\n  AAIA\n  AAA=", "base64", Some("")).unwrap(), [1, 2]);
+        assert!(decode_tile_data("AQAAAAIAAAA=", "base64", Some("zlib")).is_err());
+        assert!(decode_tile_data("AQAAAAIA", "base64", None).is_err());
+        assert!(decode_tile_data("AQ*AAAIAAAA=", "base64", None).is_err());
+        assert!(decode_tile_data("1,x", "csv", None).is_err());
+        assert!(decode_tile_data("1", "hex", None).is_err());
+    }
+
+    async fn parse_error(name: &str, text: &str) -> String {
+        format!("{:#}", TiledMap::parse(name, text).await.unwrap_err())
+    }
+
+    #[tokio::test]
+    async fn rejects_malformed_maps() {
+        assert!(TiledMap::parse("map.tmx", "<map><layer></map>").await.is_err());
+        assert!(TiledMap::parse("map.tmj", r#"{ "layers": [ }"#).await.is_err());
+
+        let error = parse_error("map.tmx", r#"<map orientation="isometric"/>"#).await;
+        assert!(error.contains("supported, not isometric"), "{}", error);
+        let json = r#"{ "orientation": "hexagonal", "tilewidth": 16, "tileheight": 16, "tilesets": [], "layers": [] }"#;
+        let error = parse_error("map.tmj", json).await;
+        assert!(error.contains("supported, not hexagonal"), "{}", error);
+
+        let error = parse_error("map.tmx", r#"<map tilewidth="wide"/>"#).await;
+        assert!(error.contains("tilewidth"), "{}", error);
+        let error = parse_error("map.tmx", r#"<map><tileset name="loose"><tile id="1"/></tileset></map>"#).await;
+        assert!(error.contains("no single image"), "{}", error);
+        let zlib = r#"<map><layer name="ground"><data encoding="base64" compression="zlib">eJw=</data></layer></map>"#;
+        assert!(TiledMap::parse("map.tmx", zlib).await.is_err());
+    }
+}
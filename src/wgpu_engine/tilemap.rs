@@ -0,0 +1,577 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use ultraviolet::Vec2;
+use wgpu::util::DeviceExt;
+
+use super::{
+    atlas::UvRect,
+    buffer::{create_buffer, create_uniform_bind_group, grow_buffer, push_uniform, uniform_stride},
+    camera::DepthMode,
+    layouts::BindGroupLayouts,
+    sprite::{create_pipeline, SpriteRenderer, SpriteTextureId, SpriteVertex},
+    view::CameraView,
+};
+
+// Tiles along each side of a chunk. Each chunk is one mesh, rebuilt when
+// one of its tiles changes.
+pub const CHUNK_SIZE: i32 = 16;
+
+const CHUNK_TILES: usize = (CHUNK_SIZE * CHUNK_SIZE) as usize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tile {
+    // Index in the layer's tileset, counting across and then down.
+    pub index: u32,
+    pub flip_x: bool,
+    pub flip_y: bool,
+    // Swaps the tile's x and y before the other flips, together they make
+    // the quarter turns Tiled uses.
+    pub flip_diagonal: bool,
+}
+
+impl Tile {
+    pub fn new(index: u32) -> Self {
+        Self {
+            index,
+            flip_x: false,
+            flip_y: false,
+            flip_diagonal: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TileFrame {
+    pub tile: u32,
+    // Seconds.
+    pub duration: f32,
+}
+
+// A texture cut into equal tiles.
+#[derive(Debug, Clone)]
+pub struct Tileset {
+    pub name: String,
+    pub texture: SpriteTextureId,
+    // Pixels.
+    pub texture_size: Vec2,
+    pub tile_width: u32,
+    pub tile_height: u32,
+    // Pixels around the tiles and between them.
+    pub margin: u32,
+    pub spacing: u32,
+    // Tiles that cycle through other tiles of the set, by index.
+    pub animations: HashMap<u32, Vec<TileFrame>>,
+}
+
+impl Tileset {
+    pub fn new(name: &str, texture: SpriteTextureId, texture_size: Vec2, tile_width: u32, tile_height: u32) -> Self {
+        Self {
+            name: name.to_string(),
+            texture,
+            texture_size,
+            tile_width,
+            tile_height,
+            margin: 0,
+            spacing: 0,
+            animations: HashMap::new(),
+        }
+    }
+
+    pub fn columns(&self) -> u32 {
+        let width = (self.texture_size.x as u32).saturating_sub(self.margin * 2) + self.spacing;
+        (width / (self.tile_width + self.spacing).max(1)).max(1)
+    }
+
+    pub fn uv(&self, index: u32) -> UvRect {
+        let (column, row) = (index % self.columns(), index / self.columns());
+        UvRect::from_pixels(
+            (self.margin + column * (self.tile_width + self.spacing)) as f32,
+            (self.margin + row * (self.tile_height + self.spacing)) as f32,
+            self.tile_width as f32,
+            self.tile_height as f32,
+            self.texture_size,
+        )
+    }
+
+    // The tile shown in place of `index` at `time` seconds.
+    pub fn frame_at(&self, index: u32, time: f32) -> u32 {
+        let frames = match self.animations.get(&index) {
+            Some(frames) => frames,
+            None => return index,
+        };
+        let total: f32 = frames.iter().map(|f| f.duration).sum();
+        if total <= 0.0 {
+            return frames.first().map_or(index, |f| f.tile);
+        }
+        let mut t = time.rem_euclid(total);
+        for frame in frames {
+            if t < frame.duration {
+                return frame.tile;
+            }
+            t -= frame.duration;
+        }
+        frames[frames.len() - 1].tile
+    }
+}
+
+#[derive(Debug)]
+struct Chunk {
+    tiles: Vec<Option<Tile>>,
+    // Changed since `vertex_buffer` was built.
+    dirty: bool,
+    // Holds animated tiles, so it is rebuilt when their frames change.
+    animated: bool,
+    // Written over on every rebuild and only replaced when more tiles than
+    // it holds are set.
+    vertex_buffer: Option<wgpu::Buffer>,
+    tile_count: u32,
+}
+
+// Tiles on a grid of columns `x` and rows `y`, rows counting down like in
+// tile editors. Any coordinate can hold a tile, only chunks with tiles take
+// up memory.
+#[derive(Debug)]
+pub struct TileLayer {
+    pub name: String,
+    // Index into `Tilemap::tilesets`.
+    pub tileset: usize,
+    // How much the layer moves with the camera: 1 like the rest of the
+    // world, less for backgrounds further away, 0 stays fixed on screen.
+    pub parallax: Vec2,
+    // World units, added to `Tilemap::position`.
+    pub offset: Vec2,
+    pub z: f32,
+    // Multiplies the tiles, alpha for opacity.
+    pub color: [f32; 4],
+    pub visible: bool,
+    // Drawn over the sprites instead of under them.
+    pub foreground: bool,
+    chunks: HashMap<(i32, i32), Chunk>,
+}
+
+impl TileLayer {
+    pub fn new(name: &str, tileset: usize) -> Self {
+        Self {
+            name: name.to_string(),
+            tileset,
+            parallax: Vec2::one(),
+            offset: Vec2::zero(),
+            z: 0.0,
+            color: [1.0; 4],
+            visible: true,
+            foreground: false,
+            chunks: HashMap::new(),
+        }
+    }
+
+    pub fn tile(&self, x: i32, y: i32) -> Option<Tile> {
+        let (key, local) = chunk_coords(x, y);
+        self.chunks.get(&key).and_then(|chunk| chunk.tiles[local])
+    }
+
+    pub fn set_tile(&mut self, x: i32, y: i32, tile: Option<Tile>) {
+        let (key, local) = chunk_coords(x, y);
+        if tile.is_none() && !self.chunks.contains_key(&key) {
+            return;
+        }
+        let chunk = self.chunks.entry(key).or_insert_with(|| Chunk {
+            tiles: vec![None; CHUNK_TILES],
+            dirty: true,
+            animated: false,
+            vertex_buffer: None,
+            tile_count: 0,
+        });
+        if chunk.tiles[local] != tile {
+            chunk.tiles[local] = tile;
+            chunk.dirty = true;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.chunks.clear();
+    }
+
+    // Every tile with its coordinates, in no particular order.
+    pub fn tiles(&self) -> impl Iterator<Item = (i32, i32, Tile)> + '_ {
+        self.chunks.iter().flat_map(|(&(cx, cy), chunk)| {
+            chunk.tiles.iter().enumerate().filter_map(move |(i, tile)| {
+                let (x, y) = (cx * CHUNK_SIZE + i as i32 % CHUNK_SIZE, cy * CHUNK_SIZE + i as i32 / CHUNK_SIZE);
+                tile.map(|tile| (x, y, tile))
+            })
+        })
+    }
+
+    // Chunks that need their mesh rebuilt before the next frame.
+    pub fn dirty_chunks(&self) -> usize {
+        self.chunks.values().filter(|c| c.dirty).count()
+    }
+}
+
+// The chunk holding tile (x, y) and the tile's index in it.
+fn chunk_coords(x: i32, y: i32) -> ((i32, i32), usize) {
+    let key = (x.div_euclid(CHUNK_SIZE), y.div_euclid(CHUNK_SIZE));
+    let local = y.rem_euclid(CHUNK_SIZE) * CHUNK_SIZE + x.rem_euclid(CHUNK_SIZE);
+    (key, local as usize)
+}
+
+// Layers of tiles drawn back to front on the z = 0 plane, for use with an
+// orthographic camera looking down -Z. Tiles larger than the map's cells
+// stick out up and to the right, like in Tiled.
+#[derive(Debug)]
+pub struct Tilemap {
+    pub tilesets: Vec<Tileset>,
+    pub layers: Vec<TileLayer>,
+    // Pixels of a cell.
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub pixels_per_unit: f32,
+    // World position of the top left corner of tile (0, 0).
+    pub position: Vec2,
+    pub visible: bool,
+    time: f32,
+}
+
+impl Tilemap {
+    pub fn new(tile_width: u32, tile_height: u32) -> Self {
+        Self {
+            tilesets: Vec::new(),
+            layers: Vec::new(),
+            tile_width,
+            tile_height,
+            pixels_per_unit: 1.0,
+            position: Vec2::zero(),
+            visible: true,
+            time: 0.0,
+        }
+    }
+
+    pub fn add_tileset(&mut self, tileset: Tileset) -> usize {
+        self.tilesets.push(tileset);
+        self.tilesets.len() - 1
+    }
+
+    // Adds a layer in front of the others.
+    pub fn add_layer(&mut self, name: &str, tileset: usize) -> &mut TileLayer {
+        self.layers.push(TileLayer::new(name, tileset));
+        self.layers.last_mut().unwrap()
+    }
+
+    pub fn layer(&self, name: &str) -> Option<&TileLayer> {
+        self.layers.iter().find(|l| l.name == name)
+    }
+
+    pub fn layer_mut(&mut self, name: &str) -> Option<&mut TileLayer> {
+        self.layers.iter_mut().find(|l| l.name == name)
+    }
+
+    // World size of a cell.
+    pub fn cell_size(&self) -> Vec2 {
+        Vec2::new(self.tile_width as f32, self.tile_height as f32) / self.pixels_per_unit
+    }
+
+    // The cell under a world point, ignoring layer offsets and parallax.
+    pub fn world_to_tile(&self, point: Vec2) -> (i32, i32) {
+        let cell = (point - self.position) / self.cell_size();
+        (cell.x.floor() as i32, (-cell.y).floor() as i32)
+    }
+
+    // World position of the top left corner of a cell.
+    pub fn tile_to_world(&self, x: i32, y: i32) -> Vec2 {
+        self.position + Vec2::new(x as f32, -y as f32) * self.cell_size()
+    }
+
+    // Moves animated tiles on by `dt` seconds.
+    pub fn update(&mut self, dt: f32) {
+        let (before, after) = (self.time, self.time + dt);
+        self.time = after;
+        for layer in &mut self.layers {
+            let tileset = match self.tilesets.get(layer.tileset) {
+                Some(tileset) => tileset,
+                None => continue,
+            };
+            let changed = tileset
+                .animations
+                .keys()
+                .any(|&index| tileset.frame_at(index, before) != tileset.frame_at(index, after));
+            if changed {
+                for chunk in layer.chunks.values_mut().filter(|c| c.animated) {
+                    chunk.dirty = true;
+                }
+            }
+        }
+    }
+}
+
+// Handle to a map added to the `TilemapRenderer`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TilemapId(pub(super) u32);
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct LayerUniform {
+    // World position of the layer's origin, after parallax.
+    offset: [f32; 2],
+    // World units per pixel.
+    scale: [f32; 2],
+    color: [f32; 4],
+    z: f32,
+    _padding: [f32; 3],
+}
+
+// Draws tilemaps in every camera view with `CameraView::sprites` set,
+// background layers before the sprites and foreground layers after them.
+pub struct TilemapRenderer {
+    pipeline: wgpu::RenderPipeline,
+    reverse_z_pipeline: wgpu::RenderPipeline,
+    layer_layout: wgpu::BindGroupLayout,
+    layer_buffer: wgpu::Buffer,
+    layer_bind_group: wgpu::BindGroup,
+    // Distance between `LayerUniform`s, rounded up for dynamic offsets.
+    layer_stride: u64,
+    // Two triangles per tile, shared by all chunks.
+    index_buffer: wgpu::Buffer,
+    maps: Vec<(TilemapId, Tilemap)>,
+    // Every view gets a `LayerUniform` for every layer, one view after another.
+    layers_per_view: usize,
+    next_id: u32,
+}
+
+impl TilemapRenderer {
+    pub fn new(device: &wgpu::Device, layouts: &BindGroupLayouts, format: wgpu::TextureFormat) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("tilemap.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../shaders/tilemap.wgsl").into()),
+        });
+        let layer_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: wgpu::BufferSize::new(size_of::<LayerUniform>() as u64),
+                },
+                count: None,
+            }],
+            label: Some("tile_layer_bind_group_layout"),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Tilemap Pipeline Layout"),
+            bind_group_layouts: &[&layouts.texture, &layouts.camera, &layer_layout],
+            push_constant_ranges: &[],
+        });
+
+        let layer_stride = uniform_stride::<LayerUniform>(device);
+        let layer_buffer = create_buffer(device, "Tile Layer Buffer", layer_stride, wgpu::BufferUsages::UNIFORM);
+        let layer_bind_group = create_uniform_bind_group::<LayerUniform>(device, &layer_layout, &layer_buffer, "tile_layer_bind_group");
+
+        let indices: Vec<u32> = (0..CHUNK_TILES as u32)
+            .flat_map(|tile| {
+                let base = tile * 4;
+                [base, base + 1, base + 2, base, base + 2, base + 3]
+            })
+            .collect();
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Tile Index Buffer"),
+            contents: bytemuck::cast_slice(&indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        Self {
+            pipeline: create_pipeline(device, "Tilemap Pipeline", &layout, &shader, "vs_main", format, DepthMode::Standard.compare()),
+            reverse_z_pipeline: create_pipeline(device, "Tilemap Pipeline", &layout, &shader, "vs_main", format, DepthMode::Reversed.compare()),
+            layer_layout,
+            layer_buffer,
+            layer_bind_group,
+            layer_stride,
+            index_buffer,
+            maps: Vec::new(),
+            layers_per_view: 0,
+            next_id: 0,
+        }
+    }
+
+    pub fn add(&mut self, map: Tilemap) -> TilemapId {
+        self.next_id += 1;
+        let id = TilemapId(self.next_id);
+        self.maps.push((id, map));
+        id
+    }
+
+    pub fn remove(&mut self, id: TilemapId) -> Result<Tilemap> {
+        let index = self.index(id)?;
+        Ok(self.maps.remove(index).1)
+    }
+
+    fn index(&self, id: TilemapId) -> Result<usize> {
+        self.maps
+            .iter()
+            .position(|(m, _)| *m == id)
+            .ok_or_else(|| anyhow!("Tilemap {:?} not found", id))
+    }
+
+    pub fn get(&self, id: TilemapId) -> Result<&Tilemap> {
+        Ok(&self.maps[self.index(id)?].1)
+    }
+
+    pub fn get_mut(&mut self, id: TilemapId) -> Result<&mut Tilemap> {
+        let index = self.index(id)?;
+        Ok(&mut self.maps[index].1)
+    }
+
+    // Moves the animated tiles of every map on by `dt` seconds.
+    pub fn update(&mut self, dt: f32) {
+        for (_, map) in &mut self.maps {
+            map.update(dt);
+        }
+    }
+
+    // Rebuilds changed chunks and places the layers for each view, once per
+    // frame before any view draws them.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, views: &[CameraView]) {
+        for (_, map) in &mut self.maps {
+            for layer in &mut map.layers {
+                let tileset = match map.tilesets.get(layer.tileset) {
+                    Some(tileset) => tileset,
+                    None => continue,
+                };
+                for (&key, chunk) in layer.chunks.iter_mut().filter(|(_, c)| c.dirty) {
+                    build_chunk(device, queue, (map.tile_width, map.tile_height), map.time, tileset, key, chunk);
+                }
+            }
+        }
+
+        self.layers_per_view = self.maps.iter().map(|(_, map)| map.layers.len()).sum();
+        let mut bytes = Vec::new();
+        for view in views {
+            let eye = view.camera.view().eye;
+            let eye = Vec2::new(eye.x, eye.y);
+            for (_, map) in &self.maps {
+                for layer in &map.layers {
+                    let offset = map.position + layer.offset + eye * (Vec2::one() - layer.parallax);
+                    let uniform = LayerUniform {
+                        offset: offset.into(),
+                        scale: [1.0 / map.pixels_per_unit; 2],
+                        color: layer.color,
+                        z: layer.z,
+                        _padding: [0.0; 3],
+                    };
+                    push_uniform(&mut bytes, self.layer_stride, &uniform);
+                }
+            }
+        }
+        if bytes.is_empty() {
+            return;
+        }
+        if grow_buffer(device, &mut self.layer_buffer, "Tile Layer Buffer", bytes.len() as u64) {
+            self.layer_bind_group =
+                create_uniform_bind_group::<LayerUniform>(device, &self.layer_layout, &self.layer_buffer, "tile_layer_bind_group");
+        }
+        queue.write_buffer(&self.layer_buffer, 0, &bytes);
+    }
+
+    // Draws the background or foreground layers for the view at `view` in
+    // the slice given to `prepare`.
+    pub fn draw_layers<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        view: usize,
+        camera_bind_group: &'a wgpu::BindGroup,
+        depth: DepthMode,
+        sprites: &'a SpriteRenderer,
+        foreground: bool,
+    ) {
+        let mut slot = view * self.layers_per_view;
+        let mut bound = false;
+        for (_, map) in &self.maps {
+            for layer in &map.layers {
+                let uniform_offset = (slot as u64 * self.layer_stride) as u32;
+                slot += 1;
+                if !map.visible || !layer.visible || layer.foreground != foreground {
+                    continue;
+                }
+                let texture = map
+                    .tilesets
+                    .get(layer.tileset)
+                    .and_then(|tileset| sprites.bind_group(tileset.texture));
+                let texture = match texture {
+                    Some(texture) => texture,
+                    None => continue,
+                };
+                if !bound {
+                    render_pass.set_pipeline(match depth {
+                        DepthMode::Standard => &self.pipeline,
+                        DepthMode::Reversed => &self.reverse_z_pipeline,
+                    });
+                    render_pass.set_bind_group(1, camera_bind_group, &[]);
+                    render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                    bound = true;
+                }
+                render_pass.set_bind_group(0, texture, &[]);
+                render_pass.set_bind_group(2, &self.layer_bind_group, &[uniform_offset]);
+                for chunk in layer.chunks.values().filter(|c| c.tile_count > 0) {
+                    if let Some(buffer) = &chunk.vertex_buffer {
+                        render_pass.set_vertex_buffer(0, buffer.slice(..));
+                        render_pass.draw_indexed(0..chunk.tile_count * 6, 0, 0..1);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Writes the chunk's tiles as quads in pixels from the layer's origin, y up.
+fn build_chunk(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    (tile_width, tile_height): (u32, u32),
+    time: f32,
+    tileset: &Tileset,
+    (cx, cy): (i32, i32),
+    chunk: &mut Chunk,
+) {
+    let mut vertices = Vec::with_capacity(CHUNK_TILES * 4);
+    chunk.animated = false;
+    for (i, tile) in chunk.tiles.iter().enumerate() {
+        let tile = match tile {
+            Some(tile) => tile,
+            None => continue,
+        };
+        chunk.animated |= tileset.animations.contains_key(&tile.index);
+        let uv = tileset.uv(tileset.frame_at(tile.index, time));
+        let x = (cx * CHUNK_SIZE + i as i32 % CHUNK_SIZE) * tile_width as i32;
+        let y = -(cy * CHUNK_SIZE + i as i32 / CHUNK_SIZE + 1) * tile_height as i32;
+        let (width, height) = (tileset.tile_width as f32, tileset.tile_height as f32);
+        // Corners in the tile's image, y down: bottom left, bottom right,
+        // top right, top left.
+        for (cs, ct) in [(0.0, 1.0), (1.0, 1.0), (1.0, 0.0), (0.0, 0.0)] {
+            // Undo the flips in reverse to find which part of the tile shows here.
+            let (mut s, mut t) = (cs, ct);
+            if tile.flip_y {
+                t = 1.0 - t;
+            }
+            if tile.flip_x {
+                s = 1.0 - s;
+            }
+            if tile.flip_diagonal {
+                std::mem::swap(&mut s, &mut t);
+            }
+            vertices.push(SpriteVertex {
+                position: [x as f32 + cs * width, y as f32 + (1.0 - ct) * height, 0.0],
+                tex_coords: (uv.min + uv.size() * Vec2::new(s, t)).into(),
+                color: [1.0; 4],
+            });
+        }
+    }
+    chunk.tile_count = (vertices.len() / 4) as u32;
+    if !vertices.is_empty() {
+        let size = size_of_val(vertices.as_slice()) as u64;
+        let buffer = chunk
+            .vertex_buffer
+            .get_or_insert_with(|| create_buffer(device, "Tile Chunk Vertex Buffer", size, wgpu::BufferUsages::VERTEX));
+        grow_buffer(device, buffer, "Tile Chunk Vertex Buffer", size);
+        queue.write_buffer(buffer, 0, bytemuck::cast_slice(&vertices));
+    }
+    chunk.dirty = false;
+}
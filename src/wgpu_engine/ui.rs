@@ -0,0 +1,1494 @@
+use std::{cell::Cell, collections::HashMap};
+
+use anyhow::{anyhow, Result};
+use sdl2::{
+    controller::{Axis, Button},
+    event::Event,
+    keyboard::{Keycode, Mod},
+    mouse::MouseButton,
+};
+use ultraviolet::Vec2;
+
+use super::{
+    atlas::UvRect,
+    sprite::{Sprite, SpriteRenderer, SpriteTextureId},
+    font::Font,
+    text::{FontId, TextLayout, TextPlacement, TextRenderer, TextStyle},
+};
+use crate::transform::Transform2d;
+
+// Handle to a widget added to a `Ui`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct WidgetId(u32);
+
+// A rectangle in pixels from the top left of the UI's screen, y down.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct UiRect {
+    pub min: Vec2,
+    pub size: Vec2,
+}
+
+impl UiRect {
+    pub fn new(min: Vec2, size: Vec2) -> Self {
+        Self { min, size }
+    }
+
+    pub fn max(&self) -> Vec2 {
+        self.min + self.size
+    }
+
+    pub fn center(&self) -> Vec2 {
+        self.min + self.size * 0.5
+    }
+
+    pub fn contains(&self, point: Vec2) -> bool {
+        let max = self.max();
+        point.x >= self.min.x && point.y >= self.min.y && point.x < max.x && point.y < max.y
+    }
+
+    // Shrunk by `edges` on each side, never below zero size.
+    pub fn inset(&self, edges: Edges) -> UiRect {
+        let min = self.min + Vec2::new(edges.left, edges.top);
+        let size = self.size - Vec2::new(edges.left + edges.right, edges.top + edges.bottom);
+        UiRect::new(min, size.max_by_component(Vec2::zero()))
+    }
+}
+
+// A widget's width or height. `Px` and the theme are in UI units, see
+// `Ui::reference_height`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Length {
+    // Fits the content.
+    #[default]
+    Auto,
+    Px(f32),
+    // Fraction of the parent's content box.
+    Percent(f32),
+    // Shares the room left in the parent's row or column by weight. Across
+    // the row or column, and for anchored widgets, fills the parent.
+    Fill(f32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Direction {
+    #[default]
+    Column,
+    Row,
+}
+
+// Where children go across their parent's row or column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Align {
+    #[default]
+    Start,
+    Center,
+    End,
+    Stretch,
+}
+
+// Where children go along their parent's row or column, when they don't fill it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Justify {
+    #[default]
+    Start,
+    Center,
+    End,
+    SpaceBetween,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Edges {
+    pub left: f32,
+    pub top: f32,
+    pub right: f32,
+    pub bottom: f32,
+}
+
+impl Edges {
+    pub fn all(value: f32) -> Self {
+        Self::symmetric(value, value)
+    }
+
+    pub fn symmetric(horizontal: f32, vertical: f32) -> Self {
+        Self { left: horizontal, top: vertical, right: horizontal, bottom: vertical }
+    }
+
+    fn scaled(&self, scale: f32) -> Self {
+        Self { left: self.left * scale, top: self.top * scale, right: self.right * scale, bottom: self.bottom * scale }
+    }
+}
+
+// How a widget lays out its children, like a flex box.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Layout {
+    pub direction: Direction,
+    pub align: Align,
+    pub justify: Justify,
+    pub gap: f32,
+    pub padding: Edges,
+}
+
+impl Layout {
+    pub fn row(gap: f32) -> Self {
+        Self { direction: Direction::Row, gap, ..Default::default() }
+    }
+
+    pub fn column(gap: f32) -> Self {
+        Self { direction: Direction::Column, gap, ..Default::default() }
+    }
+}
+
+// Places a widget relative to its parent's content box instead of in its
+// row or column: `point` of the parent, in fractions of its size, meets
+// `pivot` of the widget, then moves by `offset` UI units. Keeps a HUD in
+// the corners whatever the resolution.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Anchor {
+    pub point: Vec2,
+    pub pivot: Vec2,
+    pub offset: Vec2,
+}
+
+impl Anchor {
+    pub const TOP_LEFT: Anchor = Anchor::new(0.0, 0.0);
+    pub const TOP: Anchor = Anchor::new(0.5, 0.0);
+    pub const TOP_RIGHT: Anchor = Anchor::new(1.0, 0.0);
+    pub const LEFT: Anchor = Anchor::new(0.0, 0.5);
+    pub const CENTER: Anchor = Anchor::new(0.5, 0.5);
+    pub const RIGHT: Anchor = Anchor::new(1.0, 0.5);
+    pub const BOTTOM_LEFT: Anchor = Anchor::new(0.0, 1.0);
+    pub const BOTTOM: Anchor = Anchor::new(0.5, 1.0);
+    pub const BOTTOM_RIGHT: Anchor = Anchor::new(1.0, 1.0);
+
+    // The same point of the parent and the widget, e.g. (1, 0) for top right.
+    pub const fn new(x: f32, y: f32) -> Self {
+        Self { point: Vec2::new(x, y), pivot: Vec2::new(x, y), offset: Vec2::new(0.0, 0.0) }
+    }
+
+    pub fn offset(self, x: f32, y: f32) -> Self {
+        Self { offset: Vec2::new(x, y), ..self }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WidgetKind {
+    // Holds other widgets, drawn as a background when the style has one.
+    Panel,
+    Label {
+        text: String,
+    },
+    Button {
+        text: String,
+    },
+    Image {
+        texture: SpriteTextureId,
+        uv: UvRect,
+        color: [f32; 4],
+    },
+    Slider {
+        value: f32,
+        min: f32,
+        max: f32,
+        // Keys and gamepads move by this, or a twentieth of the range when 0.
+        step: f32,
+    },
+    // `value` from 0 to 1.
+    ProgressBar {
+        value: f32,
+    },
+    TextInput {
+        text: String,
+        placeholder: String,
+        max_chars: Option<usize>,
+        // Byte index in `text`.
+        caret: usize,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Widget {
+    pub kind: WidgetKind,
+    pub width: Length,
+    pub height: Length,
+    pub anchor: Option<Anchor>,
+    // Of the widget's children.
+    pub layout: Layout,
+    // Hidden widgets take no room and hide their children.
+    pub visible: bool,
+    // Disabled widgets can't be focused or used.
+    pub enabled: bool,
+    // Replaces the theme's colors for this widget.
+    pub style: Option<StateStyles>,
+}
+
+impl Widget {
+    pub fn new(kind: WidgetKind) -> Self {
+        Self {
+            kind,
+            width: Length::Auto,
+            height: Length::Auto,
+            anchor: None,
+            layout: Layout::default(),
+            visible: true,
+            enabled: true,
+            style: None,
+        }
+    }
+
+    pub fn panel(layout: Layout) -> Self {
+        Self { layout, ..Self::new(WidgetKind::Panel) }
+    }
+
+    pub fn label(text: &str) -> Self {
+        Self::new(WidgetKind::Label { text: text.to_string() })
+    }
+
+    pub fn button(text: &str) -> Self {
+        Self::new(WidgetKind::Button { text: text.to_string() })
+    }
+
+    // `width` and `height` in UI units.
+    pub fn image(texture: SpriteTextureId, width: f32, height: f32) -> Self {
+        Self {
+            width: Length::Px(width),
+            height: Length::Px(height),
+            ..Self::new(WidgetKind::Image { texture, uv: UvRect::FULL, color: [1.0; 4] })
+        }
+    }
+
+    pub fn slider(min: f32, max: f32, value: f32) -> Self {
+        Self::new(WidgetKind::Slider { value: value.clamp(min.min(max), max.max(min)), min, max, step: 0.0 })
+    }
+
+    pub fn progress_bar(value: f32) -> Self {
+        Self::new(WidgetKind::ProgressBar { value: value.clamp(0.0, 1.0) })
+    }
+
+    pub fn text_input(placeholder: &str) -> Self {
+        Self::new(WidgetKind::TextInput {
+            text: String::new(),
+            placeholder: placeholder.to_string(),
+            max_chars: None,
+            caret: 0,
+        })
+    }
+
+    // Whether the widget can take focus and input.
+    pub fn is_interactive(&self) -> bool {
+        matches!(self.kind, WidgetKind::Button { .. } | WidgetKind::Slider { .. } | WidgetKind::TextInput { .. })
+    }
+}
+
+// Colors of a widget, linear RGBA. A transparent background isn't drawn.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WidgetStyle {
+    pub background: [f32; 4],
+    pub text: [f32; 4],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StateStyles {
+    pub normal: WidgetStyle,
+    pub hovered: WidgetStyle,
+    pub pressed: WidgetStyle,
+    pub focused: WidgetStyle,
+    pub disabled: WidgetStyle,
+}
+
+impl StateStyles {
+    // The same colors in every state.
+    pub fn uniform(style: WidgetStyle) -> Self {
+        Self { normal: style, hovered: style, pressed: style, focused: style, disabled: style }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Theme {
+    pub font: FontId,
+    // In UI units.
+    pub font_size: f32,
+    // A white texture tinted for flat backgrounds, e.g. from
+    // `WgpuEngine::add_solid_sprite_texture`.
+    pub texture: SpriteTextureId,
+    pub panel: WidgetStyle,
+    pub label: WidgetStyle,
+    pub button: StateStyles,
+    pub input: StateStyles,
+    pub placeholder: [f32; 4],
+    // Slider and progress bar background, and the part up to their value.
+    pub track: [f32; 4],
+    pub fill: [f32; 4],
+    // Outline of the focused widget, `outline` UI units wide.
+    pub focus: [f32; 4],
+    pub outline: f32,
+    // Inside buttons and text inputs, in UI units.
+    pub padding: Edges,
+}
+
+impl Theme {
+    pub fn dark(font: FontId, texture: SpriteTextureId) -> Self {
+        let style = |background: [f32; 4], text: [f32; 4]| WidgetStyle { background, text };
+        let white = [0.92, 0.92, 0.92, 1.0];
+        Self {
+            font,
+            font_size: 20.0,
+            texture,
+            panel: style([0.02, 0.02, 0.03, 0.85], white),
+            label: style([0.0; 4], white),
+            button: StateStyles {
+                normal: style([0.08, 0.09, 0.12, 1.0], white),
+                hovered: style([0.14, 0.16, 0.22, 1.0], white),
+                pressed: style([0.04, 0.05, 0.07, 1.0], white),
+                focused: style([0.12, 0.2, 0.4, 1.0], [1.0; 4]),
+                disabled: style([0.05, 0.05, 0.05, 1.0], [0.3, 0.3, 0.3, 1.0]),
+            },
+            input: StateStyles {
+                normal: style([0.01, 0.01, 0.015, 1.0], white),
+                hovered: style([0.02, 0.02, 0.03, 1.0], white),
+                pressed: style([0.02, 0.02, 0.03, 1.0], white),
+                focused: style([0.0, 0.0, 0.0, 1.0], [1.0; 4]),
+                disabled: style([0.03, 0.03, 0.03, 1.0], [0.3, 0.3, 0.3, 1.0]),
+            },
+            placeholder: [0.35, 0.35, 0.35, 1.0],
+            track: [0.01, 0.01, 0.015, 1.0],
+            fill: [0.2, 0.45, 0.95, 1.0],
+            focus: [1.0, 0.75, 0.1, 1.0],
+            outline: 2.0,
+            padding: Edges::symmetric(12.0, 6.0),
+        }
+    }
+
+    pub fn light(font: FontId, texture: SpriteTextureId) -> Self {
+        let style = |background: [f32; 4], text: [f32; 4]| WidgetStyle { background, text };
+        let black = [0.02, 0.02, 0.02, 1.0];
+        Self {
+            panel: style([0.8, 0.8, 0.78, 0.9], black),
+            label: style([0.0; 4], black),
+            button: StateStyles {
+                normal: style([0.6, 0.6, 0.58, 1.0], black),
+                hovered: style([0.7, 0.7, 0.68, 1.0], black),
+                pressed: style([0.45, 0.45, 0.43, 1.0], black),
+                focused: style([0.55, 0.65, 0.9, 1.0], black),
+                disabled: style([0.5, 0.5, 0.5, 1.0], [0.3, 0.3, 0.3, 1.0]),
+            },
+            input: StateStyles {
+                normal: style([1.0; 4], black),
+                hovered: style([1.0; 4], black),
+                pressed: style([1.0; 4], black),
+                focused: style([1.0; 4], black),
+                disabled: style([0.6, 0.6, 0.6, 1.0], [0.3, 0.3, 0.3, 1.0]),
+            },
+            placeholder: [0.4, 0.4, 0.4, 1.0],
+            track: [0.45, 0.45, 0.43, 1.0],
+            fill: [0.1, 0.3, 0.85, 1.0],
+            focus: [0.9, 0.3, 0.0, 1.0],
+            ..Self::dark(font, texture)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum UiEvent {
+    Clicked(WidgetId),
+    // A slider's new value.
+    ValueChanged(WidgetId, f32),
+    TextChanged(WidgetId),
+    // Enter in a text input.
+    Submitted(WidgetId),
+    FocusChanged(Option<WidgetId>),
+    // Escape or the gamepad's B button, e.g. to close a menu.
+    Back,
+}
+
+// A direction to move focus in with arrows, the d-pad or the stick.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Navigate {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Navigate {
+    fn vector(self) -> Vec2 {
+        match self {
+            Navigate::Up => Vec2::new(0.0, -1.0),
+            Navigate::Down => Vec2::new(0.0, 1.0),
+            Navigate::Left => Vec2::new(-1.0, 0.0),
+            Navigate::Right => Vec2::new(1.0, 0.0),
+        }
+    }
+}
+
+struct Node {
+    widget: Widget,
+    parent: Option<WidgetId>,
+    children: Vec<WidgetId>,
+    // Results of the last `layout`, in pixels.
+    rect: UiRect,
+    measured: Vec2,
+    // Text inputs: byte index and x of every caret stop, and how far the
+    // text is scrolled to keep the caret in view.
+    carets: Vec<(usize, f32)>,
+    scroll: f32,
+}
+
+// Stick travel past which it moves focus, and back under which it can again.
+const STICK_PRESS: f32 = 0.5;
+const STICK_RELEASE: f32 = 0.3;
+
+// A retained tree of widgets for game menus and HUDs. Add widgets once, feed
+// the SDL events to `handle_event`, read what happened with `drain_events`
+// and call `WgpuEngine::draw_ui` every frame. Widgets are drawn as screen
+// sprites and screen text; text goes over all of the UI's sprites, so
+// widgets shouldn't overlap text of widgets under them.
+pub struct Ui {
+    pub theme: Theme,
+    // UI units are pixels at a screen this tall and scale with it, so the
+    // UI looks the same at any resolution. None keeps them pixels.
+    pub reference_height: Option<f32>,
+    // Sprite layer of the UI, against other screen sprites.
+    pub layer: i32,
+    nodes: HashMap<WidgetId, Node>,
+    roots: Vec<WidgetId>,
+    focus: Option<WidgetId>,
+    hovered: Option<WidgetId>,
+    // Held down by the mouse.
+    pressed: Option<WidgetId>,
+    events: Vec<UiEvent>,
+    // In window pixels, from the last layout.
+    screen: UiRect,
+    scale: f32,
+    pointer: Vec2,
+    // Whether the stick is past `STICK_PRESS` on the x and y axes.
+    stick: [bool; 2],
+    next_id: u32,
+}
+
+impl Ui {
+    pub fn new(theme: Theme) -> Self {
+        Self {
+            theme,
+            reference_height: None,
+            layer: 0,
+            nodes: HashMap::new(),
+            roots: Vec::new(),
+            focus: None,
+            hovered: None,
+            pressed: None,
+            events: Vec::new(),
+            screen: UiRect::default(),
+            scale: 1.0,
+            pointer: Vec2::broadcast(-1.0),
+            stick: [false; 2],
+            next_id: 0,
+        }
+    }
+
+    // Adds a widget as the last child of `parent`, or as a root laid out
+    // against the whole screen.
+    pub fn add(&mut self, parent: Option<WidgetId>, widget: Widget) -> Result<WidgetId> {
+        self.next_id += 1;
+        let id = WidgetId(self.next_id);
+        match parent {
+            Some(parent) => self.node_mut(parent)?.children.push(id),
+            None => self.roots.push(id),
+        }
+        self.nodes.insert(
+            id,
+            Node {
+                widget,
+                parent,
+                children: Vec::new(),
+                rect: UiRect::default(),
+                measured: Vec2::zero(),
+                carets: Vec::new(),
+                scroll: 0.0,
+            },
+        );
+        Ok(id)
+    }
+
+    // Removes a widget and all of its children.
+    pub fn remove(&mut self, id: WidgetId) -> Result<Widget> {
+        let node = self.nodes.remove(&id).ok_or_else(|| anyhow!("Widget {:?} not found", id))?;
+        match node.parent.and_then(|parent| self.nodes.get_mut(&parent)) {
+            Some(parent) => parent.children.retain(|c| *c != id),
+            None => self.roots.retain(|r| *r != id),
+        }
+        for child in node.children {
+            self.remove(child)?;
+        }
+        if self.focus == Some(id) {
+            self.set_focus(None);
+        }
+        if self.hovered == Some(id) {
+            self.hovered = None;
+        }
+        if self.pressed == Some(id) {
+            self.pressed = None;
+        }
+        Ok(node.widget)
+    }
+
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+        self.roots.clear();
+        self.set_focus(None);
+        self.hovered = None;
+        self.pressed = None;
+    }
+
+    fn node(&self, id: WidgetId) -> Result<&Node> {
+        self.nodes.get(&id).ok_or_else(|| anyhow!("Widget {:?} not found", id))
+    }
+
+    fn node_mut(&mut self, id: WidgetId) -> Result<&mut Node> {
+        self.nodes.get_mut(&id).ok_or_else(|| anyhow!("Widget {:?} not found", id))
+    }
+
+    pub fn widget(&self, id: WidgetId) -> Result<&Widget> {
+        Ok(&self.node(id)?.widget)
+    }
+
+    // Changes show after the next layout, i.e. the next `WgpuEngine::draw_ui`.
+    pub fn widget_mut(&mut self, id: WidgetId) -> Result<&mut Widget> {
+        Ok(&mut self.node_mut(id)?.widget)
+    }
+
+    pub fn roots(&self) -> &[WidgetId] {
+        &self.roots
+    }
+
+    pub fn children(&self, id: WidgetId) -> Result<&[WidgetId]> {
+        Ok(&self.node(id)?.children)
+    }
+
+    // Where the widget was put by the last layout, in pixels from the top
+    // left of the UI's screen.
+    pub fn rect(&self, id: WidgetId) -> Result<UiRect> {
+        Ok(self.node(id)?.rect)
+    }
+
+    // A slider's or progress bar's value.
+    pub fn value(&self, id: WidgetId) -> Result<f32> {
+        match self.widget(id)?.kind {
+            WidgetKind::Slider { value, .. } | WidgetKind::ProgressBar { value } => Ok(value),
+            _ => Err(anyhow!("Widget {:?} has no value", id)),
+        }
+    }
+
+    // Sets a slider's or progress bar's value, clamped to its range.
+    pub fn set_value(&mut self, id: WidgetId, new_value: f32) -> Result<()> {
+        match &mut self.widget_mut(id)?.kind {
+            WidgetKind::Slider { value, min, max, .. } => *value = new_value.clamp(min.min(*max), max.max(*min)),
+            WidgetKind::ProgressBar { value } => *value = new_value.clamp(0.0, 1.0),
+            _ => return Err(anyhow!("Widget {:?} has no value", id)),
+        }
+        Ok(())
+    }
+
+    // The text of a label, button or text input.
+    pub fn text(&self, id: WidgetId) -> Result<&str> {
+        match &self.widget(id)?.kind {
+            WidgetKind::Label { text } | WidgetKind::Button { text } | WidgetKind::TextInput { text, .. } => Ok(text),
+            _ => Err(anyhow!("Widget {:?} has no text", id)),
+        }
+    }
+
+    pub fn set_text(&mut self, id: WidgetId, new_text: &str) -> Result<()> {
+        match &mut self.widget_mut(id)?.kind {
+            WidgetKind::Label { text } | WidgetKind::Button { text } => *text = new_text.to_string(),
+            WidgetKind::TextInput { text, caret, .. } => {
+                *text = new_text.to_string();
+                *caret = text.len();
+            }
+            _ => return Err(anyhow!("Widget {:?} has no text", id)),
+        }
+        Ok(())
+    }
+
+    pub fn focus(&self) -> Option<WidgetId> {
+        self.focus
+    }
+
+    // Focuses a widget, e.g. the first button when a menu opens so the
+    // gamepad can use it right away.
+    pub fn set_focus(&mut self, id: Option<WidgetId>) {
+        if self.focus != id {
+            self.focus = id;
+            self.events.push(UiEvent::FocusChanged(id));
+        }
+    }
+
+    // What happened since the last call, in order.
+    pub fn drain_events(&mut self) -> Vec<UiEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    // Visible widgets, parents before children and in the order they're drawn.
+    fn visible(&self) -> Vec<WidgetId> {
+        let mut visible = Vec::new();
+        let mut stack: Vec<WidgetId> = self.roots.iter().rev().copied().collect();
+        while let Some(id) = stack.pop() {
+            let node = &self.nodes[&id];
+            if node.widget.visible {
+                visible.push(id);
+                stack.extend(node.children.iter().rev());
+            }
+        }
+        visible
+    }
+
+    fn focusable(&self) -> Vec<WidgetId> {
+        self.visible()
+            .into_iter()
+            .filter(|id| {
+                let widget = &self.nodes[id].widget;
+                widget.enabled && widget.is_interactive()
+            })
+            .collect()
+    }
+
+    // Whether a widget can take input now: enabled, interactive and shown
+    // along with all its parents.
+    fn usable(&self, id: WidgetId) -> bool {
+        let widget = match self.nodes.get(&id) {
+            Some(node) => &node.widget,
+            None => return false,
+        };
+        let mut at = Some(id);
+        while let Some(node) = at.map(|id| &self.nodes[&id]) {
+            if !node.widget.visible {
+                return false;
+            }
+            at = node.parent;
+        }
+        widget.enabled && widget.is_interactive()
+    }
+
+    // The focused widget if it can still take input. Hiding or disabling it
+    // drops its focus right away rather than at the next layout.
+    fn usable_focus(&mut self) -> Option<WidgetId> {
+        let id = self.focus?;
+        if !self.usable(id) {
+            self.set_focus(None);
+            return None;
+        }
+        Some(id)
+    }
+
+    // The topmost interactive widget under `point`.
+    fn interactive_at(&self, point: Vec2) -> Option<WidgetId> {
+        self.focusable().into_iter().rev().find(|id| self.nodes[id].rect.contains(point))
+    }
+
+    // Whether anything that's drawn is under `point`, so the game shouldn't get the click.
+    fn covers(&self, point: Vec2) -> bool {
+        self.visible().into_iter().any(|id| {
+            let node = &self.nodes[&id];
+            let see_through = node.widget.kind == WidgetKind::Panel && self.style(id).background[3] <= 0.0;
+            !see_through && node.rect.contains(point)
+        })
+    }
+
+    // Translates an SDL event into UI input. Returns true when the UI uses
+    // it, so the game can skip it.
+    pub fn handle_event(&mut self, event: &Event) -> bool {
+        match event {
+            Event::MouseMotion { x, y, .. } => {
+                self.pointer = Vec2::new(*x as f32, *y as f32) - self.screen.min;
+                self.hovered = self.interactive_at(self.pointer);
+                if let Some(id) = self.pressed {
+                    self.drag(id);
+                }
+                self.pressed.is_some() || self.covers(self.pointer)
+            }
+            Event::MouseButtonDown { mouse_btn: MouseButton::Left, x, y, .. } => {
+                self.pointer = Vec2::new(*x as f32, *y as f32) - self.screen.min;
+                let target = self.interactive_at(self.pointer);
+                self.set_focus(target);
+                self.pressed = target;
+                if let Some(id) = target {
+                    self.drag(id);
+                }
+                self.covers(self.pointer)
+            }
+            Event::MouseButtonUp { mouse_btn: MouseButton::Left, x, y, .. } => {
+                self.pointer = Vec2::new(*x as f32, *y as f32) - self.screen.min;
+                let pressed = self.pressed.take();
+                if let Some(id) = pressed.filter(|id| self.usable(*id)) {
+                    let button = matches!(self.nodes[&id].widget.kind, WidgetKind::Button { .. });
+                    if button && self.nodes[&id].rect.contains(self.pointer) {
+                        self.events.push(UiEvent::Clicked(id));
+                    }
+                }
+                pressed.is_some() || self.covers(self.pointer)
+            }
+            Event::TextInput { text, .. } => self.insert_text(text),
+            Event::KeyDown { keycode: Some(keycode), keymod, .. } => self.key(*keycode, *keymod),
+            Event::ControllerButtonDown { button, .. } => match button {
+                Button::DPadUp => self.navigate(Navigate::Up),
+                Button::DPadDown => self.navigate(Navigate::Down),
+                Button::DPadLeft => self.navigate(Navigate::Left),
+                Button::DPadRight => self.navigate(Navigate::Right),
+                Button::A => self.activate(),
+                Button::B => {
+                    self.events.push(UiEvent::Back);
+                    true
+                }
+                _ => false,
+            },
+            Event::ControllerAxisMotion { axis, value, .. } => {
+                let (slot, negative, positive) = match axis {
+                    Axis::LeftX => (0, Navigate::Left, Navigate::Right),
+                    Axis::LeftY => (1, Navigate::Up, Navigate::Down),
+                    _ => return false,
+                };
+                let value = *value as f32 / i16::MAX as f32;
+                if self.stick[slot] {
+                    self.stick[slot] = value.abs() > STICK_RELEASE;
+                    false
+                } else if value.abs() > STICK_PRESS {
+                    self.stick[slot] = true;
+                    self.navigate(if value < 0.0 { negative } else { positive })
+                } else {
+                    false
+                }
+            }
+            _ => false,
+        }
+    }
+
+    fn key(&mut self, keycode: Keycode, keymod: Mod) -> bool {
+        let focused = self.usable_focus().map(|id| &self.nodes[&id].widget.kind);
+        let typing = matches!(focused, Some(WidgetKind::TextInput { .. }));
+        match keycode {
+            Keycode::Tab => {
+                let back = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+                self.cycle_focus(back)
+            }
+            Keycode::Escape => {
+                self.events.push(UiEvent::Back);
+                true
+            }
+            Keycode::Up => self.navigate(Navigate::Up),
+            Keycode::Down => self.navigate(Navigate::Down),
+            Keycode::Left => self.navigate(Navigate::Left),
+            Keycode::Right => self.navigate(Navigate::Right),
+            Keycode::Return | Keycode::KpEnter => self.activate(),
+            // Spaces reach text inputs as text.
+            Keycode::Space if !typing => self.activate(),
+            Keycode::Backspace | Keycode::Delete | Keycode::Home | Keycode::End if typing => {
+                self.edit_text(keycode);
+                true
+            }
+            // Typing shouldn't also move the player.
+            _ => typing,
+        }
+    }
+
+    // Tab order is the order widgets are drawn in.
+    fn cycle_focus(&mut self, back: bool) -> bool {
+        let focusable = self.focusable();
+        if focusable.is_empty() {
+            return false;
+        }
+        let current = self.focus.and_then(|id| focusable.iter().position(|f| *f == id));
+        let next = match (current, back) {
+            (Some(i), false) => (i + 1) % focusable.len(),
+            (Some(i), true) => (i + focusable.len() - 1) % focusable.len(),
+            (None, false) => 0,
+            (None, true) => focusable.len() - 1,
+        };
+        self.set_focus(Some(focusable[next]));
+        true
+    }
+
+    // Arrows move text carets and sliders, otherwise focus moves to the
+    // closest widget that way.
+    fn navigate(&mut self, direction: Navigate) -> bool {
+        let horizontal = matches!(direction, Navigate::Left | Navigate::Right);
+        let sign = if matches!(direction, Navigate::Left | Navigate::Up) { -1.0 } else { 1.0 };
+        if let Some(id) = self.usable_focus() {
+            let node = self.nodes.get_mut(&id).unwrap();
+            match &mut node.widget.kind {
+                WidgetKind::Slider { value, min, max, step } if horizontal => {
+                    let step = if *step > 0.0 { *step } else { (*max - *min).abs() / 20.0 };
+                    let new_value = (*value + step * sign).clamp(min.min(*max), max.max(*min));
+                    if new_value != *value {
+                        *value = new_value;
+                        self.events.push(UiEvent::ValueChanged(id, new_value));
+                    }
+                    return true;
+                }
+                WidgetKind::TextInput { text, caret, .. } if horizontal => {
+                    *caret = if sign < 0.0 {
+                        text[..*caret].char_indices().next_back().map_or(0, |(i, _)| i)
+                    } else {
+                        text[*caret..].chars().next().map_or(*caret, |c| *caret + c.len_utf8())
+                    };
+                    return true;
+                }
+                _ => (),
+            }
+        }
+
+        let focusable = self.focusable();
+        let from = match self.focus {
+            Some(id) => self.nodes[&id].rect.center(),
+            None => {
+                if let Some(first) = focusable.first() {
+                    self.set_focus(Some(*first));
+                }
+                return !focusable.is_empty();
+            }
+        };
+        // Straight ahead is favored over closer widgets off to the side.
+        let forward = direction.vector();
+        let best = focusable
+            .into_iter()
+            .filter(|id| Some(*id) != self.focus)
+            .filter_map(|id| {
+                let to = self.nodes[&id].rect.center() - from;
+                let along = to.dot(forward);
+                let across = (to - forward * along).mag();
+                (along > 0.5).then_some((id, along + across * 2.0))
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+        match best {
+            Some((id, _)) => {
+                self.set_focus(Some(id));
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Enter, Space or the gamepad's A on the focused widget.
+    fn activate(&mut self) -> bool {
+        let id = match self.usable_focus() {
+            Some(id) => id,
+            None => return false,
+        };
+        match self.nodes[&id].widget.kind {
+            WidgetKind::Button { .. } => self.events.push(UiEvent::Clicked(id)),
+            WidgetKind::TextInput { .. } => self.events.push(UiEvent::Submitted(id)),
+            _ => return false,
+        }
+        true
+    }
+
+    fn insert_text(&mut self, input: &str) -> bool {
+        let id = match self.usable_focus() {
+            Some(id) => id,
+            None => return false,
+        };
+        if let WidgetKind::TextInput { text, max_chars, caret, .. } = &mut self.nodes.get_mut(&id).unwrap().widget.kind
+        {
+            let room = max_chars.map_or(usize::MAX, |max| max.saturating_sub(text.chars().count()));
+            let input: String = input.chars().filter(|c| !c.is_control()).take(room).collect();
+            if !input.is_empty() {
+                text.insert_str(*caret, &input);
+                *caret += input.len();
+                self.events.push(UiEvent::TextChanged(id));
+            }
+            return true;
+        }
+        false
+    }
+
+    fn edit_text(&mut self, keycode: Keycode) {
+        let id = match self.usable_focus() {
+            Some(id) => id,
+            None => return,
+        };
+        if let WidgetKind::TextInput { text, caret, .. } = &mut self.nodes.get_mut(&id).unwrap().widget.kind {
+            let changed = match keycode {
+                Keycode::Backspace => match text[..*caret].char_indices().next_back() {
+                    Some((i, _)) => {
+                        text.replace_range(i..*caret, "");
+                        *caret = i;
+                        true
+                    }
+                    None => false,
+                },
+                Keycode::Delete => match text[*caret..].chars().next() {
+                    Some(c) => {
+                        text.replace_range(*caret..*caret + c.len_utf8(), "");
+                        true
+                    }
+                    None => false,
+                },
+                Keycode::Home => {
+                    *caret = 0;
+                    false
+                }
+                Keycode::End => {
+                    *caret = text.len();
+                    false
+                }
+                _ => false,
+            };
+            if changed {
+                self.events.push(UiEvent::TextChanged(id));
+            }
+        }
+    }
+
+    // The mouse pressed or dragged on a widget: sliders follow it, text
+    // inputs put the caret under it.
+    fn drag(&mut self, id: WidgetId) {
+        if !self.usable(id) {
+            return;
+        }
+        let padding = self.theme.padding.scaled(self.scale);
+        let knob = self.knob_size(id);
+        let pointer = self.pointer;
+        let node = self.nodes.get_mut(&id).unwrap();
+        match &mut node.widget.kind {
+            WidgetKind::Slider { value, min, max, .. } => {
+                let travel = (node.rect.size.x - knob).max(1.0);
+                let t = ((pointer.x - node.rect.min.x - knob * 0.5) / travel).clamp(0.0, 1.0);
+                let new_value = *min + (*max - *min) * t;
+                if new_value != *value {
+                    *value = new_value;
+                    self.events.push(UiEvent::ValueChanged(id, new_value));
+                }
+            }
+            WidgetKind::TextInput { caret, .. } => {
+                let x = pointer.x - node.rect.min.x - padding.left + node.scroll;
+                if let Some((index, _)) = node.carets.iter().min_by(|a, b| (a.1 - x).abs().total_cmp(&(b.1 - x).abs()))
+                {
+                    *caret = *index;
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn knob_size(&self, id: WidgetId) -> f32 {
+        self.nodes[&id].rect.size.y
+    }
+
+    fn style(&self, id: WidgetId) -> WidgetStyle {
+        let widget = &self.nodes[&id].widget;
+        let styles = match (&widget.style, &widget.kind) {
+            (Some(styles), _) => *styles,
+            (None, WidgetKind::Panel) => StateStyles::uniform(self.theme.panel),
+            (None, WidgetKind::Button { .. }) | (None, WidgetKind::Slider { .. }) => self.theme.button,
+            (None, WidgetKind::TextInput { .. }) => self.theme.input,
+            (None, _) => StateStyles::uniform(self.theme.label),
+        };
+        if !widget.enabled {
+            styles.disabled
+        } else if self.pressed == Some(id) && self.hovered == Some(id) {
+            styles.pressed
+        } else if self.focus == Some(id) {
+            styles.focused
+        } else if self.hovered == Some(id) {
+            styles.hovered
+        } else {
+            styles.normal
+        }
+    }
+
+    fn text_style(&self) -> TextStyle {
+        TextStyle::new(self.theme.font, self.theme.font_size * self.scale)
+    }
+
+    // Places every widget in `screen`, in window pixels, e.g. the main
+    // view's viewport. Text is measured with the theme's font from `text`.
+    pub fn layout(&mut self, screen: UiRect, text: &TextRenderer) -> Result<()> {
+        self.layout_with_font(screen, text.font(self.theme.font)?)
+    }
+
+    fn layout_with_font(&mut self, screen: UiRect, font: &Font) -> Result<()> {
+        self.screen = screen;
+        self.scale = self.reference_height.map_or(1.0, |height| screen.size.y / height.max(1.0));
+        let roots = self.roots.clone();
+        for root in &roots {
+            self.measure(*root, font)?;
+        }
+        // Hidden or disabled widgets lose focus.
+        self.usable_focus();
+        let area = UiRect::new(Vec2::zero(), screen.size);
+        for root in roots {
+            let rect = self.anchored(root, area);
+            self.arrange(root, rect, font)?;
+        }
+        Ok(())
+    }
+
+    // Size each widget would like, bottom up.
+    fn measure(&mut self, id: WidgetId, font: &Font) -> Result<Vec2> {
+        let scale = self.scale;
+        let style = self.text_style();
+        let padding = self.theme.padding.scaled(scale);
+        let padded = |size: Vec2| size + Vec2::new(padding.left + padding.right, padding.top + padding.bottom);
+        let measure = |text: &str| TextLayout::new(font, text, &style).size;
+        let line = measure("Ag").y;
+        let node = self.node(id)?;
+        let layout = node.widget.layout;
+        let children = node.children.clone();
+        let content = match &node.widget.kind {
+            WidgetKind::Panel => {
+                let row = layout.direction == Direction::Row;
+                let mut size = Vec2::zero();
+                let mut count = 0;
+                for child in children {
+                    let natural = self.measure(child, font)?;
+                    let widget = &self.nodes[&child].widget;
+                    if !widget.visible || widget.anchor.is_some() {
+                        continue;
+                    }
+                    let natural =
+                        Vec2::new(basis(widget.width, natural.x, scale), basis(widget.height, natural.y, scale));
+                    if row {
+                        size = Vec2::new(size.x + natural.x, size.y.max(natural.y));
+                    } else {
+                        size = Vec2::new(size.x.max(natural.x), size.y + natural.y);
+                    }
+                    count += 1;
+                }
+                let gaps = layout.gap * scale * count.max(1) as f32 - layout.gap * scale;
+                let size = if row { size + Vec2::new(gaps, 0.0) } else { size + Vec2::new(0.0, gaps) };
+                let edges = layout.padding.scaled(scale);
+                size + Vec2::new(edges.left + edges.right, edges.top + edges.bottom)
+            }
+            WidgetKind::Label { text } => measure(text),
+            WidgetKind::Button { text } => padded(measure(text)),
+            WidgetKind::Image { .. } => Vec2::broadcast(64.0 * scale),
+            WidgetKind::Slider { .. } => Vec2::new(200.0 * scale, line),
+            WidgetKind::ProgressBar { .. } => Vec2::new(200.0 * scale, line * 0.5),
+            WidgetKind::TextInput { .. } => padded(Vec2::new(240.0 * scale, line)),
+        };
+        self.node_mut(id)?.measured = content;
+        Ok(content)
+    }
+
+    // Rect of an anchored widget, or a root, in `parent`'s content box.
+    fn anchored(&self, id: WidgetId, parent: UiRect) -> UiRect {
+        let node = &self.nodes[&id];
+        let size = Vec2::new(
+            resolve(node.widget.width, parent.size.x, node.measured.x, self.scale),
+            resolve(node.widget.height, parent.size.y, node.measured.y, self.scale),
+        );
+        let anchor = node.widget.anchor.unwrap_or(Anchor::TOP_LEFT);
+        let min = parent.min + anchor.point * parent.size - anchor.pivot * size + anchor.offset * self.scale;
+        UiRect::new(min, size)
+    }
+
+    // Places a widget and then its children, top down.
+    fn arrange(&mut self, id: WidgetId, rect: UiRect, font: &Font) -> Result<()> {
+        let scale = self.scale;
+        let node = self.node_mut(id)?;
+        node.rect = rect;
+        let layout = node.widget.layout;
+        let children = node.children.clone();
+        if let WidgetKind::TextInput { .. } = node.widget.kind {
+            self.layout_carets(id, font)?;
+        }
+
+        let content = rect.inset(layout.padding.scaled(scale));
+        let row = layout.direction == Direction::Row;
+        let main = |v: Vec2| if row { v.x } else { v.y };
+        let cross = |v: Vec2| if row { v.y } else { v.x };
+        let flow: Vec<WidgetId> = children
+            .iter()
+            .copied()
+            .filter(|c| self.nodes[c].widget.visible && self.nodes[c].widget.anchor.is_none())
+            .collect();
+
+        // Lengths along the row or column; `Fill` shares what is left.
+        let mut lengths = Vec::with_capacity(flow.len());
+        let mut weights = 0.0;
+        for child in &flow {
+            let node = &self.nodes[child];
+            let length = if row { node.widget.width } else { node.widget.height };
+            lengths.push(match length {
+                Length::Fill(weight) => {
+                    weights += weight.max(0.0);
+                    0.0
+                }
+                _ => resolve(length, main(content.size), main(node.measured), scale),
+            });
+        }
+        let mut gap = layout.gap * scale;
+        let used = lengths.iter().sum::<f32>() + gap * flow.len().saturating_sub(1) as f32;
+        let left = (main(content.size) - used).max(0.0);
+        let mut pen = 0.0;
+        if weights > 0.0 {
+            for (length, child) in lengths.iter_mut().zip(&flow) {
+                let node = &self.nodes[child];
+                if let Length::Fill(weight) = if row { node.widget.width } else { node.widget.height } {
+                    *length = left * weight.max(0.0) / weights;
+                }
+            }
+        } else {
+            match layout.justify {
+                Justify::Start => (),
+                Justify::Center => pen = left * 0.5,
+                Justify::End => pen = left,
+                Justify::SpaceBetween if flow.len() > 1 => gap += left / (flow.len() - 1) as f32,
+                Justify::SpaceBetween => (),
+            }
+        }
+
+        for (child, length) in flow.iter().zip(lengths) {
+            let node = &self.nodes[child];
+            let cross_length = if row { node.widget.height } else { node.widget.width };
+            let room = cross(content.size);
+            let size = match (cross_length, layout.align) {
+                (Length::Auto, Align::Stretch) => room,
+                _ => resolve(cross_length, room, cross(node.measured), scale),
+            };
+            let offset = match layout.align {
+                Align::Start | Align::Stretch => 0.0,
+                Align::Center => (room - size) * 0.5,
+                Align::End => room - size,
+            };
+            let child_rect = if row {
+                UiRect::new(content.min + Vec2::new(pen, offset), Vec2::new(length, size))
+            } else {
+                UiRect::new(content.min + Vec2::new(offset, pen), Vec2::new(size, length))
+            };
+            self.arrange(*child, child_rect, font)?;
+            pen += length + gap;
+        }
+
+        for child in children {
+            let node = &self.nodes[&child];
+            if node.widget.visible && node.widget.anchor.is_some() {
+                let child_rect = self.anchored(child, content);
+                self.arrange(child, child_rect, font)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Caret stops of a text input and the scroll keeping its caret in view.
+    fn layout_carets(&mut self, id: WidgetId, font: &Font) -> Result<()> {
+        let style = self.text_style();
+        let padding = self.theme.padding.scaled(self.scale);
+        let node = self.node_mut(id)?;
+        let (value, caret) = match &node.widget.kind {
+            WidgetKind::TextInput { text, caret, .. } => (text.clone(), *caret),
+            _ => return Ok(()),
+        };
+        let layout = TextLayout::new(font, &value, &style);
+        let node = self.node_mut(id)?;
+        node.carets = layout.glyphs.iter().map(|g| (g.index, g.position.x)).collect();
+        node.carets.push((value.len(), layout.glyphs.last().map_or(0.0, |g| g.position.x + g.advance)));
+        let width = node.rect.inset(padding).size.x;
+        let caret_x = node.carets.iter().find(|(index, _)| *index >= caret).map_or(0.0, |c| c.1);
+        let end = node.carets.last().map_or(0.0, |c| c.1);
+        node.scroll = node.scroll.clamp(caret_x - width, caret_x).min((end - width).max(0.0)).max(0.0);
+        Ok(())
+    }
+
+    // Queues the UI as screen sprites and text for this frame, after `layout`.
+    pub fn draw(&self, sprites: &mut SpriteRenderer, text: &mut TextRenderer) {
+        let scale = self.scale;
+        let padding = self.theme.padding.scaled(scale);
+        // Later sprites go over earlier ones.
+        let z = Cell::new(0.0);
+        let image =
+            |sprites: &mut SpriteRenderer, texture: SpriteTextureId, rect: UiRect, uv: UvRect, color: [f32; 4]| {
+                if color[3] > 0.0 && rect.size.x > 0.0 && rect.size.y > 0.0 {
+                    sprites.draw_screen(self.sprite(texture, rect, uv, color, z.get()));
+                    z.set(z.get() + 1.0);
+                }
+            };
+        let quad = |sprites: &mut SpriteRenderer, rect: UiRect, color: [f32; 4]| {
+            image(sprites, self.theme.texture, rect, UvRect::FULL, color)
+        };
+
+        for id in self.visible() {
+            let node = &self.nodes[&id];
+            let rect = node.rect;
+            let style = self.style(id);
+            let text_style = TextStyle { color: style.text, ..self.text_style() };
+            match &node.widget.kind {
+                WidgetKind::Panel => quad(sprites, rect, style.background),
+                WidgetKind::Label { text: label } => {
+                    quad(sprites, rect, style.background);
+                    let at = Vec2::new(rect.min.x, rect.center().y);
+                    let text_style = TextStyle { anchor: Vec2::new(0.0, 0.5), ..text_style };
+                    text.draw(label, TextPlacement::Screen(at), &text_style);
+                }
+                WidgetKind::Button { text: label } => {
+                    quad(sprites, rect, style.background);
+                    let text_style = TextStyle { anchor: Vec2::new(0.5, 0.5), ..text_style };
+                    text.draw(label, TextPlacement::Screen(rect.center()), &text_style);
+                }
+                WidgetKind::Image { texture, uv, color } => {
+                    image(sprites, *texture, rect, *uv, *color);
+                }
+                WidgetKind::Slider { value, min, max, .. } => {
+                    let knob = self.knob_size(id);
+                    let t = if max != min { ((value - min) / (max - min)).clamp(0.0, 1.0) } else { 0.0 };
+                    let track_height = (rect.size.y * 0.25).max(1.0);
+                    let track = UiRect::new(
+                        Vec2::new(rect.min.x + knob * 0.5, rect.center().y - track_height * 0.5),
+                        Vec2::new((rect.size.x - knob).max(0.0), track_height),
+                    );
+                    quad(sprites, track, self.theme.track);
+                    quad(sprites, UiRect::new(track.min, Vec2::new(track.size.x * t, track_height)), self.theme.fill);
+                    let knob_rect = UiRect::new(
+                        Vec2::new(track.min.x + track.size.x * t - knob * 0.5, rect.min.y),
+                        Vec2::broadcast(knob),
+                    );
+                    quad(sprites, knob_rect, style.background);
+                }
+                WidgetKind::ProgressBar { value } => {
+                    quad(sprites, rect, self.theme.track);
+                    quad(sprites, UiRect::new(rect.min, Vec2::new(rect.size.x * value, rect.size.y)), self.theme.fill);
+                }
+                WidgetKind::TextInput { text: value, placeholder, caret, .. } => {
+                    quad(sprites, rect, style.background);
+                    let inner = rect.inset(padding);
+                    let at = Vec2::new(inner.min.x, rect.center().y);
+                    let anchored = TextStyle { anchor: Vec2::new(0.0, 0.5), ..text_style };
+                    if value.is_empty() {
+                        let placeholder_style = TextStyle { color: self.theme.placeholder, ..anchored };
+                        text.draw(placeholder, TextPlacement::Screen(at), &placeholder_style);
+                    } else {
+                        // Only the characters fully inside the box.
+                        let visible = |(_, x): &&(usize, f32)| {
+                            *x >= node.scroll - 0.01 && *x <= node.scroll + inner.size.x + 0.01
+                        };
+                        let first = node.carets.iter().find(visible);
+                        let last = node.carets.iter().rev().find(visible);
+                        if let (Some(first), Some(last)) = (first, last) {
+                            let shown = &value[first.0..last.0.max(first.0)];
+                            let at = Vec2::new(inner.min.x + first.1 - node.scroll, at.y);
+                            text.draw(shown, TextPlacement::Screen(at), &anchored);
+                        }
+                    }
+                    if self.focus == Some(id) {
+                        let x = node.carets.iter().find(|(index, _)| index >= caret).map_or(0.0, |c| c.1);
+                        let height = self.theme.font_size * scale;
+                        let caret_rect = UiRect::new(
+                            Vec2::new(inner.min.x + x - node.scroll, rect.center().y - height * 0.5),
+                            Vec2::new(scale.max(1.0), height),
+                        );
+                        quad(sprites, caret_rect, style.text);
+                    }
+                }
+            }
+
+            if self.focus == Some(id) {
+                let width = self.theme.outline * scale;
+                let (min, size) = (rect.min, rect.size);
+                quad(sprites, UiRect::new(min, Vec2::new(size.x, width)), self.theme.focus);
+                quad(
+                    sprites,
+                    UiRect::new(Vec2::new(min.x, min.y + size.y - width), Vec2::new(size.x, width)),
+                    self.theme.focus,
+                );
+                quad(sprites, UiRect::new(min, Vec2::new(width, size.y)), self.theme.focus);
+                quad(
+                    sprites,
+                    UiRect::new(Vec2::new(min.x + size.x - width, min.y), Vec2::new(width, size.y)),
+                    self.theme.focus,
+                );
+            }
+        }
+    }
+
+    fn sprite(&self, texture: SpriteTextureId, rect: UiRect, uv: UvRect, color: [f32; 4], z: f32) -> Sprite {
+        Sprite {
+            transform: Transform2d::new(rect.min, 1.0, 0.0, 0),
+            uv,
+            color,
+            pivot: Vec2::zero(),
+            layer: self.layer,
+            z,
+            ..Sprite::new(texture, rect.size)
+        }
+    }
+}
+
+// Length along a parent's row or column before `Fill` shares the rest.
+fn basis(length: Length, measured: f32, scale: f32) -> f32 {
+    match length {
+        Length::Px(value) => value * scale,
+        Length::Auto => measured,
+        Length::Percent(_) | Length::Fill(_) => 0.0,
+    }
+}
+
+// A length in pixels, in a parent `room` pixels long.
+fn resolve(length: Length, room: f32, measured: f32, scale: f32) -> f32 {
+    match length {
+        Length::Auto => measured,
+        Length::Px(value) => value * scale,
+        Length::Percent(fraction) => room * fraction,
+        Length::Fill(_) => room,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCREEN: UiRect = UiRect { min: Vec2::new(0.0, 0.0), size: Vec2::new(800.0, 600.0) };
+
+    fn font() -> Font {
+        Font::from_bytes(epaint_default_fonts::HACK_REGULAR.to_vec()).unwrap()
+    }
+
+    fn ui() -> Ui {
+        Ui::new(Theme::dark(FontId(0), SpriteTextureId(0)))
+    }
+
+    fn sized(widget: Widget, width: f32, height: f32) -> Widget {
+        Widget { width: Length::Px(width), height: Length::Px(height), ..widget }
+    }
+
+    fn at(anchor: Anchor, widget: Widget) -> Widget {
+        Widget { anchor: Some(anchor), ..sized(widget, 100.0, 40.0) }
+    }
+
+    #[test]
+    fn columns_stack_children_inside_the_padding() {
+        let mut ui = ui();
+        let layout = Layout { align: Align::Stretch, padding: Edges::all(10.0), ..Layout::column(5.0) };
+        let panel = ui.add(None, Widget { width: Length::Px(300.0), ..Widget::panel(layout) }).unwrap();
+        let a = ui.add(Some(panel), Widget { height: Length::Px(40.0), ..Widget::button("A") }).unwrap();
+        let b = ui.add(Some(panel), Widget { height: Length::Px(40.0), ..Widget::button("B") }).unwrap();
+        ui.layout_with_font(SCREEN, &font()).unwrap();
+        assert_eq!(ui.rect(a).unwrap(), UiRect::new(Vec2::new(10.0, 10.0), Vec2::new(280.0, 40.0)));
+        assert_eq!(ui.rect(b).unwrap(), UiRect::new(Vec2::new(10.0, 55.0), Vec2::new(280.0, 40.0)));
+        assert_eq!(ui.rect(panel).unwrap().size, Vec2::new(300.0, 105.0));
+
+        // Hidden widgets take no room.
+        ui.widget_mut(a).unwrap().visible = false;
+        ui.layout_with_font(SCREEN, &font()).unwrap();
+        assert_eq!(ui.rect(b).unwrap().min, Vec2::new(10.0, 10.0));
+    }
+
+    #[test]
+    fn fill_shares_what_is_left_by_weight() {
+        let mut ui = ui();
+        let row = ui.add(None, sized(Widget::panel(Layout::row(0.0)), 400.0, 50.0)).unwrap();
+        let fixed = ui.add(Some(row), sized(Widget::label("Score"), 100.0, 20.0)).unwrap();
+        let one = ui.add(Some(row), Widget { width: Length::Fill(1.0), ..Widget::progress_bar(0.5) }).unwrap();
+        let two = ui.add(Some(row), Widget { width: Length::Fill(2.0), ..Widget::progress_bar(0.5) }).unwrap();
+        ui.layout_with_font(SCREEN, &font()).unwrap();
+        let spans: Vec<(f32, f32)> =
+            [fixed, one, two].iter().map(|id| ui.rect(*id).unwrap()).map(|r| (r.min.x, r.size.x)).collect();
+        assert_eq!(spans, vec![(0.0, 100.0), (100.0, 100.0), (200.0, 200.0)]);
+    }
+
+    #[test]
+    fn buttons_fit_their_text() {
+        let mut ui = ui();
+        let button = ui.add(None, Widget::button("Play")).unwrap();
+        ui.layout_with_font(SCREEN, &font()).unwrap();
+        let text = TextLayout::new(&font(), "Play", &TextStyle::new(FontId(0), 20.0)).size;
+        assert_eq!(ui.rect(button).unwrap().size, text + Vec2::new(24.0, 12.0));
+    }
+
+    #[test]
+    fn anchors_scale_with_the_reference_height() {
+        let mut ui = ui();
+        ui.reference_height = Some(300.0);
+        let corner = ui.add(None, at(Anchor::BOTTOM_RIGHT.offset(-10.0, -10.0), Widget::button("Menu"))).unwrap();
+        ui.layout_with_font(SCREEN, &font()).unwrap();
+        assert_eq!(ui.rect(corner).unwrap(), UiRect::new(Vec2::new(580.0, 500.0), Vec2::new(200.0, 80.0)));
+    }
+
+    #[test]
+    fn arrows_move_focus_to_the_closest_widget_that_way() {
+        let mut ui = ui();
+        let top_left = ui.add(None, at(Anchor::TOP_LEFT, Widget::button("1"))).unwrap();
+        let top_right = ui.add(None, at(Anchor::TOP_RIGHT, Widget::button("2"))).unwrap();
+        let bottom_left = ui.add(None, at(Anchor::BOTTOM_LEFT, Widget::button("3"))).unwrap();
+        ui.layout_with_font(SCREEN, &font()).unwrap();
+
+        // Without focus any direction picks the first widget.
+        assert!(ui.navigate(Navigate::Down));
+        assert_eq!(ui.focus(), Some(top_left));
+        assert!(ui.navigate(Navigate::Right));
+        assert_eq!(ui.focus(), Some(top_right));
+        assert!(ui.navigate(Navigate::Down));
+        assert_eq!(ui.focus(), Some(bottom_left));
+        assert!(!ui.navigate(Navigate::Down));
+        assert_eq!(ui.focus(), Some(bottom_left));
+        assert!(ui.navigate(Navigate::Up));
+        assert_eq!(
+            ui.drain_events(),
+            vec![
+                UiEvent::FocusChanged(Some(top_left)),
+                UiEvent::FocusChanged(Some(top_right)),
+                UiEvent::FocusChanged(Some(bottom_left)),
+                UiEvent::FocusChanged(Some(top_left)),
+            ]
+        );
+    }
+
+    #[test]
+    fn tab_skips_disabled_and_hidden_widgets() {
+        let mut ui = ui();
+        let panel = ui.add(None, Widget::panel(Layout::column(0.0))).unwrap();
+        let first = ui.add(Some(panel), Widget::button("First")).unwrap();
+        ui.add(Some(panel), Widget { enabled: false, ..Widget::button("Disabled") }).unwrap();
+        ui.add(Some(panel), Widget::label("Label")).unwrap();
+        let hidden = ui.add(Some(panel), Widget { visible: false, ..Widget::panel(Layout::default()) }).unwrap();
+        ui.add(Some(hidden), Widget::button("Hidden")).unwrap();
+        let last = ui.add(Some(panel), Widget::slider(0.0, 1.0, 0.0)).unwrap();
+
+        let mut tab = |shift| {
+            ui.key(Keycode::Tab, if shift { Mod::LSHIFTMOD } else { Mod::NOMOD });
+            ui.focus()
+        };
+        assert_eq!(tab(false), Some(first));
+        assert_eq!(tab(false), Some(last));
+        assert_eq!(tab(false), Some(first));
+        assert_eq!(tab(true), Some(last));
+    }
+
+    #[test]
+    fn disabled_or_hidden_buttons_do_not_click() {
+        let mut ui = ui();
+        let panel = ui.add(None, Widget::panel(Layout::column(0.0))).unwrap();
+        let button = ui.add(Some(panel), Widget::button("Start")).unwrap();
+        ui.set_focus(Some(button));
+        assert!(ui.key(Keycode::Return, Mod::NOMOD));
+        assert_eq!(ui.drain_events(), vec![UiEvent::FocusChanged(Some(button)), UiEvent::Clicked(button)]);
+
+        // Before the next layout too: the focus goes instead.
+        ui.widget_mut(button).unwrap().enabled = false;
+        assert!(!ui.activate());
+        assert_eq!(ui.focus(), None);
+        assert_eq!(ui.drain_events(), vec![UiEvent::FocusChanged(None)]);
+
+        ui.widget_mut(button).unwrap().enabled = true;
+        ui.set_focus(Some(button));
+        ui.widget_mut(panel).unwrap().visible = false;
+        assert!(!ui.activate());
+        assert_eq!(ui.focus(), None);
+        assert_eq!(ui.drain_events(), vec![UiEvent::FocusChanged(Some(button)), UiEvent::FocusChanged(None)]);
+    }
+
+    #[test]
+    fn disabled_sliders_keep_their_value() {
+        let mut ui = ui();
+        let slider = ui.add(None, sized(Widget::slider(0.0, 100.0, 80.0), 200.0, 20.0)).unwrap();
+        ui.layout_with_font(SCREEN, &font()).unwrap();
+        ui.set_focus(Some(slider));
+        // A twentieth of the range per press.
+        assert!(ui.navigate(Navigate::Right));
+        assert_eq!(ui.value(slider).unwrap(), 85.0);
+
+        ui.widget_mut(slider).unwrap().enabled = false;
+        ui.drain_events();
+        ui.navigate(Navigate::Left);
+        ui.pointer = Vec2::new(10.0, 10.0);
+        ui.drag(slider);
+        assert_eq!(ui.value(slider).unwrap(), 85.0);
+        assert_eq!(ui.drain_events(), vec![UiEvent::FocusChanged(None)]);
+    }
+}
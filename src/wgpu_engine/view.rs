@@ -0,0 +1,188 @@
+use ultraviolet::{Vec2, Vec3};
+
+use super::{
+    camera::{Camera, Ray},
+    indirect::IndirectRenderer,
+    scene::ModelId,
+    texture::Texture,
+    WindowSize,
+};
+
+// A rectangle of a render target in fractions of its size, measured from the
+// top left corner.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Viewport {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Default for Viewport {
+    fn default() -> Self {
+        Self::FULL
+    }
+}
+
+impl Viewport {
+    pub const FULL: Viewport = Viewport {
+        x: 0.0,
+        y: 0.0,
+        width: 1.0,
+        height: 1.0,
+    };
+
+    pub fn new(x: f32, y: f32, width: f32, height: f32) -> Self {
+        Self { x, y, width, height }
+    }
+
+    // `count` side by side columns, e.g. for two player split-screen.
+    pub fn column(index: u32, count: u32) -> Self {
+        let width = 1.0 / count.max(1) as f32;
+        Self::new(index as f32 * width, 0.0, width, 1.0)
+    }
+
+    // `count` stacked rows.
+    pub fn row(index: u32, count: u32) -> Self {
+        let height = 1.0 / count.max(1) as f32;
+        Self::new(0.0, index as f32 * height, 1.0, height)
+    }
+
+    // x, y, width and height in pixels of a target of `size`, clamped to it.
+    pub fn pixels(&self, size: WindowSize) -> (f32, f32, f32, f32) {
+        let (w, h) = (size.width as f32, size.height as f32);
+        let x = (self.x * w).clamp(0.0, w);
+        let y = (self.y * h).clamp(0.0, h);
+        let width = (self.width * w).clamp(0.0, w - x);
+        let height = (self.height * h).clamp(0.0, h - y);
+        (x, y, width, height)
+    }
+
+    // Whole pixels for a scissor rect, None if nothing is left of it.
+    pub fn pixel_rect(&self, size: WindowSize) -> Option<(u32, u32, u32, u32)> {
+        let (x, y, width, height) = self.pixels(size);
+        let (x0, y0) = (x.round() as u32, y.round() as u32);
+        let (x1, y1) = ((x + width).round() as u32, (y + height).round() as u32);
+        if x1 > x0 && y1 > y0 {
+            Some((x0, y0, x1 - x0, y1 - y0))
+        } else {
+            None
+        }
+    }
+}
+
+// Viewport x, y, width, height and scissor x, y, width, height of a pass.
+pub(super) type PassRects = ((f32, f32, f32, f32), (u32, u32, u32, u32));
+
+// Where a camera draws to. A texture target is the diffuse texture of one of
+// a model's materials, so whatever uses that model shows the camera's picture;
+// create the texture with `WgpuEngine::create_render_texture`. The model's own
+// instance groups are left out of that camera's view.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RenderTarget {
+    Surface,
+    Material { model: ModelId, material: usize },
+}
+
+// A camera and where it ends up on screen. Views are drawn by ascending
+// `order`, ties in creation order; the first view drawing to a target clears
+// it, later ones draw over it.
+pub struct CameraView {
+    pub camera: Camera,
+    pub viewport: Viewport,
+    // Restricts drawing further, in the same units as `viewport`.
+    pub scissor: Option<Viewport>,
+    pub order: i32,
+    pub target: RenderTarget,
+    pub enabled: bool,
+    // Whether the engine's sprites and tilemaps are drawn over this view's scene.
+    pub sprites: bool,
+    // Whether screen space text is drawn over this view, only the main
+    // camera's by default.
+    pub hud: bool,
+    // Every view culls on its own, so it needs its own buffers.
+    pub(super) indirect: IndirectRenderer,
+    pub(super) depth_texture: Texture,
+}
+
+impl CameraView {
+    pub(super) fn new(
+        camera: Camera,
+        viewport: Viewport,
+        target: RenderTarget,
+        device: &wgpu::Device,
+        adapter: &wgpu::Adapter,
+    ) -> Self {
+        Self {
+            camera,
+            viewport,
+            scissor: None,
+            order: 0,
+            target,
+            enabled: true,
+            sprites: true,
+            hud: false,
+            indirect: IndirectRenderer::new(device, adapter),
+            depth_texture: Texture::create_depth_texture_sized(device, 1, 1, "view_depth_texture"),
+        }
+    }
+
+    // The world ray through `pixel` of a target of `size`, for picking with
+    // a camera that only covers part of it.
+    pub fn screen_to_ray(&self, pixel: Vec2, size: WindowSize) -> Ray {
+        let (offset, viewport_size) = self.viewport_pixels(size);
+        self.camera.screen_to_ray(pixel - offset, viewport_size)
+    }
+
+    // Pixel position of `point` on the whole target, see `Camera::world_to_screen`.
+    pub fn world_to_screen(&self, point: Vec3, size: WindowSize) -> Option<Vec3> {
+        let (offset, viewport_size) = self.viewport_pixels(size);
+        self.camera
+            .world_to_screen(point, viewport_size)
+            .map(|p| p + offset.into())
+    }
+
+    // Whether `pixel` of a target of `size` falls inside the viewport.
+    pub fn contains(&self, pixel: Vec2, size: WindowSize) -> bool {
+        let (x, y, width, height) = self.viewport.pixels(size);
+        pixel.x >= x && pixel.y >= y && pixel.x < x + width && pixel.y < y + height
+    }
+
+    fn viewport_pixels(&self, size: WindowSize) -> (Vec2, WindowSize) {
+        let (x, y, width, height) = self.viewport.pixels(size);
+        let size = WindowSize {
+            width: width.round() as u32,
+            height: height.round() as u32,
+        };
+        (Vec2::new(x, y), size)
+    }
+
+    // The viewport in pixels and the whole pixel scissor rect on a target of
+    // `size`, None when nothing of it would be drawn.
+    pub(super) fn pass_rects(&self, size: WindowSize) -> Option<PassRects> {
+        let viewport = self.viewport.pixels(size);
+        if viewport.2 <= 0.0 || viewport.3 <= 0.0 {
+            return None;
+        }
+        let scissor = self.scissor.unwrap_or(self.viewport).pixel_rect(size)?;
+        Some((viewport, scissor))
+    }
+
+    // Keeps the projection's aspect ratio in step with the viewport's size in
+    // pixels on a target of `size`.
+    pub(super) fn fit(&mut self, size: WindowSize) {
+        let (_, _, width, height) = self.viewport.pixels(size);
+        if width > 0.0 && height > 0.0 {
+            self.camera.projection.resize(width, height);
+        }
+    }
+
+    // The depth buffer covers the whole target, recreated when its size changes.
+    pub(super) fn fit_depth(&mut self, device: &wgpu::Device, size: WindowSize) {
+        let texture = &self.depth_texture.texture;
+        if texture.width() != size.width.max(1) || texture.height() != size.height.max(1) {
+            self.depth_texture =
+                Texture::create_depth_texture_sized(device, size.width, size.height, "view_depth_texture");
+        }
+    }
+}